@@ -0,0 +1,76 @@
+//! 比较新旧两种字面量查找的耗时: `cargo run --release --example bench [MB]`
+//! 旧实现逐行调用 `str::find`, 忽略大小写时每行都要小写化(分配两个 String)
+use minigrep::{search, search_case_insensitive, Boundary};
+use std::{env, time::Instant};
+
+const WORDS: &[&str] = &[
+  "safe", "fast", "productive", "Rust", "pick", "three", "duct", "tape", "nobody", "frog", "bog", "public",
+  "somebody", "dreary", "admiring", "livelong", "Ärger", "naïve", "café", "Straße",
+];
+
+/// 用线性同余生成器拼出固定的语料, 每次运行结果相同
+fn corpus(bytes: usize) -> String {
+  let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+  let mut text = String::with_capacity(bytes + 100);
+  while text.len() < bytes {
+    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+    let words = 4 + (seed >> 60) as usize;
+    for i in 0..words {
+      seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+      if i > 0 {
+        text.push(' ');
+      }
+      text += WORDS[(seed >> 33) as usize % WORDS.len()];
+    }
+    text.push('\n');
+  }
+  text
+}
+
+fn naive_search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+  contents.lines().filter(|line| line.contains(query)).collect()
+}
+
+fn naive_search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+  let query = query.to_lowercase();
+  contents
+    .lines()
+    .filter(|line| line.to_lowercase().contains(&query))
+    .collect()
+}
+
+/// 运行 `f` 若干次, 返回最快一次的毫秒数和结果数量
+fn time<F: FnMut() -> usize>(mut f: F) -> (f64, usize) {
+  let mut best = f64::MAX;
+  let mut count = 0;
+  for _ in 0..5 {
+    let start = Instant::now();
+    count = f();
+    best = best.min(start.elapsed().as_secs_f64() * 1000.0);
+  }
+  (best, count)
+}
+
+fn main() {
+  let mb = env::args().nth(1).and_then(|n| n.parse().ok()).unwrap_or(32);
+  let contents = corpus(mb * 1024 * 1024);
+  println!("corpus: {} MB, {} lines", mb, contents.lines().count());
+  println!("{:<28} {:>12} {:>12} {:>8}", "query", "naive (ms)", "finder (ms)", "lines");
+  for &query in &["productive", "zebra", "Straße", "x"] {
+    for &ignore_case in &[false, true] {
+      let (naive, expected) = if ignore_case {
+        time(|| naive_search_case_insensitive(query, &contents).len())
+      } else {
+        time(|| naive_search(query, &contents).len())
+      };
+      let (finder, count) = if ignore_case {
+        time(|| search_case_insensitive(query.to_string(), &contents, Boundary::None).len())
+      } else {
+        time(|| search(query.to_string(), &contents, Boundary::None).len())
+      };
+      assert_eq!(expected, count, "{}", query);
+      let name = format!("{}{}", query, if ignore_case { " (-i)" } else { "" });
+      println!("{:<28} {:>12.1} {:>12.1} {:>8}", name, naive, finder, count);
+    }
+  }
+}
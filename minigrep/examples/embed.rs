@@ -0,0 +1,50 @@
+//! 在其他程序中使用 minigrep: 自定义匹配方式和输出
+//! `cargo run --example embed [PATH...]` 列出每个文件中最长的数字
+use minigrep::{search_with, Config, Line, Matcher, Sink, Summary};
+use std::{env, io, path::Path, path::PathBuf};
+
+/// 匹配一串 ASCII 数字
+struct Digits;
+
+impl Matcher for Digits {
+  fn find_at(&self, haystack: &[u8], start: usize) -> Option<(usize, usize)> {
+    let from = start + haystack[start..].iter().position(u8::is_ascii_digit)?;
+    let len = haystack[from..].iter().take_while(|b| b.is_ascii_digit()).count();
+    Some((from, from + len))
+  }
+}
+
+/// 记录当前文件中最长的数字, 文件结束时输出
+#[derive(Default)]
+struct Longest {
+  number: String,
+}
+
+impl Sink for Longest {
+  fn matched(&mut self, _file: &Path, line: &Line) -> io::Result<()> {
+    for &(start, end) in &line.ranges {
+      if end - start > self.number.len() {
+        self.number = line.text[start..end].to_string();
+      }
+    }
+    Ok(())
+  }
+
+  fn end(&mut self, file: &Path, summary: &Summary) -> io::Result<()> {
+    if summary.matches > 0 {
+      println!("{}: {}", file.display(), std::mem::take(&mut self.number));
+    }
+    Ok(())
+  }
+}
+
+fn main() {
+  let mut paths: Vec<PathBuf> = env::args_os().skip(1).map(PathBuf::from).collect();
+  if paths.is_empty() {
+    paths.push(PathBuf::from("src"));
+  }
+  let config = Config { paths, ..Config::default() };
+  if let Err(e) = search_with(&config, Digits, &mut Longest::default()) {
+    eprintln!("{}", e.report());
+  }
+}
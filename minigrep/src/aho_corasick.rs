@@ -0,0 +1,194 @@
+//! Aho-Corasick 自动机: 一次扫描同时查找多个字面量
+//! 每个状态只保存 trie 中实际存在的边, 没有对应边时沿失败链接回退, 内存与模式的总长度成正比
+//! 根状态使用完整的转移表, 扫描时大部分字节停留在根上
+const ROOT: u32 = 0;
+
+#[derive(Debug, Clone)]
+pub struct AhoCorasick {
+  /// 每个状态在 trie 中的边, 按字节排序
+  trans: Vec<Vec<(u8, u32)>>,
+  /// 根状态对每个字节的转移, 没有边的字节留在根上
+  root: Box<[u32; 256]>,
+  fail: Vec<u32>,
+  /// 每个状态对应的前缀长度
+  depth: Vec<usize>,
+  /// 在每个状态结束的模式, 包括经由失败链接可达的后缀
+  outputs: Vec<Vec<usize>>,
+  lens: Vec<usize>,
+}
+
+impl AhoCorasick {
+  pub fn new<P: AsRef<[u8]>>(patterns: &[P]) -> AhoCorasick {
+    let mut ac = AhoCorasick {
+      trans: vec![vec![]],
+      root: Box::new([ROOT; 256]),
+      fail: vec![ROOT],
+      depth: vec![0],
+      outputs: vec![vec![]],
+      lens: patterns.iter().map(|p| p.as_ref().len()).collect(),
+    };
+    for (id, pattern) in patterns.iter().enumerate() {
+      let mut state = ROOT as usize;
+      for &b in pattern.as_ref() {
+        state = match ac.trans[state].binary_search_by_key(&b, |&(c, _)| c) {
+          Ok(i) => ac.trans[state][i].1 as usize,
+          Err(i) => {
+            let next = ac.trans.len();
+            ac.trans[state].insert(i, (b, next as u32));
+            ac.trans.push(vec![]);
+            ac.fail.push(ROOT);
+            ac.depth.push(ac.depth[state] + 1);
+            ac.outputs.push(vec![]);
+            next
+          }
+        };
+      }
+      ac.outputs[state].push(id);
+    }
+    for &(b, next) in &ac.trans[ROOT as usize] {
+      ac.root[b as usize] = next;
+    }
+
+    //按广度优先顺序计算失败链接, 处理一个状态时比它浅的状态的失败链接都已经确定
+    let mut queue = std::collections::VecDeque::from([ROOT as usize]);
+    while let Some(state) = queue.pop_front() {
+      for i in 0..ac.trans[state].len() {
+        let (b, next) = ac.trans[state][i];
+        let fallback = if state == ROOT as usize {
+          ROOT
+        } else {
+          ac.next(ac.fail[state] as usize, b) as u32
+        };
+        ac.fail[next as usize] = fallback;
+        let inherited = ac.outputs[fallback as usize].clone();
+        ac.outputs[next as usize].extend(inherited);
+        queue.push_back(next as usize);
+      }
+    }
+    ac
+  }
+
+  /// 读入字节 `b` 后的状态
+  fn next(&self, mut state: usize, b: u8) -> usize {
+    loop {
+      if state == ROOT as usize {
+        return self.root[b as usize] as usize;
+      }
+      let edges = &self.trans[state];
+      if let Ok(i) = edges.binary_search_by_key(&b, |&(c, _)| c) {
+        return edges[i].1 as usize;
+      }
+      state = self.fail[state] as usize;
+    }
+  }
+
+  pub fn pattern_count(&self) -> usize {
+    self.lens.len()
+  }
+
+  pub fn is_match(&self, haystack: &[u8]) -> bool {
+    self.find_at(haystack, 0).is_some()
+  }
+
+  /// 从 `start` 开始查找最左的匹配, 同一位置开始的多个匹配取最长的
+  /// 返回 (起始, 结束, 模式序号)
+  pub fn find_at(&self, haystack: &[u8], start: usize) -> Option<(usize, usize, usize)> {
+    self.find_accepted(haystack, start, |_, _| true)
+  }
+
+  /// 与 `find_at` 相同, 但只考虑 `accept(起始, 结束)` 成立的匹配
+  pub(crate) fn find_accepted<F: Fn(usize, usize) -> bool>(
+    &self,
+    haystack: &[u8],
+    start: usize,
+    accept: F,
+  ) -> Option<(usize, usize, usize)> {
+    let mut best = None;
+    let mut state = ROOT as usize;
+    self.consider(state, start, &accept, &mut best);
+    for (i, &b) in haystack.iter().enumerate().skip(start) {
+      //之后的匹配都从 i - depth 之后开始, 不可能比已找到的更靠左
+      if best.is_some_and(|(s, _, _)| s < i - self.depth[state]) {
+        break;
+      }
+      state = self.next(state, b);
+      self.consider(state, i + 1, &accept, &mut best);
+    }
+    best
+  }
+
+  fn consider<F: Fn(usize, usize) -> bool>(
+    &self,
+    state: usize,
+    end: usize,
+    accept: &F,
+    best: &mut Option<(usize, usize, usize)>,
+  ) {
+    for &id in &self.outputs[state] {
+      let start = end - self.lens[id];
+      if !accept(start, end) {
+        continue;
+      }
+      let better = match *best {
+        None => true,
+        Some((s, e, _)) => start < s || (start == s && end > e),
+      };
+      if better {
+        *best = Some((start, end, id));
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn find(patterns: &[&str], haystack: &str) -> Option<(usize, usize, usize)> {
+    AhoCorasick::new(patterns).find_at(haystack.as_bytes(), 0)
+  }
+
+  #[test]
+  fn leftmost_longest() {
+    let patterns = ["he", "she", "his", "hers"];
+    assert_eq!(Some((1, 4, 1)), find(&patterns, "ushers"));
+    assert_eq!(Some((0, 4, 3)), find(&patterns, "hers"));
+    assert_eq!(Some((2, 5, 2)), find(&patterns, "a his"));
+    assert_eq!(None, find(&patterns, "nothing"));
+    assert_eq!(Some((0, 2, 0)), find(&["ab", "ab"], "ab"));
+    assert_eq!(Some((4, 9, 1)), find(&["bcd", "abcde", "x"], "....abcde"));
+  }
+
+  #[test]
+  fn offsets_and_edge_cases() {
+    let ac = AhoCorasick::new(&["naïve", "ï"]);
+    assert_eq!(Some((2, 4, 1)), ac.find_at("naïve".as_bytes(), 1));
+    assert_eq!(Some((8, 14, 0)), ac.find_at("naïve, naïve".as_bytes(), 3));
+    assert_eq!(2, ac.pattern_count());
+
+    assert_eq!(Some((3, 3, 0)), AhoCorasick::new(&[""]).find_at(b"abcd", 3));
+    assert!(!AhoCorasick::new::<&str>(&[]).is_match(b"abc"));
+  }
+
+  #[test]
+  fn many_patterns() {
+    //前缀和后缀互相重叠的模式, 与逐个模式查找的结果比较
+    let patterns: Vec<String> = (0..5000).map(|i| format!("{}-{}", i * 7 % 1000, i)).collect();
+    let ac = AhoCorasick::new(&patterns);
+    assert_eq!(5000, ac.pattern_count());
+    let haystack = "x 993-4999 10-1430 1-2-3 707-101 707-1010 none";
+    let mut at = 0;
+    let mut found = vec![];
+    while let Some((start, end, id)) = ac.find_at(haystack.as_bytes(), at) {
+      found.push(&haystack[start..end]);
+      let brute = patterns
+        .iter()
+        .enumerate()
+        .filter_map(|(id, p)| haystack[at..].find(p.as_str()).map(|i| (at + i, at + i + p.len(), id)))
+        .min_by_key(|&(start, end, _)| (start, std::cmp::Reverse(end)));
+      assert_eq!(brute, Some((start, end, id)));
+      at = end;
+    }
+    assert_eq!(vec!["993-4999", "10-1430", "707-101", "707-101"], found);
+  }
+}
@@ -0,0 +1,256 @@
+//! 命令行解析: 支持短选项/长选项、合并的短选项(`-in`)、`-A2`/`--color=never` 形式的值和 `--` 终止符
+use crate::error::{self, Error};
+use std::path::Path;
+
+/// 一个选项的定义, `value` 是值的占位名, 为 None 时该选项是开关
+pub(crate) struct Spec {
+  pub(crate) short: Option<char>,
+  pub(crate) long: &'static str,
+  pub(crate) value: Option<&'static str>,
+  pub(crate) help: &'static str,
+}
+
+const fn flag(short: Option<char>, long: &'static str, help: &'static str) -> Spec {
+  Spec {
+    short,
+    long,
+    value: None,
+    help,
+  }
+}
+
+const fn option(short: Option<char>, long: &'static str, value: &'static str, help: &'static str) -> Spec {
+  Spec {
+    short,
+    long,
+    value: Some(value),
+    help,
+  }
+}
+
+pub(crate) const SPECS: &[Spec] = &[
+  option(Some('e'), "regexp", "PATTERN", "search for PATTERN; may be repeated"),
+  option(Some('f'), "file", "FILE", "search for every line of FILE"),
+  option(None, "rules", "FILE", "search for the pattern of every rule in FILE, see --sarif"),
+  flag(Some('E'), "regex", "treat patterns as regular expressions"),
+  flag(Some('Q'), "query", "treat patterns as boolean queries: AND, OR, NOT, (...), \"phrases\", MODS:term"),
+  option(None, "fuzzy", "K", "match within edit distance K of a pattern and print the distance"),
+  flag(None, "show-pattern", "print the pattern that matched before each line"),
+  flag(Some('w'), "word-regexp", "only match whole words"),
+  flag(Some('x'), "line-regexp", "only match whole lines"),
+  flag(Some('i'), "ignore-case", "search case insensitively"),
+  flag(Some('s'), "case-sensitive", "search case sensitively"),
+  flag(Some('S'), "smart-case", "ignore case unless a pattern has an uppercase letter (default)"),
+  option(None, "case-fold", "MODE", "case folding for -i without -E or --fuzzy: simple, full or turkic"),
+  option(None, "normalize", "FORM", "match after normalizing patterns and lines to nfc or nfd"),
+  flag(Some('v'), "invert-match", "select non-matching lines"),
+  flag(Some('c'), "count", "print the number of selected lines per file"),
+  flag(Some('l'), "files-with-matches", "print only the names of files with selected lines"),
+  flag(Some('L'), "files-without-match", "print only the names of files without selected lines"),
+  option(Some('m'), "max-count", "NUM", "stop reading a file after NUM selected lines"),
+  flag(Some('q'), "quiet", "print nothing, only report a match through the exit status"),
+  option(Some('r'), "replace", "TEXT", "print matches replaced with TEXT, where $0 is the match"),
+  flag(None, "in-place", "write the replacements back to the files instead of printing"),
+  flag(None, "backup", "keep the original of every edited file as FILE.bak"),
+  flag(Some('n'), "line-number", "print line numbers"),
+  flag(Some('b'), "byte-offset", "print the byte offset of each line"),
+  flag(None, "column", "print the column of the first match"),
+  flag(None, "vimgrep", "print each match as file:line:column:text, one line per match"),
+  flag(None, "json", "print begin, match, context, end and summary events as JSON Lines"),
+  flag(None, "sarif", "print a SARIF 2.1.0 report with a result for every match"),
+  option(Some('A'), "after-context", "NUM", "print NUM lines after each match"),
+  option(Some('B'), "before-context", "NUM", "print NUM lines before each match"),
+  option(Some('C'), "context", "NUM", "print NUM lines around each match"),
+  option(None, "color", "WHEN", "highlight matches: auto, always or never"),
+  option(None, "binary-files", "TYPE", "binary, text or without-match"),
+  flag(Some('a'), "text", "same as --binary-files=text"),
+  flag(Some('I'), "ignore-binary", "same as --binary-files=without-match"),
+  flag(None, "rank", "print the lines most relevant to the query words by BM25 score, ignoring case"),
+  flag(None, "paragraphs", "with --rank, rank blank-line separated paragraphs instead of lines"),
+  option(None, "top", "NUM", "with --rank, print the NUM best results (default 10)"),
+  flag(None, "mmap", "read regular files through memory maps; they must not change while searched"),
+  flag(None, "index", "skip files ruled out by the index of a searched directory"),
+  flag(None, "follow", "follow symbolic links while walking directories"),
+  flag(None, "hidden", "search hidden files and directories"),
+  flag(None, "no-ignore", "don't respect .gitignore, .ignore or the global ignore file"),
+  option(None, "max-depth", "NUM", "descend at most NUM directories"),
+  option(Some('j'), "threads", "NUM", "search files on NUM threads"),
+  flag(None, "no-config", "don't read options from $MINIGREP_CONFIG"),
+  flag(Some('h'), "help", "print this help and exit"),
+  flag(Some('V'), "version", "print the version and exit"),
+];
+
+pub(crate) fn usage(message: String) -> Error {
+  Error::Usage(message)
+}
+
+/// 解析结果: 按出现顺序排列的 (长选项名, 值) 和位置参数
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Parsed {
+  pub(crate) options: Vec<(&'static str, Option<String>)>,
+  pub(crate) positional: Vec<String>,
+}
+
+/// 解析不含程序名的参数
+pub(crate) fn parse(mut args: impl Iterator<Item = String>) -> Result<Parsed, Error> {
+  let mut parsed = Parsed::default();
+  while let Some(arg) = args.next() {
+    if arg == "--" {
+      parsed.positional.extend(args);
+      break;
+    } else if let Some(long) = arg.strip_prefix("--") {
+      let (name, attached) = match long.find('=') {
+        Some(i) => (&long[..i], Some(long[i + 1..].to_string())),
+        None => (long, None),
+      };
+      let spec = SPECS
+        .iter()
+        .find(|spec| spec.long == name)
+        .ok_or_else(|| usage(format!("unknown option '--{}'", name)))?;
+      let value = match (spec.value, attached) {
+        (None, Some(_)) => return Err(usage(format!("option '--{}' doesn't take a value", name))),
+        (None, None) => None,
+        (Some(_), Some(value)) => Some(value),
+        (Some(_), None) => Some(
+          args
+            .next()
+            .ok_or_else(|| usage(format!("option '--{}' requires a value", name)))?,
+        ),
+      };
+      parsed.options.push((spec.long, value));
+    } else if arg.len() > 1 && arg.starts_with('-') {
+      //`-nA2`: 开关可以合并, 第一个需要值的选项吃掉剩余部分或下一个参数
+      for (i, c) in arg[1..].char_indices() {
+        let spec = SPECS
+          .iter()
+          .find(|spec| spec.short == Some(c))
+          .ok_or_else(|| usage(format!("unknown option '-{}'", c)))?;
+        if spec.value.is_none() {
+          parsed.options.push((spec.long, None));
+          continue;
+        }
+        let rest = &arg[1 + i + c.len_utf8()..];
+        let value = if !rest.is_empty() {
+          rest.to_string()
+        } else {
+          args
+            .next()
+            .ok_or_else(|| usage(format!("option '-{}' requires a value", c)))?
+        };
+        parsed.options.push((spec.long, Some(value)));
+        break;
+      }
+    } else {
+      parsed.positional.push(arg);
+    }
+  }
+  Ok(parsed)
+}
+
+/// 读取配置文件中的默认选项: 每行一个参数, 忽略空行和 `#` 开头的注释
+pub(crate) fn from_file(path: &Path) -> Result<Parsed, Error> {
+  let contents = error::read_to_string(path)?;
+  let lines = contents
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    .map(String::from);
+  let parsed = parse(lines).map_err(|e| usage(format!("{}: {}", path.display(), e)))?;
+  match parsed.positional.first() {
+    Some(arg) => Err(usage(format!("{}: unexpected argument '{}'", path.display(), arg))),
+    None => Ok(parsed),
+  }
+}
+
+pub(crate) fn version() -> String {
+  format!("minigrep {}", env!("CARGO_PKG_VERSION"))
+}
+
+pub(crate) fn help() -> String {
+  let mut help = format!(
+    "{}\nSearch for QUERY or any PATTERN in each FILE, or standard input when FILE is missing or '-'.\n\n\
+     Usage: minigrep [OPTIONS] QUERY [FILE]...\n       minigrep [OPTIONS] -e PATTERN... [FILE]...\n       \
+     minigrep [OPTIONS] index build DIR\n\nOptions:\n",
+    version()
+  );
+  for spec in SPECS {
+    let short = spec.short.map_or("    ".to_string(), |c| format!("-{}, ", c));
+    let long = match spec.value {
+      Some(value) => format!("--{} <{}>", spec.long, value),
+      None => format!("--{}", spec.long),
+    };
+    help += &format!("  {}{:<24} {}\n", short, long, spec.help);
+  }
+  help += "\nEnvironment:\n";
+  help += "  CASE_SENSITIVE           search case sensitively unless -i or -S is given\n";
+  help += "  MINIGREP_CONFIG          file with default options, one argument per line\n";
+  help += "  MINIGREP_COLORS          colors in GREP_COLORS format, e.g. mt=01;31:fn=35\n";
+  help
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use std::fs;
+
+  fn parse_str(args: &[&str]) -> Result<Parsed, Error> {
+    parse(args.iter().map(|s| s.to_string()))
+  }
+
+  fn options(args: &[&str]) -> Vec<(&'static str, Option<String>)> {
+    parse_str(args).unwrap().options
+  }
+
+  #[test]
+  fn short_and_long() {
+    assert_eq!(
+      vec![("ignore-case", None), ("line-number", None), ("context", Some("2".to_string()))],
+      options(&["-in", "--context=2"])
+    );
+    assert_eq!(
+      vec![("line-number", None), ("after-context", Some("3".to_string()))],
+      options(&["-nA3"])
+    );
+    assert_eq!(vec![("threads", Some("4".to_string()))], options(&["-j", "4"]));
+    assert_eq!(vec![("color", Some("never".to_string()))], options(&["--color", "never"]));
+  }
+
+  #[test]
+  fn positional_and_terminator() {
+    let parsed = parse_str(&["-n", "query", "-", "--", "-i", "--help"]).unwrap();
+    assert_eq!(vec![("line-number", None)], parsed.options);
+    assert_eq!(vec!["query", "-", "-i", "--help"], parsed.positional);
+  }
+
+  #[test]
+  fn errors() {
+    let err = |args: &[&str]| parse_str(args).unwrap_err().to_string();
+    assert_eq!("unknown option '--nope'", err(&["--nope"]));
+    assert_eq!("unknown option '-z'", err(&["-nz"]));
+    assert_eq!("option '--hidden' doesn't take a value", err(&["--hidden=yes"]));
+    assert_eq!("option '--max-depth' requires a value", err(&["--max-depth"]));
+    assert_eq!("option '-A' requires a value", err(&["-iA"]));
+  }
+
+  #[test]
+  fn config_file() {
+    let path = std::env::temp_dir().join(format!("minigrep-config-{}", std::process::id()));
+    fs::write(&path, "# defaults\n--ignore-case\n\n  --max-depth=3\n-j\n2\n").unwrap();
+    let options = from_file(&path).unwrap().options;
+    assert_eq!("ignore-case", options[0].0);
+    assert_eq!(("max-depth", Some("3".to_string())), options[1]);
+    assert_eq!(("threads", Some("2".to_string())), options[2]);
+
+    fs::write(&path, "-n\nquery\n").unwrap();
+    let err = from_file(&path).unwrap_err().to_string();
+    fs::remove_file(&path).unwrap();
+    assert!(err.ends_with(": unexpected argument 'query'"));
+    assert!(from_file(&path).is_err());
+  }
+
+  #[test]
+  fn help_lists_every_option() {
+    let help = help();
+    assert!(SPECS.iter().all(|spec| help.contains(&format!("--{}", spec.long))));
+  }
+}
@@ -0,0 +1,132 @@
+//! ANSI 颜色输出
+//! 颜色可以通过环境变量 `MINIGREP_COLORS` 配置, 格式与 GNU grep 的 `GREP_COLORS` 相同:
+//! `mt=01;31:fn=35:ln=32:bn=32:se=36`, 值为 SGR 参数, 空值表示不着色
+use std::{
+  env,
+  io::{self, IsTerminal},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+  Auto,
+  Always,
+  Never,
+}
+
+impl ColorChoice {
+  pub fn parse(when: &str) -> Option<ColorChoice> {
+    match when {
+      "auto" => Some(ColorChoice::Auto),
+      "always" => Some(ColorChoice::Always),
+      "never" => Some(ColorChoice::Never),
+      _ => None,
+    }
+  }
+
+  /// `auto` 仅在标准输出是终端且终端支持颜色时启用
+  pub fn enabled(self) -> bool {
+    match self {
+      ColorChoice::Always => true,
+      ColorChoice::Never => false,
+      ColorChoice::Auto => io::stdout().is_terminal() && env::var("TERM").ok().as_deref() != Some("dumb"),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Colors {
+  pub matched: String,
+  pub path: String,
+  pub line_number: String,
+  pub byte_offset: String,
+  pub separator: String,
+}
+
+impl Default for Colors {
+  fn default() -> Colors {
+    Colors {
+      matched: "01;31".to_string(),
+      path: "35".to_string(),
+      line_number: "32".to_string(),
+      byte_offset: "32".to_string(),
+      separator: "36".to_string(),
+    }
+  }
+}
+
+impl Colors {
+  pub fn from_env() -> Colors {
+    match env::var("MINIGREP_COLORS") {
+      Ok(spec) => Colors::parse(&spec),
+      Err(_) => Colors::default(),
+    }
+  }
+
+  /// 在默认颜色的基础上覆盖 `spec` 中给出的项, 无法识别的项被忽略
+  pub fn parse(spec: &str) -> Colors {
+    let mut colors = Colors::default();
+    for item in spec.split(':') {
+      let (key, value) = match item.find('=') {
+        Some(i) => (&item[..i], &item[i + 1..]),
+        None => continue,
+      };
+      if !value.chars().all(|c| c.is_ascii_digit() || c == ';') {
+        continue;
+      }
+      let slot = match key {
+        "mt" | "ms" => &mut colors.matched,
+        "fn" => &mut colors.path,
+        "ln" => &mut colors.line_number,
+        "bn" => &mut colors.byte_offset,
+        "se" => &mut colors.separator,
+        _ => continue,
+      };
+      *slot = value.to_string();
+    }
+    colors
+  }
+
+  pub fn paint(code: &str, text: &str) -> String {
+    if code.is_empty() || text.is_empty() {
+      text.to_string()
+    } else {
+      format!("\x1b[{}m\x1b[K{}\x1b[m\x1b[K", code, text)
+    }
+  }
+
+  /// 高亮 `line` 中的每个匹配区间
+  pub fn highlight(&self, line: &str, ranges: &[(usize, usize)]) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut last = 0;
+    for &(start, end) in ranges {
+      out += &line[last..start];
+      out += &Colors::paint(&self.matched, &line[start..end]);
+      last = end;
+    }
+    out += &line[last..];
+    out
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn parse_spec() {
+    let colors = Colors::parse("mt=01;32:fn=:ln=bad:xx=1");
+    assert_eq!("01;32", colors.matched);
+    assert_eq!("", colors.path);
+    assert_eq!(Colors::default().line_number, colors.line_number);
+  }
+
+  #[test]
+  fn highlight_ranges() {
+    let colors = Colors::parse("mt=31");
+    assert_eq!(
+      "\x1b[31m\x1b[Ka\x1b[m\x1b[Kb\x1b[31m\x1b[Ka\x1b[m\x1b[K",
+      colors.highlight("aba", &[(0, 1), (2, 3)])
+    );
+    assert_eq!("abc", Colors::parse("mt=").highlight("abc", &[(0, 1)]));
+  }
+}
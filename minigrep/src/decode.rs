@@ -0,0 +1,228 @@
+//! 输入编码处理: 通过 BOM 识别 UTF-8/UTF-16, 无效的 UTF-8 行按 Latin-1 解码
+use std::{
+  borrow::Cow,
+  io::{self, BufRead, Read},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Bom {
+  Utf8,
+  Utf16Le,
+  Utf16Be,
+}
+
+impl Bom {
+  pub(crate) fn sniff(head: &[u8]) -> Option<Bom> {
+    if head.starts_with(&[0xEF, 0xBB, 0xBF]) {
+      Some(Bom::Utf8)
+    } else if head.starts_with(&[0xFF, 0xFE]) {
+      Some(Bom::Utf16Le)
+    } else if head.starts_with(&[0xFE, 0xFF]) {
+      Some(Bom::Utf16Be)
+    } else {
+      None
+    }
+  }
+
+  pub(crate) fn len(self) -> usize {
+    match self {
+      Bom::Utf8 => 3,
+      Bom::Utf16Le | Bom::Utf16Be => 2,
+    }
+  }
+}
+
+/// 有效的 UTF-8 直接借用, 否则把每个字节当作一个 Latin-1 字符
+pub(crate) fn decode_line(raw: &[u8]) -> Cow<'_, str> {
+  match std::str::from_utf8(raw) {
+    Ok(line) => Cow::Borrowed(line),
+    Err(_) => Cow::Owned(raw.iter().map(|&b| b as char).collect()),
+  }
+}
+
+/// 按搜索时的方式逐行解码: 跳过 BOM, UTF-16 先转为 UTF-8, 无效的 UTF-8 行按 Latin-1 解码
+/// 对每行去掉换行符的内容及其行首偏移调用 `each`, 偏移从 BOM 之后开始计算, UTF-16 时按解码后的字节计
+/// 每次只读入一行
+pub(crate) fn each_line<R, F>(mut reader: R, each: F) -> io::Result<()>
+where
+  R: BufRead,
+  F: FnMut(usize, &str),
+{
+  let bom = Bom::sniff(reader.fill_buf()?);
+  let offset = bom.map_or(0, Bom::len);
+  reader.consume(offset);
+  match bom {
+    Some(Bom::Utf16Le) | Some(Bom::Utf16Be) => {
+      let decoded = io::BufReader::new(Utf16Reader::new(reader, bom == Some(Bom::Utf16Le)));
+      split_lines(decoded, offset, each)
+    }
+    _ => split_lines(reader, offset, each),
+  }
+}
+
+fn split_lines<R, F>(mut reader: R, mut offset: usize, mut each: F) -> io::Result<()>
+where
+  R: BufRead,
+  F: FnMut(usize, &str),
+{
+  let mut raw = vec![];
+  loop {
+    raw.clear();
+    let n = reader.read_until(b'\n', &mut raw)?;
+    if n == 0 {
+      return Ok(());
+    }
+    let line = raw.strip_suffix(b"\n").unwrap_or(&raw);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    each(offset, &decode_line(line));
+    offset += n;
+  }
+}
+
+/// 与 `each_line` 相同, 但一次给出整个文件的所有行
+pub(crate) fn decode_lines(bytes: &[u8]) -> io::Result<Vec<(usize, String)>> {
+  let mut lines = vec![];
+  each_line(bytes, |offset, line| lines.push((offset, line.to_string())))?;
+  Ok(lines)
+}
+
+/// 把 UTF-16 字节流转换为 UTF-8 字节流, 无法配对的代理项替换为 U+FFFD
+pub(crate) struct Utf16Reader<R> {
+  inner: R,
+  little_endian: bool,
+  //尚未解码的原始字节(奇数字节或跨块的高位代理项)
+  pending: Vec<u8>,
+  decoded: Vec<u8>,
+  pos: usize,
+}
+
+impl<R: Read> Utf16Reader<R> {
+  pub(crate) fn new(inner: R, little_endian: bool) -> Utf16Reader<R> {
+    Utf16Reader {
+      inner,
+      little_endian,
+      pending: vec![],
+      decoded: vec![],
+      pos: 0,
+    }
+  }
+
+  /// 读取并解码下一块, 返回 false 表示输入已结束
+  fn fill(&mut self) -> io::Result<bool> {
+    let mut chunk = [0; 8192];
+    let n = self.inner.read(&mut chunk)?;
+    self.pending.extend_from_slice(&chunk[..n]);
+    self.decoded.clear();
+    self.pos = 0;
+
+    let even = self.pending.len() & !1;
+    let mut units: Vec<u16> = self.pending[..even]
+      .chunks(2)
+      .map(|pair| {
+        let pair = [pair[0], pair[1]];
+        if self.little_endian {
+          u16::from_le_bytes(pair)
+        } else {
+          u16::from_be_bytes(pair)
+        }
+      })
+      .collect();
+    //块末尾的高位代理项要等下一块的低位代理项
+    let keep = if n > 0 && units.last().is_some_and(|u| (0xD800..0xDC00).contains(u)) {
+      units.pop();
+      2
+    } else {
+      0
+    };
+    for c in char::decode_utf16(units) {
+      let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+      self.decoded.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+    }
+    self.pending.drain(..even - keep);
+    if n == 0 && !self.pending.is_empty() {
+      self.pending.clear();
+      self.decoded.extend_from_slice(char::REPLACEMENT_CHARACTER.encode_utf8(&mut [0; 4]).as_bytes());
+    }
+    Ok(n > 0 || !self.decoded.is_empty())
+  }
+}
+
+impl<R: Read> Read for Utf16Reader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    while self.pos == self.decoded.len() {
+      if !self.fill()? {
+        return Ok(0);
+      }
+    }
+    let n = buf.len().min(self.decoded.len() - self.pos);
+    buf[..n].copy_from_slice(&self.decoded[self.pos..self.pos + n]);
+    self.pos += n;
+    Ok(n)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  /// 每次只返回一个字节, 用来检查跨块的解码
+  struct OneByte<'a>(&'a [u8]);
+
+  impl<'a> Read for OneByte<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+      match self.0.split_first() {
+        Some((&b, rest)) if !buf.is_empty() => {
+          buf[0] = b;
+          self.0 = rest;
+          Ok(1)
+        }
+        _ => Ok(0),
+      }
+    }
+  }
+
+  fn utf16(text: &str, little_endian: bool) -> Vec<u8> {
+    text
+      .encode_utf16()
+      .flat_map(|u| if little_endian { u.to_le_bytes() } else { u.to_be_bytes() })
+      .collect()
+  }
+
+  #[test]
+  fn utf16_streams() {
+    let text = "héllo 🦀\nwörld";
+    for &le in &[true, false] {
+      let mut out = String::new();
+      Utf16Reader::new(OneByte(&utf16(text, le)), le).read_to_string(&mut out).unwrap();
+      assert_eq!(text, out);
+    }
+
+    let mut out = String::new();
+    Utf16Reader::new(&[0x3D, 0xD8, 0x41][..], true).read_to_string(&mut out).unwrap();
+    assert_eq!("\u{FFFD}\u{FFFD}", out);
+  }
+
+  #[test]
+  fn sniff_and_latin1() {
+    assert_eq!(Some(Bom::Utf16Le), Bom::sniff(&[0xFF, 0xFE, b'a', 0]));
+    assert_eq!(None, Bom::sniff(b"plain"));
+    assert_eq!("café", decode_line(b"caf\xe9"));
+    assert!(matches!(decode_line("café".as_bytes()), Cow::Borrowed(_)));
+  }
+
+  #[test]
+  fn whole_files() {
+    let lines = |bytes: &[u8]| decode_lines(bytes).unwrap();
+    assert_eq!(vec![(3, "a".to_string()), (6, "caf\u{e9}".to_string())], lines(b"\xef\xbb\xbfa\r\ncaf\xe9\n"));
+    let mut bytes = vec![0xFF, 0xFE];
+    bytes.extend(utf16("x\ny", true));
+    assert_eq!(vec![(2, "x".to_string()), (4, "y".to_string())], lines(&bytes));
+    assert!(lines(b"").is_empty());
+
+    //逐行读取小块时结果相同
+    let mut streamed = vec![];
+    let reader = io::BufReader::with_capacity(4, &bytes[..]);
+    each_line(reader, |offset, line| streamed.push((offset, line.to_string()))).unwrap();
+    assert_eq!(lines(&bytes), streamed);
+  }
+}
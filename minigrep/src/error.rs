@@ -0,0 +1,104 @@
+//! 库中所有操作共用的错误类型, 底层错误通过 `source` 串起来
+use crate::{ParseError, QueryError};
+use std::{
+  error, fmt, fs, io,
+  path::{Path, PathBuf},
+  string::FromUtf8Error,
+};
+
+#[derive(Debug)]
+pub enum Error {
+  /// 命令行参数、配置文件或规则文件有误
+  Usage(String),
+  /// 读取 `path` 失败
+  Io { path: PathBuf, source: io::Error },
+  /// 写输出失败
+  Output(io::Error),
+  /// 模式文件、规则文件或配置文件不是 UTF-8
+  Encoding { path: PathBuf, source: FromUtf8Error },
+  /// 模式无法编译
+  Pattern { pattern: String, source: ParseError },
+  /// `--query` 的查询有语法错误或其中的正则无效
+  Query { query: String, source: QueryError },
+  /// 这么多个文件因为出错被跳过, 每个错误已经交给 `Sink::error`
+  Skipped(usize),
+}
+
+impl Error {
+  pub(crate) fn io(path: &Path, source: io::Error) -> Error {
+    Error::Io {
+      path: path.to_path_buf(),
+      source,
+    }
+  }
+
+  /// 错误及其所有原因, 用 `: ` 连接, 例如 `poem.txt: No such file or directory (os error 2)`
+  pub fn report(&self) -> String {
+    let mut report = self.to_string();
+    let mut source = error::Error::source(self);
+    while let Some(cause) = source {
+      report += &format!(": {}", cause);
+      source = cause.source();
+    }
+    report
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::Usage(message) => write!(f, "{}", message),
+      Error::Io { path, .. } => write!(f, "{}", path.display()),
+      Error::Output(_) => write!(f, "write error"),
+      Error::Encoding { path, .. } => write!(f, "{}: not valid UTF-8", path.display()),
+      Error::Pattern { pattern, .. } => write!(f, "invalid pattern '{}'", pattern),
+      Error::Query { query, .. } => write!(f, "invalid query '{}'", query),
+      Error::Skipped(1) => write!(f, "1 file could not be searched"),
+      Error::Skipped(n) => write!(f, "{} files could not be searched", n),
+    }
+  }
+}
+
+impl error::Error for Error {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match self {
+      Error::Io { source, .. } | Error::Output(source) => Some(source),
+      Error::Encoding { source, .. } => Some(source),
+      Error::Pattern { source, .. } => Some(source),
+      Error::Query { source, .. } => Some(source),
+      _ => None,
+    }
+  }
+}
+
+/// 读取整个文本文件, 区分读取失败和编码错误
+pub(crate) fn read_to_string(path: &Path) -> Result<String, Error> {
+  let bytes = fs::read(path).map_err(|e| Error::io(path, e))?;
+  String::from_utf8(bytes).map_err(|source| Error::Encoding {
+    path: path.to_path_buf(),
+    source,
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::Regex;
+
+  #[test]
+  fn source_chains() {
+    let missing = read_to_string(Path::new("no/such/file")).unwrap_err();
+    assert!(matches!(&missing, Error::Io { source, .. } if source.kind() == io::ErrorKind::NotFound));
+    assert!(missing.report().starts_with("no/such/file: "));
+
+    let pattern = Error::Pattern {
+      pattern: "a(b".to_string(),
+      source: Regex::new("a(b", false).unwrap_err(),
+    };
+    assert_eq!("invalid pattern 'a(b'", pattern.to_string());
+    assert_eq!("invalid pattern 'a(b': unclosed group at offset 1", pattern.report());
+    assert!(error::Error::source(&Error::Usage("bad".to_string())).is_none());
+    let output = Error::Output(io::Error::other("disk full"));
+    assert_eq!("write error: disk full", output.report());
+  }
+}
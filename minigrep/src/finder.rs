@@ -0,0 +1,404 @@
+//! 单个字面量的查找: 查询只在构建时预处理一次, 搜索时不分配内存
+//! 区分大小写的 ASCII 查询使用 Boyer-Moore-Horspool, 前面先按字(u64)同时比较查询中最少见的两个字节来跳过文本,
+//! 其余情况按字扫描可能的首字节, 再逐字符比较
+//! 可以直接在整个缓冲区上运行, 不需要先切分成行
+use crate::unicode::{self, Fold};
+
+/// 首字节候选集合超过这个大小时退回到逐字节查表
+const MAX_SWAR_BYTES: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct Finder {
+  needle: String,
+  /// 忽略大小写时的折叠方式, `needle` 已经折叠
+  fold: Option<Fold>,
+  /// 只用于区分大小写的 ASCII 查询
+  exact: Option<Exact>,
+  /// 匹配可能开始的字节, 包括 Latin-1 解码时对应的单字节
+  first: Vec<u8>,
+  /// `first` 中每个字节重复 8 次组成的字, 用于按字扫描
+  splats: Vec<u64>,
+  first_set: Box<[bool; 256]>,
+  /// 查询可能匹配 Latin-1 的非 ASCII 字符(或其折叠结果), 这样的行在缓冲区中无法按 UTF-8 确认
+  latin1: bool,
+}
+
+/// 把查询与一段文本的开头比较的结果
+enum Compare {
+  /// 匹配在文本中的结束位置
+  Match(usize),
+  Mismatch,
+  /// 文本在匹配完成前就结束了
+  Partial,
+}
+
+impl Finder {
+  /// 忽略大小写时使用完整的大小写折叠
+  pub fn new(needle: &str, ignore_case: bool) -> Finder {
+    Finder::with_fold(needle, if ignore_case { Some(Fold::Full) } else { None })
+  }
+
+  /// `fold` 为 None 时区分大小写
+  pub fn with_fold(needle: &str, fold: Option<Fold>) -> Finder {
+    let needle = match fold {
+      Some(mode) => unicode::fold_str(needle, mode),
+      None => needle.to_string(),
+    };
+    let exact = if fold.is_none() && needle.is_ascii() && needle.len() > 1 {
+      Some(Exact::new(needle.as_bytes()))
+    } else {
+      None
+    };
+    let mut first_set = Box::new([false; 256]);
+    if let Some(n) = needle.chars().next() {
+      for c in first_chars(n, fold) {
+        first_set[c.encode_utf8(&mut [0; 4]).as_bytes()[0] as usize] = true;
+        //无效的 UTF-8 行按 Latin-1 解码, 每个字节就是一个字符
+        if (c as u32) < 256 {
+          first_set[c as usize] = true;
+        }
+      }
+    }
+    let first: Vec<u8> = (0..=255u8).filter(|&b| first_set[b as usize]).collect();
+    let splats = first.iter().map(|&b| splat(b)).collect();
+    let latin1 = (0x80..=0xFFu8).map(char::from).any(|c| match fold {
+      Some(mode) => unicode::fold(c, mode).any(|f| needle.contains(f)),
+      None => needle.contains(c),
+    });
+    Finder {
+      needle,
+      fold,
+      exact,
+      first,
+      splats,
+      first_set,
+      latin1,
+    }
+  }
+
+  /// 在一行(有效的 UTF-8)中从 `start` 开始查找第一个匹配
+  pub fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+    let haystack = line.as_bytes();
+    if self.needle.is_empty() {
+      return Some((start, start));
+    }
+    if let Some(exact) = &self.exact {
+      return exact.find(self.needle.as_bytes(), haystack, start).map(|i| (i, i + self.needle.len()));
+    }
+    let mut at = start;
+    while let Some(i) = self.scan(haystack, at) {
+      if line.is_char_boundary(i) {
+        if let Compare::Match(end) = self.compare(&line[i..]) {
+          return Some((i, i + end));
+        }
+      }
+      at = i + 1;
+    }
+    None
+  }
+
+  /// 在任意字节(可能是多行, 也可能不是有效的 UTF-8)中查找下一个可能的匹配起点
+  /// 保证 `start` 与返回值之间没有匹配, 但返回的位置不一定真的匹配, 需要按行再确认
+  pub fn candidate(&self, haystack: &[u8], start: usize) -> Option<usize> {
+    if self.needle.is_empty() {
+      return Some(start).filter(|&start| start < haystack.len());
+    }
+    if let Some(exact) = &self.exact {
+      return exact.find(self.needle.as_bytes(), haystack, start);
+    }
+    let mut at = start;
+    while let Some(i) = self.scan(haystack, at) {
+      //每个查询字节最多对应 4 个字节的原文
+      let window = &haystack[i..haystack.len().min(i + 4 * self.needle.len())];
+      if self.latin1 && !window.is_ascii() {
+        return Some(i);
+      }
+      let valid = match std::str::from_utf8(window) {
+        Ok(text) => text,
+        Err(e) => std::str::from_utf8(&window[..e.valid_up_to()]).unwrap_or_default(),
+      };
+      match self.compare(valid) {
+        Compare::Match(_) => return Some(i),
+        //之后的内容不是有效的 UTF-8(或者在字符中间被截断), 只能交给按行的搜索确认
+        Compare::Partial if valid.len() < window.len() => return Some(i),
+        _ => at = i + 1,
+      }
+    }
+    None
+  }
+
+  fn scan(&self, haystack: &[u8], start: usize) -> Option<usize> {
+    if start >= haystack.len() {
+      return None;
+    }
+    if self.first.len() <= MAX_SWAR_BYTES {
+      memchr_any(&self.first, &self.splats, &haystack[start..]).map(|i| start + i)
+    } else {
+      haystack[start..]
+        .iter()
+        .position(|&b| self.first_set[b as usize])
+        .map(|i| start + i)
+    }
+  }
+
+  /// 检查 `text` 是否以查询开头
+  fn compare(&self, text: &str) -> Compare {
+    let mode = match self.fold {
+      Some(mode) => mode,
+      None => {
+        return if text.starts_with(self.needle.as_str()) {
+          Compare::Match(self.needle.len())
+        } else if self.needle.starts_with(text) {
+          Compare::Partial
+        } else {
+          Compare::Mismatch
+        }
+      }
+    };
+    //ASCII 的快速路径: 原文也是 ASCII 时逐字节比较即可, 土耳其规则下 I 与 i 不相等所以不能用
+    let len = self.needle.len();
+    if mode != Fold::Turkic && self.needle.is_ascii() && text.len() >= len && text.as_bytes()[..len].is_ascii() {
+      return if text.as_bytes()[..len].eq_ignore_ascii_case(self.needle.as_bytes()) {
+        Compare::Match(len)
+      } else {
+        Compare::Mismatch
+      };
+    }
+    let mut needle = self.needle.chars();
+    for (i, c) in text.char_indices() {
+      let end = i + c.len_utf8();
+      for folded in unicode::fold(c, mode) {
+        match needle.next() {
+          Some(n) if n == folded => {}
+          //查询在一个字符的折叠结果中间结束时不算匹配, 例如 `s` 不匹配 `ß`
+          _ => return Compare::Mismatch,
+        }
+      }
+      if needle.as_str().is_empty() {
+        return Compare::Match(end);
+      }
+    }
+    Compare::Partial
+  }
+}
+
+/// 折叠结果以 `n` 开头的所有字符
+fn first_chars(n: char, fold: Option<Fold>) -> Vec<char> {
+  match fold {
+    Some(mode) => unicode::folds_starting_with(n, mode),
+    None => vec![n],
+  }
+}
+
+/// 区分大小写的 ASCII 查询的预处理结果
+#[derive(Debug, Clone)]
+struct Exact {
+  /// Horspool 的坏字符跳转表
+  shift: Box<[usize; 256]>,
+  /// 查询中最少见的两个字节及其位置
+  rare: [(u8, usize); 2],
+}
+
+impl Exact {
+  fn new(needle: &[u8]) -> Exact {
+    let mut shift = Box::new([needle.len(); 256]);
+    for (i, &b) in needle[..needle.len() - 1].iter().enumerate() {
+      shift[b as usize] = needle.len() - 1 - i;
+    }
+    let mut bytes: Vec<(u8, usize)> = needle.iter().copied().zip(0..).collect();
+    bytes.sort_by_key(|&(b, i)| (frequency(b), i));
+    Exact {
+      shift,
+      rare: [bytes[0], bytes[1]],
+    }
+  }
+
+  /// 每次检查 8 个起点, 两个少见字节同时出现在各自位置上的起点才用 Horspool 的方式确认
+  /// 误报太多(确认的开销超过扫描长度的常数倍)时剩余部分直接用 Horspool 搜索, 总开销不超过 Horspool
+  fn find(&self, needle: &[u8], haystack: &[u8], start: usize) -> Option<usize> {
+    let [(b1, o1), (b2, o2)] = self.rare;
+    let (s1, s2) = (splat(b1), splat(b2));
+    let last = haystack.len().checked_sub(needle.len())?;
+    let mut at = start;
+    let mut verified = 0;
+    while at + 8 <= last + 1 {
+      let mut lanes = has_zero(word(&haystack[at + o1..]) ^ s1) & has_zero(word(&haystack[at + o2..]) ^ s2);
+      while lanes != 0 {
+        let i = at + lanes.trailing_zeros() as usize / 8;
+        if matches_at(needle, haystack, i) {
+          return Some(i);
+        }
+        verified += needle.len();
+        if verified > 4 * (i - start) + 256 {
+          return horspool(needle, &self.shift, haystack, i + 1);
+        }
+        lanes &= lanes - 1;
+      }
+      at += 8;
+    }
+    horspool(needle, &self.shift, haystack, at)
+  }
+}
+
+/// 英文文本和源代码中字节的大致出现频率, 越小越少见
+fn frequency(b: u8) -> u8 {
+  const COMMON: &[u8] = b"zqjxkvbpgwyfmculdhrsnioate ";
+  match COMMON.iter().position(|&c| c == b) {
+    Some(i) => 100 + i as u8,
+    None if b.is_ascii_uppercase() => 50 + COMMON.iter().position(|&c| c == b.to_ascii_lowercase()).unwrap_or(0) as u8,
+    None if b.is_ascii_alphanumeric() || b.is_ascii_whitespace() => 40,
+    None => 0,
+  }
+}
+
+/// 先比较最后一个字节, 再比较其余部分
+fn matches_at(needle: &[u8], haystack: &[u8], at: usize) -> bool {
+  let last = needle.len() - 1;
+  haystack[at + last] == needle[last] && haystack[at..at + last] == needle[..last]
+}
+
+fn horspool(needle: &[u8], shift: &[usize; 256], haystack: &[u8], start: usize) -> Option<usize> {
+  let last = needle.len() - 1;
+  let mut at = start;
+  while at + last < haystack.len() {
+    if matches_at(needle, haystack, at) {
+      return Some(at);
+    }
+    at += shift[haystack[at + last] as usize];
+  }
+  None
+}
+
+/// 从 `bytes` 开头读取 8 个字节
+fn word(bytes: &[u8]) -> u64 {
+  let mut word = [0; 8];
+  word.copy_from_slice(&bytes[..8]);
+  u64::from_le_bytes(word)
+}
+
+/// 某个字节为 0 时对应的最高位被置位, 比最低的 0 字节更高的字节可能误报, 但不会漏报
+fn has_zero(w: u64) -> u64 {
+  w.wrapping_sub(LO) & !w & HI
+}
+
+const LO: u64 = 0x0101_0101_0101_0101;
+const HI: u64 = 0x8080_8080_8080_8080;
+
+fn splat(b: u8) -> u64 {
+  LO * b as u64
+}
+
+/// 按 8 字节一组查找字节 `b` 第一次出现的位置
+pub(crate) fn memchr(b: u8, haystack: &[u8]) -> Option<usize> {
+  memchr_any(&[b], &[splat(b)], haystack)
+}
+
+/// 按 8 字节一组查找 `needles` 中任意一个字节第一次出现的位置, `splats` 是每个字节的 `splat`
+fn memchr_any(needles: &[u8], splats: &[u64], haystack: &[u8]) -> Option<usize> {
+  let mut chunks = haystack.chunks_exact(8);
+  let mut offset = 0;
+  for chunk in &mut chunks {
+    let word = word(chunk);
+    //每个掩码的最低位都是真正的匹配, 合并后的最低位就是第一个匹配
+    let mask = splats.iter().fold(0, |mask, &splat| mask | has_zero(word ^ splat));
+    if mask != 0 {
+      return Some(offset + mask.trailing_zeros() as usize / 8);
+    }
+    offset += 8;
+  }
+  chunks.remainder().iter().position(|b| needles.contains(b)).map(|i| offset + i)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn sensitive() {
+    let finder = Finder::new("duct", false);
+    assert_eq!(Some((15, 19)), finder.find_at("safe, fast, productive.", 0));
+    assert_eq!(None, finder.find_at("Duct tape.", 0));
+    assert_eq!(Some((5, 9)), finder.find_at("ductXduct", 1));
+    assert_eq!(Some((2, 4)), Finder::new("ï", false).find_at("naïve", 0));
+    assert_eq!(Some((3, 3)), Finder::new("", false).find_at("abc", 3));
+    assert_eq!(Some((1, 2)), Finder::new("b", false).find_at("abcb", 0));
+    //两个少见字节在同一个字里多次出现, 匹配跨过字的边界, 或者在不足一个字的结尾
+    let haystack = "zbzbzbzbzebrzebzzebra zebrazebra";
+    let finder = Finder::new("zebra", false);
+    for start in 0..=haystack.len() {
+      let expected = haystack[start..].find("zebra").map(|i| (start + i, start + i + 5));
+      assert_eq!(expected, finder.find_at(haystack, start));
+    }
+    //少见字节到处都是时预过滤不断误报, 改用 Horspool 后结果不变
+    let haystack = format!("{}b", "a".repeat(5000));
+    let needle = format!("{}b", "a".repeat(100));
+    assert_eq!(Some((4900, 5001)), Finder::new(&needle, false).find_at(&haystack, 0));
+    assert_eq!(None, Finder::new(&needle, false).find_at(&haystack[..5000], 0));
+    assert_eq!(Some(4900), Finder::new(&needle, false).candidate(haystack.as_bytes(), 7));
+  }
+
+  #[test]
+  fn insensitive() {
+    let finder = Finder::new("RUST", true);
+    assert_eq!(Some((1, 5)), finder.find_at("Trust me.", 0));
+    assert_eq!(Some((0, 4)), finder.find_at("rUsT", 0));
+    assert_eq!(Some((10, 16)), Finder::new("ärust", true).find_at("Trust me, ÄRUST.", 0));
+    //开尔文符号的小写是 ASCII 的 k
+    assert_eq!(Some((0, 4)), Finder::new("ki", true).find_at("\u{212A}I", 0));
+    assert_eq!(None, Finder::new("ab", true).find_at("a", 0));
+  }
+
+  #[test]
+  fn candidates_over_buffers() {
+    let finder = Finder::new("café", true);
+    let buffer = b"tea\nun CAF\xc9\nCaf\xc3\xa9 noir\n";
+    //Latin-1 编码的行不是有效的 UTF-8, 仍然给出候选位置
+    assert_eq!(Some(7), finder.candidate(buffer, 0));
+    assert_eq!(Some(12), finder.candidate(buffer, 8));
+    assert_eq!(None, finder.candidate(buffer, 13));
+    assert_eq!(Some(4), Finder::new("b", false).candidate(b"aaa\nb", 0));
+  }
+
+  #[test]
+  fn folded_first_chars_are_complete() {
+    //检查从折叠表得到的首字符与逐个字符折叠的结果一致
+    for n in ['k', 's', 'i', 'σ', 'a'] {
+      let brute: Vec<char> = (0..=char::MAX as u32)
+        .filter_map(char::from_u32)
+        .filter(|&c| unicode::fold(c, Fold::Full).next() == Some(n))
+        .collect();
+      let mut chars = first_chars(n, Some(Fold::Full));
+      chars.sort_unstable();
+      assert_eq!(brute, chars);
+    }
+  }
+
+  #[test]
+  fn folding_modes() {
+    let full = Finder::new("strasse", true);
+    assert_eq!(Some((4, 11)), full.find_at("die Straße", 0));
+    assert_eq!(Some((4, 12)), full.find_at("DIE STRAẞE", 0));
+    assert_eq!(None, Finder::with_fold("strasse", Some(Fold::Simple)).find_at("die Straße", 0));
+    assert_eq!(Some((0, 3)), Finder::with_fold("ıi", Some(Fold::Turkic)).find_at("Iİ", 0));
+    assert_eq!(None, Finder::with_fold("ii", Some(Fold::Turkic)).find_at("II", 0));
+    //查询必须用完一个字符的整个折叠结果
+    assert_eq!(None, Finder::new("s", true).find_at("ß", 0));
+    assert_eq!(None, Finder::new("stras", true).find_at("Straße", 0));
+    assert_eq!(Some((2, 3)), Finder::new("s", true).find_at("ßs", 0));
+    assert_eq!(Some((0, 5)), Finder::new("stras", true).find_at("STRASSE", 0));
+    //Latin-1 编码的 ß 与 SS 相等
+    assert_eq!(Some(3), full.candidate(b"xx\nStra\xdf\xa9e", 0));
+  }
+
+  #[test]
+  fn memchr_scans() {
+    let haystack = b"0123456789abcdefghij";
+    for (i, &b) in haystack.iter().enumerate() {
+      assert_eq!(Some(i), memchr(b, haystack));
+      let needles = [b'z', b, b'#'];
+      let splats: Vec<u64> = needles.iter().map(|&b| splat(b)).collect();
+      assert_eq!(Some(i), memchr_any(&needles, &splats, haystack));
+    }
+    assert_eq!(None, memchr(b'z', haystack));
+  }
+}
@@ -0,0 +1,253 @@
+//! 近似匹配: 查找与查询的编辑距离(Levenshtein)不超过 K 的子串
+//! 查询不超过 64 个字符时使用 Myers 的位并行算法, 每个文本字符只需常数次位运算, 更长的查询逐列动态规划
+use crate::unicode::{self, Fold};
+
+/// 位并行算法一个字能容纳的查询长度
+const WORD: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct Fuzzy {
+  /// 忽略大小写时已经简单折叠
+  pattern: Vec<char>,
+  max: usize,
+  ignore_case: bool,
+  /// 每个 ASCII 字符在查询中出现位置的位图
+  ascii: Box<[u64; 128]>,
+  /// 非 ASCII 字符的位图, 按字符排序
+  other: Vec<(char, u64)>,
+}
+
+impl Fuzzy {
+  /// 忽略大小写时逐字符简单折叠, 保证折叠前后字符一一对应
+  pub fn new(pattern: &str, max: usize, ignore_case: bool) -> Fuzzy {
+    let mut fuzzy = Fuzzy {
+      pattern: vec![],
+      max,
+      ignore_case,
+      ascii: Box::new([0; 128]),
+      other: vec![],
+    };
+    fuzzy.pattern = pattern.chars().map(|c| fuzzy.fold(c)).collect();
+    for (i, &c) in fuzzy.pattern.iter().enumerate().take(WORD) {
+      if c.is_ascii() {
+        fuzzy.ascii[c as usize] |= 1 << i;
+      } else {
+        match fuzzy.other.binary_search_by_key(&c, |&(k, _)| k) {
+          Ok(found) => fuzzy.other[found].1 |= 1 << i,
+          Err(at) => fuzzy.other.insert(at, (c, 1 << i)),
+        }
+      }
+    }
+    fuzzy
+  }
+
+  /// 允许的最大编辑距离
+  pub fn max(&self) -> usize {
+    self.max
+  }
+
+  /// 从 `start` 开始查找编辑距离最小的子串, 距离相同时取最先结束的
+  /// 结束位置之后距离不变时继续延伸, 起点取能达到该距离的最靠左的位置
+  pub fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+    if self.pattern.is_empty() {
+      return Some((start, start));
+    }
+    //(字节偏移, 原字符长度, 折叠后的字符)
+    let chars: Vec<(usize, usize, char)> = line[start..]
+      .char_indices()
+      .map(|(i, c)| (start + i, c.len_utf8(), self.fold(c)))
+      .collect();
+    //空子串的距离就是查询的长度
+    let mut best = Some((self.pattern.len(), 0)).filter(|&(distance, _)| distance <= self.max);
+    let mut extending = false;
+    self.scan(chars.iter().map(|&(_, _, c)| c), |i, score| {
+      let better = match best {
+        Some((distance, _)) => score < distance || (score == distance && extending),
+        None => score <= self.max,
+      };
+      if better {
+        best = Some((score, i + 1));
+        extending = true;
+      } else if score != best.map_or(score, |(distance, _)| distance) {
+        extending = false;
+      }
+      //已经是精确匹配且不再延伸时不可能更好
+      best.is_none_or(|(distance, _)| distance > 0 || extending)
+    });
+
+    let (distance, end) = best?;
+    let folded: Vec<char> = chars[..end].iter().map(|&(_, _, c)| c).collect();
+    let len = self.longest(&folded, distance);
+    let to = chars[..end].last().map_or(start, |&(i, n, _)| i + n);
+    let from = if len == 0 { to } else { chars[end - len].0 };
+    Some((from, to))
+  }
+
+  /// 查询与 `text` 整体的编辑距离
+  pub fn distance(&self, text: &str) -> usize {
+    let text: Vec<char> = text.chars().map(|c| self.fold(c)).collect();
+    let mut column: Vec<usize> = (0..=self.pattern.len()).collect();
+    for (j, &c) in text.iter().enumerate() {
+      self.step(&mut column, j + 1, c, |r| self.pattern[r]);
+    }
+    column[self.pattern.len()]
+  }
+
+  fn fold(&self, c: char) -> char {
+    if self.ignore_case {
+      unicode::fold(c, Fold::Simple).next().unwrap_or(c)
+    } else {
+      c
+    }
+  }
+
+  fn peq(&self, c: char) -> u64 {
+    if c.is_ascii() {
+      self.ascii[c as usize]
+    } else {
+      self.other.binary_search_by_key(&c, |&(k, _)| k).map_or(0, |found| self.other[found].1)
+    }
+  }
+
+  /// 依次给出以每个文本字符结尾的子串与查询的最小编辑距离, `each` 返回 false 时停止
+  fn scan<I, F>(&self, text: I, mut each: F)
+  where
+    I: Iterator<Item = char>,
+    F: FnMut(usize, usize) -> bool,
+  {
+    let m = self.pattern.len();
+    if m > WORD {
+      //子串可以从任意位置开始, 所以第 0 行始终为 0
+      let mut column: Vec<usize> = (0..=m).collect();
+      for (i, c) in text.enumerate() {
+        self.step(&mut column, 0, c, |r| self.pattern[r]);
+        if !each(i, column[m]) {
+          return;
+        }
+      }
+      return;
+    }
+    //Pv/Mv 记录当前列相邻两行之差为 +1/-1 的位置, score 是最后一行的值
+    let last = 1u64 << (m - 1);
+    let (mut pv, mut mv, mut score) = (!0u64, 0u64, m);
+    for (i, c) in text.enumerate() {
+      let eq = self.peq(c);
+      let xv = eq | mv;
+      let xh = ((eq & pv).wrapping_add(pv) ^ pv) | eq;
+      let ph = mv | !(xh | pv);
+      let mh = pv & xh;
+      if ph & last != 0 {
+        score += 1;
+      } else if mh & last != 0 {
+        score -= 1;
+      }
+      //第 0 行始终为 0, 所以移入的最低位是 0
+      let (ph, mh) = (ph << 1, mh << 1);
+      pv = mh | !(xv | ph);
+      mv = ph & xv;
+      if !each(i, score) {
+        return;
+      }
+    }
+  }
+
+  /// 编辑距离矩阵向右推进一列, `top` 是新列第 0 行的值, `pattern(r)` 是第 r 个查询字符
+  fn step<P: Fn(usize) -> char>(&self, column: &mut [usize], top: usize, c: char, pattern: P) {
+    let mut diag = column[0];
+    column[0] = top;
+    for r in 1..column.len() {
+      let up = column[r];
+      let cost = usize::from(pattern(r - 1) != c);
+      column[r] = (diag + cost).min(up + 1).min(column[r - 1] + 1);
+      diag = up;
+    }
+  }
+
+  /// 以 `text` 结尾、编辑距离不超过 `distance` 的最长子串的字符数
+  fn longest(&self, text: &[char], distance: usize) -> usize {
+    let m = self.pattern.len();
+    //从结尾向前比较, 查询也倒过来
+    let mut column: Vec<usize> = (0..=m).collect();
+    let mut longest = 0;
+    for (l, &c) in text.iter().rev().enumerate() {
+      self.step(&mut column, l + 1, c, |r| self.pattern[m - 1 - r]);
+      if column[m] <= distance {
+        longest = l + 1;
+      }
+      //每一行都超过了距离, 再向前只会更大
+      if column.iter().all(|&d| d > distance) {
+        break;
+      }
+    }
+    longest
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn find(pattern: &str, max: usize, line: &str) -> Option<(usize, usize, usize)> {
+    let fuzzy = Fuzzy::new(pattern, max, false);
+    fuzzy
+      .find_at(line, 0)
+      .map(|(s, e)| (s, e, fuzzy.distance(&line[s..e])))
+  }
+
+  #[test]
+  fn edits() {
+    assert_eq!(Some((6, 12, 0)), find("rustic", 1, "a bit rustic"));
+    //替换、插入和删除各算一次
+    assert_eq!(Some((6, 12, 1)), find("rustic", 1, "a bit rostic"));
+    assert_eq!(Some((6, 13, 1)), find("rustic", 1, "a bit rusttic"));
+    assert_eq!(Some((6, 11, 1)), find("rustic", 1, "a bit rutic."));
+    assert_eq!(None, find("rustic", 1, "a bit rotsic"));
+    //交换两个字符算两次
+    assert_eq!(Some((6, 12, 2)), find("rustic", 2, "a bit rutsic"));
+    //更好的匹配在后面
+    assert_eq!(Some((10, 14, 0)), find("duct", 1, "dict, and duct"));
+    assert_eq!(Some((0, 0, 2)), find("ab", 2, "xyz"));
+  }
+
+  #[test]
+  fn unicode_and_case() {
+    assert_eq!(Some((0, 6, 1)), find("naive", 1, "naïve"));
+    assert_eq!(Some((4, 10, 0)), Fuzzy::new("ÉCOLE", 0, true).find_at("une école", 0).map(|(s, e)| (s, e, 0)));
+    assert_eq!(None, find("ÉCOLE", 0, "une école"));
+  }
+
+  #[test]
+  fn scores_match_brute_force() {
+    let text: Vec<char> = "sitting in the kitchen with a kitten, smitten".chars().collect();
+    for pattern in ["kitten", "mitt", "the kitchen", "z"] {
+      let fuzzy = Fuzzy::new(pattern, 0, false);
+      let mut scores = vec![];
+      fuzzy.scan(text.iter().copied(), |_, score| {
+        scores.push(score);
+        true
+      });
+      let brute: Vec<usize> = (1..=text.len())
+        .map(|end| {
+          (0..=end)
+            .map(|start| fuzzy.distance(&text[start..end].iter().collect::<String>()))
+            .min()
+            .unwrap()
+        })
+        .collect();
+      assert_eq!(brute, scores, "{}", pattern);
+    }
+  }
+
+  #[test]
+  fn long_patterns() {
+    //超过 64 个字符时逐列计算, 结果应与位并行一致
+    let pattern = "abcdefghij".repeat(7);
+    let mut line = format!("xx{}yy", pattern);
+    line.replace_range(10..11, "#");
+    line.remove(40);
+    assert_eq!(Some((2, 71, 2)), find(&pattern, 2, &line));
+    assert_eq!(None, find(&pattern, 1, &line));
+    //前 60 个字符从第二轮开始只差一个删除
+    assert_eq!(Some((12, 71, 1)), find(&pattern[..60], 2, &line));
+  }
+}
@@ -0,0 +1,346 @@
+//! 按照 git 的规则解析 `.gitignore`、`.ignore` 和全局忽略文件
+//! 同一文件中靠后的规则优先, 更深层目录中的忽略文件优先于上层, 全局文件优先级最低
+use std::{
+  env, fs, io,
+  path::{Path, PathBuf},
+  rc::Rc,
+};
+
+/// 同一目录下按优先级从高到低排列的忽略文件
+const IGNORE_FILES: [&str; 2] = [".ignore", ".gitignore"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Match {
+  None,
+  Ignore,
+  /// 被 `!pattern` 重新包含
+  Whitelist,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Char(char),
+  /// `?`
+  Any,
+  /// `*`, 不跨越 `/`
+  Star,
+  /// 末尾的 `/**`中的 `**`, 匹配任意内容
+  DoubleStar,
+  /// 开头的 `**/` 或中间的 `/**/`, 匹配零或多级目录
+  Dirs,
+  Class(Vec<(char, char)>, bool),
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+  tokens: Vec<Token>,
+  negated: bool,
+  dir_only: bool,
+  //包含 `/` 的模式相对忽略文件所在目录匹配, 否则只匹配文件名
+  anchored: bool,
+}
+
+impl Rule {
+  fn parse(line: &str) -> Option<Rule> {
+    let line = trim_trailing_spaces(line);
+    if line.is_empty() || line.starts_with('#') {
+      return None;
+    }
+    let (negated, mut pattern) = match line.strip_prefix('!') {
+      Some(rest) => (true, rest),
+      None => (false, line),
+    };
+    let dir_only = pattern.ends_with('/') && !pattern.ends_with("\\/");
+    if dir_only {
+      pattern = &pattern[..pattern.len() - 1];
+    }
+    let anchored = pattern.contains('/');
+    let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+    if pattern.is_empty() {
+      return None;
+    }
+    Some(Rule {
+      tokens: tokenize(pattern),
+      negated,
+      dir_only,
+      anchored,
+    })
+  }
+
+  fn matches(&self, relative: &[char], is_dir: bool) -> bool {
+    if self.dir_only && !is_dir {
+      return false;
+    }
+    let text = if self.anchored {
+      relative
+    } else {
+      let name = relative.iter().rposition(|&c| c == '/').map_or(0, |i| i + 1);
+      &relative[name..]
+    };
+    glob_match(&self.tokens, text)
+  }
+}
+
+/// 去掉未转义的行尾空格, `\ ` 保留为空格
+fn trim_trailing_spaces(line: &str) -> &str {
+  let mut end = line.len();
+  while line[..end].ends_with(' ') && !line[..end - 1].ends_with('\\') {
+    end -= 1;
+  }
+  &line[..end]
+}
+
+fn tokenize(pattern: &str) -> Vec<Token> {
+  let chars: Vec<char> = pattern.chars().collect();
+  let mut tokens = vec![];
+  let mut i = 0;
+  while i < chars.len() {
+    match chars[i] {
+      '\\' if i + 1 < chars.len() => {
+        tokens.push(Token::Char(chars[i + 1]));
+        i += 1;
+      }
+      '?' => tokens.push(Token::Any),
+      '*' if chars.get(i + 1) == Some(&'*') => {
+        let at_start = i == 0 || chars[i - 1] == '/';
+        let end = chars[i..].iter().take_while(|&&c| c == '*').count() + i;
+        if at_start && chars.get(end) == Some(&'/') {
+          tokens.push(Token::Dirs);
+          i = end;
+        } else if at_start && end == chars.len() {
+          tokens.push(Token::DoubleStar);
+          i = end - 1;
+        } else {
+          tokens.push(Token::Star);
+          i = end - 1;
+        }
+      }
+      '*' => tokens.push(Token::Star),
+      '[' => match parse_class(&chars[i + 1..]) {
+        Some((token, len)) => {
+          tokens.push(token);
+          i += len;
+        }
+        None => tokens.push(Token::Char('[')),
+      },
+      c => tokens.push(Token::Char(c)),
+    }
+    i += 1;
+  }
+  tokens
+}
+
+/// 解析 `[` 之后的内容, 返回字符类和消耗的字符数
+fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+  let mut i = 0;
+  let negated = matches!(chars.first(), Some('!') | Some('^'));
+  if negated {
+    i += 1;
+  }
+  let mut ranges = vec![];
+  let mut first = true;
+  loop {
+    let mut c = *chars.get(i)?;
+    if c == ']' && !first {
+      return Some((Token::Class(ranges, negated), i + 1));
+    }
+    first = false;
+    if c == '\\' {
+      i += 1;
+      c = *chars.get(i)?;
+    }
+    if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|&hi| hi != ']') {
+      ranges.push((c, chars[i + 2]));
+      i += 3;
+    } else {
+      ranges.push((c, c));
+      i += 1;
+    }
+  }
+}
+
+fn glob_match(tokens: &[Token], text: &[char]) -> bool {
+  let (token, rest) = match tokens.split_first() {
+    Some(split) => split,
+    None => return text.is_empty(),
+  };
+  match token {
+    Token::Char(c) => text.first() == Some(c) && glob_match(rest, &text[1..]),
+    Token::Any => text.first().is_some_and(|&c| c != '/') && glob_match(rest, &text[1..]),
+    Token::Class(ranges, negated) => {
+      text.first().is_some_and(|&c| {
+        c != '/' && ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated
+      }) && glob_match(rest, &text[1..])
+    }
+    Token::Star => {
+      let limit = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+      (0..=limit).any(|i| glob_match(rest, &text[i..]))
+    }
+    Token::DoubleStar => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+    Token::Dirs => (0..=text.len())
+      .filter(|&i| i == 0 || text[i - 1] == '/')
+      .any(|i| glob_match(rest, &text[i..])),
+  }
+}
+
+/// 一个忽略文件中的全部规则, 相对于 `base` 目录匹配
+#[derive(Debug, Clone)]
+pub struct Gitignore {
+  base: PathBuf,
+  rules: Vec<Rule>,
+}
+
+impl Gitignore {
+  pub fn new(base: &Path, contents: &str) -> Gitignore {
+    Gitignore {
+      base: base.to_path_buf(),
+      rules: contents.lines().filter_map(Rule::parse).collect(),
+    }
+  }
+
+  /// 文件不存在时返回 `Ok(None)`
+  pub fn from_file(base: &Path, path: &Path) -> io::Result<Option<Gitignore>> {
+    match fs::read_to_string(path) {
+      Ok(contents) => Ok(Some(Gitignore::new(base, &contents))),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+      Err(e) => Err(e),
+    }
+  }
+
+  pub fn matched(&self, path: &Path, is_dir: bool) -> Match {
+    let relative = match path.strip_prefix(&self.base) {
+      Ok(relative) => relative,
+      Err(_) => return Match::None,
+    };
+    let relative: Vec<char> = relative
+      .components()
+      .map(|c| c.as_os_str().to_string_lossy())
+      .collect::<Vec<_>>()
+      .join("/")
+      .chars()
+      .collect();
+    match self.rules.iter().rev().find(|rule| rule.matches(&relative, is_dir)) {
+      Some(rule) if rule.negated => Match::Whitelist,
+      Some(_) => Match::Ignore,
+      None => Match::None,
+    }
+  }
+}
+
+/// git 默认的全局忽略文件: `$XDG_CONFIG_HOME/git/ignore` 或 `~/.config/git/ignore`
+pub fn global_path() -> Option<PathBuf> {
+  match env::var_os("XDG_CONFIG_HOME") {
+    Some(config) if !config.is_empty() => Some(PathBuf::from(config)),
+    _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+  }
+  .map(|config| config.join("git").join("ignore"))
+}
+
+/// 遍历时逐层叠加的忽略规则, 与目录层级一一对应
+pub(crate) struct IgnoreStack {
+  files: Vec<Gitignore>,
+  parent: Option<Rc<IgnoreStack>>,
+}
+
+impl IgnoreStack {
+  /// 搜索根目录之上的规则: 全局忽略文件, 以及从仓库根目录到 `dir` 之前的各级忽略文件
+  pub(crate) fn root(dir: &Path, global: Option<&Path>) -> io::Result<Option<Rc<IgnoreStack>>> {
+    let dir = std::path::absolute(dir)?;
+    let ancestors: Vec<&Path> = match dir.ancestors().position(|a| a.join(".git").exists()) {
+      Some(repo) => dir.ancestors().skip(1).take(repo).collect(),
+      None => vec![],
+    };
+    let base = ancestors.last().copied().unwrap_or(&dir);
+
+    let mut stack = match global.map(|global| Gitignore::from_file(base, global)) {
+      Some(Ok(Some(global))) => Some(Rc::new(IgnoreStack {
+        files: vec![global],
+        parent: None,
+      })),
+      Some(Err(e)) => return Err(e),
+      _ => None,
+    };
+    for ancestor in ancestors.into_iter().rev() {
+      stack = IgnoreStack::child(stack, ancestor)?;
+    }
+    Ok(stack)
+  }
+
+  /// 加载 `dir` 中的忽略文件, 没有时沿用上层规则
+  pub(crate) fn child(parent: Option<Rc<IgnoreStack>>, dir: &Path) -> io::Result<Option<Rc<IgnoreStack>>> {
+    let dir = std::path::absolute(dir)?;
+    let mut files = vec![];
+    for name in &IGNORE_FILES {
+      if let Some(file) = Gitignore::from_file(&dir, &dir.join(name))? {
+        files.push(file);
+      }
+    }
+    if files.is_empty() {
+      return Ok(parent);
+    }
+    Ok(Some(Rc::new(IgnoreStack { files, parent })))
+  }
+
+  pub(crate) fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+    let path = match std::path::absolute(path) {
+      Ok(path) => path,
+      Err(_) => return false,
+    };
+    let mut node = Some(self);
+    while let Some(stack) = node {
+      for file in &stack.files {
+        match file.matched(&path, is_dir) {
+          Match::Ignore => return true,
+          Match::Whitelist => return false,
+          Match::None => {}
+        }
+      }
+      node = stack.parent.as_deref();
+    }
+    false
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn matched(contents: &str, path: &str, is_dir: bool) -> Match {
+    Gitignore::new(Path::new("/repo"), contents).matched(&Path::new("/repo").join(path), is_dir)
+  }
+
+  #[test]
+  fn basename_and_anchored() {
+    assert_eq!(Match::Ignore, matched("*.log", "a/b/debug.log", false));
+    assert_eq!(Match::Ignore, matched("/build", "build", true));
+    assert_eq!(Match::None, matched("/build", "src/build", true));
+    assert_eq!(Match::Ignore, matched("doc/*.md", "doc/a.md", false));
+    assert_eq!(Match::None, matched("doc/*.md", "doc/x/a.md", false));
+    assert_eq!(Match::None, matched("# comment\n\n", "# comment", false));
+    assert_eq!(Match::Ignore, matched("\\#file", "#file", false));
+    assert_eq!(Match::Ignore, matched("a[0-9].txt  ", "a1.txt", false));
+    assert_eq!(Match::None, matched("a[!0-9].txt", "a1.txt", false));
+  }
+
+  #[test]
+  fn negation_and_dir_only() {
+    let rules = "*.log\n!keep.log\n";
+    assert_eq!(Match::Ignore, matched(rules, "x.log", false));
+    assert_eq!(Match::Whitelist, matched(rules, "keep.log", false));
+    assert_eq!(Match::Ignore, matched("!keep.log\n*.log", "keep.log", false));
+
+    assert_eq!(Match::Ignore, matched("target/", "target", true));
+    assert_eq!(Match::None, matched("target/", "target", false));
+  }
+
+  #[test]
+  fn double_star() {
+    assert_eq!(Match::Ignore, matched("**/foo", "foo", false));
+    assert_eq!(Match::Ignore, matched("**/foo", "a/b/foo", false));
+    assert_eq!(Match::Ignore, matched("a/**/b", "a/b", false));
+    assert_eq!(Match::Ignore, matched("a/**/b", "a/x/y/b", false));
+    assert_eq!(Match::Ignore, matched("abc/**", "abc/x/y", false));
+    assert_eq!(Match::None, matched("abc/**", "abc", true));
+    assert_eq!(Match::None, matched("a/*/b", "a/x/y/b", false));
+  }
+}
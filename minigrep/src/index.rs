@@ -0,0 +1,438 @@
+//! 三元组倒排索引: `minigrep index build DIR` 记录 DIR 中每个文件包含哪些三元组(一行中连续的三个字符)
+//! `--index` 时先用查询的三元组排除不可能匹配的文件, 剩下的文件仍按正常方式搜索
+//! 三元组取自每行完全折叠后的文本, 所以区分和不区分大小写的字面量查询都可以使用
+//! 大小或修改时间与记录不同的文件不使用索引, 重新建立索引时也只读取这些文件
+use crate::{
+  decode,
+  unicode::{self, Fold},
+  Config, Error, OutputMode, Walk, WalkOptions,
+};
+use std::{
+  collections::{BTreeMap, HashMap, HashSet},
+  fs,
+  io::{self, Write},
+  path::{Path, PathBuf},
+  process,
+  time::UNIX_EPOCH,
+};
+
+/// 索引文件的名字, 位于被索引的目录中
+pub const INDEX_NAME: &str = ".minigrep-index";
+const MAGIC: &[u8] = b"minigrep-index 1\n";
+
+/// 三个字符, 每个占 21 位
+type Trigram = u64;
+
+/// 索引建立时文件的状态
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Stamp {
+  size: u64,
+  /// 修改时间, 自 UNIX 纪元以来的纳秒数
+  modified: u64,
+}
+
+impl Stamp {
+  fn of(path: &Path) -> io::Result<Stamp> {
+    let meta = fs::metadata(path)?;
+    let modified = meta.modified()?.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+    Ok(Stamp {
+      size: meta.len(),
+      modified,
+    })
+  }
+}
+
+#[derive(Debug, Default)]
+pub struct Index {
+  dir: PathBuf,
+  /// 相对 `dir` 的路径(用 `/` 分隔)及其状态, 序号就是文件编号
+  files: Vec<(String, Stamp)>,
+  ids: HashMap<String, u32>,
+  /// 三元组 -> 包含它的文件编号, 递增排列
+  postings: BTreeMap<Trigram, Vec<u32>>,
+}
+
+/// 一次建立索引的结果
+#[derive(Debug, Default)]
+pub struct Built {
+  pub files: usize,
+  /// 新增或改变而重新读取的文件数
+  pub reindexed: usize,
+  /// 无法读取而没有进入索引的文件, 搜索时总是需要检查它们
+  pub errors: Vec<Error>,
+}
+
+impl Index {
+  /// 读取 `dir` 中的索引, 没有索引时返回 None
+  pub fn open(dir: &Path) -> Result<Option<Index>, Error> {
+    let path = dir.join(INDEX_NAME);
+    let bytes = match fs::read(&path) {
+      Ok(bytes) => bytes,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+      Err(e) => return Err(Error::io(&path, e)),
+    };
+    let invalid = |message: &str| Error::io(&path, io::Error::new(io::ErrorKind::InvalidData, message));
+    let mut index = Index::decode(&bytes).ok_or_else(|| invalid("not a minigrep index, run 'minigrep index build' again"))?;
+    index.dir = dir.to_path_buf();
+    Ok(Some(index))
+  }
+
+  /// 建立或更新 `dir` 的索引, 只读取新增和改变的文件
+  /// `walk` 决定哪些文件进入索引, 通常与搜索时相同
+  pub fn build(dir: &Path, walk: &WalkOptions) -> Result<Built, Error> {
+    if !fs::metadata(dir).map_err(|e| Error::io(dir, e))?.is_dir() {
+      return Err(Error::Usage(format!("{} is not a directory", dir.display())));
+    }
+    let old = Index::open(dir)?.unwrap_or_default();
+    let reused = old.file_trigrams();
+    let mut built = Built::default();
+    let mut files = vec![];
+    let mut sets = vec![];
+    for file in Walk::new(&[dir], walk.clone()) {
+      let file = match file {
+        Ok(file) => file,
+        Err(e) => {
+          built.errors.push(e);
+          continue;
+        }
+      };
+      //不是 UTF-8 的路径不记录, 搜索时总是读取
+      let name = match relative(dir, &file) {
+        Some(name) => name,
+        None => continue,
+      };
+      let stamp = match Stamp::of(&file) {
+        Ok(stamp) => stamp,
+        Err(e) => {
+          built.errors.push(Error::io(&file, e));
+          continue;
+        }
+      };
+      let set = match old.ids.get(&name) {
+        Some(&id) if old.files[id as usize].1 == stamp => reused[id as usize].clone(),
+        _ => match read_trigrams(&file) {
+          Ok(set) => {
+            built.reindexed += 1;
+            set
+          }
+          Err(e) => {
+            built.errors.push(Error::io(&file, e));
+            continue;
+          }
+        },
+      };
+      files.push((name, stamp));
+      sets.push(set);
+    }
+
+    let mut index = Index {
+      dir: dir.to_path_buf(),
+      ..Index::default()
+    };
+    for (id, set) in sets.into_iter().enumerate() {
+      for trigram in set {
+        index.postings.entry(trigram).or_default().push(id as u32);
+      }
+    }
+    built.files = files.len();
+    index.files = files;
+    index.save().map_err(|e| Error::io(&dir.join(INDEX_NAME), e))?;
+    Ok(built)
+  }
+
+  /// 每个文件的三元组集合, 由倒排表还原
+  fn file_trigrams(&self) -> Vec<HashSet<Trigram>> {
+    let mut sets = vec![HashSet::new(); self.files.len()];
+    for (&trigram, ids) in &self.postings {
+      for &id in ids {
+        sets[id as usize].insert(trigram);
+      }
+    }
+    sets
+  }
+
+  /// 可能包含某个模式(已经完全折叠)的文件编号, None 表示每个文件都有可能
+  fn candidates(&self, patterns: &[String]) -> Option<HashSet<u32>> {
+    let mut candidates = HashSet::new();
+    for pattern in patterns {
+      let mut required = HashSet::new();
+      add_trigrams(pattern, &mut required);
+      //太短的模式没有三元组
+      if required.is_empty() {
+        return None;
+      }
+      let mut lists: Vec<&[u32]> = vec![];
+      for trigram in &required {
+        match self.postings.get(trigram) {
+          Some(ids) => lists.push(ids),
+          None => {
+            lists.clear();
+            break;
+          }
+        }
+      }
+      //从最短的列表开始求交集
+      lists.sort_by_key(|ids| ids.len());
+      if let Some((first, rest)) = lists.split_first() {
+        let found = first.iter().filter(|id| rest.iter().all(|ids| ids.binary_search(id).is_ok()));
+        candidates.extend(found);
+      }
+    }
+    Some(candidates)
+  }
+
+  /// 写入临时文件后改名, 中途失败不会破坏原来的索引
+  fn save(&self) -> io::Result<()> {
+    let path = self.dir.join(INDEX_NAME);
+    let temp = self.dir.join(format!("{}.{}", INDEX_NAME, process::id()));
+    let result = (|| {
+      let mut file = fs::File::create(&temp)?;
+      file.write_all(&self.encode())?;
+      file.sync_all()?;
+      fs::rename(&temp, &path)
+    })();
+    if result.is_err() {
+      let _ = fs::remove_file(&temp);
+    }
+    result
+  }
+
+  /// 格式: MAGIC, 文件数, 每个文件的路径、大小和修改时间, 三元组数, 每个三元组及其文件编号(差分)
+  /// 所有整数都是 LEB128 变长编码
+  fn encode(&self) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    put(&mut out, self.files.len() as u64);
+    for (name, stamp) in &self.files {
+      put(&mut out, name.len() as u64);
+      out.extend_from_slice(name.as_bytes());
+      put(&mut out, stamp.size);
+      put(&mut out, stamp.modified);
+    }
+    put(&mut out, self.postings.len() as u64);
+    for (&trigram, ids) in &self.postings {
+      put(&mut out, trigram);
+      put(&mut out, ids.len() as u64);
+      let mut last = 0;
+      for &id in ids {
+        put(&mut out, u64::from(id - last));
+        last = id;
+      }
+    }
+    out
+  }
+
+  fn decode(bytes: &[u8]) -> Option<Index> {
+    let mut input = bytes.strip_prefix(MAGIC)?;
+    let mut index = Index::default();
+    for id in 0..get(&mut input)? {
+      let len = get(&mut input)? as usize;
+      let name = std::str::from_utf8(input.get(..len)?).ok()?.to_string();
+      input = &input[len..];
+      let stamp = Stamp {
+        size: get(&mut input)?,
+        modified: get(&mut input)?,
+      };
+      index.ids.insert(name.clone(), id as u32);
+      index.files.push((name, stamp));
+    }
+    for _ in 0..get(&mut input)? {
+      let trigram = get(&mut input)?;
+      let mut ids = vec![];
+      let mut last = 0u64;
+      for _ in 0..get(&mut input)? {
+        last = last.checked_add(get(&mut input)?)?;
+        if last >= index.files.len() as u64 {
+          return None;
+        }
+        ids.push(last as u32);
+      }
+      index.postings.insert(trigram, ids);
+    }
+    Some(index).filter(|_| input.is_empty())
+  }
+}
+
+fn put(out: &mut Vec<u8>, mut n: u64) {
+  while n >= 0x80 {
+    out.push(n as u8 | 0x80);
+    n >>= 7;
+  }
+  out.push(n as u8);
+}
+
+fn get(input: &mut &[u8]) -> Option<u64> {
+  let mut n = 0u64;
+  for shift in (0..64).step_by(7) {
+    let (&b, rest) = input.split_first()?;
+    *input = rest;
+    n |= u64::from(b & 0x7f) << shift;
+    if b < 0x80 {
+      return Some(n);
+    }
+  }
+  None
+}
+
+/// 相对 `dir` 的路径, 统一用 `/` 分隔, 不是有效的 UTF-8 时返回 None
+/// 不做有损转换, 以免两个文件得到同一个名字
+fn relative(dir: &Path, file: &Path) -> Option<String> {
+  let relative = file.strip_prefix(dir).unwrap_or(file);
+  let parts: Option<Vec<&str>> = relative.iter().map(|part| part.to_str()).collect();
+  Some(parts?.join("/"))
+}
+
+/// 索引文件本身和写入中的临时文件, 遍历目录时跳过
+pub(crate) fn is_index_file(name: &str) -> bool {
+  name.rsplit('/').next().is_some_and(|name| name.starts_with(INDEX_NAME))
+}
+
+/// 一行完全折叠后的所有三元组
+fn add_trigrams(line: &str, set: &mut HashSet<Trigram>) {
+  let chars: Vec<char> = unicode::fold_str(line, Fold::Full).chars().collect();
+  for w in chars.windows(3) {
+    set.insert((u64::from(w[0]) << 42) | (u64::from(w[1]) << 21) | u64::from(w[2]));
+  }
+}
+
+/// 按搜索时的方式逐行解码文件(BOM、UTF-16、Latin-1), 收集每一行的三元组
+fn read_trigrams(path: &Path) -> io::Result<HashSet<Trigram>> {
+  let mut set = HashSet::new();
+  let reader = io::BufReader::with_capacity(64 * 1024, fs::File::open(path)?);
+  decode::each_line(reader, |_, line| add_trigrams(line, &mut set))?;
+  Ok(set)
+}
+
+/// 用 `dir` 的索引跳过一定不匹配的文件
+pub(crate) struct Narrow {
+  index: Index,
+  candidates: HashSet<u32>,
+}
+
+impl Narrow {
+  /// 查询可以用索引缩小范围且 `dir` 有索引时返回 Some
+  pub(crate) fn new(config: &Config, dir: &Path) -> Result<Option<Narrow>, Error> {
+    //正则、布尔查询、近似匹配和规范化的查询无法直接对应到三元组, 土耳其语折叠与索引的折叠方式不同
+    //反向匹配和 -L 选中的正是不包含查询的文件, 排序时不包含查询词的文档也计入统计
+    let usable = !config.regex
+      && !config.query
+      && !config.rank
+      && config.fuzzy.is_none()
+      && config.normalization.is_none()
+      && config.fold != Fold::Turkic
+      && !config.invert
+      && config.output != OutputMode::FilesWithoutMatch;
+    if !usable || !dir.is_dir() {
+      return Ok(None);
+    }
+    let index = match Index::open(dir)? {
+      Some(index) => index,
+      None => return Ok(None),
+    };
+    let patterns: Vec<String> = config.patterns.iter().map(|p| unicode::fold_str(p, Fold::Full)).collect();
+    Ok(index.candidates(&patterns).map(|candidates| Narrow { index, candidates }))
+  }
+
+  /// `file` 在索引中, 自建立索引以来没有改变, 并且不包含任何查询
+  pub(crate) fn skips(&self, file: &Path) -> bool {
+    let name = match relative(&self.index.dir, file) {
+      Some(name) => name,
+      None => return false,
+    };
+    match self.index.ids.get(&name) {
+      Some(id) if !self.candidates.contains(id) => Stamp::of(file).ok() == Some(self.index.files[*id as usize].1),
+      _ => false,
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use std::env;
+
+  #[test]
+  fn build_and_narrow() {
+    let dir = env::temp_dir().join(format!("minigrep-index-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("logs")).unwrap();
+    fs::write(dir.join("a.txt"), "ERROR: disk full\nok\n").unwrap();
+    fs::write(dir.join("logs/b.txt"), "all good\nSTRASSE\n").unwrap();
+    fs::write(dir.join("c.txt"), "").unwrap();
+    let walk = WalkOptions::default();
+
+    let built = Index::build(&dir, &walk).unwrap();
+    assert_eq!((3, 3, 0), (built.files, built.reindexed, built.errors.len()));
+    let narrowed = |patterns: &[&str]| {
+      let config = Config {
+        patterns: patterns.iter().map(|p| p.to_string()).collect(),
+        ..Config::default()
+      };
+      let narrow = Narrow::new(&config, &dir).unwrap().unwrap();
+      let files: Vec<String> = Walk::new(&[&dir], walk.clone())
+        .map(Result::unwrap)
+        .filter(|file| !narrow.skips(file))
+        .map(|file| relative(&dir, &file).unwrap())
+        .collect();
+      files
+    };
+    assert_eq!(vec!["a.txt"], narrowed(&["error"]));
+    //折叠后的 `straße` 与 `STRASSE` 有相同的三元组
+    assert_eq!(vec!["logs/b.txt"], narrowed(&["straße"]));
+    assert_eq!(vec!["a.txt", "logs/b.txt"], narrowed(&["disk", "good"]));
+    assert!(narrowed(&["zebra"]).is_empty());
+    //三元组不能跨行
+    assert!(narrowed(&["full ok"]).is_empty());
+
+    //只重新读取改变的文件, 改变后还没有重建索引的文件总是需要搜索
+    fs::write(dir.join("c.txt"), "a zebra").unwrap();
+    assert_eq!(vec!["c.txt"], narrowed(&["zebra"]));
+    let built = Index::build(&dir, &walk).unwrap();
+    assert_eq!((3, 1), (built.files, built.reindexed));
+    assert_eq!(vec!["c.txt"], narrowed(&["zebra"]));
+
+    //太短的查询无法缩小范围
+    let config = Config {
+      patterns: vec!["ok".to_string()],
+      ..Config::default()
+    };
+    assert!(Narrow::new(&config, &dir).unwrap().is_none());
+
+    fs::write(dir.join(INDEX_NAME), "garbage").unwrap();
+    assert!(Index::open(&dir).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn non_utf8_names() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+    let dir = env::temp_dir().join(format!("minigrep-index-names-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    //有损转换后两个名字相同
+    let (found, other) = (dir.join(OsStr::from_bytes(b"\xff.txt")), dir.join(OsStr::from_bytes(b"\xfe.txt")));
+    fs::write(&found, "error").unwrap();
+    fs::write(&other, "nothing").unwrap();
+    let walk = WalkOptions::default();
+    assert_eq!(0, Index::build(&dir, &walk).unwrap().files);
+    let config = Config {
+      patterns: vec!["error".to_string()],
+      ..Config::default()
+    };
+    let narrow = Narrow::new(&config, &dir).unwrap().unwrap();
+    assert!(!narrow.skips(&found) && !narrow.skips(&other));
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn varints() {
+    let mut out = vec![];
+    for n in [0, 127, 128, 300, u64::MAX] {
+      put(&mut out, n);
+    }
+    let mut input = &out[..];
+    let decoded: Vec<u64> = (0..5).map(|_| get(&mut input).unwrap()).collect();
+    assert_eq!(vec![0, 127, 128, 300, u64::MAX], decoded);
+    assert_eq!(None, get(&mut &[0x80][..]));
+  }
+}
@@ -0,0 +1,141 @@
+//! 最小的 JSON 编码器, 只用于输出, 不需要解析
+use std::fmt::{self, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+  Null,
+  Bool(bool),
+  Number(usize),
+  String(String),
+  Array(Vec<Json>),
+  /// 字段按给出的顺序输出
+  Object(Vec<(&'static str, Json)>),
+}
+
+impl From<&str> for Json {
+  fn from(text: &str) -> Json {
+    Json::String(text.to_string())
+  }
+}
+
+impl From<usize> for Json {
+  fn from(n: usize) -> Json {
+    Json::Number(n)
+  }
+}
+
+impl From<bool> for Json {
+  fn from(b: bool) -> Json {
+    Json::Bool(b)
+  }
+}
+
+/// 输出带引号的 JSON 字符串, 控制字符全部转义
+fn write_string(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+  f.write_char('"')?;
+  for c in text.chars() {
+    match c {
+      '"' => f.write_str("\\\"")?,
+      '\\' => f.write_str("\\\\")?,
+      '\n' => f.write_str("\\n")?,
+      '\r' => f.write_str("\\r")?,
+      '\t' => f.write_str("\\t")?,
+      '\u{8}' => f.write_str("\\b")?,
+      '\u{c}' => f.write_str("\\f")?,
+      c if (c as u32) < 0x20 || c == '\u{7f}' => write!(f, "\\u{:04x}", c as u32)?,
+      c => f.write_char(c)?,
+    }
+  }
+  f.write_char('"')
+}
+
+impl Json {
+  /// 缩进两个空格的多行格式, 空数组和空对象保持在一行
+  pub fn pretty(&self) -> String {
+    let mut out = String::new();
+    self.write_pretty(&mut out, 0);
+    out
+  }
+
+  fn write_pretty(&self, out: &mut String, depth: usize) {
+    let indent = |out: &mut String, depth: usize| {
+      out.push('\n');
+      out.push_str(&"  ".repeat(depth));
+    };
+    match self {
+      Json::Array(items) if !items.is_empty() => {
+        out.push('[');
+        for (i, item) in items.iter().enumerate() {
+          out.push_str(if i > 0 { "," } else { "" });
+          indent(out, depth + 1);
+          item.write_pretty(out, depth + 1);
+        }
+        indent(out, depth);
+        out.push(']');
+      }
+      Json::Object(fields) if !fields.is_empty() => {
+        out.push('{');
+        for (i, (key, value)) in fields.iter().enumerate() {
+          out.push_str(if i > 0 { "," } else { "" });
+          indent(out, depth + 1);
+          let _ = write!(out, "{}: ", Json::from(*key));
+          value.write_pretty(out, depth + 1);
+        }
+        indent(out, depth);
+        out.push('}');
+      }
+      _ => {
+        let _ = write!(out, "{}", self);
+      }
+    }
+  }
+}
+
+impl fmt::Display for Json {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Json::Null => f.write_str("null"),
+      Json::Bool(b) => write!(f, "{}", b),
+      Json::Number(n) => write!(f, "{}", n),
+      Json::String(text) => write_string(f, text),
+      Json::Array(items) => {
+        f.write_char('[')?;
+        for (i, item) in items.iter().enumerate() {
+          if i > 0 {
+            f.write_char(',')?;
+          }
+          write!(f, "{}", item)?;
+        }
+        f.write_char(']')
+      }
+      Json::Object(fields) => {
+        f.write_char('{')?;
+        for (i, (key, value)) in fields.iter().enumerate() {
+          if i > 0 {
+            f.write_char(',')?;
+          }
+          write_string(f, key)?;
+          write!(f, ":{}", value)?;
+        }
+        f.write_char('}')
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn escaping() {
+    assert_eq!(r#""say \"hi\"\\ \n\r\t\b\f \u0001\u007f é 🦀""#, Json::from("say \"hi\"\\ \n\r\t\u{8}\u{c} \u{1}\u{7f} é 🦀").to_string());
+    let value = Json::Object(vec![
+      ("a", Json::Array(vec![Json::from(1usize), Json::Null, Json::from(true)])),
+      ("b", Json::Object(vec![])),
+      ("c", Json::Array(vec![])),
+    ]);
+    assert_eq!(r#"{"a":[1,null,true],"b":{},"c":[]}"#, value.to_string());
+    assert_eq!("{\n  \"a\": [\n    1,\n    null,\n    true\n  ],\n  \"b\": {},\n  \"c\": []\n}", value.pretty());
+  }
+}
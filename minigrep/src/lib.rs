@@ -1,83 +1,1604 @@
-use std::{env, error::Error, fs};
+use std::{
+  collections::{HashSet, VecDeque},
+  env, fs,
+  io::{self, Read, Write},
+  panic::{self, AssertUnwindSafe},
+  path::{Path, PathBuf},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc, Arc,
+  },
+  thread,
+};
 
+mod aho_corasick;
+mod args;
+mod color;
+mod decode;
+mod error;
+mod finder;
+mod fuzzy;
+mod ignore;
+mod index;
+mod json;
+mod matcher;
+mod mmap;
+mod pool;
+mod printer;
+mod query;
+mod rank;
+mod regex;
+mod replace;
+mod sarif;
+mod searcher;
+mod sink;
+mod unicode;
+mod unicode_tables;
+mod walk;
+pub use aho_corasick::AhoCorasick;
+pub use color::{ColorChoice, Colors};
+pub use error::Error;
+pub use finder::Finder;
+pub use fuzzy::Fuzzy;
+pub use ignore::Gitignore;
+pub use index::{Built, Index, INDEX_NAME};
+pub use json::Json;
+pub use matcher::Matcher;
+pub use mmap::Mmap;
+pub use pool::ThreadPool;
+pub use printer::OutputMode;
+pub use query::{Query, QueryError, Term};
+pub use rank::{Ranker, Tokenizer, Unit};
+pub use regex::{ParseError, Regex};
+pub use replace::Template;
+pub use sarif::{parse_rules, Level, Rule};
+pub use searcher::{BinaryMode, Line, Summary};
+pub use sink::Sink;
+pub use unicode::{fold_str, normalize, Fold, Normalization};
+pub use walk::{Walk, WalkOptions};
+use printer::Printer;
+use regex::is_word_char;
+use searcher::{SearchOptions, Searcher};
+
+#[derive(Debug, Clone)]
 pub struct Config {
-  pub query: String,
-  pub file_name: String,
-  pub sensitive: bool,
+  /// 任意一个匹配即选中该行
+  pub patterns: Vec<String>,
+  pub paths: Vec<PathBuf>,
+  pub case: Case,
+  /// 忽略大小写时的折叠方式
+  pub fold: Fold,
+  /// 匹配前把模式和每一行都转换为同一种规范形式
+  pub normalization: Option<Normalization>,
+  pub regex: bool,
+  /// 近似匹配允许的最大编辑距离
+  pub fuzzy: Option<usize>,
+  /// 每个模式是一个布尔查询, 见 `Query`
+  pub query: bool,
+  pub boundary: Boundary,
+  /// 在匹配行前输出匹配到的模式
+  pub show_pattern: bool,
+  pub walk: WalkOptions,
+  /// 并行搜索文件的线程数
+  pub threads: usize,
+  /// 匹配行之前/之后输出的上下文行数
+  pub before: usize,
+  pub after: usize,
+  pub line_number: bool,
+  pub byte_offset: bool,
+  pub column: bool,
+  /// `file:line:col:text` 格式, 用于 vim 的 quickfix 列表
+  pub vimgrep: bool,
+  pub color: ColorChoice,
+  /// 每个事件输出一行 JSON
+  pub json: bool,
+  /// 最后输出一份 SARIF 报告
+  pub sarif: bool,
+  /// 与 `patterns` 一一对应的规则, 用于 SARIF 报告
+  pub rules: Vec<Rule>,
+  /// 对普通文件使用内存映射代替逐块读取
+  pub mmap: bool,
+  /// 用目录中的索引跳过一定不匹配的文件
+  pub index: bool,
+  /// `minigrep index build DIR`: 建立或更新 DIR 的索引, 不搜索
+  pub build_index: Option<PathBuf>,
+  /// 按 BM25 分数输出最相关的 `top` 个文档, 而不是所有匹配行
+  pub rank: bool,
+  pub unit: Unit,
+  pub top: usize,
+  pub binary: BinaryMode,
+  /// 选择不匹配的行
+  pub invert: bool,
+  /// 每个文件最多选中的行数
+  pub max_count: Option<usize>,
+  pub output: OutputMode,
+  /// 输出时用这个模板替换匹配, `$0` 是匹配到的原文
+  pub replace: Option<String>,
+  /// 把替换结果写回文件, 而不是输出
+  pub in_place: bool,
+  /// 写回前把原文件保存为 `FILE.bak`
+  pub backup: bool,
 }
 
-impl Config {
-  pub fn new(mut args: std::env::Args) -> Result<Config, &'static str> {
-    Ok(Config {
-      query: match args.next() {
-        Some(arg) => arg,
-        None => return Err("Didn't get a query string"),
+/// `Config::new` 解析命令行的结果, `--help` 和 `--version` 不是错误, 但也不需要搜索
+#[derive(Debug)]
+pub enum Parsed {
+  Run(Box<Config>),
+  /// 帮助文本
+  Help(String),
+  /// 版本信息
+  Version(String),
+}
+
+/// 与不带任何选项的命令行相同, 没有模式, 读取标准输入
+impl Default for Config {
+  fn default() -> Config {
+    Config {
+      patterns: vec![],
+      paths: vec![PathBuf::from("-")],
+      //CASE_SENSITIVE 只是默认值, 会被配置文件和 -i/-s/-S 覆盖
+      case: if env::var_os("CASE_SENSITIVE").is_some() {
+        Case::Sensitive
+      } else {
+        Case::Smart
       },
-      file_name: match args.next() {
-        Some(arg) => arg,
-        None => return Err("Didn't get a file name"),
+      fold: Fold::Full,
+      normalization: None,
+      regex: false,
+      fuzzy: None,
+      query: false,
+      boundary: Boundary::None,
+      show_pattern: false,
+      walk: WalkOptions {
+        global_ignore: ignore::global_path(),
+        ..WalkOptions::default()
       },
-      sensitive: !env::var("CASE_SENSITIVE").is_err(),
-    })
+      threads: thread::available_parallelism().map_or(1, |n| n.get()),
+      before: 0,
+      after: 0,
+      line_number: false,
+      byte_offset: false,
+      column: false,
+      vimgrep: false,
+      color: ColorChoice::Auto,
+      json: false,
+      sarif: false,
+      rules: vec![],
+      mmap: false,
+      index: false,
+      build_index: None,
+      rank: false,
+      unit: Unit::Line,
+      top: 10,
+      binary: BinaryMode::Binary,
+      invert: false,
+      max_count: None,
+      output: OutputMode::Lines,
+      replace: None,
+      in_place: false,
+      backup: false,
+    }
+  }
+}
+
+impl Config {
+  //返回 `Parsed` 而不是 `Config`, 因为 --help 和 --version 不产生配置
+  #[allow(clippy::new_ret_no_self)]
+  pub fn new(mut args: impl Iterator<Item = String>) -> Result<Parsed, Error> {
+    //第一个参数是程序名
+    args.next();
+    let mut parsed = args::parse(args)?;
+    //配置文件中的选项排在命令行之前, 会被命令行覆盖
+    if !parsed.options.iter().any(|&(name, _)| name == "no-config") {
+      if let Some(path) = env::var_os("MINIGREP_CONFIG") {
+        let mut options = args::from_file(Path::new(&path))?.options;
+        options.append(&mut parsed.options);
+        parsed.options = options;
+      }
+    }
+
+    let mut config = Config {
+      paths: vec![],
+      ..Config::default()
+    };
+    //给出 -e/-f/--rules 时所有位置参数都是文件
+    let mut explicit = false;
+    //规则文件中定义的规则及其模式序号
+    let mut defined = vec![];
+    //给出了只对 --rank 有效的选项
+    let mut rank_options = false;
+    //正则表达式和近似匹配只支持简单折叠, 不接受 --case-fold
+    let mut fold_given = false;
+    for (name, value) in parsed.options {
+      let value = value.unwrap_or_default();
+      match name {
+        "help" => return Ok(Parsed::Help(args::help())),
+        "version" => return Ok(Parsed::Version(args::version())),
+        "regex" => config.regex = true,
+        "fuzzy" => config.fuzzy = Some(number(name, &value)?),
+        "query" => config.query = true,
+        //与 grep 一致, 一个参数中的多行是多个模式
+        "regexp" => {
+          explicit = true;
+          config.patterns.extend(value.split('\n').map(String::from));
+        }
+        "file" => {
+          explicit = true;
+          let contents = error::read_to_string(Path::new(&value))?;
+          config.patterns.extend(lines_with_offsets(&contents).map(|(_, line)| line.to_string()));
+        }
+        "rules" => {
+          explicit = true;
+          let contents = error::read_to_string(Path::new(&value))?;
+          let rules = sarif::parse_rules(&contents).map_err(|e| args::usage(format!("{}: {}", value, e)))?;
+          for rule in rules {
+            config.patterns.push(rule.pattern.clone());
+            defined.push((config.patterns.len() - 1, rule));
+          }
+        }
+        "show-pattern" => config.show_pattern = true,
+        //与 grep 一致, 同时给出时 -x 优先
+        "word-regexp" if config.boundary != Boundary::Line => config.boundary = Boundary::Word,
+        "word-regexp" => {}
+        "line-regexp" => config.boundary = Boundary::Line,
+        "ignore-case" => config.case = Case::Insensitive,
+        "case-sensitive" => config.case = Case::Sensitive,
+        "smart-case" => config.case = Case::Smart,
+        "case-fold" => {
+          fold_given = true;
+          config.fold = Fold::parse(&value)
+            .ok_or_else(|| args::usage(format!("--case-fold expects simple, full or turkic, got '{}'", value)))?
+        }
+        "normalize" => {
+          config.normalization = Some(
+            Normalization::parse(&value)
+              .ok_or_else(|| args::usage(format!("--normalize expects nfc or nfd, got '{}'", value)))?,
+          )
+        }
+        "line-number" => config.line_number = true,
+        "byte-offset" => config.byte_offset = true,
+        "column" => config.column = true,
+        "vimgrep" => config.vimgrep = true,
+        "json" => config.json = true,
+        "sarif" => config.sarif = true,
+        "after-context" => config.after = number(name, &value)?,
+        "before-context" => config.before = number(name, &value)?,
+        "context" => {
+          config.before = number(name, &value)?;
+          config.after = config.before;
+        }
+        "color" => {
+          config.color = ColorChoice::parse(&value)
+            .ok_or_else(|| args::usage(format!("--color expects auto, always or never, got '{}'", value)))?
+        }
+        "binary-files" => {
+          config.binary = BinaryMode::parse(&value).ok_or_else(|| {
+            args::usage(format!("--binary-files expects binary, text or without-match, got '{}'", value))
+          })?
+        }
+        "text" => config.binary = BinaryMode::Text,
+        "ignore-binary" => config.binary = BinaryMode::WithoutMatch,
+        "mmap" => config.mmap = true,
+        "index" => config.index = true,
+        "rank" => config.rank = true,
+        "paragraphs" => {
+          rank_options = true;
+          config.unit = Unit::Paragraph;
+        }
+        "top" => {
+          rank_options = true;
+          config.top = number(name, &value)?;
+        }
+        "invert-match" => config.invert = true,
+        "max-count" => config.max_count = Some(number(name, &value)?),
+        "count" => config.output = OutputMode::Count,
+        "files-with-matches" => config.output = OutputMode::FilesWithMatches,
+        "files-without-match" => config.output = OutputMode::FilesWithoutMatch,
+        "quiet" => config.output = OutputMode::Quiet,
+        "replace" => config.replace = Some(value),
+        "in-place" => config.in_place = true,
+        "backup" => config.backup = true,
+        "follow" => config.walk.follow_links = true,
+        "hidden" => config.walk.hidden = true,
+        "no-ignore" => config.walk.ignore = false,
+        "max-depth" => config.walk.max_depth = Some(number(name, &value)?),
+        "threads" => match number(name, &value)? {
+          0 => return Err(args::usage("--threads expects a positive number".to_string())),
+          n => config.threads = n,
+        },
+        "no-config" => {}
+        _ => unreachable!("option --{} is not handled", name),
+      }
+    }
+    if config.regex && config.fuzzy.is_some() {
+      return Err(args::usage("--fuzzy can't be combined with --regex".to_string()));
+    }
+    if fold_given && (config.regex || config.fuzzy.is_some()) {
+      return Err(args::usage("--case-fold can't be combined with -E or --fuzzy".to_string()));
+    }
+    if config.query && (config.fuzzy.is_some() || config.rank) {
+      return Err(args::usage("--query can't be combined with --fuzzy or --rank".to_string()));
+    }
+    if config.in_place && (config.replace.is_none() || config.invert) {
+      return Err(args::usage("--in-place requires --replace and can't be combined with -v".to_string()));
+    }
+    if (config.json || config.sarif) && config.output != OutputMode::Lines {
+      return Err(args::usage("--json and --sarif can't be combined with -c, -l, -L or -q".to_string()));
+    }
+    if config.json && config.sarif {
+      return Err(args::usage("--json can't be combined with --sarif".to_string()));
+    }
+    if config.backup && !config.in_place {
+      return Err(args::usage("--backup requires --in-place".to_string()));
+    }
+    let ranks = config.output == OutputMode::Lines
+      && !(config.regex || config.fuzzy.is_some() || config.invert || config.json || config.sarif || config.vimgrep)
+      && config.replace.is_none()
+      && config.before == 0
+      && config.after == 0;
+    if config.rank && !ranks {
+      return Err(args::usage(
+        "--rank can't be combined with -E, --fuzzy, -v, -c, -l, -L, -q, -r, -A, -B, -C, --json, --sarif or --vimgrep"
+          .to_string(),
+      ));
+    }
+    if rank_options && !config.rank {
+      return Err(args::usage("--paragraphs and --top require --rank".to_string()));
+    }
+
+    //`index build DIR` 是子命令, 要搜索 `index` 时用 `-e index`
+    if !explicit && parsed.positional.len() >= 2 && parsed.positional[..2] == ["index", "build"] {
+      return match &parsed.positional[2..] {
+        [dir] => {
+          config.build_index = Some(PathBuf::from(dir));
+          Ok(Parsed::Run(Box::new(config)))
+        }
+        _ => Err(args::usage("index build expects exactly one directory".to_string())),
+      };
+    }
+    let mut positional = parsed.positional.into_iter();
+    if !explicit {
+      let query = positional
+        .next()
+        .ok_or_else(|| args::usage("Didn't get a query string".to_string()))?;
+      config.patterns.push(query);
+    }
+    let mut defined = defined.into_iter().peekable();
+    for (index, pattern) in config.patterns.iter().enumerate() {
+      let rule = match defined.next_if(|(at, _)| *at == index) {
+        Some((_, rule)) => rule,
+        None => Rule::for_pattern(index, pattern),
+      };
+      //规则文件中的 id 可能与生成的 `pattern-N` 或另一个规则文件中的 id 相同
+      if config.rules.iter().any(|other| other.id == rule.id) {
+        return Err(args::usage(format!(
+          "rule id '{}' is used twice, -e and -f patterns get ids pattern-1, pattern-2, ...",
+          rule.id
+        )));
+      }
+      config.rules.push(rule);
+    }
+    config.paths = positional.map(PathBuf::from).collect();
+    //没有给出文件时读取标准输入
+    if config.paths.is_empty() {
+      config.paths.push(PathBuf::from("-"));
+    }
+    if config.in_place && config.paths.iter().any(|path| path == Path::new("-")) {
+      return Err(args::usage("--in-place can't edit standard input".to_string()));
+    }
+    Ok(Parsed::Run(Box::new(config)))
+  }
+
+  fn search_options(&self) -> SearchOptions {
+    SearchOptions {
+      before: self.before,
+      after: self.after,
+      //替换、JSON 和 SARIF 输出也需要行内所有匹配的位置
+      highlight: self.color.enabled() || self.replace.is_some() || self.json || self.sarif || self.vimgrep,
+      binary: self.binary,
+      invert: self.invert,
+      //只关心文件是否匹配时, 第一个匹配后就可以停止
+      max_count: match self.output {
+        OutputMode::Lines | OutputMode::Count => self.max_count,
+        _ => Some(self.max_count.map_or(1, |max| max.min(1))),
+      },
+    }
+  }
+}
+
+fn number(name: &str, value: &str) -> Result<usize, Error> {
+  value
+    .parse()
+    .map_err(|_| args::usage(format!("--{} expects a number, got '{}'", name, value)))
+}
+
+/// 大小写的匹配方式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Case {
+  Sensitive,
+  Insensitive,
+  /// 模式中有大写字母时区分大小写, 否则忽略大小写, 每个模式分别判断
+  #[default]
+  Smart,
+}
+
+impl Case {
+  /// 对 `pattern` 是否区分大小写, 正则中 `\S`、`\W` 这类转义不算大写字母
+  pub fn is_sensitive(self, pattern: &str, regex: bool) -> bool {
+    match self {
+      Case::Sensitive => true,
+      Case::Insensitive => false,
+      Case::Smart => {
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+          if c == '\\' && regex {
+            chars.next();
+          } else if c.is_uppercase() {
+            return true;
+          }
+        }
+        false
+      }
+    }
+  }
+}
+
+/// 匹配两端需要满足的边界
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Boundary {
+  #[default]
+  None,
+  /// 匹配前后不能是单词字符(Unicode 字母、数字或下划线)
+  Word,
+  /// 匹配必须覆盖整行
+  Line,
+}
+
+/// 一个匹配行及其位置
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<'a> {
+  /// 从 1 开始的行号
+  pub line_number: usize,
+  /// 行首在整个内容中的字节偏移
+  pub byte_offset: usize,
+  /// 行内第一个匹配的字节区间
+  pub start: usize,
+  pub end: usize,
+  pub line: &'a str,
+  /// 匹配到的模式的序号, 单个查询时总是 0
+  pub pattern: usize,
+  /// 近似匹配时与查询的编辑距离, 精确匹配时总是 0
+  pub distance: usize,
+}
+
+impl<'a> Match<'a> {
+  /// 第一个匹配所在的列, 从 1 开始按字节计
+  pub fn column(&self) -> usize {
+    self.start + 1
   }
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-  let contents = fs::read_to_string(config.file_name)?;
-  let results = if config.sensitive {
-    search(config.query, &contents)
+/// 标准输入在输出中显示的名字
+const STDIN_NAME: &str = "(standard input)";
+
+/// 输出中显示的文件名
+fn display_name(file: &Path) -> &Path {
+  if file == Path::new("-") {
+    Path::new(STDIN_NAME)
   } else {
-    search_case_insensitive(config.query, &contents)
-  };
+    file
+  }
+}
 
-  for line in results {
-    println!("{}", line);
+/// 搜索单个文件, `-` 表示标准输入, 需要输出的行一边搜索一边交给 `emit`
+fn search_file<F>(searcher: &Searcher, file: &Path, config: &Config, options: &SearchOptions, mut emit: F) -> io::Result<Summary>
+where
+  F: FnMut(Line) -> io::Result<()>,
+{
+  //写回文件时不输出行, 改变的行数当作选中的行数
+  if let (true, Some(replace)) = (config.in_place, &config.replace) {
+    let matches = replace::rewrite(searcher, &Template::new(replace), file, config.backup, config.max_count)?;
+    return Ok(Summary {
+      matches,
+      binary_match: false,
+    });
+  }
+  //只有逐行输出时才需要行的内容
+  let keep = config.output == OutputMode::Lines;
+  let emit = |line| if keep { emit(line) } else { Ok(()) };
+  if file == Path::new("-") {
+    return searcher.search_reader(io::stdin().lock(), options, emit);
+  }
+  let handle = fs::File::open(file)?;
+  //无法映射时退回到逐块读取
+  //SAFETY: 只在用户给出 --mmap 时映射, 用户需要保证搜索期间文件不被截断或改写, 见 `Mmap::open`
+  let map = if config.mmap && handle.metadata()?.is_file() {
+    unsafe { Mmap::open(&handle) }.ok()
+  } else {
+    None
+  };
+  match map {
+    Some(map) => searcher.search_slice(&map, options, emit),
+    None => searcher.search_reader(io::BufReader::with_capacity(64 * 1024, handle), options, emit),
   }
+}
+
+/// `collect_files` 给出的一个输入
+enum Input {
+  File(PathBuf),
+  /// 索引确定不会匹配的文件, 不读取, 按没有匹配的文件报告
+  Skipped(PathBuf),
+  /// 无法访问的路径, 留在原来的位置上报告
+  Error(Error),
+}
+
+/// `search_files` 按文件顺序交出的事件
+enum Event {
+  /// 一个文件的第一行或结束之前
+  Begin(PathBuf),
+  Line(Line),
+  End(Summary),
+  /// 无法搜索的文件(包括遍历时遇到的错误), 之前可能已经交出了一部分行
+  Error(Error),
+}
+
+/// 每个文件最多暂存的事件数, 输出跟不上时搜索这个文件的 worker 阻塞
+const FILE_EVENTS: usize = 1024;
 
+/// 在线程池中搜索 `files`, 按 `files` 的顺序把每个文件的事件交给 `each`
+/// 每个文件有自己的有界通道, 最多提前提交 `2 * threads` 个文件, 暂存的事件总数有上限
+/// `each` 出错时取消还没有搜索的文件, 搜索时 panic 的文件按出错报告
+fn search_files<F>(
+  searcher: Searcher,
+  files: Vec<Input>,
+  config: &Config,
+  options: &SearchOptions,
+  mut each: F,
+) -> Result<(), Error>
+where
+  F: FnMut(Event) -> Result<(), Error>,
+{
+  let searcher = Arc::new(searcher);
+  let options = Arc::new(options.clone());
+  let config = Arc::new(config.clone());
+  let pool = ThreadPool::new(config.threads);
+  let cancel = Arc::new(AtomicBool::new(false));
+  //提交一个输入, 返回接收它的事件的通道
+  let submit = |input| {
+    let (tx, rx) = mpsc::sync_channel(FILE_EVENTS);
+    let file = match input {
+      Input::File(file) => file,
+      //不需要搜索的输入的事件直接放进通道, 通道足够大, 不会阻塞
+      Input::Skipped(file) => {
+        let _ = tx.send(Event::Begin(display_name(&file).to_path_buf()));
+        let _ = tx.send(Event::End(Summary::default()));
+        return rx;
+      }
+      Input::Error(e) => {
+        let _ = tx.send(Event::Error(e));
+        return rx;
+      }
+    };
+    let (searcher, options, config) = (Arc::clone(&searcher), Arc::clone(&options), Arc::clone(&config));
+    let cancel = Arc::clone(&cancel);
+    pool.execute(move || {
+      if cancel.load(Ordering::Relaxed) {
+        return;
+      }
+      let name = display_name(&file).to_path_buf();
+      let mut begun = false;
+      let begin = |begun: &mut bool| {
+        if !std::mem::replace(begun, true) {
+          let _ = tx.send(Event::Begin(name.clone()));
+        }
+      };
+      let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        search_file(&searcher, &file, &config, &options, |line| {
+          begin(&mut begun);
+          //已经取消或接收端已经退出(出错)时停止搜索
+          if cancel.load(Ordering::Relaxed) {
+            return Err(io::Error::from(io::ErrorKind::BrokenPipe));
+          }
+          tx.send(Event::Line(line)).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+        })
+      }));
+      let event = match result {
+        Ok(Ok(summary)) => {
+          begin(&mut begun);
+          Event::End(summary)
+        }
+        Ok(Err(e)) => Event::Error(Error::io(&name, e)),
+        //仍然交出一个事件, 否则排在后面的文件永远轮不到
+        Err(payload) => {
+          let message = match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+            (Some(message), _) => message.to_string(),
+            (_, Some(message)) => message.clone(),
+            _ => String::from("unknown error"),
+          };
+          Event::Error(Error::io(&name, io::Error::other(format!("search panicked: {}", message))))
+        }
+      };
+      let _ = tx.send(event);
+    });
+    rx
+  };
+
+  let window = 2 * config.threads.max(1);
+  let finishes = |event: &Event| matches!(event, Event::End(_) | Event::Error(_));
+  let mut files = files.into_iter();
+  //已经提交的文件, 按顺序输出; 出错返回时先于线程池释放, 阻塞的 worker 因发送失败而停止
+  let mut submitted = VecDeque::new();
+  loop {
+    //线程池按提交顺序执行, 排在最前面的未完成文件总有 worker 在搜索, 不会死锁
+    submitted.extend(files.by_ref().take(window - submitted.len()).map(&submit));
+    let events = match submitted.pop_front() {
+      Some(events) => events,
+      None => break,
+    };
+    for event in events {
+      let done = finishes(&event);
+      if let Err(e) = each(event) {
+        cancel.store(true, Ordering::Relaxed);
+        return Err(e);
+      }
+      if done {
+        break;
+      }
+    }
+  }
   Ok(())
 }
 
-fn search_case_insensitive<'a>(query: String, contents: &'a str) -> Vec<&'a str> {
-  contents
-    .lines()
-    .filter(|line| line.to_lowercase().contains(&query.to_lowercase()))
-    .collect()
+/// 返回是否选中了任何内容(`-L` 时是否列出了任何文件), 用于决定退出码
+/// 无法搜索的文件报告后跳过, 最后返回 `Error::Skipped`
+pub fn run(config: Config) -> Result<bool, Error> {
+  let result = match &config.build_index {
+    Some(dir) => build_index(dir, &config),
+    None if config.rank => rank_and_print(&config),
+    None => search_and_print(&config),
+  };
+  match result {
+    //下游管道关闭(例如 `minigrep ... | head`)时安静退出
+    Err(Error::Output(source)) if source.kind() == io::ErrorKind::BrokenPipe => Ok(true),
+    result => result,
+  }
+}
+
+/// 建立索引, 无法读取的文件报告后跳过
+fn build_index(dir: &Path, config: &Config) -> Result<bool, Error> {
+  let built = Index::build(dir, &config.walk)?;
+  for e in &built.errors {
+    eprintln!("minigrep: {}", e.report());
+  }
+  let path = dir.join(INDEX_NAME);
+  writeln!(io::stdout(), "{}: {} files, {} reindexed", path.display(), built.files, built.reindexed).map_err(Error::Output)?;
+  match built.errors.len() {
+    0 => Ok(true),
+    n => Err(Error::Skipped(n)),
+  }
+}
+
+fn search_and_print(config: &Config) -> Result<bool, Error> {
+  let searcher = Searcher::new(config)?;
+  search_and_write(searcher, config, io::BufWriter::new(io::stdout()))
+}
+
+fn search_and_write<W: Write>(searcher: Searcher, config: &Config, out: W) -> Result<bool, Error> {
+  let files = collect_files(config);
+  let mut printer = Printer::new(config, out, with_path(config, &files), streams_stdin(config, &files));
+  let selected = search_into(searcher, files, config, &config.search_options(), &mut printer);
+  printer.finish().map_err(Error::Output)?;
+  selected
+}
+
+/// 读入所有文档, 按 BM25 分数输出前 `config.top` 个, 无法读取的文件报告后跳过
+/// 二进制文件除非给出 `-a`, 否则不参与排序
+fn rank_and_print(config: &Config) -> Result<bool, Error> {
+  let files = collect_files(config);
+  let tokenizer = Tokenizer {
+    fold: config.fold,
+    normalization: config.normalization,
+  };
+  let mut ranker = Ranker::new(&config.patterns, tokenizer);
+  let mut printer = Printer::new(config, io::BufWriter::new(io::stdout()), with_path(config, &files), false);
+  let mut failed = 0;
+  for file in files {
+    let read = match file {
+      Input::File(file) | Input::Skipped(file) => {
+        let name = display_name(&file).to_path_buf();
+        match read_document_lines(&file, config.binary) {
+          Ok(lines) => Ok((name, lines)),
+          Err(e) => Err(Error::io(&name, e)),
+        }
+      }
+      Input::Error(e) => Err(e),
+    };
+    let (name, lines) = match read {
+      Ok(read) => read,
+      Err(e) => {
+        failed += 1;
+        printer.error(&e).map_err(Error::Output)?;
+        continue;
+      }
+    };
+    let name = Arc::new(name);
+    let texts: Vec<&str> = lines.iter().map(|(_, text)| text.as_str()).collect();
+    for range in rank::split(&texts, config.unit) {
+      let text = texts[range.clone()].join("\n");
+      ranker.add((Arc::clone(&name), range.start, lines[range].to_vec()), &text);
+    }
+  }
+  let top = ranker.top(config.top);
+  for (score, (name, first, lines)) in &top {
+    let lines: Vec<Line> = lines
+      .iter()
+      .enumerate()
+      .map(|(i, (offset, text))| Line {
+        number: first + i + 1,
+        offset: *offset,
+        column: None,
+        pattern: None,
+        distance: None,
+        ranges: ranker.matches(text),
+        patterns: vec![],
+        text: text.clone(),
+      })
+      .collect();
+    printer.ranked(name, *score, &lines).map_err(Error::Output)?;
+  }
+  printer.finish().map_err(Error::Output)?;
+  match failed {
+    0 => Ok(!top.is_empty()),
+    n => Err(Error::Skipped(n)),
+  }
+}
+
+/// 读入 `--rank` 的一个文件, `-` 表示标准输入
+fn read_document_lines(file: &Path, binary: BinaryMode) -> io::Result<Vec<(usize, String)>> {
+  let mut bytes = vec![];
+  if file == Path::new("-") {
+    io::stdin().lock().read_to_end(&mut bytes)?;
+  } else {
+    bytes = fs::read(file)?;
+  }
+  if binary != BinaryMode::Text && decode::Bom::sniff(&bytes).is_none() && bytes.contains(&0) {
+    return Ok(vec![]);
+  }
+  decode::decode_lines(&bytes)
+}
+
+/// 用 `matcher` 代替 `config.patterns` 搜索 `config.paths`, 按文件顺序把结果交给 `sink`
+/// 其他选项(边界、上下文、反向匹配、二进制文件、输出方式等)仍按 `config`, 匹配行总是带有所有匹配的区间
+/// 返回值与 `run` 相同
+pub fn search_with<M, S>(config: &Config, matcher: M, sink: &mut S) -> Result<bool, Error>
+where
+  M: Matcher + Send + Sync + 'static,
+  S: Sink,
+{
+  let searcher = Searcher::with_matcher(matcher, config);
+  let options = SearchOptions {
+    highlight: true,
+    ..config.search_options()
+  };
+  search_into(searcher, collect_files(config), config, &options, sink)
+}
+
+/// 展开 `config.paths` 中的目录, 无法访问的路径留在原来的位置上
+/// `--index` 时标记出一定不匹配的文件, `--in-place` 时去掉指向同一个文件的重复路径
+fn collect_files(config: &Config) -> Vec<Input> {
+  let mut files = vec![];
+  let mut rewritten = HashSet::new();
+  for path in &config.paths {
+    if path == Path::new("-") {
+      files.push(Input::File(path.clone()));
+      continue;
+    }
+    let narrow = if config.index {
+      index::Narrow::new(config, path).unwrap_or_else(|e| {
+        //索引损坏时照常搜索
+        files.push(Input::Error(e));
+        None
+      })
+    } else {
+      None
+    };
+    for file in Walk::new(&[path], config.walk.clone()) {
+      //并行写回同一个文件会重复替换
+      if let (true, Ok(file)) = (config.in_place, &file) {
+        if !rewritten.insert(fs::canonicalize(file).unwrap_or_else(|_| file.clone())) {
+          continue;
+        }
+      }
+      files.push(match file {
+        Ok(file) if narrow.as_ref().is_some_and(|narrow| narrow.skips(&file)) => Input::Skipped(file),
+        Ok(file) => Input::File(file),
+        Err(e) => Input::Error(e),
+      });
+    }
+  }
+  files
+}
+
+/// 搜索多个文件或者搜索目录时在每行前加上文件路径, 与 `grep -r` 一致
+fn with_path(config: &Config, files: &[Input]) -> bool {
+  files.len() > 1 || config.paths.iter().any(|path| path.is_dir())
+}
+
+/// 只读标准输入时在当前线程上边读边输出, 不经过线程池
+fn streams_stdin(config: &Config, files: &[Input]) -> bool {
+  config.output == OutputMode::Lines && matches!(files, [Input::File(file)] if file == Path::new("-"))
 }
 
-fn search<'a>(query: String, contents: &'a str) -> Vec<&'a str> {
-  contents
-    .lines()
-    .filter(|line| line.contains(&query))
+/// 搜索 `files` 并把结果交给 `sink`, 返回是否选中了任何内容
+/// 无法搜索的文件交给 `Sink::error` 后继续, 写输出失败时停止
+fn search_into<S: Sink + ?Sized>(
+  searcher: Searcher,
+  files: Vec<Input>,
+  config: &Config,
+  options: &SearchOptions,
+  sink: &mut S,
+) -> Result<bool, Error> {
+  //`-L` 选中的是没有匹配的文件
+  let selected = |summary: &Summary| (summary.matches > 0) != (config.output == OutputMode::FilesWithoutMatch);
+  let mut any = false;
+  let mut failed = 0;
+  if streams_stdin(config, &files) {
+    let name = Path::new(STDIN_NAME);
+    sink.begin(name).map_err(Error::Output)?;
+    //区分读取失败和写输出失败
+    let mut output = None;
+    let read = searcher.search_reader(io::stdin().lock(), options, |line| {
+      sink::emit(sink, name, &line).map_err(|e| {
+        let kind = e.kind();
+        output = Some(e);
+        io::Error::from(kind)
+      })
+    });
+    if let Some(e) = output {
+      return Err(Error::Output(e));
+    }
+    match read {
+      Ok(summary) => {
+        sink.end(name, &summary).map_err(Error::Output)?;
+        any = selected(&summary);
+      }
+      Err(e) => {
+        failed += 1;
+        sink.error(&Error::io(name, e)).map_err(Error::Output)?;
+      }
+    }
+  } else {
+    let mut name = PathBuf::new();
+    search_files(searcher, files, config, options, |event| {
+      match event {
+        Event::Begin(file) => {
+          sink.begin(&file).map_err(Error::Output)?;
+          name = file;
+        }
+        Event::Line(line) => sink::emit(sink, &name, &line).map_err(Error::Output)?,
+        Event::End(summary) => {
+          sink.end(&name, &summary).map_err(Error::Output)?;
+          any |= selected(&summary);
+        }
+        Event::Error(e) => {
+          failed += 1;
+          sink.error(&e).map_err(Error::Output)?;
+        }
+      }
+      Ok(())
+    })?;
+  }
+  //与 grep 一致, 有文件出错时失败, 除非是 `-q` 且已经找到匹配
+  if failed > 0 && !(any && config.output == OutputMode::Quiet) {
+    return Err(Error::Skipped(failed));
+  }
+  Ok(any)
+}
+
+/// 与 `str::lines` 相同的分行方式, 同时给出每行行首的字节偏移
+pub fn lines_with_offsets(contents: &str) -> impl Iterator<Item = (usize, &str)> {
+  let mut offset = 0;
+  contents.split_inclusive('\n').map(move |raw| {
+    let line = raw.strip_suffix('\n').unwrap_or(raw);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let start = offset;
+    offset += raw.len();
+    (start, line)
+  })
+}
+
+/// 逐行调用 `find` 查找第一个匹配, 收集所有匹配行
+fn search_by<F>(contents: &str, find: F) -> Vec<Match<'_>>
+where
+  F: Fn(&str) -> Option<(usize, usize, usize)>,
+{
+  lines_with_offsets(contents)
+    .enumerate()
+    .filter_map(|(index, (byte_offset, line))| {
+      find(line).map(|(start, end, pattern)| Match {
+        line_number: index + 1,
+        byte_offset,
+        start,
+        end,
+        line,
+        pattern,
+        distance: 0,
+      })
+    })
     .collect()
 }
 
+pub fn search_case_insensitive(query: String, contents: &str, boundary: Boundary) -> Vec<Match<'_>> {
+  search_matcher(&Finder::new(&query, true), contents, boundary)
+}
+
+pub fn search(query: String, contents: &str, boundary: Boundary) -> Vec<Match<'_>> {
+  search_matcher(&Finder::new(&query, false), contents, boundary)
+}
+
+pub fn search_finder<'a>(finder: &Finder, contents: &'a str, boundary: Boundary) -> Vec<Match<'a>> {
+  search_matcher(finder, contents, boundary)
+}
+
+/// 用任意 `matcher` 查找每行的第一个匹配
+/// 先用 `Matcher::candidate` 在整个内容上查找, 只有可能匹配的行才需要切分出来并计算行号
+pub fn search_matcher<'a, M: Matcher + ?Sized>(matcher: &M, contents: &'a str, boundary: Boundary) -> Vec<Match<'a>> {
+  let mut matches = vec![];
+  let (mut line_number, mut line_start) = (1, 0);
+  while let Some(found) = matcher.candidate(contents.as_bytes(), line_start) {
+    //从候选位置向前找到行首, 再统计跳过的行数, 计数的循环可以被编译器向量化
+    let skipped = &contents.as_bytes()[line_start..found];
+    if let Some(i) = skipped.iter().rposition(|&b| b == b'\n') {
+      line_number += skipped[..=i].iter().filter(|&&b| b == b'\n').count();
+      line_start += i + 1;
+    }
+    let line_end = finder::memchr(b'\n', &contents.as_bytes()[found..]).map_or(contents.len(), |i| found + i);
+    let line = &contents[line_start..line_end];
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let find_at = |at| matcher.find_in_line(line, at).map(|(start, end)| (start, end, 0));
+    if let Some((start, end, pattern)) = find_bounded(line, found - line_start, boundary, find_at) {
+      matches.push(Match {
+        line_number,
+        byte_offset: line_start,
+        start,
+        end,
+        line,
+        pattern,
+        distance: 0,
+      });
+    }
+    if line_end == contents.len() {
+      break;
+    }
+    line_number += 1;
+    line_start = line_end + 1;
+  }
+  matches
+}
+
+pub fn search_regex<'a>(re: &Regex, contents: &'a str) -> Vec<Match<'a>> {
+  search_matcher(re, contents, Boundary::None)
+}
+
+/// 一次扫描查找多个字面量, 每行报告最左(同一位置取最长)的匹配及其模式序号
+pub fn search_any<'a, P: AsRef<str>>(patterns: &[P], contents: &'a str, boundary: Boundary) -> Vec<Match<'a>> {
+  let patterns: Vec<&str> = patterns.iter().map(AsRef::as_ref).collect();
+  let ac = AhoCorasick::new(&patterns);
+  search_by(contents, |line| find_bounded(line, 0, boundary, |at| ac.find_at(line.as_bytes(), at)))
+}
+
+/// 查找包含与 `fuzzy` 的查询编辑距离足够小的子串的行, 每行报告距离最小的匹配
+pub fn search_fuzzy<'a>(fuzzy: &Fuzzy, contents: &'a str, boundary: Boundary) -> Vec<Match<'a>> {
+  let mut matches = search_matcher(fuzzy, contents, boundary);
+  for found in &mut matches {
+    found.distance = fuzzy.distance(&found.line[found.start..found.end]);
+  }
+  matches
+}
+
+/// 从 `start` 开始查找第一个满足 `boundary` 的匹配, `find_at(at)` 返回从 `at` 开始的最左匹配及其模式序号
+/// 不满足单词边界时从下一个字符重试, 所以 `duct` 在 `productive duct` 中会找到后一个
+pub(crate) fn find_bounded<F>(line: &str, start: usize, boundary: Boundary, find_at: F) -> Option<(usize, usize, usize)>
+where
+  F: Fn(usize) -> Option<(usize, usize, usize)>,
+{
+  let mut at = start;
+  while at <= line.len() {
+    let (start, end, pattern) = find_at(at)?;
+    match boundary {
+      Boundary::None => return Some((start, end, pattern)),
+      //最左的匹配不从行首开始时, 其他匹配更不可能覆盖整行
+      Boundary::Line => return Some((start, end, pattern)).filter(|&(start, end, _)| start == 0 && end == line.len()),
+      Boundary::Word => {
+        let before = line[..start].chars().next_back();
+        let after = line[end..].chars().next();
+        if !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char) {
+          return Some((start, end, pattern));
+        }
+      }
+    }
+    at = start + line[start..].chars().next().map_or(1, char::len_utf8);
+  }
+  None
+}
+
 #[cfg(test)]
 mod test {
   use super::*;
+  use searcher::Pattern;
+
+  /// 解析出要运行的配置, 用于不涉及 --help 和 --version 的测试
+  pub(crate) fn parse(args: impl Iterator<Item = String>) -> Result<Config, Error> {
+    match Config::new(args)? {
+      Parsed::Run(config) => Ok(*config),
+      parsed => panic!("{:?}", parsed),
+    }
+  }
+
+  fn lines(matches: Vec<Match<'_>>) -> Vec<&str> {
+    matches.into_iter().map(|m| m.line).collect()
+  }
+
   #[test]
   fn case_sensitive() {
     let query = String::from("duct");
+    //续行后的内容不能缩进, 否则缩进会成为每行的一部分
     let contents = "\
-  Rust:
-  safe, fast, productive.
-  Pick three.
-  Duct tape.";
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.";
 
-    assert_eq!(vec!["safe, fast, productive."], search(query, contents))
+    assert_eq!(vec!["safe, fast, productive."], lines(search(query, contents, Boundary::None)))
+  }
+
+  #[test]
+  fn whole_words_and_lines() {
+    let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Duct tape.
+duct_tape, productive duct
+naïveduct
+duct";
+
+    let search_word = |query: &str| lines(search(String::from(query), contents, Boundary::Word));
+    //`productive` 中的匹配被跳过, 同一行后面的完整单词仍能找到
+    assert_eq!(vec!["duct_tape, productive duct", "duct"], search_word("duct"));
+    assert_eq!(vec!["safe, fast, productive."], search_word("fast, productive"));
+    assert!(search_word("naïve").is_empty());
+    assert_eq!(vec!["duct"], lines(search(String::from("duct"), contents, Boundary::Line)));
+
+    let matches = search_case_insensitive(String::from("DUCT"), contents, Boundary::Word);
+    assert_eq!(vec!["Duct tape.", "duct_tape, productive duct", "duct"], lines(matches));
+    assert_eq!(
+      vec!["Pick three."],
+      lines(search_case_insensitive(String::from("pick THREE."), contents, Boundary::Line))
+    );
+    assert!(search_case_insensitive(String::from("pick"), contents, Boundary::Line).is_empty());
   }
 
   #[test]
   fn case_insensitive() {
     let query = String::from("rUsT");
     let contents = "\
-  Rust:
-  safe, fast, productive.
-  Pick three.
-  Trust me.";
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
 
     assert_eq!(
       vec!["Rust:", "Trust me."],
-      search_case_insensitive(query, contents)
+      lines(search_case_insensitive(query, contents, Boundary::None))
+    );
+  }
+
+  #[test]
+  fn regex() {
+    let re = Regex::new(r"fn \w+\(", false).unwrap();
+    let contents = "\
+pub fn run(config: Config) {
+let search = fn_ptr;
+fn search(query: String) {";
+
+    assert_eq!(
+      vec!["pub fn run(config: Config) {", "fn search(query: String) {"],
+      lines(search_regex(&re, contents))
     );
   }
+
+  #[test]
+  fn config_flags() {
+    let args = vec!["minigrep", "--regex", "a|b", "poem.txt", "--max-depth", "2", "src"];
+    let config = parse(args.into_iter().map(String::from)).unwrap();
+    assert!(config.regex);
+    assert_eq!(vec!["a|b"], config.patterns);
+    assert_eq!(vec![PathBuf::from("poem.txt"), PathBuf::from("src")], config.paths);
+    assert_eq!(Some(2), config.walk.max_depth);
+
+    let config = parse(["minigrep", "-inC2", "--", "-v"].iter().map(|s| s.to_string())).unwrap();
+    assert!(config.case == Case::Insensitive && config.line_number);
+    assert_eq!((2, 2), (config.before, config.after));
+    assert_eq!(vec!["-v"], config.patterns);
+
+    let config = parse(["minigrep", "-vlSm5", "q"].iter().map(|s| s.to_string())).unwrap();
+    assert!(config.invert && config.case == Case::Smart);
+    assert_eq!((OutputMode::FilesWithMatches, Some(5)), (config.output, config.max_count));
+    assert_eq!(Some(1), config.search_options().max_count);
+
+    let parsed = |args: &[&str]| Config::new(args.iter().map(|s| s.to_string())).unwrap();
+    assert!(matches!(parsed(&["minigrep", "-h"]), Parsed::Help(_)));
+    assert!(matches!(parsed(&["minigrep", "--version"]), Parsed::Version(_)));
+    let err = |args: &[&str]| parse(args.iter().map(|s| s.to_string())).unwrap_err();
+    let usage = |args: &[&str]| match err(args) {
+      Error::Usage(message) => message,
+      e => panic!("{:?}", e),
+    };
+    assert_eq!("--threads expects a positive number", usage(&["minigrep", "-j0", "q"]));
+    assert_eq!("Didn't get a query string", usage(&["minigrep", "-n"]));
+    assert!(matches!(err(&["minigrep", "-f", "no/such/file"]), Error::Io { .. }));
+  }
+
+  #[test]
+  fn replace_options() {
+    let config = parse(["minigrep", "-r", "$0!", "--in-place", "--backup", "q", "a.txt"].iter().map(|s| s.to_string()));
+    let config = config.unwrap();
+    assert_eq!((Some("$0!"), true, true), (config.replace.as_deref(), config.in_place, config.backup));
+    assert!(config.search_options().highlight);
+
+    let err = |args: &[&str]| parse(args.iter().map(|s| s.to_string())).unwrap_err().to_string();
+    assert_eq!("--in-place requires --replace and can't be combined with -v", err(&["minigrep", "--in-place", "q", "a"]));
+    assert_eq!("--backup requires --in-place", err(&["minigrep", "-r", "x", "--backup", "q", "a"]));
+    assert_eq!("--in-place can't edit standard input", err(&["minigrep", "-r", "x", "--in-place", "q"]));
+  }
+
+  #[test]
+  fn in_place_rewrites_each_file_once() {
+    let dir = env::temp_dir().join(format!("minigrep-in-place-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("a.txt");
+    fs::write(&file, "q\n").unwrap();
+    let paths = [file.clone(), dir.join(".").join("a.txt"), dir.clone()];
+    let mut args: Vec<String> = ["minigrep", "-r", "<$0>", "--in-place", "q"].iter().map(|s| s.to_string()).collect();
+    args.extend(paths.iter().map(|path| path.to_str().unwrap().to_string()));
+    let config = parse(args.into_iter()).unwrap();
+    assert!(search_and_write(Searcher::new(&config).unwrap(), &config, io::sink()).unwrap());
+    assert_eq!("<q>\n", fs::read_to_string(&file).unwrap());
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn rank_options() {
+    let config = parse(["minigrep", "--rank", "--paragraphs", "--top", "3", "q"].iter().map(|s| s.to_string()));
+    let config = config.unwrap();
+    assert_eq!((true, Unit::Paragraph, 3), (config.rank, config.unit, config.top));
+
+    let err = |args: &[&str]| parse(args.iter().map(|s| s.to_string())).unwrap_err().to_string();
+    assert_eq!("--paragraphs and --top require --rank", err(&["minigrep", "--top", "3", "q"]));
+    assert!(err(&["minigrep", "--rank", "-c", "q"]).starts_with("--rank can't be combined"));
+  }
+
+  #[test]
+  fn parallel_results_keep_file_order() {
+    let dir = env::temp_dir().join(format!("minigrep-parallel-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let files: Vec<PathBuf> = (0..32)
+      .map(|i| {
+        let file = dir.join(format!("{:02}.txt", i));
+        fs::write(&file, format!("line {}\nother\n", i).repeat(i * 50)).unwrap();
+        file
+      })
+      .collect();
+
+    let args = ["minigrep", "-j", "4", "line", "."].iter().map(|s| s.to_string());
+    let config = parse(args).unwrap();
+    let mut seen = vec![];
+    let mut lines = 0;
+    let searcher = Searcher::from(Pattern::Literal(Finder::new("line", false)));
+    let paths = files.iter().cloned().map(Input::File).collect();
+    search_files(searcher, paths, &config, &config.search_options(), |event| {
+      match event {
+        Event::Begin(file) => seen.push(file),
+        Event::Line(_) => lines += 1,
+        Event::End(summary) => {
+          assert_eq!((seen.len() - 1) * 50, lines);
+          assert_eq!(lines, summary.matches);
+          lines = 0;
+        }
+        Event::Error(e) => panic!("{}", e.report()),
+      }
+      Ok(())
+    })
+    .unwrap();
+    assert_eq!(files, seen);
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  /// 匹配 `big`, 并统计查找过的行数
+  struct CountBig(Arc<std::sync::atomic::AtomicUsize>);
+
+  impl Matcher for CountBig {
+    fn find_at(&self, haystack: &[u8], start: usize) -> Option<(usize, usize)> {
+      self.0.fetch_add(1, Ordering::Relaxed);
+      haystack[start..].windows(3).position(|w| w == b"big").map(|i| (start + i, start + i + 3))
+    }
+  }
+
+  #[test]
+  fn parallel_search_stays_bounded() {
+    let dir = env::temp_dir().join(format!("minigrep-bounded-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (first, second) = (dir.join("first.txt"), dir.join("second.txt"));
+    fs::write(&first, "small\n").unwrap();
+    fs::write(&second, "big\n".repeat(10 * FILE_EVENTS)).unwrap();
+
+    let args = ["minigrep", "-j", "2", "big"].iter().map(|s| s.to_string());
+    let config = parse(args).unwrap();
+    let searched = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let searcher = Searcher::with_matcher(CountBig(Arc::clone(&searched)), &config);
+    let paths = vec![Input::File(first), Input::File(second)];
+    let mut lines = 0;
+    search_files(searcher, paths, &config, &config.search_options(), |event| {
+      match event {
+        //输出停在第一个文件时, 第二个文件最多提前搜索到填满它的通道
+        Event::Begin(file) if file.ends_with("first.txt") => {
+          thread::sleep(std::time::Duration::from_millis(200));
+          assert!(searched.load(Ordering::Relaxed) <= FILE_EVENTS + 2);
+        }
+        Event::Line(_) => lines += 1,
+        _ => {}
+      }
+      Ok(())
+    })
+    .unwrap();
+    assert_eq!(10 * FILE_EVENTS, lines);
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn directories_print_paths() {
+    let dir = env::temp_dir().join(format!("minigrep-one-file-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("a.txt");
+    fs::write(&file, "hello\n").unwrap();
+    let output = |path: &Path| {
+      let args = ["minigrep", "hello", path.to_str().unwrap()];
+      let config = parse(args.iter().map(|s| s.to_string())).unwrap();
+      let mut out = vec![];
+      search_and_write(Searcher::new(&config).unwrap(), &config, &mut out).unwrap();
+      String::from_utf8(out).unwrap()
+    };
+    //目录中只有一个文件时也输出路径, 直接给出文件时不输出
+    assert_eq!(format!("{}:hello\n", file.display()), output(&dir));
+    assert_eq!("hello\n", output(&file));
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn index_does_not_change_output() {
+    let dir = env::temp_dir().join(format!("minigrep-narrow-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.txt"), "hello world\nbye\n").unwrap();
+    fs::write(dir.join("b.txt"), "nothing here\n").unwrap();
+    Index::build(&dir, &WalkOptions::default()).unwrap();
+
+    let output = |args: &[&str]| {
+      let mut args: Vec<String> = ["minigrep"].iter().chain(args).map(|s| s.to_string()).collect();
+      args.push(dir.to_str().unwrap().to_string());
+      let config = parse(args.into_iter()).unwrap();
+      let mut out = vec![];
+      let selected = search_and_write(Searcher::new(&config).unwrap(), &config, &mut out).unwrap();
+      (selected, String::from_utf8(out).unwrap())
+    };
+    for args in [&["hello"][..], &["-c", "hello"], &["--json", "hello"], &["-l", "hello"], &["-nC1", "hello"]] {
+      let indexed: Vec<&str> = ["--index"].iter().chain(args).copied().collect();
+      assert_eq!(output(args), output(&indexed), "{:?}", args);
+    }
+    //`-c` 对被索引排除的文件也输出 0
+    assert!(output(&["--index", "-c", "hello"]).1.contains("b.txt:0"));
+    //索引文件本身不会被搜索, 也不会按被排除的文件报告
+    let hidden = output(&["--hidden", "--index", "-c", "hello"]).1;
+    assert!(hidden.contains("b.txt:0") && !hidden.contains(INDEX_NAME), "{}", hidden);
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  /// 匹配 `!`
+  struct Bang;
+
+  impl Matcher for Bang {
+    fn find_at(&self, haystack: &[u8], start: usize) -> Option<(usize, usize)> {
+      haystack[start..].iter().position(|&b| b == b'!').map(|i| (start + i, start + i + 1))
+    }
+  }
+
+  /// 按顺序记录收到的事件
+  #[derive(Default)]
+  struct Events(Vec<String>);
+
+  impl Sink for Events {
+    fn begin(&mut self, file: &Path) -> io::Result<()> {
+      self.0.push(format!("begin {}", file.display()));
+      Ok(())
+    }
+
+    fn matched(&mut self, _file: &Path, line: &Line) -> io::Result<()> {
+      self.0.push(format!("{}:{:?}", line.number, line.ranges));
+      Ok(())
+    }
+
+    fn context(&mut self, _file: &Path, line: &Line) -> io::Result<()> {
+      self.0.push(format!("{}-", line.number));
+      Ok(())
+    }
+
+    fn end(&mut self, _file: &Path, summary: &Summary) -> io::Result<()> {
+      self.0.push(format!("end {}", summary.matches));
+      Ok(())
+    }
+
+    fn error(&mut self, error: &Error) -> io::Result<()> {
+      self.0.push(error.to_string());
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn custom_matcher_and_sink() {
+    let config = Config {
+      paths: vec![PathBuf::from("poem.txt")],
+      after: 1,
+      ..Config::default()
+    };
+    let mut events = Events::default();
+    assert!(search_with(&config, Bang, &mut events).unwrap());
+    let expected = [
+      "begin poem.txt",
+      "1:[(10, 11)]",
+      "2-",
+      "3:[(38, 39)]",
+      "4-",
+      "6:[(25, 26)]",
+      "7-",
+      "9:[(18, 19)]",
+      "10-",
+      "end 4",
+    ];
+    assert_eq!(expected.to_vec(), events.0);
+
+    //只关心数量时只收到文件边界
+    let config = Config {
+      output: OutputMode::Count,
+      ..config
+    };
+    let mut events = Events::default();
+    assert!(search_with(&config, Bang, &mut events).unwrap());
+    assert_eq!(vec!["begin poem.txt", "end 4"], events.0);
+
+    //原有的函数与 `search_matcher` 结果相同
+    let contents = include_str!("../poem.txt");
+    let finder = Finder::new("you", false);
+    assert_eq!(search("you".to_string(), contents, Boundary::Word), search_matcher(&finder, contents, Boundary::Word));
+    let numbers: Vec<usize> = search_matcher(&Bang, contents, Boundary::None).iter().map(|m| m.line_number).collect();
+    assert_eq!(vec![1, 3, 6, 9], numbers);
+  }
+
+  #[test]
+  fn missing_files_are_skipped() {
+    let config = Config {
+      paths: vec![PathBuf::from("no/such/file"), PathBuf::from("poem.txt")],
+      output: OutputMode::Count,
+      ..Config::default()
+    };
+    let mut events = Events::default();
+    let err = search_with(&config, Bang, &mut events).unwrap_err();
+    assert!(matches!(err, Error::Skipped(1)));
+    assert_eq!(vec!["no/such/file", "begin poem.txt", "end 4"], events.0);
+
+    //`-q` 找到匹配时忽略其他文件的错误
+    let config = Config {
+      output: OutputMode::Quiet,
+      ..config
+    };
+    assert!(search_with(&config, Bang, &mut Events::default()).unwrap());
+  }
+
+  /// 与 `Bang` 相同, 但遇到 `[package]` 时 panic, 并且每次查找都很慢
+  struct Fragile(Arc<std::sync::atomic::AtomicUsize>);
+
+  impl Matcher for Fragile {
+    fn find_at(&self, haystack: &[u8], start: usize) -> Option<(usize, usize)> {
+      assert!(!haystack.starts_with(b"[package]"), "fragile");
+      self.0.fetch_add(1, Ordering::Relaxed);
+      thread::sleep(std::time::Duration::from_micros(50));
+      Bang.find_at(haystack, start)
+    }
+  }
+
+  /// 第一个文件结束时写入失败
+  struct Broken;
+
+  impl Sink for Broken {
+    fn matched(&mut self, _file: &Path, _line: &Line) -> io::Result<()> {
+      Ok(())
+    }
+
+    fn end(&mut self, _file: &Path, _summary: &Summary) -> io::Result<()> {
+      Err(io::ErrorKind::BrokenPipe.into())
+    }
+  }
+
+  #[test]
+  fn panics_and_output_errors() {
+    let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let fragile = || Fragile(Arc::clone(&calls));
+    let config = Config {
+      paths: ["poem.txt", "Cargo.toml", "poem.txt"].iter().map(PathBuf::from).collect(),
+      output: OutputMode::Count,
+      ..Config::default()
+    };
+    //panic 的文件按出错报告, 后面的文件照常输出
+    let mut events = Events::default();
+    let err = search_with(&config, fragile(), &mut events).unwrap_err();
+    assert!(matches!(err, Error::Skipped(1)));
+    assert_eq!(vec!["begin poem.txt", "end 4", "Cargo.toml", "begin poem.txt", "end 4"], events.0);
+
+    //写输出失败后不再搜索剩下的文件
+    let dir = env::temp_dir().join(format!("minigrep-cancel-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for i in 0..64 {
+      fs::write(dir.join(format!("{:02}.txt", i)), "hey!\n".repeat(200)).unwrap();
+    }
+    let config = Config {
+      paths: vec![dir.clone()],
+      threads: 4,
+      output: OutputMode::Count,
+      ..Config::default()
+    };
+    calls.store(0, Ordering::Relaxed);
+    let err = search_with(&config, fragile(), &mut Broken).unwrap_err();
+    assert!(matches!(err, Error::Output(_)));
+    assert!(calls.load(Ordering::Relaxed) < 64 * 200 / 2);
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn multiple_patterns() {
+    let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+
+    let matches = search_any(&["three", "fast", "safe"], contents, Boundary::None);
+    assert_eq!(vec!["safe, fast, productive.", "Pick three."], lines(matches.clone()));
+    assert_eq!(vec![2, 0], matches.iter().map(|m| m.pattern).collect::<Vec<_>>());
+    assert_eq!(vec!["Trust me."], lines(search_any(&["rust", "me"], contents, Boundary::Word)));
+
+    let path = env::temp_dir().join(format!("minigrep-patterns-{}", std::process::id()));
+    fs::write(&path, "fast\r\nTHREE\n").unwrap();
+    let args = ["minigrep", "-i", "-e", "trust", "-f", path.to_str().unwrap(), "poem.txt"];
+    let config = parse(args.iter().map(|s| s.to_string())).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(vec!["trust", "fast", "THREE"], config.patterns);
+    assert_eq!(vec![PathBuf::from("poem.txt")], config.paths);
+
+    let searcher = Searcher::new(&config).unwrap();
+    assert_eq!(Some((5, 10, 2)), searcher.find_at("Pick three.", 0));
+    assert_eq!(Some((0, 5, 0)), searcher.find_at("TRUST me, it's fast", 0));
+  }
+
+  #[test]
+  fn smart_case() {
+    assert!(!Case::Smart.is_sensitive("rust", false));
+    assert!(Case::Smart.is_sensitive("Rust", false));
+    assert!(Case::Smart.is_sensitive("ÄRUST", false));
+    assert!(!Case::Smart.is_sensitive(r"\Sfoo\W", true));
+    assert!(Case::Smart.is_sensitive(r"\Sfoo\W", false));
+    assert!(!Case::Insensitive.is_sensitive("Rust", false) && Case::Sensitive.is_sensitive("rust", false));
+
+    let searcher = |args: &[&str]| {
+      let args = ["minigrep", "-S"].iter().chain(args).map(|s| s.to_string());
+      Searcher::new(&parse(args).unwrap()).unwrap()
+    };
+    assert!(searcher(&["rust"]).find_at("Trust me.", 0).is_some());
+    assert!(searcher(&["Rust"]).find_at("trust me.", 0).is_none());
+    assert!(searcher(&["-s", "rust"]).find_at("RUST", 0).is_none());
+    assert!(searcher(&["-i", "Rust"]).find_at("RUST", 0).is_some());
+    //每个模式分别判断: `Me` 区分大小写, `rust` 不区分
+    let mixed = searcher(&["-e", "Me", "-e", "rust"]);
+    assert_eq!(Some((1, 5, 1)), mixed.find_at("Trust me.", 0));
+    assert_eq!(Some((0, 2, 0)), mixed.find_at("Me, RUST", 0));
+    assert_eq!(None, mixed.find_at("me, RUS", 0));
+    let mixed = searcher(&["-E", "-e", "^T", "-e", "me"]);
+    assert_eq!(Some((6, 8, 1)), mixed.find_at("trust ME", 0));
+    assert_eq!(None, mixed.find_at("trust", 0));
+  }
+
+  #[test]
+  fn fuzzy_matches() {
+    let contents = "\
+Rust:
+safe, fast, prodcutive.
+Pick three.
+Trust me.";
+    let found = search_fuzzy(&Fuzzy::new("productive", 2, false), contents, Boundary::None);
+    assert_eq!(vec!["safe, fast, prodcutive."], lines(found.clone()));
+    assert_eq!((12, 22, 2), (found[0].start, found[0].end, found[0].distance));
+    assert!(search_fuzzy(&Fuzzy::new("productive", 1, false), contents, Boundary::None).is_empty());
+    let found = search_fuzzy(&Fuzzy::new("trust", 1, false), contents, Boundary::Word);
+    assert_eq!(vec![("Trust me.", 1)], found.iter().map(|m| (m.line, m.distance)).collect::<Vec<_>>());
+
+    let args = ["minigrep", "--fuzzy", "1", "-e", "thre", "-e", "TRUST"].iter().map(|s| s.to_string());
+    let searcher = Searcher::new(&parse(args).unwrap()).unwrap();
+    let found = searcher.find_at("Pick three.", 0).unwrap();
+    assert_eq!(((5, 9, 0), Some(0)), (found, searcher.distance("Pick three.", found)));
+    //TRUST 有大写字母, 按智能大小写区分大小写
+    assert_eq!(None, searcher.find_at("trust me", 0));
+    let found = searcher.find_at("TRUSt me", 0).unwrap();
+    assert_eq!(((0, 5, 1), Some(1)), (found, searcher.distance("TRUSt me", found)));
+
+    let err = parse(["minigrep", "-E", "--fuzzy=1", "q"].iter().map(|s| s.to_string())).unwrap_err();
+    assert_eq!("--fuzzy can't be combined with --regex", err.to_string());
+  }
+
+  #[test]
+  fn multilingual() {
+    let contents = include_str!("../multilingual.txt");
+    let search_with = |args: &[&str]| -> Vec<&str> {
+      let args = ["minigrep"].iter().chain(args).map(|s| s.to_string());
+      let searcher = Searcher::new(&parse(args).unwrap()).unwrap();
+      contents.lines().filter(|line| searcher.find_at(line, 0).is_some()).collect()
+    };
+    let (strasse, ss) = ("Die Straße ist lang.", "STRASSE IN BERLIN");
+    assert_eq!(vec![strasse, ss], search_with(&["-i", "strasse"]));
+    assert_eq!(vec![strasse], search_with(&["-i", "--case-fold", "simple", "STRAẞE"]));
+    assert_eq!(vec![ss], search_with(&["-i", "--case-fold", "simple", "strasse"]));
+    //İ 的完整折叠是 i 加上组合点, 只有土耳其规则下才与 i 相等
+    assert!(search_with(&["-i", "istanbul"]).is_empty());
+    assert_eq!(vec!["İstanbul ve Iğdır"], search_with(&["-i", "--case-fold", "turkic", "istanbul ve ığdır"]));
+    assert_eq!(vec!["ılık bir gün"], search_with(&["-i", "--case-fold=turkic", "ILIK"]));
+    assert_eq!(vec!["ΣΟΦΟΣ λόγος", "σοφος φίλος"], search_with(&["-i", "σοφος"]));
+    assert_eq!(4, search_with(&["-i", "-e", "STRASSE", "-e", "σοφοσ"]).len());
+    assert_eq!(vec!["ΣΟΦΟΣ λόγος"], search_with(&["-E", "-i", "^σοφοσ [Λ]"]));
+
+    //café 的两种写法只有规范化后才相等
+    assert_eq!(vec!["un café noir"], search_with(&["café"]));
+    assert_eq!(2, search_with(&["--normalize", "nfc", "café"]).len());
+    assert_eq!(2, search_with(&["--normalize", "nfd", "-w", "-e", "CAFÉ", "-i"]).len());
+    let args = ["minigrep", "--normalize", "nfc", "é"].iter().map(|s| s.to_string());
+    let searcher = Searcher::new(&parse(args).unwrap()).unwrap();
+    assert_eq!(Some((6, 9, 0)), searcher.find_at("un cafe\u{301} au lait", 0));
+    assert_eq!(vec![(6, 9, 0), (9, 11, 0)], searcher.find_all("un cafe\u{301}é"));
+
+    let err = |args: &[&str]| parse(args.iter().map(|s| s.to_string())).unwrap_err().to_string();
+    assert_eq!("--case-fold expects simple, full or turkic, got 'upper'", err(&["minigrep", "--case-fold", "upper", "q"]));
+    assert_eq!("--normalize expects nfc or nfd, got 'nfkc'", err(&["minigrep", "--normalize=nfkc", "q"]));
+    let combined = "--case-fold can't be combined with -E or --fuzzy";
+    assert_eq!(combined, err(&["minigrep", "-i", "-E", "--case-fold", "turkic", "q"]));
+    assert_eq!(combined, err(&["minigrep", "--case-fold=full", "--fuzzy", "1", "q"]));
+  }
+
+  #[test]
+  fn match_positions() {
+    let contents = "Rust:\r\nsafe, fast, productive.\nTrust me, ÄRUST.";
+    assert_eq!(
+      vec![Match {
+        line_number: 2,
+        byte_offset: 7,
+        start: 15,
+        end: 19,
+        line: "safe, fast, productive.",
+        pattern: 0,
+        distance: 0,
+      }],
+      search(String::from("duct"), contents, Boundary::None)
+    );
+
+    let matches = search_case_insensitive(String::from("ärust"), contents, Boundary::None);
+    assert_eq!(1, matches.len());
+    assert_eq!((3, 31, 10, 16), (matches[0].line_number, matches[0].byte_offset, matches[0].start, matches[0].end));
+    assert_eq!(11, matches[0].column());
+  }
 }
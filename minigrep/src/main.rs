@@ -1,20 +1,51 @@
-use std::{env, process};
+use std::{
+    env,
+    io::{self, Write},
+    process,
+};
 
-mod lib;
-use lib::{run, Config};
+use minigrep::{run, Config, Error, Parsed};
 
 fn main() {
     //env::args 返回环境变量的迭代器，collect转为Vec对象
     //在任意参数包含无效的Unicode字符(如 , - / 开头)时 panic，需要其他的使用args_os代替(但会根据平台变化)
 
-    let config = Config::new(env::args()).unwrap_or_else(|err| {
-        //return Ok's value Or return a closure to handle error or something else
-        eprintln!("Problem parsing arguments: {}", err);
-        process::exit(1);
+    let parsed = Config::new(env::args()).unwrap_or_else(|err| match err {
+        //与 grep 一致, 用法错误的退出码是 2
+        Error::Usage(message) => {
+            eprintln!("Problem parsing arguments: {}", message);
+            eprintln!("Try 'minigrep --help' for more information.");
+            process::exit(2);
+        }
+        //读取模式文件、规则文件或配置文件失败
+        err => {
+            eprintln!("Problem parsing arguments: {}", err.report());
+            process::exit(2);
+        }
     });
-
-    if let Err(e) = run(config) {
-        eprintln!("Application error: {}", e);
-        process::exit(1);
+    let config = match parsed {
+        Parsed::Run(config) => *config,
+        //--help/--version 正常输出到标准输出, 输出被 `| head` 截断时不 panic
+        Parsed::Help(text) | Parsed::Version(text) => {
+            let _ = writeln!(io::stdout(), "{}", text);
+            return;
+        }
     };
+
+    //与 grep 一致: 0 表示有匹配, 1 表示没有匹配, 2 表示出错
+    match run(config) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        //每个出错的文件已经报告过了
+        Err(Error::Skipped(_)) => process::exit(2),
+        Err(e) => {
+            eprintln!("Application error: {}", e.report());
+            //在查询下方用 `^` 指出出错的列
+            if let Error::Query { query, source } = &e {
+                eprintln!("  {}", query);
+                eprintln!("  {:>1$}", "^", source.column);
+            }
+            process::exit(2);
+        }
+    }
 }
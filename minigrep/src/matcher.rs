@@ -0,0 +1,139 @@
+//! 查找匹配的公共接口, 其他 crate 可以实现自己的匹配方式, 交给 `search_matcher`/`search_with` 使用
+use crate::{AhoCorasick, Finder, Fuzzy, Regex};
+use std::str;
+
+/// 在字节串中查找匹配
+/// 搜索时通过 `find_in_line` 传入一行文本(不含换行符), 返回的区间必须落在字符边界上
+pub trait Matcher {
+  /// 从 `start` 开始的第一个匹配的字节区间
+  fn find_at(&self, haystack: &[u8], start: usize) -> Option<(usize, usize)>;
+
+  fn find(&self, haystack: &[u8]) -> Option<(usize, usize)> {
+    self.find_at(haystack, 0)
+  }
+
+  /// 已知是合法 UTF-8 时的 `find_at`, 按字符查找的匹配方式可以省去检查
+  fn find_in_line(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+    self.find_at(line.as_bytes(), start)
+  }
+
+  /// 在整块内容中从 `start` 开始第一个可能的匹配起点, 不能越过任何真正的匹配
+  /// 用于跳过不可能匹配的行, 默认不跳过
+  fn candidate(&self, haystack: &[u8], start: usize) -> Option<usize> {
+    Some(start).filter(|&start| start < haystack.len())
+  }
+}
+
+impl<M: Matcher + ?Sized> Matcher for &M {
+  fn find_at(&self, haystack: &[u8], start: usize) -> Option<(usize, usize)> {
+    (**self).find_at(haystack, start)
+  }
+
+  fn find_in_line(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+    (**self).find_in_line(line, start)
+  }
+
+  fn candidate(&self, haystack: &[u8], start: usize) -> Option<usize> {
+    (**self).candidate(haystack, start)
+  }
+}
+
+impl<M: Matcher + ?Sized> Matcher for Box<M> {
+  fn find_at(&self, haystack: &[u8], start: usize) -> Option<(usize, usize)> {
+    (**self).find_at(haystack, start)
+  }
+
+  fn find_in_line(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+    (**self).find_in_line(line, start)
+  }
+
+  fn candidate(&self, haystack: &[u8], start: usize) -> Option<usize> {
+    (**self).candidate(haystack, start)
+  }
+}
+
+/// 按字符查找的匹配方式只能处理合法的 UTF-8, 其他输入逐段查找, 匹配不会跨过非法字节
+fn find_utf8<F>(haystack: &[u8], start: usize, find_at: F) -> Option<(usize, usize)>
+where
+  F: Fn(&str, usize) -> Option<(usize, usize)>,
+{
+  if let Ok(text) = str::from_utf8(haystack) {
+    let start = (start..=text.len()).find(|&at| text.is_char_boundary(at))?;
+    return find_at(text, start);
+  }
+  let mut offset = 0;
+  for chunk in haystack.utf8_chunks() {
+    let text = chunk.valid();
+    if start <= offset + text.len() {
+      let at = (start.saturating_sub(offset)..=text.len()).find(|&at| text.is_char_boundary(at))?;
+      if let Some((s, e)) = find_at(text, at) {
+        return Some((offset + s, offset + e));
+      }
+    }
+    offset += text.len() + chunk.invalid().len();
+  }
+  None
+}
+
+impl Matcher for Finder {
+  fn find_at(&self, haystack: &[u8], start: usize) -> Option<(usize, usize)> {
+    find_utf8(haystack, start, |text, at| Finder::find_at(self, text, at))
+  }
+
+  fn find_in_line(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+    Finder::find_at(self, line, start)
+  }
+
+  fn candidate(&self, haystack: &[u8], start: usize) -> Option<usize> {
+    Finder::candidate(self, haystack, start)
+  }
+}
+
+impl Matcher for Fuzzy {
+  fn find_at(&self, haystack: &[u8], start: usize) -> Option<(usize, usize)> {
+    find_utf8(haystack, start, |text, at| Fuzzy::find_at(self, text, at))
+  }
+
+  fn find_in_line(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+    Fuzzy::find_at(self, line, start)
+  }
+}
+
+impl Matcher for Regex {
+  fn find_at(&self, haystack: &[u8], start: usize) -> Option<(usize, usize)> {
+    Regex::find_at(self, haystack, start)
+  }
+}
+
+/// 只报告位置, 需要模式序号时直接使用 `AhoCorasick::find_at`
+impl Matcher for AhoCorasick {
+  fn find_at(&self, haystack: &[u8], start: usize) -> Option<(usize, usize)> {
+    AhoCorasick::find_at(self, haystack, start).map(|(s, e, _)| (s, e))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn matchers() {
+    let finder = Finder::new("CAFÉ", true);
+    assert_eq!(Some((3, 8)), Matcher::find(&finder, "un café".as_bytes()));
+    //非法字节两侧分别查找
+    assert_eq!(Some((5, 10)), Matcher::find_at(&finder, b"caf\xff caf\xc3\xa9", 1));
+    assert_eq!(None, Matcher::find(&finder, b"caf\xff\xc3\xa9"));
+    assert_eq!(Some((3, 8)), finder.find_in_line("un café", 3));
+
+    let matchers: Vec<Box<dyn Matcher>> = vec![
+      Box::new(Regex::new("d.ct", false).unwrap()),
+      Box::new(AhoCorasick::new(&["tape", "duct"])),
+      Box::new(Fuzzy::new("dict", 1, false)),
+    ];
+    for matcher in &matchers {
+      assert_eq!(Some((0, 4)), matcher.find(b"duct tape"));
+      assert_eq!(Some(3), matcher.candidate(b"duct tape", 3));
+      assert_eq!(None, matcher.candidate(b"duct", 4));
+    }
+  }
+}
@@ -0,0 +1,107 @@
+//! 只读的文件内存映射, 通过 extern 块直接调用 libc 的 mmap/munmap
+//! 映射期间如果文件被其他进程截断, 访问越界部分会收到 SIGBUS, 所以只在显式要求时使用
+use std::{fs::File, io, ops::Deref};
+
+pub struct Mmap {
+  ptr: *const u8,
+  len: usize,
+}
+
+// 映射是只读且私有的, 可以在线程间共享
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+#[cfg(all(unix, target_pointer_width = "64"))]
+mod sys {
+  use std::os::raw::{c_int, c_void};
+
+  pub const PROT_READ: c_int = 1;
+  pub const MAP_PRIVATE: c_int = 2;
+  pub const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+  extern "C" {
+    pub fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: i64) -> *mut c_void;
+    pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+  }
+}
+
+impl Mmap {
+  /// 把整个文件映射为只读的字节切片
+  ///
+  /// # Safety
+  ///
+  /// 映射存在期间文件不能被截断或改写: 截断后访问越界部分会收到 SIGBUS,
+  /// 改写则会让已经借出的 `&[u8]` 的内容发生变化, 两者都是未定义行为
+  #[cfg(all(unix, target_pointer_width = "64"))]
+  pub unsafe fn open(file: &File) -> io::Result<Mmap> {
+    use std::os::unix::io::AsRawFd;
+
+    let len = file.metadata()?.len() as usize;
+    //长度为 0 的映射是非法的
+    if len == 0 {
+      return Ok(Mmap {
+        ptr: std::ptr::NonNull::dangling().as_ptr(),
+        len: 0,
+      });
+    }
+    let ptr = sys::mmap(
+      std::ptr::null_mut(),
+      len,
+      sys::PROT_READ,
+      sys::MAP_PRIVATE,
+      file.as_raw_fd(),
+      0,
+    );
+    if ptr == sys::MAP_FAILED {
+      return Err(io::Error::last_os_error());
+    }
+    Ok(Mmap {
+      ptr: ptr as *const u8,
+      len,
+    })
+  }
+
+  /// # Safety
+  ///
+  /// 与 unix 上相同, 这里总是返回错误
+  #[cfg(not(all(unix, target_pointer_width = "64")))]
+  pub unsafe fn open(_file: &File) -> io::Result<Mmap> {
+    Err(io::Error::other("memory maps are not supported on this platform"))
+  }
+}
+
+impl Deref for Mmap {
+  type Target = [u8];
+
+  fn deref(&self) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+  }
+}
+
+impl Drop for Mmap {
+  fn drop(&mut self) {
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    unsafe {
+      if self.len > 0 {
+        sys::munmap(self.ptr as *mut _, self.len);
+      }
+    }
+  }
+}
+
+#[cfg(all(test, unix, target_pointer_width = "64"))]
+mod test {
+  use super::*;
+
+  #[test]
+  fn maps_file_contents() {
+    let file = File::open("poem.txt").unwrap();
+    let map = unsafe { Mmap::open(&file) }.unwrap();
+    assert_eq!(include_bytes!("../poem.txt"), &map[..]);
+
+    let path = std::env::temp_dir().join(format!("minigrep-mmap-{}", std::process::id()));
+    std::fs::write(&path, "").unwrap();
+    assert!(unsafe { Mmap::open(&File::open(&path).unwrap()) }.unwrap().is_empty());
+    std::fs::remove_file(path).unwrap();
+  }
+}
@@ -0,0 +1,119 @@
+//! 固定大小的线程池: 任务经由 mpsc 通道发送, 所有 worker 通过 Arc<Mutex<_>> 共享同一个接收端
+//! panic 的任务只结束它自己, worker 继续执行后面的任务
+use std::{
+  panic::{self, AssertUnwindSafe},
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    mpsc, Arc, Mutex,
+  },
+  thread,
+};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+struct Worker {
+  handle: Option<thread::JoinHandle<()>>,
+}
+
+impl Worker {
+  fn new(receiver: Arc<Mutex<mpsc::Receiver<Job>>>, panicked: Arc<AtomicUsize>) -> Worker {
+    let handle = thread::spawn(move || loop {
+      //锁只在取任务时持有, 执行任务时已经释放
+      let job = receiver.lock().unwrap_or_else(|e| e.into_inner()).recv();
+      match job {
+        Ok(job) => {
+          if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+            panicked.fetch_add(1, Ordering::Relaxed);
+          }
+        }
+        //发送端已关闭, 没有更多任务
+        Err(_) => break,
+      }
+    });
+    Worker {
+      handle: Some(handle),
+    }
+  }
+}
+
+pub struct ThreadPool {
+  workers: Vec<Worker>,
+  sender: Option<mpsc::Sender<Job>>,
+  panicked: Arc<AtomicUsize>,
+}
+
+impl ThreadPool {
+  /// `size` 为 0 时按 1 处理
+  pub fn new(size: usize) -> ThreadPool {
+    let (sender, receiver) = mpsc::channel();
+    let receiver = Arc::new(Mutex::new(receiver));
+    let panicked = Arc::new(AtomicUsize::new(0));
+    let workers = (0..size.max(1))
+      .map(|_| Worker::new(Arc::clone(&receiver), Arc::clone(&panicked)))
+      .collect();
+    ThreadPool {
+      workers,
+      sender: Some(sender),
+      panicked,
+    }
+  }
+
+  /// 到目前为止 panic 的任务数
+  pub fn panicked(&self) -> usize {
+    self.panicked.load(Ordering::Relaxed)
+  }
+
+  pub fn execute<F>(&self, f: F)
+  where
+    F: FnOnce() + Send + 'static,
+  {
+    self.sender.as_ref().unwrap().send(Box::new(f)).unwrap();
+  }
+}
+
+impl Drop for ThreadPool {
+  /// 关闭通道, 等待 worker 执行完剩余任务后退出
+  /// 任务的 panic 已经在 worker 中捕获, 这里不会再次 panic
+  fn drop(&mut self) {
+    drop(self.sender.take());
+    for worker in &mut self.workers {
+      if let Some(handle) = worker.handle.take() {
+        let _ = handle.join();
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn runs_every_job() {
+    let counter = Arc::new(Mutex::new(0));
+    {
+      let pool = ThreadPool::new(4);
+      for _ in 0..100 {
+        let counter = Arc::clone(&counter);
+        pool.execute(move || *counter.lock().unwrap() += 1);
+      }
+    }
+    assert_eq!(100, *counter.lock().unwrap());
+  }
+
+  #[test]
+  fn survives_panicking_jobs() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let pool = ThreadPool::new(2);
+    for i in 0..10 {
+      let counter = Arc::clone(&counter);
+      pool.execute(move || {
+        assert!(i % 3 != 0, "job {}", i);
+        counter.fetch_add(1, Ordering::Relaxed);
+      });
+    }
+    let panicked = Arc::clone(&pool.panicked);
+    drop(pool);
+    assert_eq!((6, 4), (counter.load(Ordering::Relaxed), panicked.load(Ordering::Relaxed)));
+  }
+}
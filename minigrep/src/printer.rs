@@ -0,0 +1,398 @@
+//! 标准输出格式: `路径:行号:列号:偏移:内容`, 上下文行用 `-` 分隔, 不连续的块之间输出 `--`
+//! `--json` 时每个事件输出一行 JSON: begin、match、context、end, 最后是 summary
+//! `absolute_offset` 是行首在输入中的字节偏移(UTF-16 输入按转换为 UTF-8 后的字节计),
+//! submatch 的 `start`/`end` 是 `text`(UTF-8) 中的字节偏移, 对按 Latin-1 解码的行两者不能直接相加
+//! `--sarif` 时收集所有匹配, 最后输出一份 SARIF 报告
+use crate::{json::Json, sarif, searcher::Line, searcher::Summary, Colors, Config, Error, Sink, Template, Unit};
+use std::{
+  io::{self, Write},
+  path::Path,
+};
+
+/// 每个文件输出什么
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputMode {
+  /// 选中的行及其上下文
+  #[default]
+  Lines,
+  /// 每个文件选中的行数
+  Count,
+  FilesWithMatches,
+  FilesWithoutMatch,
+  /// 不输出, 只通过退出码报告
+  Quiet,
+}
+
+pub(crate) struct Printer<'c, W> {
+  config: &'c Config,
+  colors: Option<Colors>,
+  template: Option<Template>,
+  out: W,
+  with_path: bool,
+  //上一个输出的行号, 不连续时用 `--` 分隔(与 GNU grep 一致)
+  last: Option<usize>,
+  printed: bool,
+  //逐行刷新输出, 让 `tail -f | minigrep` 及时看到结果
+  line_buffered: bool,
+  /// JSON 输出结尾的统计
+  stats: Stats,
+  /// SARIF 报告中的结果
+  results: Vec<Json>,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+  files: usize,
+  files_with_matches: usize,
+  matched_lines: usize,
+}
+
+impl<'c, W: Write> Printer<'c, W> {
+  pub(crate) fn new(config: &'c Config, out: W, with_path: bool, line_buffered: bool) -> Printer<'c, W> {
+    Printer {
+      config,
+      colors: if config.color.enabled() && !config.json && !config.sarif {
+        Some(Colors::from_env())
+      } else {
+        None
+      },
+      template: config.replace.as_deref().map(Template::new),
+      out,
+      with_path: with_path || config.vimgrep,
+      last: None,
+      printed: false,
+      line_buffered,
+      stats: Stats::default(),
+      results: vec![],
+    }
+  }
+
+  fn line(&mut self, file: &Path, line: &Line) -> io::Result<()> {
+    let config = self.config;
+    if config.json {
+      return self.json_line(file, line);
+    }
+    if config.sarif {
+      self.results.extend(sarif::results(&config.rules, file, line));
+      return Ok(());
+    }
+    if config.vimgrep && line.column.is_none() {
+      return Ok(());
+    }
+    let context = !config.vimgrep && (config.before > 0 || config.after > 0);
+    if context && self.printed && self.last.map(|last| last + 1) != Some(line.number) {
+      writeln!(self.out, "{}", self.paint(|c| &c.separator, "--"))?;
+    }
+
+    //替换后高亮替换进去的文本
+    let replaced = self.template.as_ref().map(|template| template.apply(&line.text, &line.ranges));
+    let (text, ranges) = match &replaced {
+      Some((text, ranges)) => (text, ranges),
+      None => (&line.text, &line.ranges),
+    };
+    let text = match &self.colors {
+      Some(colors) if !ranges.is_empty() => colors.highlight(text, ranges),
+      _ => text.to_string(),
+    };
+    //--vimgrep 时每个匹配输出一条记录, 列号是这个匹配的位置
+    let columns: Vec<Option<usize>> = if config.vimgrep && !line.ranges.is_empty() {
+      line.ranges.iter().map(|&(start, _)| Some(start + 1)).collect()
+    } else {
+      vec![line.column]
+    };
+    for column in columns {
+      writeln!(self.out, "{}{}", self.prefix(file, line, column), text)?;
+    }
+    self.last = Some(line.number);
+    self.printed = true;
+    if self.line_buffered {
+      self.out.flush()?;
+    }
+    Ok(())
+  }
+
+  fn binary_match(&mut self, file: &Path) -> io::Result<()> {
+    writeln!(self.out, "Binary file {} matches", file.display())?;
+    if self.line_buffered {
+      self.out.flush()?;
+    }
+    Ok(())
+  }
+
+  /// `-c` 的输出: 多个文件时是 `路径:数量`
+  fn count(&mut self, file: &Path, count: usize) -> io::Result<()> {
+    if self.with_path {
+      let path = self.paint(|c| &c.path, &file.display().to_string());
+      write!(self.out, "{}{}", path, self.paint(|c| &c.separator, ":"))?;
+    }
+    writeln!(self.out, "{}", count)
+  }
+
+  /// `-l`/`-L` 的输出
+  fn path(&mut self, file: &Path) -> io::Result<()> {
+    writeln!(self.out, "{}", self.paint(|c| &c.path, &file.display().to_string()))
+  }
+
+  /// `--rank` 的一个结果: 第一行以分数开头, 段落的其余行按上下文行输出, 段落之间用 `--` 分隔
+  pub(crate) fn ranked(&mut self, file: &Path, score: f64, lines: &[Line]) -> io::Result<()> {
+    if self.printed && self.config.unit == Unit::Paragraph {
+      writeln!(self.out, "{}", self.paint(|c| &c.separator, "--"))?;
+    }
+    for (i, line) in lines.iter().enumerate() {
+      let mut prefix = String::new();
+      let mut column = line.column;
+      if i == 0 {
+        prefix += &self.paint(|c| &c.line_number, &format!("{:.3}", score));
+        prefix += &self.paint(|c| &c.separator, ":");
+        column = Some(line.ranges.first().map_or(1, |&(start, _)| start + 1));
+      }
+      prefix += &self.prefix(file, line, column);
+      match &self.colors {
+        Some(colors) if !line.ranges.is_empty() => {
+          writeln!(self.out, "{}{}", prefix, colors.highlight(&line.text, &line.ranges))?
+        }
+        _ => writeln!(self.out, "{}{}", prefix, line.text)?,
+      }
+    }
+    self.printed = true;
+    Ok(())
+  }
+
+  pub(crate) fn finish(&mut self) -> io::Result<()> {
+    if self.config.json {
+      let stats = Json::Object(vec![
+        ("files", Json::from(self.stats.files)),
+        ("files_with_matches", Json::from(self.stats.files_with_matches)),
+        ("matched_lines", Json::from(self.stats.matched_lines)),
+      ]);
+      let summary = Json::Object(vec![("type", Json::from("summary")), ("stats", stats)]);
+      writeln!(self.out, "{}", summary)?;
+    }
+    if self.config.sarif {
+      let report = sarif::report(&self.config.rules, std::mem::take(&mut self.results));
+      writeln!(self.out, "{}", report.pretty())?;
+    }
+    self.out.flush()
+  }
+
+  /// 输出 `{"type": kind, "path": file, ...fields}`
+  fn event(&mut self, kind: &str, file: &Path, fields: Vec<(&'static str, Json)>) -> io::Result<()> {
+    let mut event = vec![
+      ("type", Json::from(kind)),
+      ("path", Json::from(&*file.to_string_lossy())),
+    ];
+    event.extend(fields);
+    writeln!(self.out, "{}", Json::Object(event))?;
+    if self.line_buffered {
+      self.out.flush()?;
+    }
+    Ok(())
+  }
+
+  /// 匹配行带有行内每个匹配的文本和在 `text` 中的字节区间, 替换时还有替换后的文本
+  fn json_line(&mut self, file: &Path, line: &Line) -> io::Result<()> {
+    let mut fields = vec![
+      ("line_number", Json::from(line.number)),
+      ("absolute_offset", Json::from(line.offset)),
+      ("text", Json::from(line.text.as_str())),
+    ];
+    if line.column.is_none() {
+      return self.event("context", file, fields);
+    }
+    let replaced = self.template.as_ref().map(|template| template.apply(&line.text, &line.ranges));
+    let submatches = line.ranges.iter().enumerate().map(|(i, &(start, end))| {
+      let mut submatch = vec![
+        ("match", Json::from(&line.text[start..end])),
+        ("start", Json::from(start)),
+        ("end", Json::from(end)),
+      ];
+      if let Some((text, ranges)) = &replaced {
+        submatch.push(("replacement", Json::from(&text[ranges[i].0..ranges[i].1])));
+      }
+      Json::Object(submatch)
+    });
+    fields.push(("submatches", Json::Array(submatches.collect())));
+    if let Some(distance) = line.distance {
+      fields.push(("distance", Json::from(distance)));
+    }
+    self.event("match", file, fields)
+  }
+
+  fn paint<F>(&self, code: F, text: &str) -> String
+  where
+    F: Fn(&Colors) -> &String,
+  {
+    match &self.colors {
+      Some(colors) => Colors::paint(code(colors), text),
+      None => text.to_string(),
+    }
+  }
+
+  /// 输出行的前缀: 路径、行号、列号和字节偏移, 匹配行以 `:` 分隔, 上下文行以 `-` 分隔
+  /// `column` 通常是 `line.column`, `--vimgrep` 时是每个匹配的列号
+  fn prefix(&self, file: &Path, line: &Line, column: Option<usize>) -> String {
+    let config = self.config;
+    let sep = self.paint(|c| &c.separator, if column.is_some() { ":" } else { "-" });
+    let mut prefix = String::new();
+    if self.with_path {
+      prefix += &self.paint(|c| &c.path, &file.display().to_string());
+      prefix += &sep;
+    }
+    if config.line_number || config.column || config.vimgrep {
+      prefix += &self.paint(|c| &c.line_number, &line.number.to_string());
+      prefix += &sep;
+    }
+    if let (true, Some(column)) = (config.column || config.vimgrep, column) {
+      prefix += &self.paint(|c| &c.line_number, &column.to_string());
+      prefix += &sep;
+    }
+    if config.byte_offset {
+      prefix += &self.paint(|c| &c.byte_offset, &line.offset.to_string());
+      prefix += &sep;
+    }
+    //编辑距离以 `~` 开头, 与列号区分
+    if let Some(distance) = line.distance {
+      prefix += &self.paint(|c| &c.line_number, &format!("~{}", distance));
+      prefix += &sep;
+    }
+    if let (true, Some(pattern)) = (config.show_pattern, line.pattern) {
+      prefix += &self.paint(|c| &c.matched, &config.patterns[pattern]);
+      prefix += &sep;
+    }
+    prefix
+  }
+}
+
+impl<'c, W: Write> Sink for Printer<'c, W> {
+  /// 开始输出一个新文件
+  fn begin(&mut self, file: &Path) -> io::Result<()> {
+    self.last = None;
+    if !self.config.json {
+      return Ok(());
+    }
+    self.event("begin", file, vec![])
+  }
+
+  fn matched(&mut self, file: &Path, line: &Line) -> io::Result<()> {
+    self.line(file, line)
+  }
+
+  fn context(&mut self, file: &Path, line: &Line) -> io::Result<()> {
+    self.line(file, line)
+  }
+
+  /// 一个文件输出完毕, `-c`/`-l`/`-L` 在这里输出
+  fn end(&mut self, file: &Path, summary: &Summary) -> io::Result<()> {
+    self.stats.files += 1;
+    self.stats.files_with_matches += usize::from(summary.matches > 0);
+    self.stats.matched_lines += summary.matches;
+    match self.config.output {
+      OutputMode::Count => self.count(file, summary.matches),
+      OutputMode::FilesWithMatches if summary.matches > 0 => self.path(file),
+      OutputMode::FilesWithoutMatch if summary.matches == 0 => self.path(file),
+      OutputMode::Lines if self.config.json => {
+        let fields = vec![
+          ("binary_match", Json::from(summary.binary_match)),
+          ("stats", Json::Object(vec![("matched_lines", Json::from(summary.matches))])),
+        ];
+        self.event("end", file, fields)
+      }
+      OutputMode::Lines if summary.binary_match && !self.config.sarif => self.binary_match(file),
+      _ => Ok(()),
+    }
+  }
+
+  /// 与 grep 一致, 输出到标准错误, 之前的输出先刷新以保持顺序
+  fn error(&mut self, error: &Error) -> io::Result<()> {
+    self.out.flush()?;
+    writeln!(io::stderr(), "minigrep: {}", error.report())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{searcher::Searcher, test::parse};
+
+  /// 用 `args` 搜索 `contents`, 当作文件 `file` 输出
+  fn output<C: AsRef<[u8]>>(args: &[&str], file: &str, contents: C) -> String {
+    let args = ["minigrep"].iter().chain(args).map(|s| s.to_string());
+    let config = parse(args).unwrap();
+    let searcher = Searcher::new(&config).unwrap();
+    let mut lines = vec![];
+    let summary = searcher
+      .search_slice(contents.as_ref(), &config.search_options(), |line| {
+        lines.push(line);
+        Ok(())
+      })
+      .unwrap();
+    let mut printer = Printer::new(&config, vec![], false, false);
+    let file = Path::new(file);
+    printer.begin(file).unwrap();
+    for line in &lines {
+      printer.line(file, line).unwrap();
+    }
+    printer.end(file, &summary).unwrap();
+    printer.finish().unwrap();
+    String::from_utf8(printer.out).unwrap()
+  }
+
+  fn json_output(args: &[&str], contents: &str) -> Vec<String> {
+    let args: Vec<&str> = ["--json"].iter().chain(args).copied().collect();
+    output(&args, "poem \"1\".txt", contents).lines().map(String::from).collect()
+  }
+
+  #[test]
+  fn json_lines() {
+    //Latin-1 的 é 在输入中是一个字节, 在 `text` 中是两个字节
+    let latin1 = output(&["--json", "rust"], "latin1.txt", b"x\ncaf\xe9 rust\n");
+    assert!(latin1.contains(r#""absolute_offset":2,"text":"café rust","submatches":[{"match":"rust","start":6,"end":10}]"#));
+
+    let output = json_output(&["-A1", "--color=always", "-r", "<$0>", "rust"], "Rust:\ttrust\nsafe\nnothing\n");
+    assert_eq!(
+      vec![
+        r#"{"type":"begin","path":"poem \"1\".txt"}"#,
+        concat!(
+          r#"{"type":"match","path":"poem \"1\".txt","line_number":1,"absolute_offset":0,"text":"Rust:\ttrust","#,
+          r#""submatches":[{"match":"Rust","start":0,"end":4,"replacement":"<Rust>"},"#,
+          r#"{"match":"rust","start":7,"end":11,"replacement":"<rust>"}]}"#
+        ),
+        r#"{"type":"context","path":"poem \"1\".txt","line_number":2,"absolute_offset":12,"text":"safe"}"#,
+        r#"{"type":"end","path":"poem \"1\".txt","binary_match":false,"stats":{"matched_lines":1}}"#,
+        r#"{"type":"summary","stats":{"files":1,"files_with_matches":1,"matched_lines":1}}"#,
+      ],
+      output
+    );
+
+    let output = json_output(&["--fuzzy=1", "rusty"], "trust me\n");
+    assert!(output[1].ends_with(r#""submatches":[{"match":"rust ","start":1,"end":6}],"distance":1}"#));
+
+    let err = parse(["minigrep", "--json", "-c", "q"].iter().map(|s| s.to_string())).unwrap_err();
+    assert_eq!("--json and --sarif can't be combined with -c, -l, -L or -q", err.to_string());
+  }
+
+  #[test]
+  fn vimgrep_records() {
+    let output = output(&["--vimgrep", "you"], "a.txt", "you and you\nme\n");
+    assert_eq!("a.txt:1:1:you and you\na.txt:1:9:you and you\n", output);
+  }
+
+  #[test]
+  fn sarif_report() {
+    let args = ["--sarif", "-C1", "--rules", "testdata/lint.rules", "poem.txt"];
+    let report = output(&args, "poem.txt", include_str!("../poem.txt"));
+    assert_eq!(include_str!("../testdata/poem.sarif"), report);
+  }
+
+  #[test]
+  fn sarif_rule_ids_are_unique() {
+    let path = std::env::temp_dir().join(format!("minigrep-rules-{}", std::process::id()));
+    std::fs::write(&path, "[pattern-3]\npattern = x\n").unwrap();
+    let err = |args: &[&str]| parse(args.iter().map(|s| s.to_string())).unwrap_err().to_string();
+    let duplicate = err(&["minigrep", "-e", "a", "--rules", path.to_str().unwrap(), "-e", "b"]);
+    std::fs::remove_file(&path).unwrap();
+    assert!(duplicate.starts_with("rule id 'pattern-3' is used twice"));
+    let rules = "testdata/lint.rules";
+    assert!(err(&["minigrep", "--rules", rules, "--rules", rules]).starts_with("rule id 'no-nobody' is used twice"));
+  }
+}
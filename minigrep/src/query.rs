@@ -0,0 +1,520 @@
+//! `--query` 的查询语言: AND/OR/NOT、括号、引号中的短语和每个词的修饰符, 对每一行求值
+//!
+//! ```text
+//! ERROR AND NOT (timeout OR "connection reset")
+//! w:fail s:Warn r:"err(or)?[0-9]+"
+//! ```
+//!
+//! 相邻的词之间默认是 AND, 优先级从高到低是 NOT、AND、OR, 关键字必须大写
+//! 修饰符写在词或短语前面, 以 `:` 结束: `i` 忽略大小写, `s` 区分大小写, `w` 整词, `x` 整行, `r` 正则, `l` 字面量
+//! 前缀不全是修饰符时 `:` 是词的一部分, 要搜索 `w:x` 本身时加上引号
+use crate::{find_bounded, unicode, Boundary, Case, Config, Error, Finder, Matcher, Regex};
+use std::{error, fmt};
+
+/// 查询的语法树
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+  Term(Term),
+  Not(Box<Query>),
+  And(Vec<Query>),
+  Or(Vec<Query>),
+}
+
+/// 一个词或短语, 没有给出的修饰符按命令行选项
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Term {
+  pub text: String,
+  /// `i` 或 `s`
+  pub case: Option<Case>,
+  /// `w` 或 `x`
+  pub boundary: Option<Boundary>,
+  /// `r` 或 `l`
+  pub regex: Option<bool>,
+  /// 词在查询中的列号, 从 1 开始按字符计
+  pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+  pub message: String,
+  /// 出错位置的列号, 从 1 开始按字符计, 查询意外结束时是最后一个字符之后
+  pub column: usize,
+}
+
+impl QueryError {
+  fn new(message: &str, column: usize) -> QueryError {
+    QueryError {
+      message: message.to_string(),
+      column,
+    }
+  }
+}
+
+impl fmt::Display for QueryError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} at column {}", self.message, self.column)
+  }
+}
+
+impl error::Error for QueryError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Open,
+  Close,
+  And,
+  Or,
+  Not,
+  Term(Term),
+}
+
+/// 把查询切分为 (列号, 记号)
+fn lex(query: &str) -> Result<Vec<(usize, Token)>, QueryError> {
+  let chars: Vec<char> = query.chars().collect();
+  let mut tokens = vec![];
+  let mut i = 0;
+  while i < chars.len() {
+    let column = i + 1;
+    match chars[i] {
+      c if c.is_whitespace() => i += 1,
+      '(' => {
+        tokens.push((column, Token::Open));
+        i += 1;
+      }
+      ')' => {
+        tokens.push((column, Token::Close));
+        i += 1;
+      }
+      '"' => {
+        let mut term = Term {
+          column,
+          ..Term::default()
+        };
+        i = phrase(&chars, i, &mut term.text)?;
+        tokens.push((column, Token::Term(term)));
+      }
+      _ => {
+        let start = i;
+        while i < chars.len() && !chars[i].is_whitespace() && !"()\"".contains(chars[i]) {
+          i += 1;
+        }
+        let word: String = chars[start..i].iter().collect();
+        let token = match word.as_str() {
+          "AND" => Token::And,
+          "OR" => Token::Or,
+          "NOT" => Token::Not,
+          _ => {
+            let mut term = Term {
+              column,
+              ..Term::default()
+            };
+            match word.split_once(':') {
+              Some((mods, text)) if is_modifiers(mods) && (!text.is_empty() || chars.get(i) == Some(&'"')) => {
+                modifiers(mods, &mut term)?;
+                term.text = text.to_string();
+                if text.is_empty() {
+                  i = phrase(&chars, i, &mut term.text)?;
+                }
+              }
+              _ => term.text = word,
+            }
+            Token::Term(term)
+          }
+        };
+        tokens.push((column, token));
+      }
+    }
+  }
+  Ok(tokens)
+}
+
+/// 读取从 `chars[open]` 的引号开始的短语, `\"` 和 `\\` 是转义, 返回短语之后的位置
+fn phrase(chars: &[char], open: usize, text: &mut String) -> Result<usize, QueryError> {
+  let mut i = open + 1;
+  loop {
+    match chars.get(i) {
+      None => return Err(QueryError::new("unclosed quote", open + 1)),
+      Some('"') => return Ok(i + 1),
+      Some('\\') if matches!(chars.get(i + 1), Some('"') | Some('\\')) => {
+        text.push(chars[i + 1]);
+        i += 2;
+      }
+      Some(&c) => {
+        text.push(c);
+        i += 1;
+      }
+    }
+  }
+}
+
+fn is_modifiers(mods: &str) -> bool {
+  !mods.is_empty() && mods.chars().all(|c| "iswxrl".contains(c))
+}
+
+/// 设置 `term` 的修饰符, 同一类修饰符互相冲突
+fn modifiers(mods: &str, term: &mut Term) -> Result<(), QueryError> {
+  fn set<T: PartialEq>(slot: &mut Option<T>, value: T, column: usize) -> Result<(), QueryError> {
+    match slot {
+      Some(old) if *old != value => Err(QueryError::new("conflicting modifiers", column)),
+      _ => {
+        *slot = Some(value);
+        Ok(())
+      }
+    }
+  }
+  for (i, c) in mods.chars().enumerate() {
+    let column = term.column + i;
+    match c {
+      'i' => set(&mut term.case, Case::Insensitive, column)?,
+      's' => set(&mut term.case, Case::Sensitive, column)?,
+      'w' => set(&mut term.boundary, Boundary::Word, column)?,
+      'x' => set(&mut term.boundary, Boundary::Line, column)?,
+      'r' => set(&mut term.regex, true, column)?,
+      _ => set(&mut term.regex, false, column)?,
+    }
+  }
+  Ok(())
+}
+
+struct Parser {
+  tokens: Vec<(usize, Token)>,
+  pos: usize,
+  /// 查询结束的列号
+  end: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.pos).map(|(_, token)| token)
+  }
+
+  fn column(&self) -> usize {
+    self.tokens.get(self.pos).map_or(self.end, |&(column, _)| column)
+  }
+
+  fn eat(&mut self, want: &Token) -> bool {
+    if self.peek() == Some(want) {
+      self.pos += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn parse_or(&mut self) -> Result<Query, QueryError> {
+    let mut branches = vec![self.parse_and()?];
+    while self.eat(&Token::Or) {
+      branches.push(self.parse_and()?);
+    }
+    Ok(if branches.len() == 1 {
+      branches.pop().unwrap()
+    } else {
+      Query::Or(branches)
+    })
+  }
+
+  fn parse_and(&mut self) -> Result<Query, QueryError> {
+    let mut operands = vec![self.parse_not()?];
+    loop {
+      //相邻的词之间省略了 AND
+      match self.peek() {
+        Some(Token::And) => self.pos += 1,
+        Some(Token::Term(_)) | Some(Token::Not) | Some(Token::Open) => {}
+        _ => break,
+      }
+      operands.push(self.parse_not()?);
+    }
+    Ok(if operands.len() == 1 {
+      operands.pop().unwrap()
+    } else {
+      Query::And(operands)
+    })
+  }
+
+  fn parse_not(&mut self) -> Result<Query, QueryError> {
+    if self.eat(&Token::Not) {
+      return Ok(Query::Not(Box::new(self.parse_not()?)));
+    }
+    let column = self.column();
+    match self.tokens.get(self.pos).map(|(_, token)| token.clone()) {
+      Some(Token::Open) => {
+        self.pos += 1;
+        let query = self.parse_or()?;
+        if !self.eat(&Token::Close) {
+          return Err(QueryError::new("unclosed parenthesis", column));
+        }
+        Ok(query)
+      }
+      Some(Token::Term(term)) if term.text.is_empty() => Err(QueryError::new("empty term", column)),
+      Some(Token::Term(term)) => {
+        self.pos += 1;
+        Ok(Query::Term(term))
+      }
+      _ => Err(QueryError::new("expected a term", column)),
+    }
+  }
+}
+
+impl Query {
+  pub fn parse(query: &str) -> Result<Query, QueryError> {
+    let mut parser = Parser {
+      tokens: lex(query)?,
+      pos: 0,
+      end: query.chars().count() + 1,
+    };
+    let parsed = parser.parse_or()?;
+    match parser.peek() {
+      None => Ok(parsed),
+      //其他记号都会被当作省略了 AND 的下一个操作数
+      Some(_) => Err(QueryError::new("unmatched ')'", parser.column())),
+    }
+  }
+
+  /// 按先序排列的所有词, 以及它们是否在偶数个 NOT 之下
+  fn terms(&self, positive: bool, out: &mut Vec<(Term, bool)>) {
+    match self {
+      Query::Term(term) => out.push((term.clone(), positive)),
+      Query::Not(query) => query.terms(!positive, out),
+      Query::And(queries) | Query::Or(queries) => queries.iter().for_each(|query| query.terms(positive, out)),
+    }
+  }
+
+  /// `found[i]` 是先序第 i 个词是否出现在行中, `next` 是下一个词的序号
+  fn eval(&self, found: &[bool], next: &mut usize) -> bool {
+    match self {
+      Query::Term(_) => {
+        *next += 1;
+        found[*next - 1]
+      }
+      Query::Not(query) => !query.eval(found, next),
+      Query::And(queries) | Query::Or(queries) => {
+        //不能短路, 每个分支都要让 `next` 越过其中的词
+        let values: Vec<bool> = queries.iter().map(|query| query.eval(found, next)).collect();
+        match self {
+          Query::And(_) => values.iter().all(|&value| value),
+          _ => values.iter().any(|&value| value),
+        }
+      }
+    }
+  }
+}
+
+/// 词的文本在查询中开始的列号: 跳过修饰符和开头的引号
+fn text_column(query: &str, term: &Term) -> usize {
+  let rest: Vec<char> = query.chars().skip(term.column - 1).collect();
+  let mut skip = 0;
+  if term.case.is_some() || term.boundary.is_some() || term.regex.is_some() {
+    skip = rest.iter().position(|&c| c == ':').map_or(0, |i| i + 1);
+  }
+  if rest.get(skip) == Some(&'"') {
+    skip += 1;
+  }
+  term.column + skip
+}
+
+/// 一个词的匹配方式
+struct Leaf {
+  matcher: Box<dyn Matcher + Send + Sync>,
+  boundary: Boundary,
+  /// 肯定的词, 匹配时会被高亮
+  positive: bool,
+}
+
+/// 编译后的查询, 每个词按命令行选项和它的修饰符生成匹配器
+pub(crate) struct Compiled {
+  query: Query,
+  leaves: Vec<Leaf>,
+}
+
+impl Compiled {
+  /// 语法错误和无效的正则都报告为 `Error::Query`
+  pub(crate) fn new(text: &str, config: &Config) -> Result<Compiled, Error> {
+    let invalid = |source| Error::Query {
+      query: text.to_string(),
+      source,
+    };
+    let query = Query::parse(text).map_err(invalid)?;
+    let mut terms = vec![];
+    query.terms(true, &mut terms);
+    let mut leaves = vec![];
+    for (term, positive) in terms {
+      let pattern = match config.normalization {
+        Some(form) => unicode::normalize(&term.text, form),
+        None => term.text.clone(),
+      };
+      let regex = term.regex.unwrap_or(config.regex);
+      let sensitive = match term.case {
+        Some(case) => case == Case::Sensitive,
+        None => config.case.is_sensitive(&pattern, regex),
+      };
+      let boundary = term.boundary.unwrap_or(config.boundary);
+      let matcher: Box<dyn Matcher + Send + Sync> = if regex {
+        //与 `Searcher::new` 相同, 整行匹配时锚定整个模式
+        let (anchored, prefix) = match boundary {
+          Boundary::Line => (format!("^(?:{})$", pattern), "^(?:".len()),
+          _ => (pattern.clone(), 0),
+        };
+        let re = Regex::new(&anchored, !sensitive).map_err(|e| {
+          //正则中的错误位置换算为查询中的列号, 只减去开头添加的部分
+          let at = e.offset.saturating_sub(prefix).min(pattern.len());
+          let at = (0..=at).rev().find(|&at| pattern.is_char_boundary(at)).unwrap_or(0);
+          invalid(QueryError::new(&e.message, text_column(text, &term) + pattern[..at].chars().count()))
+        })?;
+        Box::new(re)
+      } else {
+        Box::new(Finder::with_fold(&pattern, Some(config.fold).filter(|_| !sensitive)))
+      };
+      leaves.push(Leaf {
+        matcher,
+        boundary,
+        positive,
+      });
+    }
+    Ok(Compiled { query, leaves })
+  }
+
+  fn find_leaf(&self, leaf: &Leaf, line: &str, start: usize) -> Option<(usize, usize)> {
+    let find_at = |at| leaf.matcher.find_in_line(line, at).map(|(s, e)| (s, e, 0));
+    find_bounded(line, start, leaf.boundary, find_at).map(|(s, e, _)| (s, e))
+  }
+
+  /// 每个词是否出现在行中, 行不满足查询时返回 None
+  fn eval(&self, line: &str) -> Option<Vec<bool>> {
+    let found: Vec<bool> = self.leaves.iter().map(|leaf| self.find_leaf(leaf, line, 0).is_some()).collect();
+    Some(found).filter(|found| self.query.eval(found, &mut 0))
+  }
+
+  /// 行满足查询时, 从 `start` 开始最左(同一位置取最长)的肯定词的匹配
+  /// 行首之后已经没有这样的匹配时(例如只有 NOT 的查询)在行首返回空匹配
+  pub(crate) fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+    let found = self.eval(line)?;
+    let first = self
+      .leaves
+      .iter()
+      .zip(&found)
+      .filter(|&(leaf, &found)| leaf.positive && found)
+      .filter_map(|(leaf, _)| self.find_leaf(leaf, line, start))
+      .min_by_key(|&(s, e)| (s, std::cmp::Reverse(e)));
+    match first {
+      Some(found) => Some(found),
+      None if start == 0 => Some((0, 0)),
+      None => None,
+    }
+  }
+
+  /// 行满足查询时所有肯定词的非空匹配, 按起点排序, 可能互相重叠
+  /// 查询只求值一次, 每个词从行首开始各自查找
+  pub(crate) fn find_all(&self, line: &str) -> Vec<(usize, usize)> {
+    let found = match self.eval(line) {
+      Some(found) => found,
+      None => return vec![],
+    };
+    let mut ranges = vec![];
+    for (leaf, _) in self.leaves.iter().zip(&found).filter(|&(leaf, &found)| leaf.positive && found) {
+      let mut at = 0;
+      while let Some((start, end)) = self.find_leaf(leaf, line, at) {
+        if end > start {
+          ranges.push((start, end));
+          at = end;
+        } else {
+          //空匹配时前进一个字符, 避免死循环
+          match line[end..].chars().next() {
+            Some(c) => at = end + c.len_utf8(),
+            None => break,
+          }
+        }
+      }
+    }
+    ranges.sort_by_key(|&(s, e)| (s, std::cmp::Reverse(e)));
+    ranges
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn term(text: &str, column: usize) -> Query {
+    Query::Term(Term {
+      text: text.to_string(),
+      column,
+      ..Term::default()
+    })
+  }
+
+  #[test]
+  fn parse_precedence() {
+    //NOT 优先于 AND, AND 优先于 OR, 相邻的词是 AND
+    assert_eq!(
+      Query::Or(vec![
+        Query::And(vec![term("a", 1), Query::Not(Box::new(term("b", 11)))]),
+        term("c", 16),
+      ]),
+      Query::parse("a AND NOT b OR c").unwrap()
+    );
+    assert_eq!(
+      Query::And(vec![term("a", 1), Query::Or(vec![term("b c", 4), term("d", 13)])]),
+      Query::parse(r#"a ("b c" OR d)"#).unwrap()
+    );
+  }
+
+  #[test]
+  fn parse_modifiers() {
+    let terms = |query: &str| {
+      let mut terms = vec![];
+      Query::parse(query).unwrap().terms(true, &mut terms);
+      terms
+    };
+    let parsed = terms(r#"wi:Fail r:"a\"b" http://x NOT is"#);
+    assert_eq!(
+      (Some(Case::Insensitive), Some(Boundary::Word), "Fail"),
+      (parsed[0].0.case, parsed[0].0.boundary, parsed[0].0.text.as_str())
+    );
+    assert_eq!((Some(true), "a\"b"), (parsed[1].0.regex, parsed[1].0.text.as_str()));
+    assert_eq!(("http://x", None), (parsed[2].0.text.as_str(), parsed[2].0.regex));
+    assert_eq!(("is", false), (parsed[3].0.text.as_str(), parsed[3].1));
+  }
+
+  #[test]
+  fn errors_point_at_columns() {
+    let error = |query: &str| Query::parse(query).unwrap_err();
+    assert_eq!(QueryError::new("expected a term", 10), error("ERROR AND"));
+    assert_eq!(QueryError::new("unclosed parenthesis", 7), error("ERROR (timeout OR x"));
+    assert_eq!(QueryError::new("unmatched ')'", 2), error("a) b"));
+    assert_eq!(QueryError::new("unclosed quote", 3), error(r#"a "b c"#));
+    assert_eq!(QueryError::new("conflicting modifiers", 2), error("is:open"));
+    assert_eq!(QueryError::new("empty term", 1), error(r#""""#));
+    assert_eq!("expected a term at column 1", error("").to_string());
+  }
+
+  #[test]
+  fn evaluate_lines() {
+    let config = Config::default();
+    //按智能大小写, 全小写的词忽略大小写
+    let query = Compiled::new("error AND NOT (timeout OR w:retry)", &config).unwrap();
+    assert_eq!(Some((0, 5)), query.find_at("ERROR: disk full", 0));
+    assert_eq!(Some((3, 8)), query.find_at("an error occurred", 0));
+    assert_eq!(None, query.find_at("error: timeout", 0));
+    //整词修饰符: `retrying` 不算 `retry`
+    assert_eq!(Some((0, 5)), query.find_at("error, retrying", 0));
+    assert_eq!(None, query.find_at("error, retry", 0));
+    assert_eq!(None, query.find_at("all good", 0));
+
+    //只有否定的查询匹配时返回行首的空匹配
+    let query = Compiled::new("NOT s:Debug", &config).unwrap();
+    assert_eq!(Some((0, 0)), query.find_at("debug", 0));
+    assert_eq!(None, query.find_at("Debug", 0));
+    assert_eq!(None, query.find_at("debug", 1));
+
+    let err = Compiled::new(r#"a r:"x(y""#, &config).err().unwrap();
+    assert_eq!("invalid query 'a r:\"x(y\"': unclosed group at column 7", err.report());
+    //整行匹配时在模式外添加的 `^(?:` 不计入列号
+    let err = Compiled::new("a rx:b**", &config).err().unwrap();
+    assert_eq!("invalid query 'a rx:b**': repetition operator repeated at column 8", err.report());
+
+    //每个肯定词的所有匹配, 否定的词不高亮
+    let query = Compiled::new("error OR warn AND NOT ok", &config).unwrap();
+    assert_eq!(vec![(0, 5), (7, 11), (12, 17)], query.find_all("error: warn error"));
+    assert!(query.find_all("warn ok").is_empty());
+  }
+}
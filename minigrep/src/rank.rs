@@ -0,0 +1,216 @@
+//! `--rank`: 把行或空行分隔的段落当作文档, 用 BM25 按与查询词的相关度排序
+use crate::{
+  regex::is_word_char,
+  unicode::{self, Fold, Normalization},
+};
+use std::{mem, ops::Range};
+
+/// 词频的饱和速度
+const K1: f64 = 1.2;
+/// 文档长度对分数的影响程度
+const B: f64 = 0.75;
+
+/// 排序的文档单位
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Unit {
+  #[default]
+  Line,
+  /// 连续的非空行, 只含空白的行也是分隔
+  Paragraph,
+}
+
+/// 把文本切分为词: 连续的单词字符, 规范化并折叠大小写后比较, 与 `-i` 的方式相同
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tokenizer {
+  pub fold: Fold,
+  pub normalization: Option<Normalization>,
+}
+
+impl Tokenizer {
+  /// 每个词在 `text` 中的字节区间及其比较用的形式
+  pub fn tokens(&self, text: &str) -> Vec<(usize, usize, String)> {
+    let mut tokens = vec![];
+    let mut start = None;
+    for (i, c) in text.char_indices().chain(Some((text.len(), ' '))) {
+      match (start, is_word_char(c)) {
+        (None, true) => start = Some(i),
+        (Some(s), false) => {
+          tokens.push((s, i, self.term(&text[s..i])));
+          start = None;
+        }
+        _ => {}
+      }
+    }
+    tokens
+  }
+
+  fn term(&self, word: &str) -> String {
+    match self.normalization {
+      Some(form) => unicode::fold_str(&unicode::normalize(word, form), self.fold),
+      None => unicode::fold_str(word, self.fold),
+    }
+  }
+}
+
+/// 依次加入文档, 最后取出分数最高的几个
+/// 只保留包含查询词的文档, 其他文档只计入文档数和平均长度
+pub struct Ranker<D> {
+  tokenizer: Tokenizer,
+  /// 去重后的查询词
+  terms: Vec<String>,
+  documents: usize,
+  total_length: usize,
+  /// 包含查询词的文档、它的词数和每个查询词的词频
+  candidates: Vec<(D, usize, Vec<usize>)>,
+}
+
+impl<D> Ranker<D> {
+  /// `query` 中每个模式的所有词都是查询词
+  pub fn new<S: AsRef<str>>(query: &[S], tokenizer: Tokenizer) -> Ranker<D> {
+    let mut terms: Vec<String> = vec![];
+    for pattern in query {
+      for (_, _, term) in tokenizer.tokens(pattern.as_ref()) {
+        if !terms.contains(&term) {
+          terms.push(term);
+        }
+      }
+    }
+    Ranker {
+      tokenizer,
+      terms,
+      documents: 0,
+      total_length: 0,
+      candidates: vec![],
+    }
+  }
+
+  /// 加入内容为 `text` 的文档
+  pub fn add(&mut self, document: D, text: &str) {
+    let tokens = self.tokenizer.tokens(text);
+    self.documents += 1;
+    self.total_length += tokens.len();
+    let mut frequencies = vec![0; self.terms.len()];
+    for (_, _, token) in &tokens {
+      if let Some(i) = self.terms.iter().position(|term| term == token) {
+        frequencies[i] += 1;
+      }
+    }
+    if frequencies.iter().any(|&n| n > 0) {
+      self.candidates.push((document, tokens.len(), frequencies));
+    }
+  }
+
+  /// `text` 中查询词的字节区间, 用于高亮
+  pub fn matches(&self, text: &str) -> Vec<(usize, usize)> {
+    let tokens = self.tokenizer.tokens(text);
+    tokens
+      .into_iter()
+      .filter(|(_, _, token)| self.terms.contains(token))
+      .map(|(start, end, _)| (start, end))
+      .collect()
+  }
+
+  /// 按分数从高到低取出前 `top` 个文档, 分数相同时先加入的在前
+  pub fn top(&mut self, top: usize) -> Vec<(f64, D)> {
+    let n = self.documents as f64;
+    let average = self.total_length as f64 / n.max(1.0);
+    //包含每个查询词的文档数
+    let mut containing = vec![0usize; self.terms.len()];
+    for (_, _, frequencies) in &self.candidates {
+      for (count, &tf) in containing.iter_mut().zip(frequencies) {
+        *count += usize::from(tf > 0);
+      }
+    }
+    let idf: Vec<f64> = containing
+      .iter()
+      .map(|&df| (1.0 + (n - df as f64 + 0.5) / (df as f64 + 0.5)).ln())
+      .collect();
+    let mut scored: Vec<(f64, D)> = mem::take(&mut self.candidates)
+      .into_iter()
+      .map(|(document, length, frequencies)| {
+        let norm = K1 * (1.0 - B + B * length as f64 / average);
+        let score = frequencies
+          .iter()
+          .zip(&idf)
+          .map(|(&tf, idf)| idf * tf as f64 * (K1 + 1.0) / (tf as f64 + norm))
+          .sum();
+        (score, document)
+      })
+      .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.truncate(top);
+    scored
+  }
+}
+
+/// 把各行划分为文档, 返回每个文档的行区间
+pub fn split<S: AsRef<str>>(lines: &[S], unit: Unit) -> Vec<Range<usize>> {
+  match unit {
+    Unit::Line => (0..lines.len()).map(|i| i..i + 1).collect(),
+    Unit::Paragraph => {
+      let mut documents = vec![];
+      let mut start = None;
+      for (i, line) in lines.iter().enumerate() {
+        match (start, line.as_ref().trim().is_empty()) {
+          (None, false) => start = Some(i),
+          (Some(s), true) => {
+            documents.push(s..i);
+            start = None;
+          }
+          _ => {}
+        }
+      }
+      documents.extend(start.map(|s| s..lines.len()));
+      documents
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn tokens_fold_case() {
+    let tokens = Tokenizer::default().tokens("Straße, STRASSE_2 ok");
+    let terms: Vec<&str> = tokens.iter().map(|(_, _, term)| term.as_str()).collect();
+    assert_eq!(vec!["strasse", "strasse_2", "ok"], terms);
+    assert_eq!((0, 7), (tokens[0].0, tokens[0].1));
+  }
+
+  #[test]
+  fn bm25() {
+    let docs = [
+      "the frog and the bog",
+      "a frog, a frog, a frog",
+      "nobody here",
+      "the public frog that tells its name to an admiring bog all the livelong day",
+    ];
+    let mut ranker = Ranker::new(&["FROG bog"], Tokenizer::default());
+    for (i, doc) in docs.iter().enumerate() {
+      ranker.add(i, doc);
+    }
+    assert_eq!(vec![(3, 7)], ranker.matches("my Frog"));
+    let top = ranker.top(3);
+    let order: Vec<usize> = top.iter().map(|&(_, doc)| doc).collect();
+    //两个词都有的短文档最相关, 同样的词出现在更长的文档中分数更低
+    assert_eq!(vec![0, 3, 1], order);
+    assert!(top.windows(2).all(|w| w[0].0 > w[1].0));
+
+    //词频高的文档分数更高, 但增长会饱和
+    let mut ranker = Ranker::new(&["frog"], Tokenizer::default());
+    ranker.add(1, "frog toad newt");
+    ranker.add(3, "frog frog frog");
+    ranker.add(0, "toad newt salamander");
+    let top = ranker.top(10);
+    assert_eq!(vec![3, 1], top.iter().map(|&(_, doc)| doc).collect::<Vec<_>>());
+    assert!(top[0].0 < 3.0 * top[1].0);
+  }
+
+  #[test]
+  fn paragraphs() {
+    let lines = ["", "one", "two", "  ", "three", "", "", "four"];
+    assert_eq!(vec![1..3, 4..5, 7..8], split(&lines, Unit::Paragraph));
+    assert_eq!(8, split(&lines, Unit::Line).len());
+  }
+}
@@ -0,0 +1,773 @@
+//! 一个小型正则引擎: 解析 -> 编译为指令 -> Pike VM 执行
+//! 支持字符类、选择(|)、重复(* + ? {m,n})、锚点(^ $ \b \B)和分组
+//! Pike VM 同时推进所有线程, 不会出现回溯引擎的指数级耗时
+use crate::unicode::{self, Fold};
+use std::{error::Error, fmt};
+
+/// 单次编译允许的最大指令数, 防止 `(a{1000}){1000}` 一类的模式耗尽内存
+const MAX_PROGRAM_SIZE: usize = 100_000;
+const MAX_REPEAT: u32 = 1_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+  pub message: String,
+  /// 出错位置在模式串中的字节偏移
+  pub offset: usize,
+}
+
+impl ParseError {
+  fn new(message: &str, offset: usize) -> ParseError {
+    ParseError {
+      message: message.to_string(),
+      offset,
+    }
+  }
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} at offset {}", self.message, self.offset)
+  }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Look {
+  Start,
+  End,
+  WordBoundary,
+  NotWordBoundary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Perl {
+  Digit,
+  Word,
+  Space,
+}
+
+impl Perl {
+  fn matches(self, c: char) -> bool {
+    match self {
+      Perl::Digit => c.is_numeric(),
+      Perl::Word => is_word_char(c),
+      Perl::Space => c.is_whitespace(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+  Range(char, char),
+  Perl(Perl, bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Class {
+  items: Vec<ClassItem>,
+  negated: bool,
+}
+
+impl Class {
+  fn contains(&self, c: char) -> bool {
+    self.items.iter().any(|item| match *item {
+      ClassItem::Range(lo, hi) => lo <= c && c <= hi,
+      ClassItem::Perl(perl, negated) => perl.matches(c) != negated,
+    })
+  }
+
+  fn matches(&self, c: char, ignore_case: bool) -> bool {
+    let hit = if ignore_case {
+      case_variants(c).iter().any(|&v| self.contains(v))
+    } else {
+      self.contains(c)
+    };
+    hit != self.negated
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+  Empty,
+  Char(char),
+  Any,
+  Class(Class),
+  Look(Look),
+  Concat(Vec<Node>),
+  Alt(Vec<Node>),
+  Repeat {
+    node: Box<Node>,
+    min: u32,
+    max: Option<u32>,
+    greedy: bool,
+  },
+}
+
+struct Parser {
+  chars: Vec<(usize, char)>,
+  pos: usize,
+  len: usize,
+}
+
+impl Parser {
+  fn new(pattern: &str) -> Parser {
+    Parser {
+      chars: pattern.char_indices().collect(),
+      pos: 0,
+      len: pattern.len(),
+    }
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.chars.get(self.pos).map(|&(_, c)| c)
+  }
+
+  fn offset(&self) -> usize {
+    self.chars.get(self.pos).map_or(self.len, |&(i, _)| i)
+  }
+
+  fn next(&mut self) -> Option<char> {
+    let c = self.peek();
+    if c.is_some() {
+      self.pos += 1;
+    }
+    c
+  }
+
+  fn eat(&mut self, want: char) -> bool {
+    if self.peek() == Some(want) {
+      self.pos += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn error(&self, message: &str) -> ParseError {
+    ParseError::new(message, self.offset())
+  }
+
+  fn parse(mut self) -> Result<Node, ParseError> {
+    let node = self.parse_alternation()?;
+    match self.peek() {
+      None => Ok(node),
+      Some(')') => Err(self.error("unopened group")),
+      Some(_) => Err(self.error("unexpected character")),
+    }
+  }
+
+  fn parse_alternation(&mut self) -> Result<Node, ParseError> {
+    let mut branches = vec![self.parse_concat()?];
+    while self.eat('|') {
+      branches.push(self.parse_concat()?);
+    }
+    Ok(if branches.len() == 1 {
+      branches.pop().unwrap()
+    } else {
+      Node::Alt(branches)
+    })
+  }
+
+  fn parse_concat(&mut self) -> Result<Node, ParseError> {
+    let mut nodes = vec![];
+    while let Some(c) = self.peek() {
+      if c == '|' || c == ')' {
+        break;
+      }
+      let atom = self.parse_atom()?;
+      nodes.push(self.parse_repeat(atom)?);
+    }
+    Ok(match nodes.len() {
+      0 => Node::Empty,
+      1 => nodes.pop().unwrap(),
+      _ => Node::Concat(nodes),
+    })
+  }
+
+  /// `atom` 可能是带括号的分组, 已经是重复也可以再次重复, 只拒绝紧跟在另一个重复符之后的重复符
+  fn parse_repeat(&mut self, mut atom: Node) -> Result<Node, ParseError> {
+    let mut repeated = false;
+    loop {
+      let start = self.pos;
+      let (min, max) = match self.peek() {
+        Some('*') => {
+          self.pos += 1;
+          (0, None)
+        }
+        Some('+') => {
+          self.pos += 1;
+          (1, None)
+        }
+        Some('?') => {
+          self.pos += 1;
+          (0, Some(1))
+        }
+        Some('{') => match self.parse_counted()? {
+          Some(range) => range,
+          None => return Ok(atom),
+        },
+        _ => return Ok(atom),
+      };
+      if repeated {
+        self.pos = start;
+        return Err(self.error("repetition operator repeated"));
+      }
+      repeated = true;
+      let greedy = !self.eat('?');
+      atom = Node::Repeat {
+        node: Box::new(atom),
+        min,
+        max,
+        greedy,
+      };
+    }
+  }
+
+  /// 解析 `{n}`, `{n,}`, `{n,m}`; 不构成计数重复时按字面量 `{` 处理
+  fn parse_counted(&mut self) -> Result<Option<(u32, Option<u32>)>, ParseError> {
+    let start = self.pos;
+    self.pos += 1;
+    let min = self.parse_number();
+    let max = if self.eat(',') {
+      self.parse_number()
+    } else {
+      min
+    };
+    if min.is_none() || !self.eat('}') {
+      self.pos = start;
+      return Ok(None);
+    }
+    let min = min.unwrap();
+    if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+      self.pos = start;
+      return Err(self.error("repetition count too large"));
+    }
+    if max.is_some_and(|max| max < min) {
+      self.pos = start;
+      return Err(self.error("invalid repetition range"));
+    }
+    Ok(Some((min, max)))
+  }
+
+  fn parse_number(&mut self) -> Option<u32> {
+    let mut value: Option<u32> = None;
+    while let Some(d) = self.peek().and_then(|c| c.to_digit(10)) {
+      self.pos += 1;
+      value = Some(value.unwrap_or(0).saturating_mul(10).saturating_add(d));
+    }
+    value
+  }
+
+  fn parse_atom(&mut self) -> Result<Node, ParseError> {
+    let offset = self.offset();
+    match self.next().unwrap() {
+      '(' => {
+        if self.eat('?') && !self.eat(':') {
+          return Err(self.error("unsupported group flag"));
+        }
+        let node = self.parse_alternation()?;
+        if !self.eat(')') {
+          return Err(ParseError::new("unclosed group", offset));
+        }
+        Ok(node)
+      }
+      '[' => self.parse_class(offset),
+      '.' => Ok(Node::Any),
+      '^' => Ok(Node::Look(Look::Start)),
+      '$' => Ok(Node::Look(Look::End)),
+      '*' | '+' | '?' => Err(ParseError::new("nothing to repeat", offset)),
+      '\\' => self.parse_escape(),
+      c => Ok(Node::Char(c)),
+    }
+  }
+
+  fn parse_escape(&mut self) -> Result<Node, ParseError> {
+    let c = match self.next() {
+      Some(c) => c,
+      None => return Err(self.error("trailing backslash")),
+    };
+    Ok(match c {
+      'b' => Node::Look(Look::WordBoundary),
+      'B' => Node::Look(Look::NotWordBoundary),
+      _ => match self.escape_item(c)? {
+        ClassItem::Range(c, _) => Node::Char(c),
+        item => Node::Class(Class {
+          items: vec![item],
+          negated: false,
+        }),
+      },
+    })
+  }
+
+  /// 解析转义字符(反斜杠已被消耗), 在字符类内外共用
+  fn escape_item(&self, c: char) -> Result<ClassItem, ParseError> {
+    let item = match c {
+      'd' => ClassItem::Perl(Perl::Digit, false),
+      'D' => ClassItem::Perl(Perl::Digit, true),
+      'w' => ClassItem::Perl(Perl::Word, false),
+      'W' => ClassItem::Perl(Perl::Word, true),
+      's' => ClassItem::Perl(Perl::Space, false),
+      'S' => ClassItem::Perl(Perl::Space, true),
+      'n' => ClassItem::Range('\n', '\n'),
+      't' => ClassItem::Range('\t', '\t'),
+      'r' => ClassItem::Range('\r', '\r'),
+      c if c.is_ascii_alphanumeric() => return Err(self.error("unknown escape sequence")),
+      c => ClassItem::Range(c, c),
+    };
+    Ok(item)
+  }
+
+  fn parse_class(&mut self, offset: usize) -> Result<Node, ParseError> {
+    let negated = self.eat('^');
+    let mut items = vec![];
+    // `[]a]` 和 `[^]a]` 中的首个 `]` 是字面量
+    if self.eat(']') {
+      items.push(ClassItem::Range(']', ']'));
+    }
+    loop {
+      let lo = match self.next() {
+        None => return Err(ParseError::new("unclosed character class", offset)),
+        Some(']') => break,
+        Some('\\') => match self.next() {
+          Some(c) => self.escape_item(c)?,
+          None => return Err(self.error("trailing backslash")),
+        },
+        Some(c) => ClassItem::Range(c, c),
+      };
+      let lo = match lo {
+        ClassItem::Range(lo, _) => lo,
+        perl => {
+          items.push(perl);
+          continue;
+        }
+      };
+      // `-` 出现在末尾时是字面量
+      if self.peek() == Some('-') && self.chars.get(self.pos + 1).map(|&(_, c)| c) != Some(']') {
+        self.pos += 1;
+        let hi = match self.next() {
+          None => return Err(ParseError::new("unclosed character class", offset)),
+          Some('\\') => match self.next().map(|c| self.escape_item(c)) {
+            Some(Ok(ClassItem::Range(hi, _))) => hi,
+            Some(Err(e)) => return Err(e),
+            _ => return Err(self.error("invalid range end")),
+          },
+          Some(c) => c,
+        };
+        if hi < lo {
+          return Err(self.error("invalid range"));
+        }
+        items.push(ClassItem::Range(lo, hi));
+      } else {
+        items.push(ClassItem::Range(lo, lo));
+      }
+    }
+    Ok(Node::Class(Class { items, negated }))
+  }
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+  Char(char),
+  Any,
+  Class(Class),
+  Look(Look),
+  /// 优先尝试第一个分支
+  Split(usize, usize),
+  Jmp(usize),
+  Match,
+}
+
+struct Compiler {
+  prog: Vec<Inst>,
+}
+
+impl Compiler {
+  fn push(&mut self, inst: Inst) -> Result<usize, ParseError> {
+    if self.prog.len() >= MAX_PROGRAM_SIZE {
+      return Err(ParseError::new("pattern too large", 0));
+    }
+    self.prog.push(inst);
+    Ok(self.prog.len() - 1)
+  }
+
+  fn compile(&mut self, node: &Node) -> Result<(), ParseError> {
+    match node {
+      Node::Empty => {}
+      Node::Char(c) => {
+        self.push(Inst::Char(*c))?;
+      }
+      Node::Any => {
+        self.push(Inst::Any)?;
+      }
+      Node::Class(class) => {
+        self.push(Inst::Class(class.clone()))?;
+      }
+      Node::Look(look) => {
+        self.push(Inst::Look(*look))?;
+      }
+      Node::Concat(nodes) => {
+        for node in nodes {
+          self.compile(node)?;
+        }
+      }
+      Node::Alt(branches) => {
+        let mut jumps = vec![];
+        for (i, branch) in branches.iter().enumerate() {
+          if i + 1 == branches.len() {
+            self.compile(branch)?;
+          } else {
+            let split = self.push(Inst::Split(0, 0))?;
+            self.compile(branch)?;
+            jumps.push(self.push(Inst::Jmp(0))?);
+            self.prog[split] = Inst::Split(split + 1, self.prog.len());
+          }
+        }
+        let end = self.prog.len();
+        for jump in jumps {
+          self.prog[jump] = Inst::Jmp(end);
+        }
+      }
+      Node::Repeat {
+        node,
+        min,
+        max,
+        greedy,
+      } => {
+        for _ in 0..*min {
+          self.compile(node)?;
+        }
+        match max {
+          None => {
+            let split = self.push(Inst::Split(0, 0))?;
+            self.compile(node)?;
+            self.push(Inst::Jmp(split))?;
+            self.prog[split] = self.split(split + 1, self.prog.len(), *greedy);
+          }
+          Some(max) => {
+            let mut splits = vec![];
+            for _ in *min..*max {
+              splits.push(self.push(Inst::Split(0, 0))?);
+              self.compile(node)?;
+            }
+            let end = self.prog.len();
+            for split in splits {
+              self.prog[split] = self.split(split + 1, end, *greedy);
+            }
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+
+  fn split(&self, body: usize, skip: usize, greedy: bool) -> Inst {
+    if greedy {
+      Inst::Split(body, skip)
+    } else {
+      Inst::Split(skip, body)
+    }
+  }
+}
+
+/// 某个位置两侧的字符, 用于判断零宽断言
+struct Context {
+  prev: Option<char>,
+  cur: Option<char>,
+}
+
+impl Context {
+  fn satisfies(&self, look: Look) -> bool {
+    let boundary = || self.prev.is_some_and(is_word_char) != self.cur.is_some_and(is_word_char);
+    match look {
+      Look::Start => self.prev.is_none(),
+      Look::End => self.cur.is_none(),
+      Look::WordBoundary => boundary(),
+      Look::NotWordBoundary => !boundary(),
+    }
+  }
+}
+
+/// Pike VM 的线程表: 按优先级排列的 (pc, 匹配起点), 每个 pc 每步只出现一次
+struct Threads {
+  threads: Vec<(usize, usize)>,
+  seen: Vec<bool>,
+  visited: Vec<usize>,
+  stack: Vec<usize>,
+}
+
+impl Threads {
+  fn new(size: usize) -> Threads {
+    Threads {
+      threads: vec![],
+      seen: vec![false; size],
+      visited: vec![],
+      stack: vec![],
+    }
+  }
+
+  fn clear(&mut self) {
+    for &pc in &self.visited {
+      self.seen[pc] = false;
+    }
+    self.visited.clear();
+    self.threads.clear();
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+  prog: Vec<Inst>,
+  ignore_case: bool,
+}
+
+impl Regex {
+  pub fn new(pattern: &str, ignore_case: bool) -> Result<Regex, ParseError> {
+    let node = Parser::new(pattern).parse()?;
+    let mut compiler = Compiler { prog: vec![] };
+    compiler.compile(&node)?;
+    compiler.push(Inst::Match)?;
+    Ok(Regex {
+      prog: compiler.prog,
+      ignore_case,
+    })
+  }
+
+  pub fn is_match(&self, haystack: &str) -> bool {
+    self.find(haystack).is_some()
+  }
+
+  pub fn find(&self, haystack: &str) -> Option<(usize, usize)> {
+    self.find_at(haystack.as_bytes(), 0)
+  }
+
+  /// 从 `start` 开始查找最左侧的匹配, 返回字节区间 `[start, end)`
+  /// 无效的 UTF-8 字节按单字节的 U+FFFD 处理
+  pub fn find_at(&self, haystack: &[u8], start: usize) -> Option<(usize, usize)> {
+    let mut clist = Threads::new(self.prog.len());
+    let mut nlist = Threads::new(self.prog.len());
+    let mut matched = None;
+    let mut pos = start;
+    let mut prev = decode_last(&haystack[..start]);
+    let mut cur = decode(haystack, pos);
+
+    loop {
+      if matched.is_none() {
+        let ctx = Context {
+          prev,
+          cur: cur.map(|(c, _)| c),
+        };
+        self.add(&mut clist, 0, pos, &ctx);
+      }
+      if clist.threads.is_empty() && matched.is_some() {
+        break;
+      }
+
+      let next = cur.map(|(c, width)| (c, pos + width, decode(haystack, pos + width)));
+      let next_ctx = next.map(|(c, _, after)| Context {
+        prev: Some(c),
+        cur: after.map(|(c, _)| c),
+      });
+      for i in 0..clist.threads.len() {
+        let (pc, from) = clist.threads[i];
+        let inst = &self.prog[pc];
+        if let Inst::Match = inst {
+          matched = Some((from, pos));
+          // 优先级更低的线程不再需要
+          break;
+        }
+        if let (Some((c, _, _)), Some(ctx)) = (next, &next_ctx) {
+          if self.step(inst, c) {
+            self.add(&mut nlist, pc + 1, from, ctx);
+          }
+        }
+      }
+
+      match next {
+        Some((c, at, after)) => {
+          std::mem::swap(&mut clist, &mut nlist);
+          nlist.clear();
+          prev = Some(c);
+          pos = at;
+          cur = after;
+        }
+        None => break,
+      }
+    }
+    matched
+  }
+
+  fn add(&self, list: &mut Threads, pc: usize, from: usize, ctx: &Context) {
+    list.stack.push(pc);
+    while let Some(pc) = list.stack.pop() {
+      if list.seen[pc] {
+        continue;
+      }
+      list.seen[pc] = true;
+      list.visited.push(pc);
+      match self.prog[pc] {
+        Inst::Jmp(to) => list.stack.push(to),
+        Inst::Split(first, second) => {
+          list.stack.push(second);
+          list.stack.push(first);
+        }
+        Inst::Look(look) => {
+          if ctx.satisfies(look) {
+            list.stack.push(pc + 1);
+          }
+        }
+        _ => list.threads.push((pc, from)),
+      }
+    }
+  }
+
+  fn step(&self, inst: &Inst, c: char) -> bool {
+    match inst {
+      Inst::Char(want) => {
+        *want == c || (self.ignore_case && (case_variants(c).contains(want) || case_variants(*want).contains(&c)))
+      }
+      Inst::Any => c != '\n',
+      Inst::Class(class) => class.matches(c, self.ignore_case),
+      _ => false,
+    }
+  }
+}
+
+pub(crate) fn is_word_char(c: char) -> bool {
+  c.is_alphanumeric() || c == '_'
+}
+
+/// 字符自身、单字符的大小写形式及其简单折叠(ſ、K 这类字符的大小写形式不能互相转换)
+fn case_variants(c: char) -> [char; 4] {
+  [
+    c,
+    single(c.to_lowercase()).unwrap_or(c),
+    single(c.to_uppercase()).unwrap_or(c),
+    unicode::fold(c, Fold::Simple).next().unwrap_or(c),
+  ]
+}
+
+fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+  match (chars.next(), chars.next()) {
+    (Some(c), None) => Some(c),
+    _ => None,
+  }
+}
+
+fn decode(bytes: &[u8], at: usize) -> Option<(char, usize)> {
+  let first = *bytes.get(at)?;
+  if first < 0x80 {
+    return Some((first as char, 1));
+  }
+  let width = match first {
+    0xC0..=0xDF => 2,
+    0xE0..=0xEF => 3,
+    0xF0..=0xF7 => 4,
+    _ => return Some(('\u{FFFD}', 1)),
+  };
+  let c = bytes
+    .get(at..at + width)
+    .and_then(|seq| std::str::from_utf8(seq).ok())
+    .and_then(|s| s.chars().next());
+  Some(c.map_or(('\u{FFFD}', 1), |c| (c, width)))
+}
+
+fn decode_last(bytes: &[u8]) -> Option<char> {
+  if bytes.is_empty() {
+    return None;
+  }
+  let floor = bytes.len().saturating_sub(4);
+  let mut at = bytes.len() - 1;
+  while at > floor && bytes[at] & 0xC0 == 0x80 {
+    at -= 1;
+  }
+  match decode(bytes, at) {
+    Some((c, width)) if at + width == bytes.len() => Some(c),
+    _ => Some('\u{FFFD}'),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn find(pattern: &str, haystack: &str) -> Option<(usize, usize)> {
+    Regex::new(pattern, false).unwrap().find(haystack)
+  }
+
+  #[test]
+  fn literals_and_classes() {
+    assert_eq!(Some((3, 6)), find("fn ", "pubfn x"));
+    assert_eq!(Some((0, 3)), find("[a-c]+", "abcd"));
+    assert_eq!(Some((2, 3)), find("[^a-c]", "abxd"));
+    assert_eq!(Some((1, 4)), find(r"\d+", "x123y"));
+    assert_eq!(Some((0, 3)), find(r"[\w-]+", "a-b c"));
+    assert_eq!(Some((0, 1)), find("[]]", "]"));
+    assert_eq!(Some((0, 2)), find("a.", "ab"));
+  }
+
+  #[test]
+  fn alternation_and_groups() {
+    assert_eq!(Some((4, 7)), find("cat|dog", "hot dog"));
+    assert_eq!(Some((0, 6)), find("(ab)+", "ababab"));
+    assert_eq!(Some((1, 3)), find("(?:a|b)c", "xbc"));
+    assert_eq!(Some((0, 1)), find("a|ab", "ab"));
+    //带括号的分组即使本身是重复也可以再次重复
+    assert_eq!(Some((0, 5)), find(r"foo(\d+)?", "foo12"));
+    assert_eq!(Some((0, 3)), find(r"foo(\d+)?", "foo"));
+    assert_eq!(Some((0, 4)), find("(a+)+", "aaaa"));
+    assert_eq!(Some((0, 4)), find("(a*)*b", "aaab"));
+    assert_eq!(Some((0, 5)), find("((ab)+c?){2}", "abcabx"));
+    assert_eq!(Some((0, 1)), find("(a+?)+?", "aa"));
+  }
+
+  #[test]
+  fn repetition() {
+    assert_eq!(Some((0, 3)), find("a{2,3}", "aaaa"));
+    assert_eq!(Some((0, 2)), find("a{2,3}?", "aaaa"));
+    assert_eq!(Some((0, 4)), find("a{2,}", "aaaa"));
+    assert_eq!(None, find("a{3}", "aa"));
+    assert_eq!(Some((0, 3)), find("<.*?>", "<a><b>"));
+    assert_eq!(Some((0, 4)), find("x{a}", "x{a}"));
+    assert_eq!(Some((0, 0)), find("b*", "aaa"));
+  }
+
+  #[test]
+  fn anchors_and_boundaries() {
+    assert_eq!(None, find("^b", "ab"));
+    assert_eq!(Some((1, 2)), find("b$", "ab"));
+    assert_eq!(Some((4, 8)), find(r"\bduct\b", "pro duct"));
+    assert_eq!(None, find(r"\bduct\b", "productive"));
+    assert_eq!(Some((3, 7)), find(r"\Bduct", "productive"));
+  }
+
+  #[test]
+  fn ignore_case_and_unicode() {
+    let re = Regex::new("rust", true).unwrap();
+    assert!(re.is_match("Trust me."));
+    let re = Regex::new("[^a]", true).unwrap();
+    assert!(!re.is_match("A"));
+    assert_eq!(Some((0, 4)), find("é.", "éÜ"));
+    assert_eq!(Some((1, 2)), Regex::new("a", false).unwrap().find_at(b"\xffa", 0));
+  }
+
+  #[test]
+  fn fn_signatures() {
+    let re = Regex::new(r"fn \w+\(", false).unwrap();
+    assert!(re.is_match("pub fn search(query: String)"));
+    assert!(!re.is_match("fn (x)"));
+  }
+
+  #[test]
+  fn parse_errors() {
+    let err = |pattern| Regex::new(pattern, false).unwrap_err();
+    assert_eq!(0, err("*a").offset);
+    assert_eq!(0, err("(ab").offset);
+    assert_eq!(2, err("ab)").offset);
+    assert_eq!(2, err("a**").offset);
+    assert_eq!(3, err("a+?+").offset);
+    assert_eq!(4, err("(a)*+").offset);
+    assert_eq!(0, err("[ab").offset);
+    assert_eq!("unknown escape sequence", err(r"\q").message);
+  }
+}
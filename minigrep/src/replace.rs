@@ -0,0 +1,203 @@
+//! 替换: `--replace` 在输出中替换匹配, `--in-place` 把替换结果写回文件
+//! 写回时先写入同一目录下的临时文件再改名, 中途失败不会留下写了一半的文件
+use crate::searcher::Searcher;
+use std::{
+  fs,
+  io::{self, Write},
+  path::{Path, PathBuf},
+  process,
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+  Text(String),
+  /// `$0`: 整个匹配
+  Match,
+}
+
+/// 替换文本, `$0` 引用匹配到的原文, `$$` 是一个 `$`, 其他 `$` 原样保留
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+  parts: Vec<Part>,
+}
+
+impl Template {
+  pub fn new(template: &str) -> Template {
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+      match (c, chars.peek()) {
+        ('$', Some('$')) => {
+          chars.next();
+          text.push('$');
+        }
+        ('$', Some('0')) => {
+          chars.next();
+          if !text.is_empty() {
+            parts.push(Part::Text(std::mem::take(&mut text)));
+          }
+          parts.push(Part::Match);
+        }
+        _ => text.push(c),
+      }
+    }
+    if !text.is_empty() {
+      parts.push(Part::Text(text));
+    }
+    Template { parts }
+  }
+
+  /// 替换 `line` 中 `ranges` 处的匹配, 同时给出替换结果在新行中的区间
+  pub fn apply(&self, line: &str, ranges: &[(usize, usize)]) -> (String, Vec<(usize, usize)>) {
+    let mut replaced = String::with_capacity(line.len());
+    let mut new_ranges = Vec::with_capacity(ranges.len());
+    let mut last = 0;
+    for &(start, end) in ranges {
+      replaced.push_str(&line[last..start]);
+      let from = replaced.len();
+      for part in &self.parts {
+        match part {
+          Part::Text(text) => replaced.push_str(text),
+          Part::Match => replaced.push_str(&line[start..end]),
+        }
+      }
+      new_ranges.push((from, replaced.len()));
+      last = end;
+    }
+    replaced.push_str(&line[last..]);
+    (replaced, new_ranges)
+  }
+}
+
+/// 替换文件中每一行的所有匹配并写回, 返回改变的行数
+/// 给出 `max_count` 时只替换前这么多个改变的行
+/// 二进制文件和不是 UTF-8 的文件保持不变, 返回 `InvalidData` 错误
+pub(crate) fn rewrite(
+  searcher: &Searcher,
+  template: &Template,
+  path: &Path,
+  backup: bool,
+  max_count: Option<usize>,
+) -> io::Result<usize> {
+  //编辑符号链接指向的文件, 而不是把链接替换成普通文件
+  let path = fs::canonicalize(path)?;
+  let unchanged = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}, left unchanged", reason));
+  let contents = match String::from_utf8(fs::read(&path)?) {
+    Ok(contents) if !contents.contains('\0') => contents,
+    Ok(_) => return Err(unchanged("binary file")),
+    Err(_) => return Err(unchanged("not valid UTF-8")),
+  };
+  let mut out = String::with_capacity(contents.len());
+  let mut changed = 0;
+  for raw in contents.split_inclusive('\n') {
+    let line = raw.strip_suffix('\n').unwrap_or(raw);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    if max_count.is_some_and(|max| changed >= max) {
+      out.push_str(raw);
+      continue;
+    }
+    let ranges: Vec<(usize, usize)> = searcher.find_all(line).iter().map(|&(start, end, _)| (start, end)).collect();
+    let (replaced, _) = template.apply(line, &ranges);
+    if replaced != line {
+      changed += 1;
+    }
+    out.push_str(&replaced);
+    //保留原来的换行符
+    out.push_str(&raw[line.len()..]);
+  }
+  if changed > 0 {
+    write_atomic(&path, out.as_bytes(), backup)?;
+  }
+  Ok(changed)
+}
+
+/// 给 `path` 的文件名加上前缀和后缀, 例如 `poem.txt` -> `poem.txt.bak`
+fn with_suffix(path: &Path, prefix: &str, suffix: &str) -> PathBuf {
+  let name = path.file_name().unwrap_or_default().to_string_lossy();
+  path.with_file_name(format!("{}{}{}", prefix, name, suffix))
+}
+
+/// 写入临时文件, 复制原文件的权限, 然后改名覆盖原文件
+fn write_atomic(path: &Path, contents: &[u8], backup: bool) -> io::Result<()> {
+  //同一进程中并行写回的文件各用一个临时文件
+  static NEXT: AtomicUsize = AtomicUsize::new(0);
+  let permissions = fs::metadata(path)?.permissions();
+  let id = NEXT.fetch_add(1, Ordering::Relaxed);
+  let temp = with_suffix(path, ".", &format!(".minigrep-{}-{}", process::id(), id));
+  let result = (|| {
+    let mut file = fs::OpenOptions::new().write(true).create_new(true).open(&temp)?;
+    file.write_all(contents)?;
+    file.set_permissions(permissions)?;
+    file.sync_all()?;
+    if backup {
+      fs::copy(path, with_suffix(path, "", ".bak"))?;
+    }
+    fs::rename(&temp, path)
+  })();
+  if result.is_err() {
+    let _ = fs::remove_file(&temp);
+  }
+  result
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{searcher::Pattern, Finder};
+  use std::env;
+
+  #[test]
+  fn templates() {
+    let ranges = [(0, 4), (11, 15)];
+    let (replaced, new_ranges) = Template::new("[$0]").apply("duct tape, duct", &ranges);
+    assert_eq!("[duct] tape, [duct]", replaced);
+    assert_eq!(vec![(0, 6), (13, 19)], new_ranges);
+    assert_eq!("$5 $0 $x $", Template::new("$$5 $$0 $x $").apply("q", &[(0, 1)]).0);
+    assert_eq!(" tape, ", Template::new("").apply("duct tape, duct", &ranges).0);
+  }
+
+  #[test]
+  fn rewrite_in_place() {
+    let dir = env::temp_dir().join(format!("minigrep-replace-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("poem.txt");
+    fs::write(&file, "Rust:\r\nsafe, fast, productive.\nTrust me").unwrap();
+    let searcher = Searcher::from(Pattern::Literal(Finder::new("rust", true)));
+
+    let changed = rewrite(&searcher, &Template::new("<$0>"), &file, true, None).unwrap();
+    assert_eq!(2, changed);
+    assert_eq!("<Rust>:\r\nsafe, fast, productive.\nT<rust> me", fs::read_to_string(&file).unwrap());
+    assert_eq!("Rust:\r\nsafe, fast, productive.\nTrust me", fs::read_to_string(dir.join("poem.txt.bak")).unwrap());
+
+    //没有匹配时不写文件
+    let searcher = Searcher::from(Pattern::Literal(Finder::new("zebra", false)));
+    assert_eq!(0, rewrite(&searcher, &Template::new("x"), &file, false, None).unwrap());
+
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+      let searcher = Searcher::from(Pattern::Literal(Finder::new("<", false)));
+      assert_eq!(2, rewrite(&searcher, &Template::new("("), &file, false, None).unwrap());
+      assert_eq!(0o640, fs::metadata(&file).unwrap().permissions().mode() & 0o777);
+    }
+
+    //`-m` 只替换前几个改变的行
+    let searcher = Searcher::from(Pattern::Literal(Finder::new("s", false)));
+    assert_eq!(1, rewrite(&searcher, &Template::new("S"), &file, false, Some(1)).unwrap());
+    let contents = fs::read_to_string(&file).unwrap();
+    assert!(contents.contains("RuSt>:\r\nsafe, fast"));
+
+    //不能写回的文件报告错误
+    let other = dir.join("latin1.txt");
+    fs::write(&other, b"caf\xe9").unwrap();
+    let err = rewrite(&searcher, &Template::new("S"), &other, false, None).unwrap_err();
+    assert_eq!("not valid UTF-8, left unchanged", err.to_string());
+    fs::remove_file(&other).unwrap();
+    let leftovers = fs::read_dir(&dir).unwrap().count();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(2, leftovers);
+  }
+}
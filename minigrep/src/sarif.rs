@@ -0,0 +1,222 @@
+//! SARIF 2.1.0 报告: 每个模式是一条规则, 每个匹配是一个结果
+//! 规则文件的格式:
+//!
+//! ```text
+//! # 注释
+//! [no-todo]
+//! pattern = TODO
+//! level = warning
+//! message = TODO should be tracked in an issue
+//! ```
+use crate::{json::Json, searcher::Line};
+use std::path::Path;
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Level {
+  Error,
+  #[default]
+  Warning,
+  Note,
+}
+
+impl Level {
+  pub fn parse(level: &str) -> Option<Level> {
+    match level {
+      "error" => Some(Level::Error),
+      "warning" => Some(Level::Warning),
+      "note" => Some(Level::Note),
+      _ => None,
+    }
+  }
+
+  fn name(self) -> &'static str {
+    match self {
+      Level::Error => "error",
+      Level::Warning => "warning",
+      Level::Note => "note",
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+  pub id: String,
+  pub pattern: String,
+  pub level: Level,
+  pub message: String,
+}
+
+impl Rule {
+  /// 没有在规则文件中定义的模式使用的规则
+  pub(crate) fn for_pattern(index: usize, pattern: &str) -> Rule {
+    Rule {
+      id: format!("pattern-{}", index + 1),
+      pattern: pattern.to_string(),
+      level: Level::default(),
+      message: format!("Matches '{}'", pattern),
+    }
+  }
+}
+
+/// 解析规则文件, 错误信息带有行号
+pub fn parse_rules(contents: &str) -> Result<Vec<Rule>, String> {
+  let mut rules: Vec<Rule> = vec![];
+  //每条规则开始的行号, 用于报告缺少 pattern 的规则
+  let mut starts = vec![];
+  for (index, line) in contents.lines().enumerate() {
+    let number = index + 1;
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    if let Some(id) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+      let id = id.trim();
+      if id.is_empty() || rules.iter().any(|rule| rule.id == id) {
+        return Err(format!("line {}: rule id '{}' is empty or used twice", number, id));
+      }
+      rules.push(Rule {
+        id: id.to_string(),
+        pattern: String::new(),
+        level: Level::default(),
+        message: String::new(),
+      });
+      starts.push(number);
+      continue;
+    }
+    let (key, value) = match line.split_once('=') {
+      Some((key, value)) => (key.trim(), value.trim()),
+      None => return Err(format!("line {}: expected '[id]' or 'key = value'", number)),
+    };
+    let rule = rules
+      .last_mut()
+      .ok_or_else(|| format!("line {}: '{}' comes before the first [id]", number, key))?;
+    match key {
+      "pattern" => rule.pattern = value.to_string(),
+      "message" => rule.message = value.to_string(),
+      "level" => {
+        rule.level = Level::parse(value)
+          .ok_or_else(|| format!("line {}: level must be error, warning or note, got '{}'", number, value))?
+      }
+      _ => return Err(format!("line {}: unknown key '{}'", number, key)),
+    }
+  }
+  for (rule, number) in rules.iter_mut().zip(starts) {
+    if rule.pattern.is_empty() {
+      return Err(format!("line {}: rule '{}' has no pattern", number, rule.id));
+    }
+    if rule.message.is_empty() {
+      rule.message = format!("Matches '{}'", rule.pattern);
+    }
+  }
+  Ok(rules)
+}
+
+/// 相对路径形式的 URI, 分隔符统一为 `/`, 保留字符以外的字节按百分号编码
+fn uri(path: &Path) -> String {
+  let path = path.to_string_lossy().replace('\\', "/");
+  let mut uri = String::with_capacity(path.len());
+  for b in path.bytes() {
+    match b {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => uri.push(b as char),
+      _ => uri.push_str(&format!("%{:02X}", b)),
+    }
+  }
+  uri
+}
+
+/// 一个匹配行中每个匹配对应的结果, 列号按 Unicode 字符计, 从 1 开始
+pub(crate) fn results(rules: &[Rule], file: &Path, line: &Line) -> Vec<Json> {
+  let column = |at: usize| line.text[..at].chars().count() + 1;
+  line
+    .ranges
+    .iter()
+    .zip(&line.patterns)
+    .map(|(&(start, end), &pattern)| {
+      let rule = &rules[pattern];
+      let region = Json::Object(vec![
+        ("startLine", Json::from(line.number)),
+        ("startColumn", Json::from(column(start))),
+        ("endColumn", Json::from(column(end))),
+        ("snippet", Json::Object(vec![("text", Json::from(&line.text[start..end]))])),
+      ]);
+      let location = Json::Object(vec![(
+        "physicalLocation",
+        Json::Object(vec![
+          ("artifactLocation", Json::Object(vec![("uri", Json::from(&*uri(file)))])),
+          ("region", region),
+        ]),
+      )]);
+      Json::Object(vec![
+        ("ruleId", Json::from(rule.id.as_str())),
+        ("ruleIndex", Json::from(pattern)),
+        ("level", Json::from(rule.level.name())),
+        ("message", Json::Object(vec![("text", Json::from(rule.message.as_str()))])),
+        ("locations", Json::Array(vec![location])),
+      ])
+    })
+    .collect()
+}
+
+/// 包含一次运行的完整报告
+pub(crate) fn report(rules: &[Rule], results: Vec<Json>) -> Json {
+  let rules = rules.iter().map(|rule| {
+    Json::Object(vec![
+      ("id", Json::from(rule.id.as_str())),
+      ("shortDescription", Json::Object(vec![("text", Json::from(rule.message.as_str()))])),
+      ("defaultConfiguration", Json::Object(vec![("level", Json::from(rule.level.name()))])),
+      ("properties", Json::Object(vec![("pattern", Json::from(rule.pattern.as_str()))])),
+    ])
+  });
+  let driver = Json::Object(vec![
+    ("name", Json::from("minigrep")),
+    ("version", Json::from(env!("CARGO_PKG_VERSION"))),
+    ("rules", Json::Array(rules.collect())),
+  ]);
+  let run = Json::Object(vec![
+    ("tool", Json::Object(vec![("driver", driver)])),
+    ("columnKind", Json::from("unicodeCodePoints")),
+    ("results", Json::Array(results)),
+  ]);
+  Json::Object(vec![
+    ("$schema", Json::from(SCHEMA)),
+    ("version", Json::from("2.1.0")),
+    ("runs", Json::Array(vec![run])),
+  ])
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn rules_file() {
+    let rules = parse_rules(include_str!("../testdata/lint.rules")).unwrap();
+    assert_eq!(3, rules.len());
+    assert_eq!(
+      Rule {
+        id: "no-nobody".to_string(),
+        pattern: "nobody".to_string(),
+        level: Level::Error,
+        message: "Somebody, please".to_string(),
+      },
+      rules[0]
+    );
+    assert_eq!(("Matches 'frog'", Level::Warning), (rules[2].message.as_str(), rules[2].level));
+
+    let err = |contents: &str| parse_rules(contents).unwrap_err();
+    assert_eq!("line 1: 'pattern' comes before the first [id]", err("pattern = x"));
+    assert_eq!("line 3: rule id 'a' is empty or used twice", err("[a]\npattern = x\n[a]"));
+    assert_eq!("line 2: level must be error, warning or note, got 'fatal'", err("[a]\nlevel = fatal"));
+    assert_eq!("line 2: unknown key 'severity'", err("[a]\nseverity = note"));
+    assert_eq!("line 1: rule 'a' has no pattern", err("[a]\n\n[b]\npattern = y"));
+    assert_eq!("line 2: expected '[id]' or 'key = value'", err("[a]\npattern"));
+  }
+
+  #[test]
+  fn uris() {
+    assert_eq!("src/main.rs", uri(Path::new("src/main.rs")));
+    assert_eq!("a%20b/%E6%97%A5.txt", uri(Path::new("a b/日.txt")));
+  }
+}
@@ -0,0 +1,739 @@
+//! 逐行流式搜索: 只保留上下文行和正在读取的块, 内存占用取决于上下文行数和最长的行, 选中的行立即交给回调
+use crate::{
+  decode::{decode_line, Bom, Utf16Reader},
+  find_bounded,
+  fuzzy::Fuzzy,
+  query::Compiled,
+  unicode::{self, Fold, Mapped, Normalization},
+  AhoCorasick, Boundary, Config, Error, Finder, Matcher, Regex,
+};
+use std::{
+  collections::VecDeque,
+  io::{self, BufRead},
+};
+
+/// 只检查开头这么多字节来判断二进制文件, 之后遇到的 NUL 同样生效
+const SNIFF_LEN: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BinaryMode {
+  /// 不输出二进制文件的内容, 只报告是否匹配
+  #[default]
+  Binary,
+  /// 当作文本搜索
+  Text,
+  /// 认为二进制文件不匹配
+  WithoutMatch,
+}
+
+impl BinaryMode {
+  pub fn parse(mode: &str) -> Option<BinaryMode> {
+    match mode {
+      "binary" => Some(BinaryMode::Binary),
+      "text" => Some(BinaryMode::Text),
+      "without-match" => Some(BinaryMode::WithoutMatch),
+      _ => None,
+    }
+  }
+}
+
+/// 查询的匹配方式
+pub(crate) enum Pattern {
+  Literal(Finder),
+  /// 多个字面量, 给出折叠方式时自动机由折叠后的模式构建, 搜索折叠后的行
+  Literals(AhoCorasick, Option<Fold>),
+  /// 大小写方式各不相同的多个字面量, 逐个查找
+  Finders(Vec<Finder>),
+  /// 近似匹配, 每个模式一个
+  Fuzzy(Vec<Fuzzy>),
+  Regex(Regex),
+  Regexes(Vec<Regex>),
+  /// 库的使用者提供的匹配方式
+  Custom(Box<dyn Matcher + Send + Sync>),
+  /// `--query` 的布尔查询, 每个模式一个, 各自带有词的边界
+  Query(Vec<Compiled>),
+}
+
+impl Pattern {
+  /// 从 `start` 开始的最左匹配及其模式序号
+  fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize, usize)> {
+    match self {
+      Pattern::Literal(finder) => finder.find_at(line, start).map(|(s, e)| (s, e, 0)),
+      Pattern::Literals(ac, None) => ac.find_at(line.as_bytes(), start),
+      Pattern::Literals(ac, Some(mode)) => {
+        let folded = Mapped::fold(&line[start..], *mode);
+        //与 `Finder` 一致, 匹配不能在一个字符的折叠结果中间开始或结束
+        let accept = |s, e| folded.is_boundary(s) && folded.is_boundary(e);
+        let (s, e, pattern) = ac.find_accepted(folded.text.as_bytes(), 0, accept)?;
+        let (s, e) = folded.origin(s, e);
+        Some((start + s, start + e, pattern))
+      }
+      Pattern::Finders(finders) => leftmost_longest(finders.iter().map(|finder| finder.find_at(line, start))),
+      Pattern::Fuzzy(fuzzy) => leftmost_longest(fuzzy.iter().map(|fuzzy| fuzzy.find_at(line, start))),
+      Pattern::Regex(re) => re.find_at(line.as_bytes(), start).map(|(s, e)| (s, e, 0)),
+      Pattern::Regexes(res) => leftmost_longest(res.iter().map(|re| re.find_at(line.as_bytes(), start))),
+      Pattern::Custom(matcher) => matcher.find_in_line(line, start).map(|(s, e)| (s, e, 0)),
+      Pattern::Query(queries) => leftmost_longest(queries.iter().map(|query| query.find_at(line, start))),
+    }
+  }
+}
+
+/// 与 Aho-Corasick 一致: 最左优先, 同一位置取最长
+fn leftmost_longest(found: impl Iterator<Item = Option<(usize, usize)>>) -> Option<(usize, usize, usize)> {
+  found
+    .enumerate()
+    .filter_map(|(i, found)| found.map(|(s, e)| (s, e, i)))
+    .min_by_key(|&(s, e, i)| (s, std::cmp::Reverse(e), i))
+}
+
+/// 单个文件内容的搜索方式, 在线程间共享
+pub(crate) struct Searcher {
+  pattern: Pattern,
+  boundary: Boundary,
+  /// 模式已经规范化, 每一行在匹配前也要规范化
+  normalization: Option<Normalization>,
+}
+
+impl From<Pattern> for Searcher {
+  fn from(pattern: Pattern) -> Searcher {
+    Searcher {
+      pattern,
+      boundary: Boundary::None,
+      normalization: None,
+    }
+  }
+}
+
+impl Searcher {
+  pub(crate) fn new(config: &Config) -> Result<Searcher, Error> {
+    let patterns: Vec<String> = match config.normalization {
+      Some(form) => config.patterns.iter().map(|p| unicode::normalize(p, form)).collect(),
+      None => config.patterns.clone(),
+    };
+    //每个模式分别决定是否区分大小写
+    let folds: Vec<Option<Fold>> = patterns
+      .iter()
+      .map(|p| Some(config.fold).filter(|_| !config.case.is_sensitive(p, config.regex)))
+      .collect();
+    let pattern = if config.query {
+      let queries = config.patterns.iter().map(|p| Compiled::new(p, config)).collect::<Result<_, _>>()?;
+      return Ok(Searcher {
+        pattern: Pattern::Query(queries),
+        //边界由查询中的每个词自己检查
+        boundary: Boundary::None,
+        normalization: config.normalization,
+      });
+    } else if config.regex {
+      let mut res = vec![];
+      for (query, fold) in patterns.iter().zip(&folds) {
+        let invalid = |source| Error::Pattern {
+          pattern: query.clone(),
+          source,
+        };
+        let re = Regex::new(query, fold.is_some()).map_err(invalid)?;
+        //整行匹配时锚定整个模式, 否则最左匹配可能只覆盖行的一部分
+        //原模式已经通过解析, 括号是配对的, 包装后不会改变其含义
+        res.push(if config.boundary == Boundary::Line {
+          Regex::new(&format!("^(?:{})$", query), fold.is_some()).map_err(invalid)?
+        } else {
+          re
+        });
+      }
+      match res.len() {
+        1 => Pattern::Regex(res.remove(0)),
+        _ => Pattern::Regexes(res),
+      }
+    } else if let Some(max) = config.fuzzy {
+      Pattern::Fuzzy(patterns.iter().zip(&folds).map(|(p, fold)| Fuzzy::new(p, max, fold.is_some())).collect())
+    } else if patterns.len() == 1 {
+      Pattern::Literal(Finder::with_fold(&patterns[0], folds[0]))
+    } else if folds.iter().any(|fold| *fold != folds[0]) {
+      Pattern::Finders(patterns.iter().zip(&folds).map(|(p, &fold)| Finder::with_fold(p, fold)).collect())
+    } else {
+      match folds.first().copied().flatten() {
+        Some(mode) => {
+          let folded: Vec<String> = patterns.iter().map(|p| unicode::fold_str(p, mode)).collect();
+          Pattern::Literals(AhoCorasick::new(&folded), Some(mode))
+        }
+        None => Pattern::Literals(AhoCorasick::new(&patterns), None),
+      }
+    };
+    Ok(Searcher {
+      pattern,
+      boundary: config.boundary,
+      normalization: config.normalization,
+    })
+  }
+
+  /// 使用 `matcher` 代替 `config.patterns`, 边界和规范化仍按 `config`
+  pub(crate) fn with_matcher<M: Matcher + Send + Sync + 'static>(matcher: M, config: &Config) -> Searcher {
+    Searcher {
+      pattern: Pattern::Custom(Box::new(matcher)),
+      boundary: config.boundary,
+      normalization: config.normalization,
+    }
+  }
+
+  /// 可以在整块内容上查找候选位置的字面量查询, 需要规范化时原文中的字节与查询对不上
+  fn prefilter(&self) -> Option<&Finder> {
+    match &self.pattern {
+      Pattern::Literal(finder) if self.normalization.is_none() => Some(finder),
+      _ => None,
+    }
+  }
+
+  /// 从 `start` 开始的第一个匹配及其模式序号
+  pub(crate) fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize, usize)> {
+    let form = match self.normalization {
+      Some(form) => form,
+      None => return find_bounded(line, start, self.boundary, |at| self.pattern.find_at(line, at)),
+    };
+    //规范化整行, 这样单词和整行边界都按规范化后的文本判断
+    let mapped = Mapped::normalize(line, form);
+    let text = mapped.text.as_str();
+    let (s, e, pattern) = find_bounded(text, mapped.position(start), self.boundary, |at| self.pattern.find_at(text, at))?;
+    let (s, e) = mapped.origin(s, e);
+    Some((s, e, pattern))
+  }
+
+  /// 近似匹配时 `find_at` 找到的匹配与模式的编辑距离
+  pub(crate) fn distance(&self, line: &str, (start, end, pattern): (usize, usize, usize)) -> Option<usize> {
+    match &self.pattern {
+      Pattern::Fuzzy(fuzzy) => Some(fuzzy[pattern].distance(&line[start..end])),
+      _ => None,
+    }
+  }
+
+  /// 行内所有不重叠的非空匹配及其模式序号, 用于高亮和替换
+  pub(crate) fn find_all(&self, line: &str) -> Vec<(usize, usize, usize)> {
+    if let Pattern::Query(queries) = &self.pattern {
+      return self.find_all_queries(queries, line);
+    }
+    let mut ranges = vec![];
+    let mut at = 0;
+    while at <= line.len() {
+      let (start, end, pattern) = match self.find_at(line, at) {
+        Some(found) => found,
+        None => break,
+      };
+      if end > start {
+        ranges.push((start, end, pattern));
+        at = end;
+      } else {
+        //空匹配时前进一个字符, 避免死循环
+        at = end + line[end..].chars().next().map_or(1, char::len_utf8);
+      }
+    }
+    ranges
+  }
+
+  /// `--query` 时每个查询对一行只求值一次, 合并各自的匹配, 同一位置取最长, 跳过与前面重叠的匹配
+  fn find_all_queries(&self, queries: &[Compiled], line: &str) -> Vec<(usize, usize, usize)> {
+    let mapped = self.normalization.map(|form| Mapped::normalize(line, form));
+    let text = mapped.as_ref().map_or(line, |mapped| mapped.text.as_str());
+    let mut found: Vec<(usize, usize, usize)> = queries
+      .iter()
+      .enumerate()
+      .flat_map(|(i, query)| query.find_all(text).into_iter().map(move |(s, e)| (s, e, i)))
+      .collect();
+    found.sort_by_key(|&(s, e, i)| (s, std::cmp::Reverse(e), i));
+    let mut ranges = vec![];
+    let mut at = 0;
+    for (s, e, i) in found {
+      if s < at {
+        continue;
+      }
+      at = e;
+      let (s, e) = mapped.as_ref().map_or((s, e), |mapped| mapped.origin(s, e));
+      ranges.push((s, e, i));
+    }
+    ranges
+  }
+
+  /// 从 `reader` 中逐行读取并搜索, 每个需要输出的行交给 `emit`
+  pub(crate) fn search_reader<R, F>(&self, mut reader: R, options: &SearchOptions, emit: F) -> io::Result<Summary>
+  where
+    R: BufRead,
+    F: FnMut(Line) -> io::Result<()>,
+  {
+    let head = reader.fill_buf()?;
+    let bom = Bom::sniff(head);
+    let binary = bom.is_none() && is_binary(head);
+    let mut window = Window::new(self, options, binary, emit);
+    match bom {
+      Some(Bom::Utf16Le) | Some(Bom::Utf16Be) => {
+        reader.consume(2);
+        let decoded = io::BufReader::new(Utf16Reader::new(reader, bom == Some(Bom::Utf16Le)));
+        window.offset = 2;
+        window.read(decoded)?;
+      }
+      Some(Bom::Utf8) => {
+        reader.consume(3);
+        window.offset = 3;
+        window.read(reader)?;
+      }
+      None => window.read(reader)?,
+    }
+    Ok(window.summary())
+  }
+
+  /// 搜索整块内存(例如内存映射的文件)
+  pub(crate) fn search_slice<F>(&self, bytes: &[u8], options: &SearchOptions, emit: F) -> io::Result<Summary>
+  where
+    F: FnMut(Line) -> io::Result<()>,
+  {
+    let bom = Bom::sniff(bytes);
+    if let Some(Bom::Utf16Le) | Some(Bom::Utf16Be) = bom {
+      return self.search_reader(bytes, options, emit);
+    }
+    let mut window = Window::new(self, options, bom.is_none() && is_binary(bytes), emit);
+    let skip = bom.map_or(0, Bom::len);
+    window.offset = skip;
+    window.push_lines(&bytes[skip..])?;
+    Ok(window.summary())
+  }
+}
+
+fn is_binary(bytes: &[u8]) -> bool {
+  bytes[..bytes.len().min(SNIFF_LEN)].contains(&0)
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SearchOptions {
+  pub(crate) before: usize,
+  pub(crate) after: usize,
+  /// 是否计算行内所有匹配的区间
+  pub(crate) highlight: bool,
+  pub(crate) binary: BinaryMode,
+  /// 选择不匹配的行
+  pub(crate) invert: bool,
+  /// 选中这么多行后停止, 之后只输出剩余的下文
+  pub(crate) max_count: Option<usize>,
+}
+
+/// 单个输入的搜索结果统计
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Summary {
+  /// 选中的行数
+  pub matches: usize,
+  /// 这是一个匹配的二进制文件, 其内容没有输出
+  pub binary_match: bool,
+}
+
+/// 输出的一行, `column` 为 None 时是上下文行, 反向匹配选中的行列号总是 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+  /// 从 1 开始的行号
+  pub number: usize,
+  /// 行首在输入中的字节偏移
+  pub offset: usize,
+  /// 第一个匹配的列号, 从 1 开始按字节计
+  pub column: Option<usize>,
+  /// 第一个匹配的模式序号, 反向匹配选中的行和上下文行没有
+  pub pattern: Option<usize>,
+  /// 近似匹配时第一个匹配的编辑距离
+  pub distance: Option<usize>,
+  pub text: String,
+  /// 行内所有匹配的区间, 命令行中仅在需要高亮、替换或 JSON/SARIF 输出时计算
+  pub ranges: Vec<(usize, usize)>,
+  /// `ranges` 中每个区间匹配到的模式序号
+  pub patterns: Vec<usize>,
+}
+
+/// 维护上下文窗口: 最近的 `before` 行暂存在队列中, 匹配后继续输出 `after` 行
+/// 重叠或相邻的窗口自然合并, 每行最多输出一次
+struct Window<'s, F> {
+  searcher: &'s Searcher,
+  options: &'s SearchOptions,
+  before: VecDeque<Line>,
+  after_left: usize,
+  number: usize,
+  offset: usize,
+  /// 已经判定为二进制文件, 不再输出内容
+  binary: bool,
+  binary_match: bool,
+  matches: usize,
+  /// 不需要再读取剩余内容
+  done: bool,
+  emit: F,
+}
+
+impl<'s, F> Window<'s, F>
+where
+  F: FnMut(Line) -> io::Result<()>,
+{
+  fn new(searcher: &'s Searcher, options: &'s SearchOptions, binary: bool, emit: F) -> Window<'s, F> {
+    let mut window = Window {
+      searcher,
+      options,
+      before: VecDeque::with_capacity(options.before),
+      after_left: 0,
+      number: 0,
+      offset: 0,
+      binary: false,
+      binary_match: false,
+      matches: 0,
+      done: false,
+      emit,
+    };
+    if binary {
+      window.found_binary();
+    }
+    window.done = window.done || options.max_count == Some(0);
+    window
+  }
+
+  fn summary(&self) -> Summary {
+    Summary {
+      matches: self.matches,
+      binary_match: self.binary_match,
+    }
+  }
+
+  fn limited(&self) -> bool {
+    self.options.max_count.is_some_and(|max| self.matches >= max)
+  }
+
+  fn found_binary(&mut self) {
+    match self.options.binary {
+      BinaryMode::Text => {}
+      BinaryMode::Binary => self.binary = true,
+      BinaryMode::WithoutMatch => self.done = true,
+    }
+  }
+
+  fn read<R: BufRead>(&mut self, mut reader: R) -> io::Result<()> {
+    let mut buf = vec![];
+    while !self.done {
+      let chunk = reader.fill_buf()?;
+      if chunk.is_empty() {
+        break;
+      }
+      //缓冲区中完整的行一起处理, 剩下不完整的一行单独读取
+      match chunk.iter().rposition(|&b| b == b'\n') {
+        Some(last) => {
+          self.push_lines(&chunk[..=last])?;
+          reader.consume(last + 1);
+        }
+        None => {
+          buf.clear();
+          reader.read_until(b'\n', &mut buf)?;
+          self.push(&buf)?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// 处理若干完整的行, 有字面量查询时先在整块内容中查找候选位置, 跳过其间不可能被选中的行
+  fn push_lines(&mut self, bytes: &[u8]) -> io::Result<()> {
+    let finder = match self.searcher.prefilter() {
+      Some(finder) if !self.options.invert => finder,
+      _ => return self.push_each(bytes),
+    };
+    let mut pos = 0;
+    while pos < bytes.len() && !self.done {
+      let next = match finder.candidate(bytes, pos) {
+        Some(found) => bytes[pos..found].iter().rposition(|&b| b == b'\n').map_or(pos, |i| pos + i + 1),
+        None => bytes.len(),
+      };
+      self.skip(&bytes[pos..next])?;
+      if next == bytes.len() || self.done {
+        break;
+      }
+      let end = bytes[next..].iter().position(|&b| b == b'\n').map_or(bytes.len(), |i| next + i + 1);
+      self.push(&bytes[next..end])?;
+      pos = end;
+    }
+    Ok(())
+  }
+
+  fn push_each(&mut self, bytes: &[u8]) -> io::Result<()> {
+    for raw in bytes.split_inclusive(|&b| b == b'\n') {
+      if self.done {
+        break;
+      }
+      self.push(raw)?;
+    }
+    Ok(())
+  }
+
+  /// 跳过确定不会被选中的若干完整行, 只处理仍需作为上下文输出的行
+  fn skip(&mut self, lines: &[u8]) -> io::Result<()> {
+    //二进制检测需要看到每一行
+    if !self.binary && self.options.binary != BinaryMode::Text && lines.contains(&0) {
+      return self.push_each(lines);
+    }
+    let mut rest = lines;
+    while self.after_left > 0 && !rest.is_empty() && !self.done {
+      let end = rest.iter().position(|&b| b == b'\n').map_or(rest.len(), |i| i + 1);
+      self.push(&rest[..end])?;
+      rest = &rest[end..];
+    }
+    //最后 `before` 行仍要进入上文队列
+    let mut keep = rest.len();
+    for _ in 0..self.options.before {
+      if keep == 0 {
+        break;
+      }
+      keep = rest[..keep - 1].iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    }
+    let skipped = &rest[..keep];
+    self.number += skipped.iter().filter(|&&b| b == b'\n').count();
+    self.offset += skipped.len();
+    self.push_each(&rest[keep..])
+  }
+
+  /// 处理一行原始内容(可能带有行尾的 `\n` 或 `\r\n`)
+  fn push(&mut self, raw: &[u8]) -> io::Result<()> {
+    let offset = self.offset;
+    self.offset += raw.len();
+    self.number += 1;
+    let stripped = raw.strip_suffix(b"\n").unwrap_or(raw);
+    let stripped = stripped.strip_suffix(b"\r").unwrap_or(stripped);
+    if !self.binary && self.options.binary != BinaryMode::Text && stripped.contains(&0) {
+      self.found_binary();
+      if self.done {
+        return Ok(());
+      }
+    }
+    let text = decode_line(stripped);
+    //达到 max_count 后只输出下文, 其中的匹配行也当作上下文
+    let limited = self.limited();
+    let found = if limited { None } else { self.searcher.find_at(&text, 0) };
+    let selected = !limited && found.is_some() != self.options.invert;
+    if self.binary {
+      if selected {
+        self.matches += 1;
+        self.binary_match = true;
+        self.done = true;
+      }
+      return Ok(());
+    }
+
+    let mut line = Line {
+      number: self.number,
+      offset,
+      column: None,
+      pattern: None,
+      distance: None,
+      text: String::new(),
+      ranges: vec![],
+      patterns: vec![],
+    };
+    if selected {
+      for context in self.before.drain(..) {
+        (self.emit)(context)?;
+      }
+      self.matches += 1;
+      line.column = Some(found.map_or(1, |(start, _, _)| start + 1));
+      line.pattern = found.map(|(_, _, pattern)| pattern);
+      line.distance = found.and_then(|found| self.searcher.distance(&text, found));
+      if self.options.highlight && found.is_some() {
+        let all = self.searcher.find_all(&text);
+        line.ranges = all.iter().map(|&(start, end, _)| (start, end)).collect();
+        line.patterns = all.iter().map(|&(_, _, pattern)| pattern).collect();
+      }
+      line.text = text.into_owned();
+      self.after_left = self.options.after;
+      self.done = self.limited() && self.after_left == 0;
+      (self.emit)(line)
+    } else if self.after_left > 0 {
+      self.after_left -= 1;
+      self.done = limited && self.after_left == 0;
+      line.text = text.into_owned();
+      (self.emit)(line)
+    } else {
+      if self.options.before > 0 {
+        if self.before.len() == self.options.before {
+          self.before.pop_front();
+        }
+        line.text = text.into_owned();
+        self.before.push_back(line);
+      }
+      Ok(())
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn numbers(contents: &str, before: usize, after: usize) -> Vec<(usize, bool)> {
+    let searcher = Searcher::from(Pattern::Literal(Finder::new("x", false)));
+    let options = SearchOptions {
+      before,
+      after,
+      ..SearchOptions::default()
+    };
+    let mut lines = vec![];
+    searcher
+      .search_reader(contents.as_bytes(), &options, |line| {
+        lines.push((line.number, line.column.is_some()));
+        Ok(())
+      })
+      .unwrap();
+    lines
+  }
+
+  #[test]
+  fn context_windows() {
+    assert!(numbers("a\nb\nc\n", 2, 2).is_empty());
+
+    assert_eq!(vec![(1, false), (2, true), (3, false)], numbers("a\nx\nb\nc\n", 1, 1));
+    //窗口在文件边界处截断
+    assert_eq!(vec![(1, true), (2, false), (3, false)], numbers("x\na\nb", 2, 5));
+    //重叠的窗口合并, 落在上一个下文中的匹配行仍标记为匹配
+    assert_eq!(
+      vec![(2, false), (3, true), (4, false), (5, true), (6, false)],
+      numbers("a\na\nx\na\nx\na\na\n", 1, 1)
+    );
+    //不相交的窗口保持分离
+    assert_eq!(
+      vec![(1, false), (2, true), (7, false), (8, true)],
+      numbers("a\nx\na\na\na\na\na\nx\n", 1, 0)
+    );
+    assert_eq!(vec![(4, true), (5, true)], numbers("a\na\na\nx\nx\n", 0, 0));
+  }
+
+  #[test]
+  fn invert_and_max_count() {
+    let searcher = Searcher::from(Pattern::Literal(Finder::new("x", false)));
+    let run = |options: SearchOptions| {
+      let mut lines = vec![];
+      let summary = searcher
+        .search_reader(&b"x\na\nx\nb\nx\nx\n"[..], &options, |line| {
+          lines.push((line.number, line.column));
+          Ok(())
+        })
+        .unwrap();
+      (lines, summary.matches)
+    };
+    let invert = SearchOptions {
+      invert: true,
+      ..SearchOptions::default()
+    };
+    assert_eq!((vec![(2, Some(1)), (4, Some(1))], 2), run(invert));
+
+    //达到上限后仍输出下文, 下文中的匹配行不再计数
+    let max = SearchOptions {
+      after: 2,
+      max_count: Some(2),
+      ..SearchOptions::default()
+    };
+    assert_eq!((vec![(1, Some(1)), (2, None), (3, Some(1)), (4, None), (5, None)], 2), run(max));
+    let none = SearchOptions {
+      max_count: Some(0),
+      ..SearchOptions::default()
+    };
+    assert_eq!((vec![], 0), run(none));
+  }
+
+  #[test]
+  fn prefilter_matches_line_by_line() {
+    let contents = [include_bytes!("../poem.txt").repeat(3), b"caf\xe9 CAF\xc9\n".to_vec()].concat();
+    let collect = |searcher: &Searcher, options: &SearchOptions| {
+      let mut lines = vec![];
+      let summary = searcher
+        .search_slice(&contents, options, |line| {
+          lines.push(line);
+          Ok(())
+        })
+        .unwrap();
+      (lines, summary)
+    };
+    for query in &["nobody", "o", "How", "é", "zzz"] {
+      for &(before, after, max_count) in &[(0, 0, None), (2, 1, None), (1, 3, Some(2)), (5, 0, Some(4))] {
+        let options = SearchOptions {
+          before,
+          after,
+          max_count,
+          ..SearchOptions::default()
+        };
+        //正则查询没有前置过滤, 逐行搜索
+        let by_line = Searcher::from(Pattern::Regex(Regex::new(query, true).unwrap()));
+        let buffered = Searcher::from(Pattern::Literal(Finder::new(query, true)));
+        assert_eq!(collect(&by_line, &options), collect(&buffered, &options), "{}", query);
+      }
+    }
+  }
+
+  #[test]
+  fn slice_matches_reader() {
+    let searcher = Searcher::from(Pattern::Regex(Regex::new("o.y", false).unwrap()));
+    let contents = include_str!("../poem.txt");
+    let options = SearchOptions {
+      before: 1,
+      after: 2,
+      highlight: true,
+      ..SearchOptions::default()
+    };
+    let (mut from_reader, mut from_slice) = (vec![], vec![]);
+    searcher
+      .search_reader(contents.as_bytes(), &options, |line| {
+        from_reader.push(line);
+        Ok(())
+      })
+      .unwrap();
+    searcher
+      .search_slice(contents.as_bytes(), &options, |line| {
+        from_slice.push(line);
+        Ok(())
+      })
+      .unwrap();
+    assert_eq!(from_reader, from_slice);
+    assert_eq!((1, 0, Some(8)), (from_slice[0].number, from_slice[0].offset, from_slice[0].column));
+    assert_eq!(vec![(7, 10)], from_slice[0].ranges);
+  }
+
+  fn search(contents: &[u8], binary: BinaryMode) -> (Vec<String>, bool) {
+    let searcher = Searcher::from(Pattern::Literal(Finder::new("café", true)));
+    let options = SearchOptions {
+      binary,
+      ..SearchOptions::default()
+    };
+    let mut lines = vec![];
+    let summary = searcher
+      .search_reader(contents, &options, |line| {
+        lines.push(line.text);
+        Ok(())
+      })
+      .unwrap();
+    let mut from_slice = vec![];
+    let slice_summary = searcher
+      .search_slice(contents, &options, |line| {
+        from_slice.push(line.text);
+        Ok(())
+      })
+      .unwrap();
+    assert_eq!((&lines, summary), (&from_slice, slice_summary));
+    (lines, summary.binary_match)
+  }
+
+  #[test]
+  fn binary_files() {
+    let contents = "\x7fELF\0\0\ncafé au lait\n".as_bytes();
+    assert_eq!((vec![], true), search(contents, BinaryMode::Binary));
+    assert_eq!((vec![], false), search(contents, BinaryMode::WithoutMatch));
+    assert_eq!((vec!["café au lait".to_string()], false), search(contents, BinaryMode::Text));
+    assert_eq!((vec![], false), search(b"\0\0\ntea\n", BinaryMode::Binary));
+
+    //开头之后才出现的 NUL 同样会把文件判定为二进制
+    let late = [&b"cafe\n".repeat(SNIFF_LEN)[..], b"\0\nCAF\xc9\n"].concat();
+    assert_eq!((vec![], true), search(&late, BinaryMode::Binary));
+  }
+
+  #[test]
+  fn other_encodings() {
+    assert_eq!((vec!["un caf\u{e9}".to_string()], false), search(b"un caf\xe9\nthe", BinaryMode::Binary));
+
+    let utf16: Vec<u8> = [0xFF, 0xFE]
+      .iter()
+      .copied()
+      .chain("tea\r\nCAFÉ\n".encode_utf16().flat_map(u16::to_le_bytes))
+      .collect();
+    assert_eq!((vec!["CAFÉ".to_string()], false), search(&utf16, BinaryMode::Binary));
+    assert_eq!((vec!["café".to_string()], false), search("\u{feff}café".as_bytes(), BinaryMode::Binary));
+  }
+}
@@ -0,0 +1,64 @@
+//! 接收搜索结果的公共接口, 命令行的输出(`Printer`)也是通过它完成的
+use crate::{
+  searcher::{Line, Summary},
+  Error,
+};
+use std::{io, path::Path};
+
+/// 按文件顺序接收搜索结果: 每个文件先 `begin`, 然后是它的行, 最后 `end`
+/// 返回错误时停止搜索
+pub trait Sink {
+  /// 开始一个新文件, 标准输入的名字是 `(standard input)`
+  fn begin(&mut self, _file: &Path) -> io::Result<()> {
+    Ok(())
+  }
+
+  /// 选中的行, 反向匹配时没有匹配区间
+  fn matched(&mut self, file: &Path, line: &Line) -> io::Result<()>;
+
+  /// `-A`/`-B`/`-C` 要求的上下文行
+  fn context(&mut self, _file: &Path, _line: &Line) -> io::Result<()> {
+    Ok(())
+  }
+
+  /// 一个文件结束, 只关心数量或文件名(`-c`/`-l`/`-L`/`-q`)时只会收到这一次调用
+  fn end(&mut self, _file: &Path, _summary: &Summary) -> io::Result<()> {
+    Ok(())
+  }
+
+  /// 无法读取的文件或目录, 之后继续搜索其他文件, 默认忽略
+  fn error(&mut self, _error: &Error) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+  fn begin(&mut self, file: &Path) -> io::Result<()> {
+    (**self).begin(file)
+  }
+
+  fn matched(&mut self, file: &Path, line: &Line) -> io::Result<()> {
+    (**self).matched(file, line)
+  }
+
+  fn context(&mut self, file: &Path, line: &Line) -> io::Result<()> {
+    (**self).context(file, line)
+  }
+
+  fn end(&mut self, file: &Path, summary: &Summary) -> io::Result<()> {
+    (**self).end(file, summary)
+  }
+
+  fn error(&mut self, error: &Error) -> io::Result<()> {
+    (**self).error(error)
+  }
+}
+
+/// 把一行交给 `sink` 对应的方法
+pub(crate) fn emit<S: Sink + ?Sized>(sink: &mut S, file: &Path, line: &Line) -> io::Result<()> {
+  if line.column.is_some() {
+    sink.matched(file, line)
+  } else {
+    sink.context(file, line)
+  }
+}
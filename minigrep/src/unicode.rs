@@ -0,0 +1,306 @@
+//! Unicode 大小写折叠和规范化(NFC/NFD), 用到的表由 tools/unicode_tables.py 生成
+use crate::unicode_tables::{COMBINING_CLASS, COMPOSITION, DECOMPOSITION, FULL_FOLD, SIMPLE_FOLD};
+
+/// 忽略大小写时使用的折叠方式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Fold {
+  /// 每个字符折叠为一个字符, `ß` 与 `SS` 不相等
+  Simple,
+  /// 一个字符可以折叠为多个字符, `ß` 与 `SS` 相等
+  #[default]
+  Full,
+  /// 在 Full 的基础上使用土耳其语规则: `I` 对应 `ı`, `İ` 对应 `i`
+  Turkic,
+}
+
+impl Fold {
+  pub fn parse(mode: &str) -> Option<Fold> {
+    match mode {
+      "simple" => Some(Fold::Simple),
+      "full" => Some(Fold::Full),
+      "turkic" => Some(Fold::Turkic),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Normalization {
+  /// 规范组合
+  Nfc,
+  /// 规范分解
+  Nfd,
+}
+
+impl Normalization {
+  pub fn parse(form: &str) -> Option<Normalization> {
+    match form {
+      "nfc" | "NFC" => Some(Normalization::Nfc),
+      "nfd" | "NFD" => Some(Normalization::Nfd),
+      _ => None,
+    }
+  }
+}
+
+/// 一个字符折叠后的字符序列
+pub(crate) enum FoldChars {
+  One(Option<char>),
+  Many(std::str::Chars<'static>),
+}
+
+impl Iterator for FoldChars {
+  type Item = char;
+
+  fn next(&mut self) -> Option<char> {
+    match self {
+      FoldChars::One(c) => c.take(),
+      FoldChars::Many(chars) => chars.next(),
+    }
+  }
+}
+
+pub(crate) fn fold(c: char, mode: Fold) -> FoldChars {
+  match (mode, c) {
+    (Fold::Turkic, 'I') => FoldChars::One(Some('ı')),
+    (Fold::Turkic, 'İ') => FoldChars::One(Some('i')),
+    _ if c.is_ascii() => FoldChars::One(Some(c.to_ascii_lowercase())),
+    (Fold::Simple, _) => {
+      let folded = SIMPLE_FOLD.binary_search_by_key(&c, |&(from, _)| from);
+      FoldChars::One(Some(folded.map_or(c, |i| SIMPLE_FOLD[i].1)))
+    }
+    _ => match FULL_FOLD.binary_search_by_key(&c, |&(from, _)| from) {
+      Ok(i) => FoldChars::Many(FULL_FOLD[i].1.chars()),
+      Err(_) => FoldChars::One(Some(c)),
+    },
+  }
+}
+
+pub fn fold_str(text: &str, mode: Fold) -> String {
+  text.chars().flat_map(|c| fold(c, mode)).collect()
+}
+
+/// 折叠结果以 `first` 开头的所有字符, 包括 `first` 本身
+pub(crate) fn folds_starting_with(first: char, mode: Fold) -> Vec<char> {
+  let mut chars = vec![first];
+  let keys = FULL_FOLD.iter().map(|&(c, _)| c).chain(SIMPLE_FOLD.iter().map(|&(c, _)| c));
+  for c in keys {
+    if c != first && fold(c, mode).next() == Some(first) && !chars.contains(&c) {
+      chars.push(c);
+    }
+  }
+  chars
+}
+
+pub fn normalize(text: &str, form: Normalization) -> String {
+  Mapped::normalize(text, form).text
+}
+
+/// 字符及其在原文中的字节区间
+type Item = (char, usize, usize);
+
+/// 变换后的文本, 记录每个字节来自原文的哪个区间, 用于把匹配位置映射回原文
+pub(crate) struct Mapped {
+  pub(crate) text: String,
+  starts: Vec<usize>,
+  ends: Vec<usize>,
+  len: usize,
+}
+
+impl Mapped {
+  pub(crate) fn fold(line: &str, mode: Fold) -> Mapped {
+    let items = line
+      .char_indices()
+      .flat_map(|(i, c)| fold(c, mode).map(move |f| (f, i, i + c.len_utf8())));
+    Mapped::from_items(items, line.len())
+  }
+
+  pub(crate) fn normalize(line: &str, form: Normalization) -> Mapped {
+    let items = decompose(line);
+    match form {
+      Normalization::Nfd => Mapped::from_items(items, line.len()),
+      Normalization::Nfc => Mapped::from_items(compose(items), line.len()),
+    }
+  }
+
+  fn from_items<I: IntoIterator<Item = Item>>(items: I, len: usize) -> Mapped {
+    let mut mapped = Mapped {
+      text: String::with_capacity(len),
+      starts: Vec::with_capacity(len),
+      ends: Vec::with_capacity(len),
+      len,
+    };
+    for (c, start, end) in items {
+      mapped.text.push(c);
+      mapped.starts.resize(mapped.text.len(), start);
+      mapped.ends.resize(mapped.text.len(), end);
+    }
+    mapped
+  }
+
+  /// 原文 `at` 处对应的变换后文本的位置
+  pub(crate) fn position(&self, at: usize) -> usize {
+    self.starts.iter().position(|&start| start >= at).unwrap_or(self.text.len())
+  }
+
+  /// `at` 是否位于两个原始字符的变换结果之间, 例如 `ß` 折叠成的 `ss` 中间不是
+  pub(crate) fn is_boundary(&self, at: usize) -> bool {
+    at == 0 || at >= self.text.len() || self.starts[at] != self.starts[at - 1]
+  }
+
+  /// 把变换后文本中的区间映射回原文, 结果覆盖所有相关的原始字符
+  pub(crate) fn origin(&self, start: usize, end: usize) -> (usize, usize) {
+    let from = self.starts.get(start).copied().unwrap_or(self.len);
+    let to = if end > start { self.ends[end - 1] } else { from };
+    (from, to.max(from))
+  }
+}
+
+const S_BASE: u32 = 0xAC00;
+const L_BASE: u32 = 0x1100;
+const V_BASE: u32 = 0x1161;
+const T_BASE: u32 = 0x11A7;
+const V_COUNT: u32 = 21;
+const T_COUNT: u32 = 28;
+const N_COUNT: u32 = V_COUNT * T_COUNT;
+const S_COUNT: u32 = 19 * N_COUNT;
+
+fn combining_class(c: char) -> u8 {
+  if c.is_ascii() {
+    return 0;
+  }
+  let found = COMBINING_CLASS.binary_search_by(|&(lo, hi, _)| {
+    if hi < c {
+      std::cmp::Ordering::Less
+    } else if lo > c {
+      std::cmp::Ordering::Greater
+    } else {
+      std::cmp::Ordering::Equal
+    }
+  });
+  found.map_or(0, |i| COMBINING_CLASS[i].2)
+}
+
+/// 完全分解后按规范顺序排列(连续的非起始字符按组合类稳定排序)
+fn decompose(text: &str) -> Vec<Item> {
+  let mut items = Vec::with_capacity(text.len());
+  for (i, c) in text.char_indices() {
+    let end = i + c.len_utf8();
+    let s = (c as u32).wrapping_sub(S_BASE);
+    if s < S_COUNT {
+      //韩文音节按算法分解为初声、中声和可选的终声
+      let jamo = [L_BASE + s / N_COUNT, V_BASE + (s % N_COUNT) / T_COUNT, T_BASE + s % T_COUNT];
+      let count = if jamo[2] == T_BASE { 2 } else { 3 };
+      items.extend(jamo[..count].iter().filter_map(|&j| char::from_u32(j)).map(|j| (j, i, end)));
+    } else if c.is_ascii() {
+      items.push((c, i, end));
+    } else {
+      match DECOMPOSITION.binary_search_by_key(&c, |&(from, _)| from) {
+        Ok(found) => items.extend(DECOMPOSITION[found].1.chars().map(|d| (d, i, end))),
+        Err(_) => items.push((c, i, end)),
+      }
+    }
+  }
+
+  let mut at = 0;
+  while at < items.len() {
+    if combining_class(items[at].0) == 0 {
+      at += 1;
+      continue;
+    }
+    let run = items[at..].iter().position(|item| combining_class(item.0) == 0);
+    let end = run.map_or(items.len(), |n| at + n);
+    items[at..end].sort_by_key(|item| combining_class(item.0));
+    at = end;
+  }
+  items
+}
+
+fn compose_pair(a: char, b: char) -> Option<char> {
+  let (a32, b32) = (a as u32, b as u32);
+  if (L_BASE..L_BASE + 19).contains(&a32) && (V_BASE..V_BASE + V_COUNT).contains(&b32) {
+    return char::from_u32(S_BASE + ((a32 - L_BASE) * V_COUNT + b32 - V_BASE) * T_COUNT);
+  }
+  let s = a32.wrapping_sub(S_BASE);
+  if s < S_COUNT && s % T_COUNT == 0 && (T_BASE + 1..T_BASE + T_COUNT).contains(&b32) {
+    return char::from_u32(a32 + b32 - T_BASE);
+  }
+  COMPOSITION
+    .binary_search_by_key(&(a, b), |&(x, y, _)| (x, y))
+    .ok()
+    .map(|i| COMPOSITION[i].2)
+}
+
+/// 规范组合: 每个字符尝试与前面最近的、没有被阻隔的起始字符组合
+fn compose(items: Vec<Item>) -> Vec<Item> {
+  let mut out: Vec<Item> = Vec::with_capacity(items.len());
+  let mut starter = None;
+  for item in items {
+    let class = combining_class(item.0);
+    if let Some(at) = starter {
+      //中间的字符组合类为 0 或不小于当前字符时, 当前字符被阻隔
+      let blocked = out.len() > at + 1 && {
+        let last = combining_class(out[out.len() - 1].0);
+        last == 0 || last >= class
+      };
+      if !blocked {
+        let (base, start, end): Item = out[at];
+        if let Some(composed) = compose_pair(base, item.0) {
+          out[at] = (composed, start.min(item.1), end.max(item.2));
+          continue;
+        }
+      }
+    }
+    if class == 0 {
+      starter = Some(out.len());
+    }
+    out.push(item);
+  }
+  out
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn case_folding() {
+    assert_eq!("strasse", fold_str("STRAẞE", Fold::Full));
+    assert_eq!("straße", fold_str("STRAẞE", Fold::Simple));
+    assert_eq!("σοφοσ", fold_str("ΣΟΦΟΣ", Fold::Full));
+    assert_eq!(fold_str("σοφος", Fold::Simple), fold_str("ΣΟΦΟΣ", Fold::Simple));
+    assert_eq!("i\u{307}stanbul", fold_str("İSTANBUL", Fold::Full));
+    assert_eq!("istanbul", fold_str("İstanbul", Fold::Turkic));
+    assert_eq!("ılık", fold_str("ILIK", Fold::Turkic));
+    assert_eq!("k", fold_str("\u{212A}", Fold::Simple));
+
+    let starts = folds_starting_with('s', Fold::Full);
+    assert!(['s', 'S', 'ß', 'ẞ', 'ſ'].iter().all(|c| starts.contains(c)));
+  }
+
+  #[test]
+  fn normalization() {
+    let composed = "Ångström café 한국어";
+    let decomposed = normalize(composed, Normalization::Nfd);
+    assert_eq!("A\u{30A}ngstro\u{308}m cafe\u{301} \u{1112}\u{1161}\u{11AB}\u{1100}\u{116E}\u{11A8}\u{110B}\u{1165}", decomposed);
+    assert_eq!(composed, normalize(&decomposed, Normalization::Nfc));
+    //组合标记按组合类重新排序后再组合
+    assert_eq!("\u{1E69}", normalize("s\u{307}\u{323}", Normalization::Nfc));
+    assert_eq!("s\u{323}\u{307}", normalize("\u{1E69}", Normalization::Nfd));
+    //组合排除表中的字符不会被重新组合
+    assert_eq!("\u{915}\u{93C}", normalize("\u{958}", Normalization::Nfc));
+  }
+
+  #[test]
+  fn mapped_offsets() {
+    let mapped = Mapped::normalize("cafe\u{301}!", Normalization::Nfc);
+    assert_eq!("café!", mapped.text);
+    assert_eq!((3, 6), mapped.origin(3, 5));
+    assert_eq!((6, 7), mapped.origin(5, 6));
+
+    let mapped = Mapped::fold("Maße", Fold::Full);
+    assert_eq!("masse", mapped.text);
+    assert_eq!((2, 4), mapped.origin(2, 3));
+    assert_eq!((0, 5), mapped.origin(0, 5));
+    assert!(mapped.is_boundary(2) && mapped.is_boundary(4) && !mapped.is_boundary(3));
+  }
+}
@@ -0,0 +1,1085 @@
+//! 由 tools/unicode_tables.py 生成, 不要手动修改
+//! Unicode 14.0.0
+
+/// 完整大小写折叠(CaseFolding.txt 的 C+F), 只列出会改变的字符
+pub(crate) const FULL_FOLD: &[(char, &str)] = &[
+  ('\u{41}', "\u{61}"), ('\u{42}', "\u{62}"), ('\u{43}', "\u{63}"), ('\u{44}', "\u{64}"), ('\u{45}', "\u{65}"), ('\u{46}', "\u{66}"),
+  ('\u{47}', "\u{67}"), ('\u{48}', "\u{68}"), ('\u{49}', "\u{69}"), ('\u{4A}', "\u{6A}"), ('\u{4B}', "\u{6B}"), ('\u{4C}', "\u{6C}"),
+  ('\u{4D}', "\u{6D}"), ('\u{4E}', "\u{6E}"), ('\u{4F}', "\u{6F}"), ('\u{50}', "\u{70}"), ('\u{51}', "\u{71}"), ('\u{52}', "\u{72}"),
+  ('\u{53}', "\u{73}"), ('\u{54}', "\u{74}"), ('\u{55}', "\u{75}"), ('\u{56}', "\u{76}"), ('\u{57}', "\u{77}"), ('\u{58}', "\u{78}"),
+  ('\u{59}', "\u{79}"), ('\u{5A}', "\u{7A}"), ('\u{B5}', "\u{3BC}"), ('\u{C0}', "\u{E0}"), ('\u{C1}', "\u{E1}"), ('\u{C2}', "\u{E2}"),
+  ('\u{C3}', "\u{E3}"), ('\u{C4}', "\u{E4}"), ('\u{C5}', "\u{E5}"), ('\u{C6}', "\u{E6}"), ('\u{C7}', "\u{E7}"), ('\u{C8}', "\u{E8}"),
+  ('\u{C9}', "\u{E9}"), ('\u{CA}', "\u{EA}"), ('\u{CB}', "\u{EB}"), ('\u{CC}', "\u{EC}"), ('\u{CD}', "\u{ED}"), ('\u{CE}', "\u{EE}"),
+  ('\u{CF}', "\u{EF}"), ('\u{D0}', "\u{F0}"), ('\u{D1}', "\u{F1}"), ('\u{D2}', "\u{F2}"), ('\u{D3}', "\u{F3}"), ('\u{D4}', "\u{F4}"),
+  ('\u{D5}', "\u{F5}"), ('\u{D6}', "\u{F6}"), ('\u{D8}', "\u{F8}"), ('\u{D9}', "\u{F9}"), ('\u{DA}', "\u{FA}"), ('\u{DB}', "\u{FB}"),
+  ('\u{DC}', "\u{FC}"), ('\u{DD}', "\u{FD}"), ('\u{DE}', "\u{FE}"), ('\u{DF}', "\u{73}\u{73}"), ('\u{100}', "\u{101}"), ('\u{102}', "\u{103}"),
+  ('\u{104}', "\u{105}"), ('\u{106}', "\u{107}"), ('\u{108}', "\u{109}"), ('\u{10A}', "\u{10B}"), ('\u{10C}', "\u{10D}"), ('\u{10E}', "\u{10F}"),
+  ('\u{110}', "\u{111}"), ('\u{112}', "\u{113}"), ('\u{114}', "\u{115}"), ('\u{116}', "\u{117}"), ('\u{118}', "\u{119}"), ('\u{11A}', "\u{11B}"),
+  ('\u{11C}', "\u{11D}"), ('\u{11E}', "\u{11F}"), ('\u{120}', "\u{121}"), ('\u{122}', "\u{123}"), ('\u{124}', "\u{125}"), ('\u{126}', "\u{127}"),
+  ('\u{128}', "\u{129}"), ('\u{12A}', "\u{12B}"), ('\u{12C}', "\u{12D}"), ('\u{12E}', "\u{12F}"), ('\u{130}', "\u{69}\u{307}"), ('\u{132}', "\u{133}"),
+  ('\u{134}', "\u{135}"), ('\u{136}', "\u{137}"), ('\u{139}', "\u{13A}"), ('\u{13B}', "\u{13C}"), ('\u{13D}', "\u{13E}"), ('\u{13F}', "\u{140}"),
+  ('\u{141}', "\u{142}"), ('\u{143}', "\u{144}"), ('\u{145}', "\u{146}"), ('\u{147}', "\u{148}"), ('\u{149}', "\u{2BC}\u{6E}"), ('\u{14A}', "\u{14B}"),
+  ('\u{14C}', "\u{14D}"), ('\u{14E}', "\u{14F}"), ('\u{150}', "\u{151}"), ('\u{152}', "\u{153}"), ('\u{154}', "\u{155}"), ('\u{156}', "\u{157}"),
+  ('\u{158}', "\u{159}"), ('\u{15A}', "\u{15B}"), ('\u{15C}', "\u{15D}"), ('\u{15E}', "\u{15F}"), ('\u{160}', "\u{161}"), ('\u{162}', "\u{163}"),
+  ('\u{164}', "\u{165}"), ('\u{166}', "\u{167}"), ('\u{168}', "\u{169}"), ('\u{16A}', "\u{16B}"), ('\u{16C}', "\u{16D}"), ('\u{16E}', "\u{16F}"),
+  ('\u{170}', "\u{171}"), ('\u{172}', "\u{173}"), ('\u{174}', "\u{175}"), ('\u{176}', "\u{177}"), ('\u{178}', "\u{FF}"), ('\u{179}', "\u{17A}"),
+  ('\u{17B}', "\u{17C}"), ('\u{17D}', "\u{17E}"), ('\u{17F}', "\u{73}"), ('\u{181}', "\u{253}"), ('\u{182}', "\u{183}"), ('\u{184}', "\u{185}"),
+  ('\u{186}', "\u{254}"), ('\u{187}', "\u{188}"), ('\u{189}', "\u{256}"), ('\u{18A}', "\u{257}"), ('\u{18B}', "\u{18C}"), ('\u{18E}', "\u{1DD}"),
+  ('\u{18F}', "\u{259}"), ('\u{190}', "\u{25B}"), ('\u{191}', "\u{192}"), ('\u{193}', "\u{260}"), ('\u{194}', "\u{263}"), ('\u{196}', "\u{269}"),
+  ('\u{197}', "\u{268}"), ('\u{198}', "\u{199}"), ('\u{19C}', "\u{26F}"), ('\u{19D}', "\u{272}"), ('\u{19F}', "\u{275}"), ('\u{1A0}', "\u{1A1}"),
+  ('\u{1A2}', "\u{1A3}"), ('\u{1A4}', "\u{1A5}"), ('\u{1A6}', "\u{280}"), ('\u{1A7}', "\u{1A8}"), ('\u{1A9}', "\u{283}"), ('\u{1AC}', "\u{1AD}"),
+  ('\u{1AE}', "\u{288}"), ('\u{1AF}', "\u{1B0}"), ('\u{1B1}', "\u{28A}"), ('\u{1B2}', "\u{28B}"), ('\u{1B3}', "\u{1B4}"), ('\u{1B5}', "\u{1B6}"),
+  ('\u{1B7}', "\u{292}"), ('\u{1B8}', "\u{1B9}"), ('\u{1BC}', "\u{1BD}"), ('\u{1C4}', "\u{1C6}"), ('\u{1C5}', "\u{1C6}"), ('\u{1C7}', "\u{1C9}"),
+  ('\u{1C8}', "\u{1C9}"), ('\u{1CA}', "\u{1CC}"), ('\u{1CB}', "\u{1CC}"), ('\u{1CD}', "\u{1CE}"), ('\u{1CF}', "\u{1D0}"), ('\u{1D1}', "\u{1D2}"),
+  ('\u{1D3}', "\u{1D4}"), ('\u{1D5}', "\u{1D6}"), ('\u{1D7}', "\u{1D8}"), ('\u{1D9}', "\u{1DA}"), ('\u{1DB}', "\u{1DC}"), ('\u{1DE}', "\u{1DF}"),
+  ('\u{1E0}', "\u{1E1}"), ('\u{1E2}', "\u{1E3}"), ('\u{1E4}', "\u{1E5}"), ('\u{1E6}', "\u{1E7}"), ('\u{1E8}', "\u{1E9}"), ('\u{1EA}', "\u{1EB}"),
+  ('\u{1EC}', "\u{1ED}"), ('\u{1EE}', "\u{1EF}"), ('\u{1F0}', "\u{6A}\u{30C}"), ('\u{1F1}', "\u{1F3}"), ('\u{1F2}', "\u{1F3}"), ('\u{1F4}', "\u{1F5}"),
+  ('\u{1F6}', "\u{195}"), ('\u{1F7}', "\u{1BF}"), ('\u{1F8}', "\u{1F9}"), ('\u{1FA}', "\u{1FB}"), ('\u{1FC}', "\u{1FD}"), ('\u{1FE}', "\u{1FF}"),
+  ('\u{200}', "\u{201}"), ('\u{202}', "\u{203}"), ('\u{204}', "\u{205}"), ('\u{206}', "\u{207}"), ('\u{208}', "\u{209}"), ('\u{20A}', "\u{20B}"),
+  ('\u{20C}', "\u{20D}"), ('\u{20E}', "\u{20F}"), ('\u{210}', "\u{211}"), ('\u{212}', "\u{213}"), ('\u{214}', "\u{215}"), ('\u{216}', "\u{217}"),
+  ('\u{218}', "\u{219}"), ('\u{21A}', "\u{21B}"), ('\u{21C}', "\u{21D}"), ('\u{21E}', "\u{21F}"), ('\u{220}', "\u{19E}"), ('\u{222}', "\u{223}"),
+  ('\u{224}', "\u{225}"), ('\u{226}', "\u{227}"), ('\u{228}', "\u{229}"), ('\u{22A}', "\u{22B}"), ('\u{22C}', "\u{22D}"), ('\u{22E}', "\u{22F}"),
+  ('\u{230}', "\u{231}"), ('\u{232}', "\u{233}"), ('\u{23A}', "\u{2C65}"), ('\u{23B}', "\u{23C}"), ('\u{23D}', "\u{19A}"), ('\u{23E}', "\u{2C66}"),
+  ('\u{241}', "\u{242}"), ('\u{243}', "\u{180}"), ('\u{244}', "\u{289}"), ('\u{245}', "\u{28C}"), ('\u{246}', "\u{247}"), ('\u{248}', "\u{249}"),
+  ('\u{24A}', "\u{24B}"), ('\u{24C}', "\u{24D}"), ('\u{24E}', "\u{24F}"), ('\u{345}', "\u{3B9}"), ('\u{370}', "\u{371}"), ('\u{372}', "\u{373}"),
+  ('\u{376}', "\u{377}"), ('\u{37F}', "\u{3F3}"), ('\u{386}', "\u{3AC}"), ('\u{388}', "\u{3AD}"), ('\u{389}', "\u{3AE}"), ('\u{38A}', "\u{3AF}"),
+  ('\u{38C}', "\u{3CC}"), ('\u{38E}', "\u{3CD}"), ('\u{38F}', "\u{3CE}"), ('\u{390}', "\u{3B9}\u{308}\u{301}"), ('\u{391}', "\u{3B1}"), ('\u{392}', "\u{3B2}"),
+  ('\u{393}', "\u{3B3}"), ('\u{394}', "\u{3B4}"), ('\u{395}', "\u{3B5}"), ('\u{396}', "\u{3B6}"), ('\u{397}', "\u{3B7}"), ('\u{398}', "\u{3B8}"),
+  ('\u{399}', "\u{3B9}"), ('\u{39A}', "\u{3BA}"), ('\u{39B}', "\u{3BB}"), ('\u{39C}', "\u{3BC}"), ('\u{39D}', "\u{3BD}"), ('\u{39E}', "\u{3BE}"),
+  ('\u{39F}', "\u{3BF}"), ('\u{3A0}', "\u{3C0}"), ('\u{3A1}', "\u{3C1}"), ('\u{3A3}', "\u{3C3}"), ('\u{3A4}', "\u{3C4}"), ('\u{3A5}', "\u{3C5}"),
+  ('\u{3A6}', "\u{3C6}"), ('\u{3A7}', "\u{3C7}"), ('\u{3A8}', "\u{3C8}"), ('\u{3A9}', "\u{3C9}"), ('\u{3AA}', "\u{3CA}"), ('\u{3AB}', "\u{3CB}"),
+  ('\u{3B0}', "\u{3C5}\u{308}\u{301}"), ('\u{3C2}', "\u{3C3}"), ('\u{3CF}', "\u{3D7}"), ('\u{3D0}', "\u{3B2}"), ('\u{3D1}', "\u{3B8}"), ('\u{3D5}', "\u{3C6}"),
+  ('\u{3D6}', "\u{3C0}"), ('\u{3D8}', "\u{3D9}"), ('\u{3DA}', "\u{3DB}"), ('\u{3DC}', "\u{3DD}"), ('\u{3DE}', "\u{3DF}"), ('\u{3E0}', "\u{3E1}"),
+  ('\u{3E2}', "\u{3E3}"), ('\u{3E4}', "\u{3E5}"), ('\u{3E6}', "\u{3E7}"), ('\u{3E8}', "\u{3E9}"), ('\u{3EA}', "\u{3EB}"), ('\u{3EC}', "\u{3ED}"),
+  ('\u{3EE}', "\u{3EF}"), ('\u{3F0}', "\u{3BA}"), ('\u{3F1}', "\u{3C1}"), ('\u{3F4}', "\u{3B8}"), ('\u{3F5}', "\u{3B5}"), ('\u{3F7}', "\u{3F8}"),
+  ('\u{3F9}', "\u{3F2}"), ('\u{3FA}', "\u{3FB}"), ('\u{3FD}', "\u{37B}"), ('\u{3FE}', "\u{37C}"), ('\u{3FF}', "\u{37D}"), ('\u{400}', "\u{450}"),
+  ('\u{401}', "\u{451}"), ('\u{402}', "\u{452}"), ('\u{403}', "\u{453}"), ('\u{404}', "\u{454}"), ('\u{405}', "\u{455}"), ('\u{406}', "\u{456}"),
+  ('\u{407}', "\u{457}"), ('\u{408}', "\u{458}"), ('\u{409}', "\u{459}"), ('\u{40A}', "\u{45A}"), ('\u{40B}', "\u{45B}"), ('\u{40C}', "\u{45C}"),
+  ('\u{40D}', "\u{45D}"), ('\u{40E}', "\u{45E}"), ('\u{40F}', "\u{45F}"), ('\u{410}', "\u{430}"), ('\u{411}', "\u{431}"), ('\u{412}', "\u{432}"),
+  ('\u{413}', "\u{433}"), ('\u{414}', "\u{434}"), ('\u{415}', "\u{435}"), ('\u{416}', "\u{436}"), ('\u{417}', "\u{437}"), ('\u{418}', "\u{438}"),
+  ('\u{419}', "\u{439}"), ('\u{41A}', "\u{43A}"), ('\u{41B}', "\u{43B}"), ('\u{41C}', "\u{43C}"), ('\u{41D}', "\u{43D}"), ('\u{41E}', "\u{43E}"),
+  ('\u{41F}', "\u{43F}"), ('\u{420}', "\u{440}"), ('\u{421}', "\u{441}"), ('\u{422}', "\u{442}"), ('\u{423}', "\u{443}"), ('\u{424}', "\u{444}"),
+  ('\u{425}', "\u{445}"), ('\u{426}', "\u{446}"), ('\u{427}', "\u{447}"), ('\u{428}', "\u{448}"), ('\u{429}', "\u{449}"), ('\u{42A}', "\u{44A}"),
+  ('\u{42B}', "\u{44B}"), ('\u{42C}', "\u{44C}"), ('\u{42D}', "\u{44D}"), ('\u{42E}', "\u{44E}"), ('\u{42F}', "\u{44F}"), ('\u{460}', "\u{461}"),
+  ('\u{462}', "\u{463}"), ('\u{464}', "\u{465}"), ('\u{466}', "\u{467}"), ('\u{468}', "\u{469}"), ('\u{46A}', "\u{46B}"), ('\u{46C}', "\u{46D}"),
+  ('\u{46E}', "\u{46F}"), ('\u{470}', "\u{471}"), ('\u{472}', "\u{473}"), ('\u{474}', "\u{475}"), ('\u{476}', "\u{477}"), ('\u{478}', "\u{479}"),
+  ('\u{47A}', "\u{47B}"), ('\u{47C}', "\u{47D}"), ('\u{47E}', "\u{47F}"), ('\u{480}', "\u{481}"), ('\u{48A}', "\u{48B}"), ('\u{48C}', "\u{48D}"),
+  ('\u{48E}', "\u{48F}"), ('\u{490}', "\u{491}"), ('\u{492}', "\u{493}"), ('\u{494}', "\u{495}"), ('\u{496}', "\u{497}"), ('\u{498}', "\u{499}"),
+  ('\u{49A}', "\u{49B}"), ('\u{49C}', "\u{49D}"), ('\u{49E}', "\u{49F}"), ('\u{4A0}', "\u{4A1}"), ('\u{4A2}', "\u{4A3}"), ('\u{4A4}', "\u{4A5}"),
+  ('\u{4A6}', "\u{4A7}"), ('\u{4A8}', "\u{4A9}"), ('\u{4AA}', "\u{4AB}"), ('\u{4AC}', "\u{4AD}"), ('\u{4AE}', "\u{4AF}"), ('\u{4B0}', "\u{4B1}"),
+  ('\u{4B2}', "\u{4B3}"), ('\u{4B4}', "\u{4B5}"), ('\u{4B6}', "\u{4B7}"), ('\u{4B8}', "\u{4B9}"), ('\u{4BA}', "\u{4BB}"), ('\u{4BC}', "\u{4BD}"),
+  ('\u{4BE}', "\u{4BF}"), ('\u{4C0}', "\u{4CF}"), ('\u{4C1}', "\u{4C2}"), ('\u{4C3}', "\u{4C4}"), ('\u{4C5}', "\u{4C6}"), ('\u{4C7}', "\u{4C8}"),
+  ('\u{4C9}', "\u{4CA}"), ('\u{4CB}', "\u{4CC}"), ('\u{4CD}', "\u{4CE}"), ('\u{4D0}', "\u{4D1}"), ('\u{4D2}', "\u{4D3}"), ('\u{4D4}', "\u{4D5}"),
+  ('\u{4D6}', "\u{4D7}"), ('\u{4D8}', "\u{4D9}"), ('\u{4DA}', "\u{4DB}"), ('\u{4DC}', "\u{4DD}"), ('\u{4DE}', "\u{4DF}"), ('\u{4E0}', "\u{4E1}"),
+  ('\u{4E2}', "\u{4E3}"), ('\u{4E4}', "\u{4E5}"), ('\u{4E6}', "\u{4E7}"), ('\u{4E8}', "\u{4E9}"), ('\u{4EA}', "\u{4EB}"), ('\u{4EC}', "\u{4ED}"),
+  ('\u{4EE}', "\u{4EF}"), ('\u{4F0}', "\u{4F1}"), ('\u{4F2}', "\u{4F3}"), ('\u{4F4}', "\u{4F5}"), ('\u{4F6}', "\u{4F7}"), ('\u{4F8}', "\u{4F9}"),
+  ('\u{4FA}', "\u{4FB}"), ('\u{4FC}', "\u{4FD}"), ('\u{4FE}', "\u{4FF}"), ('\u{500}', "\u{501}"), ('\u{502}', "\u{503}"), ('\u{504}', "\u{505}"),
+  ('\u{506}', "\u{507}"), ('\u{508}', "\u{509}"), ('\u{50A}', "\u{50B}"), ('\u{50C}', "\u{50D}"), ('\u{50E}', "\u{50F}"), ('\u{510}', "\u{511}"),
+  ('\u{512}', "\u{513}"), ('\u{514}', "\u{515}"), ('\u{516}', "\u{517}"), ('\u{518}', "\u{519}"), ('\u{51A}', "\u{51B}"), ('\u{51C}', "\u{51D}"),
+  ('\u{51E}', "\u{51F}"), ('\u{520}', "\u{521}"), ('\u{522}', "\u{523}"), ('\u{524}', "\u{525}"), ('\u{526}', "\u{527}"), ('\u{528}', "\u{529}"),
+  ('\u{52A}', "\u{52B}"), ('\u{52C}', "\u{52D}"), ('\u{52E}', "\u{52F}"), ('\u{531}', "\u{561}"), ('\u{532}', "\u{562}"), ('\u{533}', "\u{563}"),
+  ('\u{534}', "\u{564}"), ('\u{535}', "\u{565}"), ('\u{536}', "\u{566}"), ('\u{537}', "\u{567}"), ('\u{538}', "\u{568}"), ('\u{539}', "\u{569}"),
+  ('\u{53A}', "\u{56A}"), ('\u{53B}', "\u{56B}"), ('\u{53C}', "\u{56C}"), ('\u{53D}', "\u{56D}"), ('\u{53E}', "\u{56E}"), ('\u{53F}', "\u{56F}"),
+  ('\u{540}', "\u{570}"), ('\u{541}', "\u{571}"), ('\u{542}', "\u{572}"), ('\u{543}', "\u{573}"), ('\u{544}', "\u{574}"), ('\u{545}', "\u{575}"),
+  ('\u{546}', "\u{576}"), ('\u{547}', "\u{577}"), ('\u{548}', "\u{578}"), ('\u{549}', "\u{579}"), ('\u{54A}', "\u{57A}"), ('\u{54B}', "\u{57B}"),
+  ('\u{54C}', "\u{57C}"), ('\u{54D}', "\u{57D}"), ('\u{54E}', "\u{57E}"), ('\u{54F}', "\u{57F}"), ('\u{550}', "\u{580}"), ('\u{551}', "\u{581}"),
+  ('\u{552}', "\u{582}"), ('\u{553}', "\u{583}"), ('\u{554}', "\u{584}"), ('\u{555}', "\u{585}"), ('\u{556}', "\u{586}"), ('\u{587}', "\u{565}\u{582}"),
+  ('\u{10A0}', "\u{2D00}"), ('\u{10A1}', "\u{2D01}"), ('\u{10A2}', "\u{2D02}"), ('\u{10A3}', "\u{2D03}"), ('\u{10A4}', "\u{2D04}"), ('\u{10A5}', "\u{2D05}"),
+  ('\u{10A6}', "\u{2D06}"), ('\u{10A7}', "\u{2D07}"), ('\u{10A8}', "\u{2D08}"), ('\u{10A9}', "\u{2D09}"), ('\u{10AA}', "\u{2D0A}"), ('\u{10AB}', "\u{2D0B}"),
+  ('\u{10AC}', "\u{2D0C}"), ('\u{10AD}', "\u{2D0D}"), ('\u{10AE}', "\u{2D0E}"), ('\u{10AF}', "\u{2D0F}"), ('\u{10B0}', "\u{2D10}"), ('\u{10B1}', "\u{2D11}"),
+  ('\u{10B2}', "\u{2D12}"), ('\u{10B3}', "\u{2D13}"), ('\u{10B4}', "\u{2D14}"), ('\u{10B5}', "\u{2D15}"), ('\u{10B6}', "\u{2D16}"), ('\u{10B7}', "\u{2D17}"),
+  ('\u{10B8}', "\u{2D18}"), ('\u{10B9}', "\u{2D19}"), ('\u{10BA}', "\u{2D1A}"), ('\u{10BB}', "\u{2D1B}"), ('\u{10BC}', "\u{2D1C}"), ('\u{10BD}', "\u{2D1D}"),
+  ('\u{10BE}', "\u{2D1E}"), ('\u{10BF}', "\u{2D1F}"), ('\u{10C0}', "\u{2D20}"), ('\u{10C1}', "\u{2D21}"), ('\u{10C2}', "\u{2D22}"), ('\u{10C3}', "\u{2D23}"),
+  ('\u{10C4}', "\u{2D24}"), ('\u{10C5}', "\u{2D25}"), ('\u{10C7}', "\u{2D27}"), ('\u{10CD}', "\u{2D2D}"), ('\u{13F8}', "\u{13F0}"), ('\u{13F9}', "\u{13F1}"),
+  ('\u{13FA}', "\u{13F2}"), ('\u{13FB}', "\u{13F3}"), ('\u{13FC}', "\u{13F4}"), ('\u{13FD}', "\u{13F5}"), ('\u{1C80}', "\u{432}"), ('\u{1C81}', "\u{434}"),
+  ('\u{1C82}', "\u{43E}"), ('\u{1C83}', "\u{441}"), ('\u{1C84}', "\u{442}"), ('\u{1C85}', "\u{442}"), ('\u{1C86}', "\u{44A}"), ('\u{1C87}', "\u{463}"),
+  ('\u{1C88}', "\u{A64B}"), ('\u{1C90}', "\u{10D0}"), ('\u{1C91}', "\u{10D1}"), ('\u{1C92}', "\u{10D2}"), ('\u{1C93}', "\u{10D3}"), ('\u{1C94}', "\u{10D4}"),
+  ('\u{1C95}', "\u{10D5}"), ('\u{1C96}', "\u{10D6}"), ('\u{1C97}', "\u{10D7}"), ('\u{1C98}', "\u{10D8}"), ('\u{1C99}', "\u{10D9}"), ('\u{1C9A}', "\u{10DA}"),
+  ('\u{1C9B}', "\u{10DB}"), ('\u{1C9C}', "\u{10DC}"), ('\u{1C9D}', "\u{10DD}"), ('\u{1C9E}', "\u{10DE}"), ('\u{1C9F}', "\u{10DF}"), ('\u{1CA0}', "\u{10E0}"),
+  ('\u{1CA1}', "\u{10E1}"), ('\u{1CA2}', "\u{10E2}"), ('\u{1CA3}', "\u{10E3}"), ('\u{1CA4}', "\u{10E4}"), ('\u{1CA5}', "\u{10E5}"), ('\u{1CA6}', "\u{10E6}"),
+  ('\u{1CA7}', "\u{10E7}"), ('\u{1CA8}', "\u{10E8}"), ('\u{1CA9}', "\u{10E9}"), ('\u{1CAA}', "\u{10EA}"), ('\u{1CAB}', "\u{10EB}"), ('\u{1CAC}', "\u{10EC}"),
+  ('\u{1CAD}', "\u{10ED}"), ('\u{1CAE}', "\u{10EE}"), ('\u{1CAF}', "\u{10EF}"), ('\u{1CB0}', "\u{10F0}"), ('\u{1CB1}', "\u{10F1}"), ('\u{1CB2}', "\u{10F2}"),
+  ('\u{1CB3}', "\u{10F3}"), ('\u{1CB4}', "\u{10F4}"), ('\u{1CB5}', "\u{10F5}"), ('\u{1CB6}', "\u{10F6}"), ('\u{1CB7}', "\u{10F7}"), ('\u{1CB8}', "\u{10F8}"),
+  ('\u{1CB9}', "\u{10F9}"), ('\u{1CBA}', "\u{10FA}"), ('\u{1CBD}', "\u{10FD}"), ('\u{1CBE}', "\u{10FE}"), ('\u{1CBF}', "\u{10FF}"), ('\u{1E00}', "\u{1E01}"),
+  ('\u{1E02}', "\u{1E03}"), ('\u{1E04}', "\u{1E05}"), ('\u{1E06}', "\u{1E07}"), ('\u{1E08}', "\u{1E09}"), ('\u{1E0A}', "\u{1E0B}"), ('\u{1E0C}', "\u{1E0D}"),
+  ('\u{1E0E}', "\u{1E0F}"), ('\u{1E10}', "\u{1E11}"), ('\u{1E12}', "\u{1E13}"), ('\u{1E14}', "\u{1E15}"), ('\u{1E16}', "\u{1E17}"), ('\u{1E18}', "\u{1E19}"),
+  ('\u{1E1A}', "\u{1E1B}"), ('\u{1E1C}', "\u{1E1D}"), ('\u{1E1E}', "\u{1E1F}"), ('\u{1E20}', "\u{1E21}"), ('\u{1E22}', "\u{1E23}"), ('\u{1E24}', "\u{1E25}"),
+  ('\u{1E26}', "\u{1E27}"), ('\u{1E28}', "\u{1E29}"), ('\u{1E2A}', "\u{1E2B}"), ('\u{1E2C}', "\u{1E2D}"), ('\u{1E2E}', "\u{1E2F}"), ('\u{1E30}', "\u{1E31}"),
+  ('\u{1E32}', "\u{1E33}"), ('\u{1E34}', "\u{1E35}"), ('\u{1E36}', "\u{1E37}"), ('\u{1E38}', "\u{1E39}"), ('\u{1E3A}', "\u{1E3B}"), ('\u{1E3C}', "\u{1E3D}"),
+  ('\u{1E3E}', "\u{1E3F}"), ('\u{1E40}', "\u{1E41}"), ('\u{1E42}', "\u{1E43}"), ('\u{1E44}', "\u{1E45}"), ('\u{1E46}', "\u{1E47}"), ('\u{1E48}', "\u{1E49}"),
+  ('\u{1E4A}', "\u{1E4B}"), ('\u{1E4C}', "\u{1E4D}"), ('\u{1E4E}', "\u{1E4F}"), ('\u{1E50}', "\u{1E51}"), ('\u{1E52}', "\u{1E53}"), ('\u{1E54}', "\u{1E55}"),
+  ('\u{1E56}', "\u{1E57}"), ('\u{1E58}', "\u{1E59}"), ('\u{1E5A}', "\u{1E5B}"), ('\u{1E5C}', "\u{1E5D}"), ('\u{1E5E}', "\u{1E5F}"), ('\u{1E60}', "\u{1E61}"),
+  ('\u{1E62}', "\u{1E63}"), ('\u{1E64}', "\u{1E65}"), ('\u{1E66}', "\u{1E67}"), ('\u{1E68}', "\u{1E69}"), ('\u{1E6A}', "\u{1E6B}"), ('\u{1E6C}', "\u{1E6D}"),
+  ('\u{1E6E}', "\u{1E6F}"), ('\u{1E70}', "\u{1E71}"), ('\u{1E72}', "\u{1E73}"), ('\u{1E74}', "\u{1E75}"), ('\u{1E76}', "\u{1E77}"), ('\u{1E78}', "\u{1E79}"),
+  ('\u{1E7A}', "\u{1E7B}"), ('\u{1E7C}', "\u{1E7D}"), ('\u{1E7E}', "\u{1E7F}"), ('\u{1E80}', "\u{1E81}"), ('\u{1E82}', "\u{1E83}"), ('\u{1E84}', "\u{1E85}"),
+  ('\u{1E86}', "\u{1E87}"), ('\u{1E88}', "\u{1E89}"), ('\u{1E8A}', "\u{1E8B}"), ('\u{1E8C}', "\u{1E8D}"), ('\u{1E8E}', "\u{1E8F}"), ('\u{1E90}', "\u{1E91}"),
+  ('\u{1E92}', "\u{1E93}"), ('\u{1E94}', "\u{1E95}"), ('\u{1E96}', "\u{68}\u{331}"), ('\u{1E97}', "\u{74}\u{308}"), ('\u{1E98}', "\u{77}\u{30A}"), ('\u{1E99}', "\u{79}\u{30A}"),
+  ('\u{1E9A}', "\u{61}\u{2BE}"), ('\u{1E9B}', "\u{1E61}"), ('\u{1E9E}', "\u{73}\u{73}"), ('\u{1EA0}', "\u{1EA1}"), ('\u{1EA2}', "\u{1EA3}"), ('\u{1EA4}', "\u{1EA5}"),
+  ('\u{1EA6}', "\u{1EA7}"), ('\u{1EA8}', "\u{1EA9}"), ('\u{1EAA}', "\u{1EAB}"), ('\u{1EAC}', "\u{1EAD}"), ('\u{1EAE}', "\u{1EAF}"), ('\u{1EB0}', "\u{1EB1}"),
+  ('\u{1EB2}', "\u{1EB3}"), ('\u{1EB4}', "\u{1EB5}"), ('\u{1EB6}', "\u{1EB7}"), ('\u{1EB8}', "\u{1EB9}"), ('\u{1EBA}', "\u{1EBB}"), ('\u{1EBC}', "\u{1EBD}"),
+  ('\u{1EBE}', "\u{1EBF}"), ('\u{1EC0}', "\u{1EC1}"), ('\u{1EC2}', "\u{1EC3}"), ('\u{1EC4}', "\u{1EC5}"), ('\u{1EC6}', "\u{1EC7}"), ('\u{1EC8}', "\u{1EC9}"),
+  ('\u{1ECA}', "\u{1ECB}"), ('\u{1ECC}', "\u{1ECD}"), ('\u{1ECE}', "\u{1ECF}"), ('\u{1ED0}', "\u{1ED1}"), ('\u{1ED2}', "\u{1ED3}"), ('\u{1ED4}', "\u{1ED5}"),
+  ('\u{1ED6}', "\u{1ED7}"), ('\u{1ED8}', "\u{1ED9}"), ('\u{1EDA}', "\u{1EDB}"), ('\u{1EDC}', "\u{1EDD}"), ('\u{1EDE}', "\u{1EDF}"), ('\u{1EE0}', "\u{1EE1}"),
+  ('\u{1EE2}', "\u{1EE3}"), ('\u{1EE4}', "\u{1EE5}"), ('\u{1EE6}', "\u{1EE7}"), ('\u{1EE8}', "\u{1EE9}"), ('\u{1EEA}', "\u{1EEB}"), ('\u{1EEC}', "\u{1EED}"),
+  ('\u{1EEE}', "\u{1EEF}"), ('\u{1EF0}', "\u{1EF1}"), ('\u{1EF2}', "\u{1EF3}"), ('\u{1EF4}', "\u{1EF5}"), ('\u{1EF6}', "\u{1EF7}"), ('\u{1EF8}', "\u{1EF9}"),
+  ('\u{1EFA}', "\u{1EFB}"), ('\u{1EFC}', "\u{1EFD}"), ('\u{1EFE}', "\u{1EFF}"), ('\u{1F08}', "\u{1F00}"), ('\u{1F09}', "\u{1F01}"), ('\u{1F0A}', "\u{1F02}"),
+  ('\u{1F0B}', "\u{1F03}"), ('\u{1F0C}', "\u{1F04}"), ('\u{1F0D}', "\u{1F05}"), ('\u{1F0E}', "\u{1F06}"), ('\u{1F0F}', "\u{1F07}"), ('\u{1F18}', "\u{1F10}"),
+  ('\u{1F19}', "\u{1F11}"), ('\u{1F1A}', "\u{1F12}"), ('\u{1F1B}', "\u{1F13}"), ('\u{1F1C}', "\u{1F14}"), ('\u{1F1D}', "\u{1F15}"), ('\u{1F28}', "\u{1F20}"),
+  ('\u{1F29}', "\u{1F21}"), ('\u{1F2A}', "\u{1F22}"), ('\u{1F2B}', "\u{1F23}"), ('\u{1F2C}', "\u{1F24}"), ('\u{1F2D}', "\u{1F25}"), ('\u{1F2E}', "\u{1F26}"),
+  ('\u{1F2F}', "\u{1F27}"), ('\u{1F38}', "\u{1F30}"), ('\u{1F39}', "\u{1F31}"), ('\u{1F3A}', "\u{1F32}"), ('\u{1F3B}', "\u{1F33}"), ('\u{1F3C}', "\u{1F34}"),
+  ('\u{1F3D}', "\u{1F35}"), ('\u{1F3E}', "\u{1F36}"), ('\u{1F3F}', "\u{1F37}"), ('\u{1F48}', "\u{1F40}"), ('\u{1F49}', "\u{1F41}"), ('\u{1F4A}', "\u{1F42}"),
+  ('\u{1F4B}', "\u{1F43}"), ('\u{1F4C}', "\u{1F44}"), ('\u{1F4D}', "\u{1F45}"), ('\u{1F50}', "\u{3C5}\u{313}"), ('\u{1F52}', "\u{3C5}\u{313}\u{300}"), ('\u{1F54}', "\u{3C5}\u{313}\u{301}"),
+  ('\u{1F56}', "\u{3C5}\u{313}\u{342}"), ('\u{1F59}', "\u{1F51}"), ('\u{1F5B}', "\u{1F53}"), ('\u{1F5D}', "\u{1F55}"), ('\u{1F5F}', "\u{1F57}"), ('\u{1F68}', "\u{1F60}"),
+  ('\u{1F69}', "\u{1F61}"), ('\u{1F6A}', "\u{1F62}"), ('\u{1F6B}', "\u{1F63}"), ('\u{1F6C}', "\u{1F64}"), ('\u{1F6D}', "\u{1F65}"), ('\u{1F6E}', "\u{1F66}"),
+  ('\u{1F6F}', "\u{1F67}"), ('\u{1F80}', "\u{1F00}\u{3B9}"), ('\u{1F81}', "\u{1F01}\u{3B9}"), ('\u{1F82}', "\u{1F02}\u{3B9}"), ('\u{1F83}', "\u{1F03}\u{3B9}"), ('\u{1F84}', "\u{1F04}\u{3B9}"),
+  ('\u{1F85}', "\u{1F05}\u{3B9}"), ('\u{1F86}', "\u{1F06}\u{3B9}"), ('\u{1F87}', "\u{1F07}\u{3B9}"), ('\u{1F88}', "\u{1F00}\u{3B9}"), ('\u{1F89}', "\u{1F01}\u{3B9}"), ('\u{1F8A}', "\u{1F02}\u{3B9}"),
+  ('\u{1F8B}', "\u{1F03}\u{3B9}"), ('\u{1F8C}', "\u{1F04}\u{3B9}"), ('\u{1F8D}', "\u{1F05}\u{3B9}"), ('\u{1F8E}', "\u{1F06}\u{3B9}"), ('\u{1F8F}', "\u{1F07}\u{3B9}"), ('\u{1F90}', "\u{1F20}\u{3B9}"),
+  ('\u{1F91}', "\u{1F21}\u{3B9}"), ('\u{1F92}', "\u{1F22}\u{3B9}"), ('\u{1F93}', "\u{1F23}\u{3B9}"), ('\u{1F94}', "\u{1F24}\u{3B9}"), ('\u{1F95}', "\u{1F25}\u{3B9}"), ('\u{1F96}', "\u{1F26}\u{3B9}"),
+  ('\u{1F97}', "\u{1F27}\u{3B9}"), ('\u{1F98}', "\u{1F20}\u{3B9}"), ('\u{1F99}', "\u{1F21}\u{3B9}"), ('\u{1F9A}', "\u{1F22}\u{3B9}"), ('\u{1F9B}', "\u{1F23}\u{3B9}"), ('\u{1F9C}', "\u{1F24}\u{3B9}"),
+  ('\u{1F9D}', "\u{1F25}\u{3B9}"), ('\u{1F9E}', "\u{1F26}\u{3B9}"), ('\u{1F9F}', "\u{1F27}\u{3B9}"), ('\u{1FA0}', "\u{1F60}\u{3B9}"), ('\u{1FA1}', "\u{1F61}\u{3B9}"), ('\u{1FA2}', "\u{1F62}\u{3B9}"),
+  ('\u{1FA3}', "\u{1F63}\u{3B9}"), ('\u{1FA4}', "\u{1F64}\u{3B9}"), ('\u{1FA5}', "\u{1F65}\u{3B9}"), ('\u{1FA6}', "\u{1F66}\u{3B9}"), ('\u{1FA7}', "\u{1F67}\u{3B9}"), ('\u{1FA8}', "\u{1F60}\u{3B9}"),
+  ('\u{1FA9}', "\u{1F61}\u{3B9}"), ('\u{1FAA}', "\u{1F62}\u{3B9}"), ('\u{1FAB}', "\u{1F63}\u{3B9}"), ('\u{1FAC}', "\u{1F64}\u{3B9}"), ('\u{1FAD}', "\u{1F65}\u{3B9}"), ('\u{1FAE}', "\u{1F66}\u{3B9}"),
+  ('\u{1FAF}', "\u{1F67}\u{3B9}"), ('\u{1FB2}', "\u{1F70}\u{3B9}"), ('\u{1FB3}', "\u{3B1}\u{3B9}"), ('\u{1FB4}', "\u{3AC}\u{3B9}"), ('\u{1FB6}', "\u{3B1}\u{342}"), ('\u{1FB7}', "\u{3B1}\u{342}\u{3B9}"),
+  ('\u{1FB8}', "\u{1FB0}"), ('\u{1FB9}', "\u{1FB1}"), ('\u{1FBA}', "\u{1F70}"), ('\u{1FBB}', "\u{1F71}"), ('\u{1FBC}', "\u{3B1}\u{3B9}"), ('\u{1FBE}', "\u{3B9}"),
+  ('\u{1FC2}', "\u{1F74}\u{3B9}"), ('\u{1FC3}', "\u{3B7}\u{3B9}"), ('\u{1FC4}', "\u{3AE}\u{3B9}"), ('\u{1FC6}', "\u{3B7}\u{342}"), ('\u{1FC7}', "\u{3B7}\u{342}\u{3B9}"), ('\u{1FC8}', "\u{1F72}"),
+  ('\u{1FC9}', "\u{1F73}"), ('\u{1FCA}', "\u{1F74}"), ('\u{1FCB}', "\u{1F75}"), ('\u{1FCC}', "\u{3B7}\u{3B9}"), ('\u{1FD2}', "\u{3B9}\u{308}\u{300}"), ('\u{1FD3}', "\u{3B9}\u{308}\u{301}"),
+  ('\u{1FD6}', "\u{3B9}\u{342}"), ('\u{1FD7}', "\u{3B9}\u{308}\u{342}"), ('\u{1FD8}', "\u{1FD0}"), ('\u{1FD9}', "\u{1FD1}"), ('\u{1FDA}', "\u{1F76}"), ('\u{1FDB}', "\u{1F77}"),
+  ('\u{1FE2}', "\u{3C5}\u{308}\u{300}"), ('\u{1FE3}', "\u{3C5}\u{308}\u{301}"), ('\u{1FE4}', "\u{3C1}\u{313}"), ('\u{1FE6}', "\u{3C5}\u{342}"), ('\u{1FE7}', "\u{3C5}\u{308}\u{342}"), ('\u{1FE8}', "\u{1FE0}"),
+  ('\u{1FE9}', "\u{1FE1}"), ('\u{1FEA}', "\u{1F7A}"), ('\u{1FEB}', "\u{1F7B}"), ('\u{1FEC}', "\u{1FE5}"), ('\u{1FF2}', "\u{1F7C}\u{3B9}"), ('\u{1FF3}', "\u{3C9}\u{3B9}"),
+  ('\u{1FF4}', "\u{3CE}\u{3B9}"), ('\u{1FF6}', "\u{3C9}\u{342}"), ('\u{1FF7}', "\u{3C9}\u{342}\u{3B9}"), ('\u{1FF8}', "\u{1F78}"), ('\u{1FF9}', "\u{1F79}"), ('\u{1FFA}', "\u{1F7C}"),
+  ('\u{1FFB}', "\u{1F7D}"), ('\u{1FFC}', "\u{3C9}\u{3B9}"), ('\u{2126}', "\u{3C9}"), ('\u{212A}', "\u{6B}"), ('\u{212B}', "\u{E5}"), ('\u{2132}', "\u{214E}"),
+  ('\u{2160}', "\u{2170}"), ('\u{2161}', "\u{2171}"), ('\u{2162}', "\u{2172}"), ('\u{2163}', "\u{2173}"), ('\u{2164}', "\u{2174}"), ('\u{2165}', "\u{2175}"),
+  ('\u{2166}', "\u{2176}"), ('\u{2167}', "\u{2177}"), ('\u{2168}', "\u{2178}"), ('\u{2169}', "\u{2179}"), ('\u{216A}', "\u{217A}"), ('\u{216B}', "\u{217B}"),
+  ('\u{216C}', "\u{217C}"), ('\u{216D}', "\u{217D}"), ('\u{216E}', "\u{217E}"), ('\u{216F}', "\u{217F}"), ('\u{2183}', "\u{2184}"), ('\u{24B6}', "\u{24D0}"),
+  ('\u{24B7}', "\u{24D1}"), ('\u{24B8}', "\u{24D2}"), ('\u{24B9}', "\u{24D3}"), ('\u{24BA}', "\u{24D4}"), ('\u{24BB}', "\u{24D5}"), ('\u{24BC}', "\u{24D6}"),
+  ('\u{24BD}', "\u{24D7}"), ('\u{24BE}', "\u{24D8}"), ('\u{24BF}', "\u{24D9}"), ('\u{24C0}', "\u{24DA}"), ('\u{24C1}', "\u{24DB}"), ('\u{24C2}', "\u{24DC}"),
+  ('\u{24C3}', "\u{24DD}"), ('\u{24C4}', "\u{24DE}"), ('\u{24C5}', "\u{24DF}"), ('\u{24C6}', "\u{24E0}"), ('\u{24C7}', "\u{24E1}"), ('\u{24C8}', "\u{24E2}"),
+  ('\u{24C9}', "\u{24E3}"), ('\u{24CA}', "\u{24E4}"), ('\u{24CB}', "\u{24E5}"), ('\u{24CC}', "\u{24E6}"), ('\u{24CD}', "\u{24E7}"), ('\u{24CE}', "\u{24E8}"),
+  ('\u{24CF}', "\u{24E9}"), ('\u{2C00}', "\u{2C30}"), ('\u{2C01}', "\u{2C31}"), ('\u{2C02}', "\u{2C32}"), ('\u{2C03}', "\u{2C33}"), ('\u{2C04}', "\u{2C34}"),
+  ('\u{2C05}', "\u{2C35}"), ('\u{2C06}', "\u{2C36}"), ('\u{2C07}', "\u{2C37}"), ('\u{2C08}', "\u{2C38}"), ('\u{2C09}', "\u{2C39}"), ('\u{2C0A}', "\u{2C3A}"),
+  ('\u{2C0B}', "\u{2C3B}"), ('\u{2C0C}', "\u{2C3C}"), ('\u{2C0D}', "\u{2C3D}"), ('\u{2C0E}', "\u{2C3E}"), ('\u{2C0F}', "\u{2C3F}"), ('\u{2C10}', "\u{2C40}"),
+  ('\u{2C11}', "\u{2C41}"), ('\u{2C12}', "\u{2C42}"), ('\u{2C13}', "\u{2C43}"), ('\u{2C14}', "\u{2C44}"), ('\u{2C15}', "\u{2C45}"), ('\u{2C16}', "\u{2C46}"),
+  ('\u{2C17}', "\u{2C47}"), ('\u{2C18}', "\u{2C48}"), ('\u{2C19}', "\u{2C49}"), ('\u{2C1A}', "\u{2C4A}"), ('\u{2C1B}', "\u{2C4B}"), ('\u{2C1C}', "\u{2C4C}"),
+  ('\u{2C1D}', "\u{2C4D}"), ('\u{2C1E}', "\u{2C4E}"), ('\u{2C1F}', "\u{2C4F}"), ('\u{2C20}', "\u{2C50}"), ('\u{2C21}', "\u{2C51}"), ('\u{2C22}', "\u{2C52}"),
+  ('\u{2C23}', "\u{2C53}"), ('\u{2C24}', "\u{2C54}"), ('\u{2C25}', "\u{2C55}"), ('\u{2C26}', "\u{2C56}"), ('\u{2C27}', "\u{2C57}"), ('\u{2C28}', "\u{2C58}"),
+  ('\u{2C29}', "\u{2C59}"), ('\u{2C2A}', "\u{2C5A}"), ('\u{2C2B}', "\u{2C5B}"), ('\u{2C2C}', "\u{2C5C}"), ('\u{2C2D}', "\u{2C5D}"), ('\u{2C2E}', "\u{2C5E}"),
+  ('\u{2C2F}', "\u{2C5F}"), ('\u{2C60}', "\u{2C61}"), ('\u{2C62}', "\u{26B}"), ('\u{2C63}', "\u{1D7D}"), ('\u{2C64}', "\u{27D}"), ('\u{2C67}', "\u{2C68}"),
+  ('\u{2C69}', "\u{2C6A}"), ('\u{2C6B}', "\u{2C6C}"), ('\u{2C6D}', "\u{251}"), ('\u{2C6E}', "\u{271}"), ('\u{2C6F}', "\u{250}"), ('\u{2C70}', "\u{252}"),
+  ('\u{2C72}', "\u{2C73}"), ('\u{2C75}', "\u{2C76}"), ('\u{2C7E}', "\u{23F}"), ('\u{2C7F}', "\u{240}"), ('\u{2C80}', "\u{2C81}"), ('\u{2C82}', "\u{2C83}"),
+  ('\u{2C84}', "\u{2C85}"), ('\u{2C86}', "\u{2C87}"), ('\u{2C88}', "\u{2C89}"), ('\u{2C8A}', "\u{2C8B}"), ('\u{2C8C}', "\u{2C8D}"), ('\u{2C8E}', "\u{2C8F}"),
+  ('\u{2C90}', "\u{2C91}"), ('\u{2C92}', "\u{2C93}"), ('\u{2C94}', "\u{2C95}"), ('\u{2C96}', "\u{2C97}"), ('\u{2C98}', "\u{2C99}"), ('\u{2C9A}', "\u{2C9B}"),
+  ('\u{2C9C}', "\u{2C9D}"), ('\u{2C9E}', "\u{2C9F}"), ('\u{2CA0}', "\u{2CA1}"), ('\u{2CA2}', "\u{2CA3}"), ('\u{2CA4}', "\u{2CA5}"), ('\u{2CA6}', "\u{2CA7}"),
+  ('\u{2CA8}', "\u{2CA9}"), ('\u{2CAA}', "\u{2CAB}"), ('\u{2CAC}', "\u{2CAD}"), ('\u{2CAE}', "\u{2CAF}"), ('\u{2CB0}', "\u{2CB1}"), ('\u{2CB2}', "\u{2CB3}"),
+  ('\u{2CB4}', "\u{2CB5}"), ('\u{2CB6}', "\u{2CB7}"), ('\u{2CB8}', "\u{2CB9}"), ('\u{2CBA}', "\u{2CBB}"), ('\u{2CBC}', "\u{2CBD}"), ('\u{2CBE}', "\u{2CBF}"),
+  ('\u{2CC0}', "\u{2CC1}"), ('\u{2CC2}', "\u{2CC3}"), ('\u{2CC4}', "\u{2CC5}"), ('\u{2CC6}', "\u{2CC7}"), ('\u{2CC8}', "\u{2CC9}"), ('\u{2CCA}', "\u{2CCB}"),
+  ('\u{2CCC}', "\u{2CCD}"), ('\u{2CCE}', "\u{2CCF}"), ('\u{2CD0}', "\u{2CD1}"), ('\u{2CD2}', "\u{2CD3}"), ('\u{2CD4}', "\u{2CD5}"), ('\u{2CD6}', "\u{2CD7}"),
+  ('\u{2CD8}', "\u{2CD9}"), ('\u{2CDA}', "\u{2CDB}"), ('\u{2CDC}', "\u{2CDD}"), ('\u{2CDE}', "\u{2CDF}"), ('\u{2CE0}', "\u{2CE1}"), ('\u{2CE2}', "\u{2CE3}"),
+  ('\u{2CEB}', "\u{2CEC}"), ('\u{2CED}', "\u{2CEE}"), ('\u{2CF2}', "\u{2CF3}"), ('\u{A640}', "\u{A641}"), ('\u{A642}', "\u{A643}"), ('\u{A644}', "\u{A645}"),
+  ('\u{A646}', "\u{A647}"), ('\u{A648}', "\u{A649}"), ('\u{A64A}', "\u{A64B}"), ('\u{A64C}', "\u{A64D}"), ('\u{A64E}', "\u{A64F}"), ('\u{A650}', "\u{A651}"),
+  ('\u{A652}', "\u{A653}"), ('\u{A654}', "\u{A655}"), ('\u{A656}', "\u{A657}"), ('\u{A658}', "\u{A659}"), ('\u{A65A}', "\u{A65B}"), ('\u{A65C}', "\u{A65D}"),
+  ('\u{A65E}', "\u{A65F}"), ('\u{A660}', "\u{A661}"), ('\u{A662}', "\u{A663}"), ('\u{A664}', "\u{A665}"), ('\u{A666}', "\u{A667}"), ('\u{A668}', "\u{A669}"),
+  ('\u{A66A}', "\u{A66B}"), ('\u{A66C}', "\u{A66D}"), ('\u{A680}', "\u{A681}"), ('\u{A682}', "\u{A683}"), ('\u{A684}', "\u{A685}"), ('\u{A686}', "\u{A687}"),
+  ('\u{A688}', "\u{A689}"), ('\u{A68A}', "\u{A68B}"), ('\u{A68C}', "\u{A68D}"), ('\u{A68E}', "\u{A68F}"), ('\u{A690}', "\u{A691}"), ('\u{A692}', "\u{A693}"),
+  ('\u{A694}', "\u{A695}"), ('\u{A696}', "\u{A697}"), ('\u{A698}', "\u{A699}"), ('\u{A69A}', "\u{A69B}"), ('\u{A722}', "\u{A723}"), ('\u{A724}', "\u{A725}"),
+  ('\u{A726}', "\u{A727}"), ('\u{A728}', "\u{A729}"), ('\u{A72A}', "\u{A72B}"), ('\u{A72C}', "\u{A72D}"), ('\u{A72E}', "\u{A72F}"), ('\u{A732}', "\u{A733}"),
+  ('\u{A734}', "\u{A735}"), ('\u{A736}', "\u{A737}"), ('\u{A738}', "\u{A739}"), ('\u{A73A}', "\u{A73B}"), ('\u{A73C}', "\u{A73D}"), ('\u{A73E}', "\u{A73F}"),
+  ('\u{A740}', "\u{A741}"), ('\u{A742}', "\u{A743}"), ('\u{A744}', "\u{A745}"), ('\u{A746}', "\u{A747}"), ('\u{A748}', "\u{A749}"), ('\u{A74A}', "\u{A74B}"),
+  ('\u{A74C}', "\u{A74D}"), ('\u{A74E}', "\u{A74F}"), ('\u{A750}', "\u{A751}"), ('\u{A752}', "\u{A753}"), ('\u{A754}', "\u{A755}"), ('\u{A756}', "\u{A757}"),
+  ('\u{A758}', "\u{A759}"), ('\u{A75A}', "\u{A75B}"), ('\u{A75C}', "\u{A75D}"), ('\u{A75E}', "\u{A75F}"), ('\u{A760}', "\u{A761}"), ('\u{A762}', "\u{A763}"),
+  ('\u{A764}', "\u{A765}"), ('\u{A766}', "\u{A767}"), ('\u{A768}', "\u{A769}"), ('\u{A76A}', "\u{A76B}"), ('\u{A76C}', "\u{A76D}"), ('\u{A76E}', "\u{A76F}"),
+  ('\u{A779}', "\u{A77A}"), ('\u{A77B}', "\u{A77C}"), ('\u{A77D}', "\u{1D79}"), ('\u{A77E}', "\u{A77F}"), ('\u{A780}', "\u{A781}"), ('\u{A782}', "\u{A783}"),
+  ('\u{A784}', "\u{A785}"), ('\u{A786}', "\u{A787}"), ('\u{A78B}', "\u{A78C}"), ('\u{A78D}', "\u{265}"), ('\u{A790}', "\u{A791}"), ('\u{A792}', "\u{A793}"),
+  ('\u{A796}', "\u{A797}"), ('\u{A798}', "\u{A799}"), ('\u{A79A}', "\u{A79B}"), ('\u{A79C}', "\u{A79D}"), ('\u{A79E}', "\u{A79F}"), ('\u{A7A0}', "\u{A7A1}"),
+  ('\u{A7A2}', "\u{A7A3}"), ('\u{A7A4}', "\u{A7A5}"), ('\u{A7A6}', "\u{A7A7}"), ('\u{A7A8}', "\u{A7A9}"), ('\u{A7AA}', "\u{266}"), ('\u{A7AB}', "\u{25C}"),
+  ('\u{A7AC}', "\u{261}"), ('\u{A7AD}', "\u{26C}"), ('\u{A7AE}', "\u{26A}"), ('\u{A7B0}', "\u{29E}"), ('\u{A7B1}', "\u{287}"), ('\u{A7B2}', "\u{29D}"),
+  ('\u{A7B3}', "\u{AB53}"), ('\u{A7B4}', "\u{A7B5}"), ('\u{A7B6}', "\u{A7B7}"), ('\u{A7B8}', "\u{A7B9}"), ('\u{A7BA}', "\u{A7BB}"), ('\u{A7BC}', "\u{A7BD}"),
+  ('\u{A7BE}', "\u{A7BF}"), ('\u{A7C0}', "\u{A7C1}"), ('\u{A7C2}', "\u{A7C3}"), ('\u{A7C4}', "\u{A794}"), ('\u{A7C5}', "\u{282}"), ('\u{A7C6}', "\u{1D8E}"),
+  ('\u{A7C7}', "\u{A7C8}"), ('\u{A7C9}', "\u{A7CA}"), ('\u{A7D0}', "\u{A7D1}"), ('\u{A7D6}', "\u{A7D7}"), ('\u{A7D8}', "\u{A7D9}"), ('\u{A7F5}', "\u{A7F6}"),
+  ('\u{AB70}', "\u{13A0}"), ('\u{AB71}', "\u{13A1}"), ('\u{AB72}', "\u{13A2}"), ('\u{AB73}', "\u{13A3}"), ('\u{AB74}', "\u{13A4}"), ('\u{AB75}', "\u{13A5}"),
+  ('\u{AB76}', "\u{13A6}"), ('\u{AB77}', "\u{13A7}"), ('\u{AB78}', "\u{13A8}"), ('\u{AB79}', "\u{13A9}"), ('\u{AB7A}', "\u{13AA}"), ('\u{AB7B}', "\u{13AB}"),
+  ('\u{AB7C}', "\u{13AC}"), ('\u{AB7D}', "\u{13AD}"), ('\u{AB7E}', "\u{13AE}"), ('\u{AB7F}', "\u{13AF}"), ('\u{AB80}', "\u{13B0}"), ('\u{AB81}', "\u{13B1}"),
+  ('\u{AB82}', "\u{13B2}"), ('\u{AB83}', "\u{13B3}"), ('\u{AB84}', "\u{13B4}"), ('\u{AB85}', "\u{13B5}"), ('\u{AB86}', "\u{13B6}"), ('\u{AB87}', "\u{13B7}"),
+  ('\u{AB88}', "\u{13B8}"), ('\u{AB89}', "\u{13B9}"), ('\u{AB8A}', "\u{13BA}"), ('\u{AB8B}', "\u{13BB}"), ('\u{AB8C}', "\u{13BC}"), ('\u{AB8D}', "\u{13BD}"),
+  ('\u{AB8E}', "\u{13BE}"), ('\u{AB8F}', "\u{13BF}"), ('\u{AB90}', "\u{13C0}"), ('\u{AB91}', "\u{13C1}"), ('\u{AB92}', "\u{13C2}"), ('\u{AB93}', "\u{13C3}"),
+  ('\u{AB94}', "\u{13C4}"), ('\u{AB95}', "\u{13C5}"), ('\u{AB96}', "\u{13C6}"), ('\u{AB97}', "\u{13C7}"), ('\u{AB98}', "\u{13C8}"), ('\u{AB99}', "\u{13C9}"),
+  ('\u{AB9A}', "\u{13CA}"), ('\u{AB9B}', "\u{13CB}"), ('\u{AB9C}', "\u{13CC}"), ('\u{AB9D}', "\u{13CD}"), ('\u{AB9E}', "\u{13CE}"), ('\u{AB9F}', "\u{13CF}"),
+  ('\u{ABA0}', "\u{13D0}"), ('\u{ABA1}', "\u{13D1}"), ('\u{ABA2}', "\u{13D2}"), ('\u{ABA3}', "\u{13D3}"), ('\u{ABA4}', "\u{13D4}"), ('\u{ABA5}', "\u{13D5}"),
+  ('\u{ABA6}', "\u{13D6}"), ('\u{ABA7}', "\u{13D7}"), ('\u{ABA8}', "\u{13D8}"), ('\u{ABA9}', "\u{13D9}"), ('\u{ABAA}', "\u{13DA}"), ('\u{ABAB}', "\u{13DB}"),
+  ('\u{ABAC}', "\u{13DC}"), ('\u{ABAD}', "\u{13DD}"), ('\u{ABAE}', "\u{13DE}"), ('\u{ABAF}', "\u{13DF}"), ('\u{ABB0}', "\u{13E0}"), ('\u{ABB1}', "\u{13E1}"),
+  ('\u{ABB2}', "\u{13E2}"), ('\u{ABB3}', "\u{13E3}"), ('\u{ABB4}', "\u{13E4}"), ('\u{ABB5}', "\u{13E5}"), ('\u{ABB6}', "\u{13E6}"), ('\u{ABB7}', "\u{13E7}"),
+  ('\u{ABB8}', "\u{13E8}"), ('\u{ABB9}', "\u{13E9}"), ('\u{ABBA}', "\u{13EA}"), ('\u{ABBB}', "\u{13EB}"), ('\u{ABBC}', "\u{13EC}"), ('\u{ABBD}', "\u{13ED}"),
+  ('\u{ABBE}', "\u{13EE}"), ('\u{ABBF}', "\u{13EF}"), ('\u{FB00}', "\u{66}\u{66}"), ('\u{FB01}', "\u{66}\u{69}"), ('\u{FB02}', "\u{66}\u{6C}"), ('\u{FB03}', "\u{66}\u{66}\u{69}"),
+  ('\u{FB04}', "\u{66}\u{66}\u{6C}"), ('\u{FB05}', "\u{73}\u{74}"), ('\u{FB06}', "\u{73}\u{74}"), ('\u{FB13}', "\u{574}\u{576}"), ('\u{FB14}', "\u{574}\u{565}"), ('\u{FB15}', "\u{574}\u{56B}"),
+  ('\u{FB16}', "\u{57E}\u{576}"), ('\u{FB17}', "\u{574}\u{56D}"), ('\u{FF21}', "\u{FF41}"), ('\u{FF22}', "\u{FF42}"), ('\u{FF23}', "\u{FF43}"), ('\u{FF24}', "\u{FF44}"),
+  ('\u{FF25}', "\u{FF45}"), ('\u{FF26}', "\u{FF46}"), ('\u{FF27}', "\u{FF47}"), ('\u{FF28}', "\u{FF48}"), ('\u{FF29}', "\u{FF49}"), ('\u{FF2A}', "\u{FF4A}"),
+  ('\u{FF2B}', "\u{FF4B}"), ('\u{FF2C}', "\u{FF4C}"), ('\u{FF2D}', "\u{FF4D}"), ('\u{FF2E}', "\u{FF4E}"), ('\u{FF2F}', "\u{FF4F}"), ('\u{FF30}', "\u{FF50}"),
+  ('\u{FF31}', "\u{FF51}"), ('\u{FF32}', "\u{FF52}"), ('\u{FF33}', "\u{FF53}"), ('\u{FF34}', "\u{FF54}"), ('\u{FF35}', "\u{FF55}"), ('\u{FF36}', "\u{FF56}"),
+  ('\u{FF37}', "\u{FF57}"), ('\u{FF38}', "\u{FF58}"), ('\u{FF39}', "\u{FF59}"), ('\u{FF3A}', "\u{FF5A}"), ('\u{10400}', "\u{10428}"), ('\u{10401}', "\u{10429}"),
+  ('\u{10402}', "\u{1042A}"), ('\u{10403}', "\u{1042B}"), ('\u{10404}', "\u{1042C}"), ('\u{10405}', "\u{1042D}"), ('\u{10406}', "\u{1042E}"), ('\u{10407}', "\u{1042F}"),
+  ('\u{10408}', "\u{10430}"), ('\u{10409}', "\u{10431}"), ('\u{1040A}', "\u{10432}"), ('\u{1040B}', "\u{10433}"), ('\u{1040C}', "\u{10434}"), ('\u{1040D}', "\u{10435}"),
+  ('\u{1040E}', "\u{10436}"), ('\u{1040F}', "\u{10437}"), ('\u{10410}', "\u{10438}"), ('\u{10411}', "\u{10439}"), ('\u{10412}', "\u{1043A}"), ('\u{10413}', "\u{1043B}"),
+  ('\u{10414}', "\u{1043C}"), ('\u{10415}', "\u{1043D}"), ('\u{10416}', "\u{1043E}"), ('\u{10417}', "\u{1043F}"), ('\u{10418}', "\u{10440}"), ('\u{10419}', "\u{10441}"),
+  ('\u{1041A}', "\u{10442}"), ('\u{1041B}', "\u{10443}"), ('\u{1041C}', "\u{10444}"), ('\u{1041D}', "\u{10445}"), ('\u{1041E}', "\u{10446}"), ('\u{1041F}', "\u{10447}"),
+  ('\u{10420}', "\u{10448}"), ('\u{10421}', "\u{10449}"), ('\u{10422}', "\u{1044A}"), ('\u{10423}', "\u{1044B}"), ('\u{10424}', "\u{1044C}"), ('\u{10425}', "\u{1044D}"),
+  ('\u{10426}', "\u{1044E}"), ('\u{10427}', "\u{1044F}"), ('\u{104B0}', "\u{104D8}"), ('\u{104B1}', "\u{104D9}"), ('\u{104B2}', "\u{104DA}"), ('\u{104B3}', "\u{104DB}"),
+  ('\u{104B4}', "\u{104DC}"), ('\u{104B5}', "\u{104DD}"), ('\u{104B6}', "\u{104DE}"), ('\u{104B7}', "\u{104DF}"), ('\u{104B8}', "\u{104E0}"), ('\u{104B9}', "\u{104E1}"),
+  ('\u{104BA}', "\u{104E2}"), ('\u{104BB}', "\u{104E3}"), ('\u{104BC}', "\u{104E4}"), ('\u{104BD}', "\u{104E5}"), ('\u{104BE}', "\u{104E6}"), ('\u{104BF}', "\u{104E7}"),
+  ('\u{104C0}', "\u{104E8}"), ('\u{104C1}', "\u{104E9}"), ('\u{104C2}', "\u{104EA}"), ('\u{104C3}', "\u{104EB}"), ('\u{104C4}', "\u{104EC}"), ('\u{104C5}', "\u{104ED}"),
+  ('\u{104C6}', "\u{104EE}"), ('\u{104C7}', "\u{104EF}"), ('\u{104C8}', "\u{104F0}"), ('\u{104C9}', "\u{104F1}"), ('\u{104CA}', "\u{104F2}"), ('\u{104CB}', "\u{104F3}"),
+  ('\u{104CC}', "\u{104F4}"), ('\u{104CD}', "\u{104F5}"), ('\u{104CE}', "\u{104F6}"), ('\u{104CF}', "\u{104F7}"), ('\u{104D0}', "\u{104F8}"), ('\u{104D1}', "\u{104F9}"),
+  ('\u{104D2}', "\u{104FA}"), ('\u{104D3}', "\u{104FB}"), ('\u{10570}', "\u{10597}"), ('\u{10571}', "\u{10598}"), ('\u{10572}', "\u{10599}"), ('\u{10573}', "\u{1059A}"),
+  ('\u{10574}', "\u{1059B}"), ('\u{10575}', "\u{1059C}"), ('\u{10576}', "\u{1059D}"), ('\u{10577}', "\u{1059E}"), ('\u{10578}', "\u{1059F}"), ('\u{10579}', "\u{105A0}"),
+  ('\u{1057A}', "\u{105A1}"), ('\u{1057C}', "\u{105A3}"), ('\u{1057D}', "\u{105A4}"), ('\u{1057E}', "\u{105A5}"), ('\u{1057F}', "\u{105A6}"), ('\u{10580}', "\u{105A7}"),
+  ('\u{10581}', "\u{105A8}"), ('\u{10582}', "\u{105A9}"), ('\u{10583}', "\u{105AA}"), ('\u{10584}', "\u{105AB}"), ('\u{10585}', "\u{105AC}"), ('\u{10586}', "\u{105AD}"),
+  ('\u{10587}', "\u{105AE}"), ('\u{10588}', "\u{105AF}"), ('\u{10589}', "\u{105B0}"), ('\u{1058A}', "\u{105B1}"), ('\u{1058C}', "\u{105B3}"), ('\u{1058D}', "\u{105B4}"),
+  ('\u{1058E}', "\u{105B5}"), ('\u{1058F}', "\u{105B6}"), ('\u{10590}', "\u{105B7}"), ('\u{10591}', "\u{105B8}"), ('\u{10592}', "\u{105B9}"), ('\u{10594}', "\u{105BB}"),
+  ('\u{10595}', "\u{105BC}"), ('\u{10C80}', "\u{10CC0}"), ('\u{10C81}', "\u{10CC1}"), ('\u{10C82}', "\u{10CC2}"), ('\u{10C83}', "\u{10CC3}"), ('\u{10C84}', "\u{10CC4}"),
+  ('\u{10C85}', "\u{10CC5}"), ('\u{10C86}', "\u{10CC6}"), ('\u{10C87}', "\u{10CC7}"), ('\u{10C88}', "\u{10CC8}"), ('\u{10C89}', "\u{10CC9}"), ('\u{10C8A}', "\u{10CCA}"),
+  ('\u{10C8B}', "\u{10CCB}"), ('\u{10C8C}', "\u{10CCC}"), ('\u{10C8D}', "\u{10CCD}"), ('\u{10C8E}', "\u{10CCE}"), ('\u{10C8F}', "\u{10CCF}"), ('\u{10C90}', "\u{10CD0}"),
+  ('\u{10C91}', "\u{10CD1}"), ('\u{10C92}', "\u{10CD2}"), ('\u{10C93}', "\u{10CD3}"), ('\u{10C94}', "\u{10CD4}"), ('\u{10C95}', "\u{10CD5}"), ('\u{10C96}', "\u{10CD6}"),
+  ('\u{10C97}', "\u{10CD7}"), ('\u{10C98}', "\u{10CD8}"), ('\u{10C99}', "\u{10CD9}"), ('\u{10C9A}', "\u{10CDA}"), ('\u{10C9B}', "\u{10CDB}"), ('\u{10C9C}', "\u{10CDC}"),
+  ('\u{10C9D}', "\u{10CDD}"), ('\u{10C9E}', "\u{10CDE}"), ('\u{10C9F}', "\u{10CDF}"), ('\u{10CA0}', "\u{10CE0}"), ('\u{10CA1}', "\u{10CE1}"), ('\u{10CA2}', "\u{10CE2}"),
+  ('\u{10CA3}', "\u{10CE3}"), ('\u{10CA4}', "\u{10CE4}"), ('\u{10CA5}', "\u{10CE5}"), ('\u{10CA6}', "\u{10CE6}"), ('\u{10CA7}', "\u{10CE7}"), ('\u{10CA8}', "\u{10CE8}"),
+  ('\u{10CA9}', "\u{10CE9}"), ('\u{10CAA}', "\u{10CEA}"), ('\u{10CAB}', "\u{10CEB}"), ('\u{10CAC}', "\u{10CEC}"), ('\u{10CAD}', "\u{10CED}"), ('\u{10CAE}', "\u{10CEE}"),
+  ('\u{10CAF}', "\u{10CEF}"), ('\u{10CB0}', "\u{10CF0}"), ('\u{10CB1}', "\u{10CF1}"), ('\u{10CB2}', "\u{10CF2}"), ('\u{118A0}', "\u{118C0}"), ('\u{118A1}', "\u{118C1}"),
+  ('\u{118A2}', "\u{118C2}"), ('\u{118A3}', "\u{118C3}"), ('\u{118A4}', "\u{118C4}"), ('\u{118A5}', "\u{118C5}"), ('\u{118A6}', "\u{118C6}"), ('\u{118A7}', "\u{118C7}"),
+  ('\u{118A8}', "\u{118C8}"), ('\u{118A9}', "\u{118C9}"), ('\u{118AA}', "\u{118CA}"), ('\u{118AB}', "\u{118CB}"), ('\u{118AC}', "\u{118CC}"), ('\u{118AD}', "\u{118CD}"),
+  ('\u{118AE}', "\u{118CE}"), ('\u{118AF}', "\u{118CF}"), ('\u{118B0}', "\u{118D0}"), ('\u{118B1}', "\u{118D1}"), ('\u{118B2}', "\u{118D2}"), ('\u{118B3}', "\u{118D3}"),
+  ('\u{118B4}', "\u{118D4}"), ('\u{118B5}', "\u{118D5}"), ('\u{118B6}', "\u{118D6}"), ('\u{118B7}', "\u{118D7}"), ('\u{118B8}', "\u{118D8}"), ('\u{118B9}', "\u{118D9}"),
+  ('\u{118BA}', "\u{118DA}"), ('\u{118BB}', "\u{118DB}"), ('\u{118BC}', "\u{118DC}"), ('\u{118BD}', "\u{118DD}"), ('\u{118BE}', "\u{118DE}"), ('\u{118BF}', "\u{118DF}"),
+  ('\u{16E40}', "\u{16E60}"), ('\u{16E41}', "\u{16E61}"), ('\u{16E42}', "\u{16E62}"), ('\u{16E43}', "\u{16E63}"), ('\u{16E44}', "\u{16E64}"), ('\u{16E45}', "\u{16E65}"),
+  ('\u{16E46}', "\u{16E66}"), ('\u{16E47}', "\u{16E67}"), ('\u{16E48}', "\u{16E68}"), ('\u{16E49}', "\u{16E69}"), ('\u{16E4A}', "\u{16E6A}"), ('\u{16E4B}', "\u{16E6B}"),
+  ('\u{16E4C}', "\u{16E6C}"), ('\u{16E4D}', "\u{16E6D}"), ('\u{16E4E}', "\u{16E6E}"), ('\u{16E4F}', "\u{16E6F}"), ('\u{16E50}', "\u{16E70}"), ('\u{16E51}', "\u{16E71}"),
+  ('\u{16E52}', "\u{16E72}"), ('\u{16E53}', "\u{16E73}"), ('\u{16E54}', "\u{16E74}"), ('\u{16E55}', "\u{16E75}"), ('\u{16E56}', "\u{16E76}"), ('\u{16E57}', "\u{16E77}"),
+  ('\u{16E58}', "\u{16E78}"), ('\u{16E59}', "\u{16E79}"), ('\u{16E5A}', "\u{16E7A}"), ('\u{16E5B}', "\u{16E7B}"), ('\u{16E5C}', "\u{16E7C}"), ('\u{16E5D}', "\u{16E7D}"),
+  ('\u{16E5E}', "\u{16E7E}"), ('\u{16E5F}', "\u{16E7F}"), ('\u{1E900}', "\u{1E922}"), ('\u{1E901}', "\u{1E923}"), ('\u{1E902}', "\u{1E924}"), ('\u{1E903}', "\u{1E925}"),
+  ('\u{1E904}', "\u{1E926}"), ('\u{1E905}', "\u{1E927}"), ('\u{1E906}', "\u{1E928}"), ('\u{1E907}', "\u{1E929}"), ('\u{1E908}', "\u{1E92A}"), ('\u{1E909}', "\u{1E92B}"),
+  ('\u{1E90A}', "\u{1E92C}"), ('\u{1E90B}', "\u{1E92D}"), ('\u{1E90C}', "\u{1E92E}"), ('\u{1E90D}', "\u{1E92F}"), ('\u{1E90E}', "\u{1E930}"), ('\u{1E90F}', "\u{1E931}"),
+  ('\u{1E910}', "\u{1E932}"), ('\u{1E911}', "\u{1E933}"), ('\u{1E912}', "\u{1E934}"), ('\u{1E913}', "\u{1E935}"), ('\u{1E914}', "\u{1E936}"), ('\u{1E915}', "\u{1E937}"),
+  ('\u{1E916}', "\u{1E938}"), ('\u{1E917}', "\u{1E939}"), ('\u{1E918}', "\u{1E93A}"), ('\u{1E919}', "\u{1E93B}"), ('\u{1E91A}', "\u{1E93C}"), ('\u{1E91B}', "\u{1E93D}"),
+  ('\u{1E91C}', "\u{1E93E}"), ('\u{1E91D}', "\u{1E93F}"), ('\u{1E91E}', "\u{1E940}"), ('\u{1E91F}', "\u{1E941}"), ('\u{1E920}', "\u{1E942}"), ('\u{1E921}', "\u{1E943}"),
+];
+
+/// 简单大小写折叠(CaseFolding.txt 的 C+S)
+pub(crate) const SIMPLE_FOLD: &[(char, char)] = &[
+  ('\u{41}', '\u{61}'), ('\u{42}', '\u{62}'), ('\u{43}', '\u{63}'), ('\u{44}', '\u{64}'), ('\u{45}', '\u{65}'), ('\u{46}', '\u{66}'),
+  ('\u{47}', '\u{67}'), ('\u{48}', '\u{68}'), ('\u{49}', '\u{69}'), ('\u{4A}', '\u{6A}'), ('\u{4B}', '\u{6B}'), ('\u{4C}', '\u{6C}'),
+  ('\u{4D}', '\u{6D}'), ('\u{4E}', '\u{6E}'), ('\u{4F}', '\u{6F}'), ('\u{50}', '\u{70}'), ('\u{51}', '\u{71}'), ('\u{52}', '\u{72}'),
+  ('\u{53}', '\u{73}'), ('\u{54}', '\u{74}'), ('\u{55}', '\u{75}'), ('\u{56}', '\u{76}'), ('\u{57}', '\u{77}'), ('\u{58}', '\u{78}'),
+  ('\u{59}', '\u{79}'), ('\u{5A}', '\u{7A}'), ('\u{B5}', '\u{3BC}'), ('\u{C0}', '\u{E0}'), ('\u{C1}', '\u{E1}'), ('\u{C2}', '\u{E2}'),
+  ('\u{C3}', '\u{E3}'), ('\u{C4}', '\u{E4}'), ('\u{C5}', '\u{E5}'), ('\u{C6}', '\u{E6}'), ('\u{C7}', '\u{E7}'), ('\u{C8}', '\u{E8}'),
+  ('\u{C9}', '\u{E9}'), ('\u{CA}', '\u{EA}'), ('\u{CB}', '\u{EB}'), ('\u{CC}', '\u{EC}'), ('\u{CD}', '\u{ED}'), ('\u{CE}', '\u{EE}'),
+  ('\u{CF}', '\u{EF}'), ('\u{D0}', '\u{F0}'), ('\u{D1}', '\u{F1}'), ('\u{D2}', '\u{F2}'), ('\u{D3}', '\u{F3}'), ('\u{D4}', '\u{F4}'),
+  ('\u{D5}', '\u{F5}'), ('\u{D6}', '\u{F6}'), ('\u{D8}', '\u{F8}'), ('\u{D9}', '\u{F9}'), ('\u{DA}', '\u{FA}'), ('\u{DB}', '\u{FB}'),
+  ('\u{DC}', '\u{FC}'), ('\u{DD}', '\u{FD}'), ('\u{DE}', '\u{FE}'), ('\u{100}', '\u{101}'), ('\u{102}', '\u{103}'), ('\u{104}', '\u{105}'),
+  ('\u{106}', '\u{107}'), ('\u{108}', '\u{109}'), ('\u{10A}', '\u{10B}'), ('\u{10C}', '\u{10D}'), ('\u{10E}', '\u{10F}'), ('\u{110}', '\u{111}'),
+  ('\u{112}', '\u{113}'), ('\u{114}', '\u{115}'), ('\u{116}', '\u{117}'), ('\u{118}', '\u{119}'), ('\u{11A}', '\u{11B}'), ('\u{11C}', '\u{11D}'),
+  ('\u{11E}', '\u{11F}'), ('\u{120}', '\u{121}'), ('\u{122}', '\u{123}'), ('\u{124}', '\u{125}'), ('\u{126}', '\u{127}'), ('\u{128}', '\u{129}'),
+  ('\u{12A}', '\u{12B}'), ('\u{12C}', '\u{12D}'), ('\u{12E}', '\u{12F}'), ('\u{132}', '\u{133}'), ('\u{134}', '\u{135}'), ('\u{136}', '\u{137}'),
+  ('\u{139}', '\u{13A}'), ('\u{13B}', '\u{13C}'), ('\u{13D}', '\u{13E}'), ('\u{13F}', '\u{140}'), ('\u{141}', '\u{142}'), ('\u{143}', '\u{144}'),
+  ('\u{145}', '\u{146}'), ('\u{147}', '\u{148}'), ('\u{14A}', '\u{14B}'), ('\u{14C}', '\u{14D}'), ('\u{14E}', '\u{14F}'), ('\u{150}', '\u{151}'),
+  ('\u{152}', '\u{153}'), ('\u{154}', '\u{155}'), ('\u{156}', '\u{157}'), ('\u{158}', '\u{159}'), ('\u{15A}', '\u{15B}'), ('\u{15C}', '\u{15D}'),
+  ('\u{15E}', '\u{15F}'), ('\u{160}', '\u{161}'), ('\u{162}', '\u{163}'), ('\u{164}', '\u{165}'), ('\u{166}', '\u{167}'), ('\u{168}', '\u{169}'),
+  ('\u{16A}', '\u{16B}'), ('\u{16C}', '\u{16D}'), ('\u{16E}', '\u{16F}'), ('\u{170}', '\u{171}'), ('\u{172}', '\u{173}'), ('\u{174}', '\u{175}'),
+  ('\u{176}', '\u{177}'), ('\u{178}', '\u{FF}'), ('\u{179}', '\u{17A}'), ('\u{17B}', '\u{17C}'), ('\u{17D}', '\u{17E}'), ('\u{17F}', '\u{73}'),
+  ('\u{181}', '\u{253}'), ('\u{182}', '\u{183}'), ('\u{184}', '\u{185}'), ('\u{186}', '\u{254}'), ('\u{187}', '\u{188}'), ('\u{189}', '\u{256}'),
+  ('\u{18A}', '\u{257}'), ('\u{18B}', '\u{18C}'), ('\u{18E}', '\u{1DD}'), ('\u{18F}', '\u{259}'), ('\u{190}', '\u{25B}'), ('\u{191}', '\u{192}'),
+  ('\u{193}', '\u{260}'), ('\u{194}', '\u{263}'), ('\u{196}', '\u{269}'), ('\u{197}', '\u{268}'), ('\u{198}', '\u{199}'), ('\u{19C}', '\u{26F}'),
+  ('\u{19D}', '\u{272}'), ('\u{19F}', '\u{275}'), ('\u{1A0}', '\u{1A1}'), ('\u{1A2}', '\u{1A3}'), ('\u{1A4}', '\u{1A5}'), ('\u{1A6}', '\u{280}'),
+  ('\u{1A7}', '\u{1A8}'), ('\u{1A9}', '\u{283}'), ('\u{1AC}', '\u{1AD}'), ('\u{1AE}', '\u{288}'), ('\u{1AF}', '\u{1B0}'), ('\u{1B1}', '\u{28A}'),
+  ('\u{1B2}', '\u{28B}'), ('\u{1B3}', '\u{1B4}'), ('\u{1B5}', '\u{1B6}'), ('\u{1B7}', '\u{292}'), ('\u{1B8}', '\u{1B9}'), ('\u{1BC}', '\u{1BD}'),
+  ('\u{1C4}', '\u{1C6}'), ('\u{1C5}', '\u{1C6}'), ('\u{1C7}', '\u{1C9}'), ('\u{1C8}', '\u{1C9}'), ('\u{1CA}', '\u{1CC}'), ('\u{1CB}', '\u{1CC}'),
+  ('\u{1CD}', '\u{1CE}'), ('\u{1CF}', '\u{1D0}'), ('\u{1D1}', '\u{1D2}'), ('\u{1D3}', '\u{1D4}'), ('\u{1D5}', '\u{1D6}'), ('\u{1D7}', '\u{1D8}'),
+  ('\u{1D9}', '\u{1DA}'), ('\u{1DB}', '\u{1DC}'), ('\u{1DE}', '\u{1DF}'), ('\u{1E0}', '\u{1E1}'), ('\u{1E2}', '\u{1E3}'), ('\u{1E4}', '\u{1E5}'),
+  ('\u{1E6}', '\u{1E7}'), ('\u{1E8}', '\u{1E9}'), ('\u{1EA}', '\u{1EB}'), ('\u{1EC}', '\u{1ED}'), ('\u{1EE}', '\u{1EF}'), ('\u{1F1}', '\u{1F3}'),
+  ('\u{1F2}', '\u{1F3}'), ('\u{1F4}', '\u{1F5}'), ('\u{1F6}', '\u{195}'), ('\u{1F7}', '\u{1BF}'), ('\u{1F8}', '\u{1F9}'), ('\u{1FA}', '\u{1FB}'),
+  ('\u{1FC}', '\u{1FD}'), ('\u{1FE}', '\u{1FF}'), ('\u{200}', '\u{201}'), ('\u{202}', '\u{203}'), ('\u{204}', '\u{205}'), ('\u{206}', '\u{207}'),
+  ('\u{208}', '\u{209}'), ('\u{20A}', '\u{20B}'), ('\u{20C}', '\u{20D}'), ('\u{20E}', '\u{20F}'), ('\u{210}', '\u{211}'), ('\u{212}', '\u{213}'),
+  ('\u{214}', '\u{215}'), ('\u{216}', '\u{217}'), ('\u{218}', '\u{219}'), ('\u{21A}', '\u{21B}'), ('\u{21C}', '\u{21D}'), ('\u{21E}', '\u{21F}'),
+  ('\u{220}', '\u{19E}'), ('\u{222}', '\u{223}'), ('\u{224}', '\u{225}'), ('\u{226}', '\u{227}'), ('\u{228}', '\u{229}'), ('\u{22A}', '\u{22B}'),
+  ('\u{22C}', '\u{22D}'), ('\u{22E}', '\u{22F}'), ('\u{230}', '\u{231}'), ('\u{232}', '\u{233}'), ('\u{23A}', '\u{2C65}'), ('\u{23B}', '\u{23C}'),
+  ('\u{23D}', '\u{19A}'), ('\u{23E}', '\u{2C66}'), ('\u{241}', '\u{242}'), ('\u{243}', '\u{180}'), ('\u{244}', '\u{289}'), ('\u{245}', '\u{28C}'),
+  ('\u{246}', '\u{247}'), ('\u{248}', '\u{249}'), ('\u{24A}', '\u{24B}'), ('\u{24C}', '\u{24D}'), ('\u{24E}', '\u{24F}'), ('\u{345}', '\u{3B9}'),
+  ('\u{370}', '\u{371}'), ('\u{372}', '\u{373}'), ('\u{376}', '\u{377}'), ('\u{37F}', '\u{3F3}'), ('\u{386}', '\u{3AC}'), ('\u{388}', '\u{3AD}'),
+  ('\u{389}', '\u{3AE}'), ('\u{38A}', '\u{3AF}'), ('\u{38C}', '\u{3CC}'), ('\u{38E}', '\u{3CD}'), ('\u{38F}', '\u{3CE}'), ('\u{391}', '\u{3B1}'),
+  ('\u{392}', '\u{3B2}'), ('\u{393}', '\u{3B3}'), ('\u{394}', '\u{3B4}'), ('\u{395}', '\u{3B5}'), ('\u{396}', '\u{3B6}'), ('\u{397}', '\u{3B7}'),
+  ('\u{398}', '\u{3B8}'), ('\u{399}', '\u{3B9}'), ('\u{39A}', '\u{3BA}'), ('\u{39B}', '\u{3BB}'), ('\u{39C}', '\u{3BC}'), ('\u{39D}', '\u{3BD}'),
+  ('\u{39E}', '\u{3BE}'), ('\u{39F}', '\u{3BF}'), ('\u{3A0}', '\u{3C0}'), ('\u{3A1}', '\u{3C1}'), ('\u{3A3}', '\u{3C3}'), ('\u{3A4}', '\u{3C4}'),
+  ('\u{3A5}', '\u{3C5}'), ('\u{3A6}', '\u{3C6}'), ('\u{3A7}', '\u{3C7}'), ('\u{3A8}', '\u{3C8}'), ('\u{3A9}', '\u{3C9}'), ('\u{3AA}', '\u{3CA}'),
+  ('\u{3AB}', '\u{3CB}'), ('\u{3C2}', '\u{3C3}'), ('\u{3CF}', '\u{3D7}'), ('\u{3D0}', '\u{3B2}'), ('\u{3D1}', '\u{3B8}'), ('\u{3D5}', '\u{3C6}'),
+  ('\u{3D6}', '\u{3C0}'), ('\u{3D8}', '\u{3D9}'), ('\u{3DA}', '\u{3DB}'), ('\u{3DC}', '\u{3DD}'), ('\u{3DE}', '\u{3DF}'), ('\u{3E0}', '\u{3E1}'),
+  ('\u{3E2}', '\u{3E3}'), ('\u{3E4}', '\u{3E5}'), ('\u{3E6}', '\u{3E7}'), ('\u{3E8}', '\u{3E9}'), ('\u{3EA}', '\u{3EB}'), ('\u{3EC}', '\u{3ED}'),
+  ('\u{3EE}', '\u{3EF}'), ('\u{3F0}', '\u{3BA}'), ('\u{3F1}', '\u{3C1}'), ('\u{3F4}', '\u{3B8}'), ('\u{3F5}', '\u{3B5}'), ('\u{3F7}', '\u{3F8}'),
+  ('\u{3F9}', '\u{3F2}'), ('\u{3FA}', '\u{3FB}'), ('\u{3FD}', '\u{37B}'), ('\u{3FE}', '\u{37C}'), ('\u{3FF}', '\u{37D}'), ('\u{400}', '\u{450}'),
+  ('\u{401}', '\u{451}'), ('\u{402}', '\u{452}'), ('\u{403}', '\u{453}'), ('\u{404}', '\u{454}'), ('\u{405}', '\u{455}'), ('\u{406}', '\u{456}'),
+  ('\u{407}', '\u{457}'), ('\u{408}', '\u{458}'), ('\u{409}', '\u{459}'), ('\u{40A}', '\u{45A}'), ('\u{40B}', '\u{45B}'), ('\u{40C}', '\u{45C}'),
+  ('\u{40D}', '\u{45D}'), ('\u{40E}', '\u{45E}'), ('\u{40F}', '\u{45F}'), ('\u{410}', '\u{430}'), ('\u{411}', '\u{431}'), ('\u{412}', '\u{432}'),
+  ('\u{413}', '\u{433}'), ('\u{414}', '\u{434}'), ('\u{415}', '\u{435}'), ('\u{416}', '\u{436}'), ('\u{417}', '\u{437}'), ('\u{418}', '\u{438}'),
+  ('\u{419}', '\u{439}'), ('\u{41A}', '\u{43A}'), ('\u{41B}', '\u{43B}'), ('\u{41C}', '\u{43C}'), ('\u{41D}', '\u{43D}'), ('\u{41E}', '\u{43E}'),
+  ('\u{41F}', '\u{43F}'), ('\u{420}', '\u{440}'), ('\u{421}', '\u{441}'), ('\u{422}', '\u{442}'), ('\u{423}', '\u{443}'), ('\u{424}', '\u{444}'),
+  ('\u{425}', '\u{445}'), ('\u{426}', '\u{446}'), ('\u{427}', '\u{447}'), ('\u{428}', '\u{448}'), ('\u{429}', '\u{449}'), ('\u{42A}', '\u{44A}'),
+  ('\u{42B}', '\u{44B}'), ('\u{42C}', '\u{44C}'), ('\u{42D}', '\u{44D}'), ('\u{42E}', '\u{44E}'), ('\u{42F}', '\u{44F}'), ('\u{460}', '\u{461}'),
+  ('\u{462}', '\u{463}'), ('\u{464}', '\u{465}'), ('\u{466}', '\u{467}'), ('\u{468}', '\u{469}'), ('\u{46A}', '\u{46B}'), ('\u{46C}', '\u{46D}'),
+  ('\u{46E}', '\u{46F}'), ('\u{470}', '\u{471}'), ('\u{472}', '\u{473}'), ('\u{474}', '\u{475}'), ('\u{476}', '\u{477}'), ('\u{478}', '\u{479}'),
+  ('\u{47A}', '\u{47B}'), ('\u{47C}', '\u{47D}'), ('\u{47E}', '\u{47F}'), ('\u{480}', '\u{481}'), ('\u{48A}', '\u{48B}'), ('\u{48C}', '\u{48D}'),
+  ('\u{48E}', '\u{48F}'), ('\u{490}', '\u{491}'), ('\u{492}', '\u{493}'), ('\u{494}', '\u{495}'), ('\u{496}', '\u{497}'), ('\u{498}', '\u{499}'),
+  ('\u{49A}', '\u{49B}'), ('\u{49C}', '\u{49D}'), ('\u{49E}', '\u{49F}'), ('\u{4A0}', '\u{4A1}'), ('\u{4A2}', '\u{4A3}'), ('\u{4A4}', '\u{4A5}'),
+  ('\u{4A6}', '\u{4A7}'), ('\u{4A8}', '\u{4A9}'), ('\u{4AA}', '\u{4AB}'), ('\u{4AC}', '\u{4AD}'), ('\u{4AE}', '\u{4AF}'), ('\u{4B0}', '\u{4B1}'),
+  ('\u{4B2}', '\u{4B3}'), ('\u{4B4}', '\u{4B5}'), ('\u{4B6}', '\u{4B7}'), ('\u{4B8}', '\u{4B9}'), ('\u{4BA}', '\u{4BB}'), ('\u{4BC}', '\u{4BD}'),
+  ('\u{4BE}', '\u{4BF}'), ('\u{4C0}', '\u{4CF}'), ('\u{4C1}', '\u{4C2}'), ('\u{4C3}', '\u{4C4}'), ('\u{4C5}', '\u{4C6}'), ('\u{4C7}', '\u{4C8}'),
+  ('\u{4C9}', '\u{4CA}'), ('\u{4CB}', '\u{4CC}'), ('\u{4CD}', '\u{4CE}'), ('\u{4D0}', '\u{4D1}'), ('\u{4D2}', '\u{4D3}'), ('\u{4D4}', '\u{4D5}'),
+  ('\u{4D6}', '\u{4D7}'), ('\u{4D8}', '\u{4D9}'), ('\u{4DA}', '\u{4DB}'), ('\u{4DC}', '\u{4DD}'), ('\u{4DE}', '\u{4DF}'), ('\u{4E0}', '\u{4E1}'),
+  ('\u{4E2}', '\u{4E3}'), ('\u{4E4}', '\u{4E5}'), ('\u{4E6}', '\u{4E7}'), ('\u{4E8}', '\u{4E9}'), ('\u{4EA}', '\u{4EB}'), ('\u{4EC}', '\u{4ED}'),
+  ('\u{4EE}', '\u{4EF}'), ('\u{4F0}', '\u{4F1}'), ('\u{4F2}', '\u{4F3}'), ('\u{4F4}', '\u{4F5}'), ('\u{4F6}', '\u{4F7}'), ('\u{4F8}', '\u{4F9}'),
+  ('\u{4FA}', '\u{4FB}'), ('\u{4FC}', '\u{4FD}'), ('\u{4FE}', '\u{4FF}'), ('\u{500}', '\u{501}'), ('\u{502}', '\u{503}'), ('\u{504}', '\u{505}'),
+  ('\u{506}', '\u{507}'), ('\u{508}', '\u{509}'), ('\u{50A}', '\u{50B}'), ('\u{50C}', '\u{50D}'), ('\u{50E}', '\u{50F}'), ('\u{510}', '\u{511}'),
+  ('\u{512}', '\u{513}'), ('\u{514}', '\u{515}'), ('\u{516}', '\u{517}'), ('\u{518}', '\u{519}'), ('\u{51A}', '\u{51B}'), ('\u{51C}', '\u{51D}'),
+  ('\u{51E}', '\u{51F}'), ('\u{520}', '\u{521}'), ('\u{522}', '\u{523}'), ('\u{524}', '\u{525}'), ('\u{526}', '\u{527}'), ('\u{528}', '\u{529}'),
+  ('\u{52A}', '\u{52B}'), ('\u{52C}', '\u{52D}'), ('\u{52E}', '\u{52F}'), ('\u{531}', '\u{561}'), ('\u{532}', '\u{562}'), ('\u{533}', '\u{563}'),
+  ('\u{534}', '\u{564}'), ('\u{535}', '\u{565}'), ('\u{536}', '\u{566}'), ('\u{537}', '\u{567}'), ('\u{538}', '\u{568}'), ('\u{539}', '\u{569}'),
+  ('\u{53A}', '\u{56A}'), ('\u{53B}', '\u{56B}'), ('\u{53C}', '\u{56C}'), ('\u{53D}', '\u{56D}'), ('\u{53E}', '\u{56E}'), ('\u{53F}', '\u{56F}'),
+  ('\u{540}', '\u{570}'), ('\u{541}', '\u{571}'), ('\u{542}', '\u{572}'), ('\u{543}', '\u{573}'), ('\u{544}', '\u{574}'), ('\u{545}', '\u{575}'),
+  ('\u{546}', '\u{576}'), ('\u{547}', '\u{577}'), ('\u{548}', '\u{578}'), ('\u{549}', '\u{579}'), ('\u{54A}', '\u{57A}'), ('\u{54B}', '\u{57B}'),
+  ('\u{54C}', '\u{57C}'), ('\u{54D}', '\u{57D}'), ('\u{54E}', '\u{57E}'), ('\u{54F}', '\u{57F}'), ('\u{550}', '\u{580}'), ('\u{551}', '\u{581}'),
+  ('\u{552}', '\u{582}'), ('\u{553}', '\u{583}'), ('\u{554}', '\u{584}'), ('\u{555}', '\u{585}'), ('\u{556}', '\u{586}'), ('\u{10A0}', '\u{2D00}'),
+  ('\u{10A1}', '\u{2D01}'), ('\u{10A2}', '\u{2D02}'), ('\u{10A3}', '\u{2D03}'), ('\u{10A4}', '\u{2D04}'), ('\u{10A5}', '\u{2D05}'), ('\u{10A6}', '\u{2D06}'),
+  ('\u{10A7}', '\u{2D07}'), ('\u{10A8}', '\u{2D08}'), ('\u{10A9}', '\u{2D09}'), ('\u{10AA}', '\u{2D0A}'), ('\u{10AB}', '\u{2D0B}'), ('\u{10AC}', '\u{2D0C}'),
+  ('\u{10AD}', '\u{2D0D}'), ('\u{10AE}', '\u{2D0E}'), ('\u{10AF}', '\u{2D0F}'), ('\u{10B0}', '\u{2D10}'), ('\u{10B1}', '\u{2D11}'), ('\u{10B2}', '\u{2D12}'),
+  ('\u{10B3}', '\u{2D13}'), ('\u{10B4}', '\u{2D14}'), ('\u{10B5}', '\u{2D15}'), ('\u{10B6}', '\u{2D16}'), ('\u{10B7}', '\u{2D17}'), ('\u{10B8}', '\u{2D18}'),
+  ('\u{10B9}', '\u{2D19}'), ('\u{10BA}', '\u{2D1A}'), ('\u{10BB}', '\u{2D1B}'), ('\u{10BC}', '\u{2D1C}'), ('\u{10BD}', '\u{2D1D}'), ('\u{10BE}', '\u{2D1E}'),
+  ('\u{10BF}', '\u{2D1F}'), ('\u{10C0}', '\u{2D20}'), ('\u{10C1}', '\u{2D21}'), ('\u{10C2}', '\u{2D22}'), ('\u{10C3}', '\u{2D23}'), ('\u{10C4}', '\u{2D24}'),
+  ('\u{10C5}', '\u{2D25}'), ('\u{10C7}', '\u{2D27}'), ('\u{10CD}', '\u{2D2D}'), ('\u{13F8}', '\u{13F0}'), ('\u{13F9}', '\u{13F1}'), ('\u{13FA}', '\u{13F2}'),
+  ('\u{13FB}', '\u{13F3}'), ('\u{13FC}', '\u{13F4}'), ('\u{13FD}', '\u{13F5}'), ('\u{1C80}', '\u{432}'), ('\u{1C81}', '\u{434}'), ('\u{1C82}', '\u{43E}'),
+  ('\u{1C83}', '\u{441}'), ('\u{1C84}', '\u{442}'), ('\u{1C85}', '\u{442}'), ('\u{1C86}', '\u{44A}'), ('\u{1C87}', '\u{463}'), ('\u{1C88}', '\u{A64B}'),
+  ('\u{1C90}', '\u{10D0}'), ('\u{1C91}', '\u{10D1}'), ('\u{1C92}', '\u{10D2}'), ('\u{1C93}', '\u{10D3}'), ('\u{1C94}', '\u{10D4}'), ('\u{1C95}', '\u{10D5}'),
+  ('\u{1C96}', '\u{10D6}'), ('\u{1C97}', '\u{10D7}'), ('\u{1C98}', '\u{10D8}'), ('\u{1C99}', '\u{10D9}'), ('\u{1C9A}', '\u{10DA}'), ('\u{1C9B}', '\u{10DB}'),
+  ('\u{1C9C}', '\u{10DC}'), ('\u{1C9D}', '\u{10DD}'), ('\u{1C9E}', '\u{10DE}'), ('\u{1C9F}', '\u{10DF}'), ('\u{1CA0}', '\u{10E0}'), ('\u{1CA1}', '\u{10E1}'),
+  ('\u{1CA2}', '\u{10E2}'), ('\u{1CA3}', '\u{10E3}'), ('\u{1CA4}', '\u{10E4}'), ('\u{1CA5}', '\u{10E5}'), ('\u{1CA6}', '\u{10E6}'), ('\u{1CA7}', '\u{10E7}'),
+  ('\u{1CA8}', '\u{10E8}'), ('\u{1CA9}', '\u{10E9}'), ('\u{1CAA}', '\u{10EA}'), ('\u{1CAB}', '\u{10EB}'), ('\u{1CAC}', '\u{10EC}'), ('\u{1CAD}', '\u{10ED}'),
+  ('\u{1CAE}', '\u{10EE}'), ('\u{1CAF}', '\u{10EF}'), ('\u{1CB0}', '\u{10F0}'), ('\u{1CB1}', '\u{10F1}'), ('\u{1CB2}', '\u{10F2}'), ('\u{1CB3}', '\u{10F3}'),
+  ('\u{1CB4}', '\u{10F4}'), ('\u{1CB5}', '\u{10F5}'), ('\u{1CB6}', '\u{10F6}'), ('\u{1CB7}', '\u{10F7}'), ('\u{1CB8}', '\u{10F8}'), ('\u{1CB9}', '\u{10F9}'),
+  ('\u{1CBA}', '\u{10FA}'), ('\u{1CBD}', '\u{10FD}'), ('\u{1CBE}', '\u{10FE}'), ('\u{1CBF}', '\u{10FF}'), ('\u{1E00}', '\u{1E01}'), ('\u{1E02}', '\u{1E03}'),
+  ('\u{1E04}', '\u{1E05}'), ('\u{1E06}', '\u{1E07}'), ('\u{1E08}', '\u{1E09}'), ('\u{1E0A}', '\u{1E0B}'), ('\u{1E0C}', '\u{1E0D}'), ('\u{1E0E}', '\u{1E0F}'),
+  ('\u{1E10}', '\u{1E11}'), ('\u{1E12}', '\u{1E13}'), ('\u{1E14}', '\u{1E15}'), ('\u{1E16}', '\u{1E17}'), ('\u{1E18}', '\u{1E19}'), ('\u{1E1A}', '\u{1E1B}'),
+  ('\u{1E1C}', '\u{1E1D}'), ('\u{1E1E}', '\u{1E1F}'), ('\u{1E20}', '\u{1E21}'), ('\u{1E22}', '\u{1E23}'), ('\u{1E24}', '\u{1E25}'), ('\u{1E26}', '\u{1E27}'),
+  ('\u{1E28}', '\u{1E29}'), ('\u{1E2A}', '\u{1E2B}'), ('\u{1E2C}', '\u{1E2D}'), ('\u{1E2E}', '\u{1E2F}'), ('\u{1E30}', '\u{1E31}'), ('\u{1E32}', '\u{1E33}'),
+  ('\u{1E34}', '\u{1E35}'), ('\u{1E36}', '\u{1E37}'), ('\u{1E38}', '\u{1E39}'), ('\u{1E3A}', '\u{1E3B}'), ('\u{1E3C}', '\u{1E3D}'), ('\u{1E3E}', '\u{1E3F}'),
+  ('\u{1E40}', '\u{1E41}'), ('\u{1E42}', '\u{1E43}'), ('\u{1E44}', '\u{1E45}'), ('\u{1E46}', '\u{1E47}'), ('\u{1E48}', '\u{1E49}'), ('\u{1E4A}', '\u{1E4B}'),
+  ('\u{1E4C}', '\u{1E4D}'), ('\u{1E4E}', '\u{1E4F}'), ('\u{1E50}', '\u{1E51}'), ('\u{1E52}', '\u{1E53}'), ('\u{1E54}', '\u{1E55}'), ('\u{1E56}', '\u{1E57}'),
+  ('\u{1E58}', '\u{1E59}'), ('\u{1E5A}', '\u{1E5B}'), ('\u{1E5C}', '\u{1E5D}'), ('\u{1E5E}', '\u{1E5F}'), ('\u{1E60}', '\u{1E61}'), ('\u{1E62}', '\u{1E63}'),
+  ('\u{1E64}', '\u{1E65}'), ('\u{1E66}', '\u{1E67}'), ('\u{1E68}', '\u{1E69}'), ('\u{1E6A}', '\u{1E6B}'), ('\u{1E6C}', '\u{1E6D}'), ('\u{1E6E}', '\u{1E6F}'),
+  ('\u{1E70}', '\u{1E71}'), ('\u{1E72}', '\u{1E73}'), ('\u{1E74}', '\u{1E75}'), ('\u{1E76}', '\u{1E77}'), ('\u{1E78}', '\u{1E79}'), ('\u{1E7A}', '\u{1E7B}'),
+  ('\u{1E7C}', '\u{1E7D}'), ('\u{1E7E}', '\u{1E7F}'), ('\u{1E80}', '\u{1E81}'), ('\u{1E82}', '\u{1E83}'), ('\u{1E84}', '\u{1E85}'), ('\u{1E86}', '\u{1E87}'),
+  ('\u{1E88}', '\u{1E89}'), ('\u{1E8A}', '\u{1E8B}'), ('\u{1E8C}', '\u{1E8D}'), ('\u{1E8E}', '\u{1E8F}'), ('\u{1E90}', '\u{1E91}'), ('\u{1E92}', '\u{1E93}'),
+  ('\u{1E94}', '\u{1E95}'), ('\u{1E9B}', '\u{1E61}'), ('\u{1E9E}', '\u{DF}'), ('\u{1EA0}', '\u{1EA1}'), ('\u{1EA2}', '\u{1EA3}'), ('\u{1EA4}', '\u{1EA5}'),
+  ('\u{1EA6}', '\u{1EA7}'), ('\u{1EA8}', '\u{1EA9}'), ('\u{1EAA}', '\u{1EAB}'), ('\u{1EAC}', '\u{1EAD}'), ('\u{1EAE}', '\u{1EAF}'), ('\u{1EB0}', '\u{1EB1}'),
+  ('\u{1EB2}', '\u{1EB3}'), ('\u{1EB4}', '\u{1EB5}'), ('\u{1EB6}', '\u{1EB7}'), ('\u{1EB8}', '\u{1EB9}'), ('\u{1EBA}', '\u{1EBB}'), ('\u{1EBC}', '\u{1EBD}'),
+  ('\u{1EBE}', '\u{1EBF}'), ('\u{1EC0}', '\u{1EC1}'), ('\u{1EC2}', '\u{1EC3}'), ('\u{1EC4}', '\u{1EC5}'), ('\u{1EC6}', '\u{1EC7}'), ('\u{1EC8}', '\u{1EC9}'),
+  ('\u{1ECA}', '\u{1ECB}'), ('\u{1ECC}', '\u{1ECD}'), ('\u{1ECE}', '\u{1ECF}'), ('\u{1ED0}', '\u{1ED1}'), ('\u{1ED2}', '\u{1ED3}'), ('\u{1ED4}', '\u{1ED5}'),
+  ('\u{1ED6}', '\u{1ED7}'), ('\u{1ED8}', '\u{1ED9}'), ('\u{1EDA}', '\u{1EDB}'), ('\u{1EDC}', '\u{1EDD}'), ('\u{1EDE}', '\u{1EDF}'), ('\u{1EE0}', '\u{1EE1}'),
+  ('\u{1EE2}', '\u{1EE3}'), ('\u{1EE4}', '\u{1EE5}'), ('\u{1EE6}', '\u{1EE7}'), ('\u{1EE8}', '\u{1EE9}'), ('\u{1EEA}', '\u{1EEB}'), ('\u{1EEC}', '\u{1EED}'),
+  ('\u{1EEE}', '\u{1EEF}'), ('\u{1EF0}', '\u{1EF1}'), ('\u{1EF2}', '\u{1EF3}'), ('\u{1EF4}', '\u{1EF5}'), ('\u{1EF6}', '\u{1EF7}'), ('\u{1EF8}', '\u{1EF9}'),
+  ('\u{1EFA}', '\u{1EFB}'), ('\u{1EFC}', '\u{1EFD}'), ('\u{1EFE}', '\u{1EFF}'), ('\u{1F08}', '\u{1F00}'), ('\u{1F09}', '\u{1F01}'), ('\u{1F0A}', '\u{1F02}'),
+  ('\u{1F0B}', '\u{1F03}'), ('\u{1F0C}', '\u{1F04}'), ('\u{1F0D}', '\u{1F05}'), ('\u{1F0E}', '\u{1F06}'), ('\u{1F0F}', '\u{1F07}'), ('\u{1F18}', '\u{1F10}'),
+  ('\u{1F19}', '\u{1F11}'), ('\u{1F1A}', '\u{1F12}'), ('\u{1F1B}', '\u{1F13}'), ('\u{1F1C}', '\u{1F14}'), ('\u{1F1D}', '\u{1F15}'), ('\u{1F28}', '\u{1F20}'),
+  ('\u{1F29}', '\u{1F21}'), ('\u{1F2A}', '\u{1F22}'), ('\u{1F2B}', '\u{1F23}'), ('\u{1F2C}', '\u{1F24}'), ('\u{1F2D}', '\u{1F25}'), ('\u{1F2E}', '\u{1F26}'),
+  ('\u{1F2F}', '\u{1F27}'), ('\u{1F38}', '\u{1F30}'), ('\u{1F39}', '\u{1F31}'), ('\u{1F3A}', '\u{1F32}'), ('\u{1F3B}', '\u{1F33}'), ('\u{1F3C}', '\u{1F34}'),
+  ('\u{1F3D}', '\u{1F35}'), ('\u{1F3E}', '\u{1F36}'), ('\u{1F3F}', '\u{1F37}'), ('\u{1F48}', '\u{1F40}'), ('\u{1F49}', '\u{1F41}'), ('\u{1F4A}', '\u{1F42}'),
+  ('\u{1F4B}', '\u{1F43}'), ('\u{1F4C}', '\u{1F44}'), ('\u{1F4D}', '\u{1F45}'), ('\u{1F59}', '\u{1F51}'), ('\u{1F5B}', '\u{1F53}'), ('\u{1F5D}', '\u{1F55}'),
+  ('\u{1F5F}', '\u{1F57}'), ('\u{1F68}', '\u{1F60}'), ('\u{1F69}', '\u{1F61}'), ('\u{1F6A}', '\u{1F62}'), ('\u{1F6B}', '\u{1F63}'), ('\u{1F6C}', '\u{1F64}'),
+  ('\u{1F6D}', '\u{1F65}'), ('\u{1F6E}', '\u{1F66}'), ('\u{1F6F}', '\u{1F67}'), ('\u{1F88}', '\u{1F80}'), ('\u{1F89}', '\u{1F81}'), ('\u{1F8A}', '\u{1F82}'),
+  ('\u{1F8B}', '\u{1F83}'), ('\u{1F8C}', '\u{1F84}'), ('\u{1F8D}', '\u{1F85}'), ('\u{1F8E}', '\u{1F86}'), ('\u{1F8F}', '\u{1F87}'), ('\u{1F98}', '\u{1F90}'),
+  ('\u{1F99}', '\u{1F91}'), ('\u{1F9A}', '\u{1F92}'), ('\u{1F9B}', '\u{1F93}'), ('\u{1F9C}', '\u{1F94}'), ('\u{1F9D}', '\u{1F95}'), ('\u{1F9E}', '\u{1F96}'),
+  ('\u{1F9F}', '\u{1F97}'), ('\u{1FA8}', '\u{1FA0}'), ('\u{1FA9}', '\u{1FA1}'), ('\u{1FAA}', '\u{1FA2}'), ('\u{1FAB}', '\u{1FA3}'), ('\u{1FAC}', '\u{1FA4}'),
+  ('\u{1FAD}', '\u{1FA5}'), ('\u{1FAE}', '\u{1FA6}'), ('\u{1FAF}', '\u{1FA7}'), ('\u{1FB8}', '\u{1FB0}'), ('\u{1FB9}', '\u{1FB1}'), ('\u{1FBA}', '\u{1F70}'),
+  ('\u{1FBB}', '\u{1F71}'), ('\u{1FBC}', '\u{1FB3}'), ('\u{1FBE}', '\u{3B9}'), ('\u{1FC8}', '\u{1F72}'), ('\u{1FC9}', '\u{1F73}'), ('\u{1FCA}', '\u{1F74}'),
+  ('\u{1FCB}', '\u{1F75}'), ('\u{1FCC}', '\u{1FC3}'), ('\u{1FD8}', '\u{1FD0}'), ('\u{1FD9}', '\u{1FD1}'), ('\u{1FDA}', '\u{1F76}'), ('\u{1FDB}', '\u{1F77}'),
+  ('\u{1FE8}', '\u{1FE0}'), ('\u{1FE9}', '\u{1FE1}'), ('\u{1FEA}', '\u{1F7A}'), ('\u{1FEB}', '\u{1F7B}'), ('\u{1FEC}', '\u{1FE5}'), ('\u{1FF8}', '\u{1F78}'),
+  ('\u{1FF9}', '\u{1F79}'), ('\u{1FFA}', '\u{1F7C}'), ('\u{1FFB}', '\u{1F7D}'), ('\u{1FFC}', '\u{1FF3}'), ('\u{2126}', '\u{3C9}'), ('\u{212A}', '\u{6B}'),
+  ('\u{212B}', '\u{E5}'), ('\u{2132}', '\u{214E}'), ('\u{2160}', '\u{2170}'), ('\u{2161}', '\u{2171}'), ('\u{2162}', '\u{2172}'), ('\u{2163}', '\u{2173}'),
+  ('\u{2164}', '\u{2174}'), ('\u{2165}', '\u{2175}'), ('\u{2166}', '\u{2176}'), ('\u{2167}', '\u{2177}'), ('\u{2168}', '\u{2178}'), ('\u{2169}', '\u{2179}'),
+  ('\u{216A}', '\u{217A}'), ('\u{216B}', '\u{217B}'), ('\u{216C}', '\u{217C}'), ('\u{216D}', '\u{217D}'), ('\u{216E}', '\u{217E}'), ('\u{216F}', '\u{217F}'),
+  ('\u{2183}', '\u{2184}'), ('\u{24B6}', '\u{24D0}'), ('\u{24B7}', '\u{24D1}'), ('\u{24B8}', '\u{24D2}'), ('\u{24B9}', '\u{24D3}'), ('\u{24BA}', '\u{24D4}'),
+  ('\u{24BB}', '\u{24D5}'), ('\u{24BC}', '\u{24D6}'), ('\u{24BD}', '\u{24D7}'), ('\u{24BE}', '\u{24D8}'), ('\u{24BF}', '\u{24D9}'), ('\u{24C0}', '\u{24DA}'),
+  ('\u{24C1}', '\u{24DB}'), ('\u{24C2}', '\u{24DC}'), ('\u{24C3}', '\u{24DD}'), ('\u{24C4}', '\u{24DE}'), ('\u{24C5}', '\u{24DF}'), ('\u{24C6}', '\u{24E0}'),
+  ('\u{24C7}', '\u{24E1}'), ('\u{24C8}', '\u{24E2}'), ('\u{24C9}', '\u{24E3}'), ('\u{24CA}', '\u{24E4}'), ('\u{24CB}', '\u{24E5}'), ('\u{24CC}', '\u{24E6}'),
+  ('\u{24CD}', '\u{24E7}'), ('\u{24CE}', '\u{24E8}'), ('\u{24CF}', '\u{24E9}'), ('\u{2C00}', '\u{2C30}'), ('\u{2C01}', '\u{2C31}'), ('\u{2C02}', '\u{2C32}'),
+  ('\u{2C03}', '\u{2C33}'), ('\u{2C04}', '\u{2C34}'), ('\u{2C05}', '\u{2C35}'), ('\u{2C06}', '\u{2C36}'), ('\u{2C07}', '\u{2C37}'), ('\u{2C08}', '\u{2C38}'),
+  ('\u{2C09}', '\u{2C39}'), ('\u{2C0A}', '\u{2C3A}'), ('\u{2C0B}', '\u{2C3B}'), ('\u{2C0C}', '\u{2C3C}'), ('\u{2C0D}', '\u{2C3D}'), ('\u{2C0E}', '\u{2C3E}'),
+  ('\u{2C0F}', '\u{2C3F}'), ('\u{2C10}', '\u{2C40}'), ('\u{2C11}', '\u{2C41}'), ('\u{2C12}', '\u{2C42}'), ('\u{2C13}', '\u{2C43}'), ('\u{2C14}', '\u{2C44}'),
+  ('\u{2C15}', '\u{2C45}'), ('\u{2C16}', '\u{2C46}'), ('\u{2C17}', '\u{2C47}'), ('\u{2C18}', '\u{2C48}'), ('\u{2C19}', '\u{2C49}'), ('\u{2C1A}', '\u{2C4A}'),
+  ('\u{2C1B}', '\u{2C4B}'), ('\u{2C1C}', '\u{2C4C}'), ('\u{2C1D}', '\u{2C4D}'), ('\u{2C1E}', '\u{2C4E}'), ('\u{2C1F}', '\u{2C4F}'), ('\u{2C20}', '\u{2C50}'),
+  ('\u{2C21}', '\u{2C51}'), ('\u{2C22}', '\u{2C52}'), ('\u{2C23}', '\u{2C53}'), ('\u{2C24}', '\u{2C54}'), ('\u{2C25}', '\u{2C55}'), ('\u{2C26}', '\u{2C56}'),
+  ('\u{2C27}', '\u{2C57}'), ('\u{2C28}', '\u{2C58}'), ('\u{2C29}', '\u{2C59}'), ('\u{2C2A}', '\u{2C5A}'), ('\u{2C2B}', '\u{2C5B}'), ('\u{2C2C}', '\u{2C5C}'),
+  ('\u{2C2D}', '\u{2C5D}'), ('\u{2C2E}', '\u{2C5E}'), ('\u{2C2F}', '\u{2C5F}'), ('\u{2C60}', '\u{2C61}'), ('\u{2C62}', '\u{26B}'), ('\u{2C63}', '\u{1D7D}'),
+  ('\u{2C64}', '\u{27D}'), ('\u{2C67}', '\u{2C68}'), ('\u{2C69}', '\u{2C6A}'), ('\u{2C6B}', '\u{2C6C}'), ('\u{2C6D}', '\u{251}'), ('\u{2C6E}', '\u{271}'),
+  ('\u{2C6F}', '\u{250}'), ('\u{2C70}', '\u{252}'), ('\u{2C72}', '\u{2C73}'), ('\u{2C75}', '\u{2C76}'), ('\u{2C7E}', '\u{23F}'), ('\u{2C7F}', '\u{240}'),
+  ('\u{2C80}', '\u{2C81}'), ('\u{2C82}', '\u{2C83}'), ('\u{2C84}', '\u{2C85}'), ('\u{2C86}', '\u{2C87}'), ('\u{2C88}', '\u{2C89}'), ('\u{2C8A}', '\u{2C8B}'),
+  ('\u{2C8C}', '\u{2C8D}'), ('\u{2C8E}', '\u{2C8F}'), ('\u{2C90}', '\u{2C91}'), ('\u{2C92}', '\u{2C93}'), ('\u{2C94}', '\u{2C95}'), ('\u{2C96}', '\u{2C97}'),
+  ('\u{2C98}', '\u{2C99}'), ('\u{2C9A}', '\u{2C9B}'), ('\u{2C9C}', '\u{2C9D}'), ('\u{2C9E}', '\u{2C9F}'), ('\u{2CA0}', '\u{2CA1}'), ('\u{2CA2}', '\u{2CA3}'),
+  ('\u{2CA4}', '\u{2CA5}'), ('\u{2CA6}', '\u{2CA7}'), ('\u{2CA8}', '\u{2CA9}'), ('\u{2CAA}', '\u{2CAB}'), ('\u{2CAC}', '\u{2CAD}'), ('\u{2CAE}', '\u{2CAF}'),
+  ('\u{2CB0}', '\u{2CB1}'), ('\u{2CB2}', '\u{2CB3}'), ('\u{2CB4}', '\u{2CB5}'), ('\u{2CB6}', '\u{2CB7}'), ('\u{2CB8}', '\u{2CB9}'), ('\u{2CBA}', '\u{2CBB}'),
+  ('\u{2CBC}', '\u{2CBD}'), ('\u{2CBE}', '\u{2CBF}'), ('\u{2CC0}', '\u{2CC1}'), ('\u{2CC2}', '\u{2CC3}'), ('\u{2CC4}', '\u{2CC5}'), ('\u{2CC6}', '\u{2CC7}'),
+  ('\u{2CC8}', '\u{2CC9}'), ('\u{2CCA}', '\u{2CCB}'), ('\u{2CCC}', '\u{2CCD}'), ('\u{2CCE}', '\u{2CCF}'), ('\u{2CD0}', '\u{2CD1}'), ('\u{2CD2}', '\u{2CD3}'),
+  ('\u{2CD4}', '\u{2CD5}'), ('\u{2CD6}', '\u{2CD7}'), ('\u{2CD8}', '\u{2CD9}'), ('\u{2CDA}', '\u{2CDB}'), ('\u{2CDC}', '\u{2CDD}'), ('\u{2CDE}', '\u{2CDF}'),
+  ('\u{2CE0}', '\u{2CE1}'), ('\u{2CE2}', '\u{2CE3}'), ('\u{2CEB}', '\u{2CEC}'), ('\u{2CED}', '\u{2CEE}'), ('\u{2CF2}', '\u{2CF3}'), ('\u{A640}', '\u{A641}'),
+  ('\u{A642}', '\u{A643}'), ('\u{A644}', '\u{A645}'), ('\u{A646}', '\u{A647}'), ('\u{A648}', '\u{A649}'), ('\u{A64A}', '\u{A64B}'), ('\u{A64C}', '\u{A64D}'),
+  ('\u{A64E}', '\u{A64F}'), ('\u{A650}', '\u{A651}'), ('\u{A652}', '\u{A653}'), ('\u{A654}', '\u{A655}'), ('\u{A656}', '\u{A657}'), ('\u{A658}', '\u{A659}'),
+  ('\u{A65A}', '\u{A65B}'), ('\u{A65C}', '\u{A65D}'), ('\u{A65E}', '\u{A65F}'), ('\u{A660}', '\u{A661}'), ('\u{A662}', '\u{A663}'), ('\u{A664}', '\u{A665}'),
+  ('\u{A666}', '\u{A667}'), ('\u{A668}', '\u{A669}'), ('\u{A66A}', '\u{A66B}'), ('\u{A66C}', '\u{A66D}'), ('\u{A680}', '\u{A681}'), ('\u{A682}', '\u{A683}'),
+  ('\u{A684}', '\u{A685}'), ('\u{A686}', '\u{A687}'), ('\u{A688}', '\u{A689}'), ('\u{A68A}', '\u{A68B}'), ('\u{A68C}', '\u{A68D}'), ('\u{A68E}', '\u{A68F}'),
+  ('\u{A690}', '\u{A691}'), ('\u{A692}', '\u{A693}'), ('\u{A694}', '\u{A695}'), ('\u{A696}', '\u{A697}'), ('\u{A698}', '\u{A699}'), ('\u{A69A}', '\u{A69B}'),
+  ('\u{A722}', '\u{A723}'), ('\u{A724}', '\u{A725}'), ('\u{A726}', '\u{A727}'), ('\u{A728}', '\u{A729}'), ('\u{A72A}', '\u{A72B}'), ('\u{A72C}', '\u{A72D}'),
+  ('\u{A72E}', '\u{A72F}'), ('\u{A732}', '\u{A733}'), ('\u{A734}', '\u{A735}'), ('\u{A736}', '\u{A737}'), ('\u{A738}', '\u{A739}'), ('\u{A73A}', '\u{A73B}'),
+  ('\u{A73C}', '\u{A73D}'), ('\u{A73E}', '\u{A73F}'), ('\u{A740}', '\u{A741}'), ('\u{A742}', '\u{A743}'), ('\u{A744}', '\u{A745}'), ('\u{A746}', '\u{A747}'),
+  ('\u{A748}', '\u{A749}'), ('\u{A74A}', '\u{A74B}'), ('\u{A74C}', '\u{A74D}'), ('\u{A74E}', '\u{A74F}'), ('\u{A750}', '\u{A751}'), ('\u{A752}', '\u{A753}'),
+  ('\u{A754}', '\u{A755}'), ('\u{A756}', '\u{A757}'), ('\u{A758}', '\u{A759}'), ('\u{A75A}', '\u{A75B}'), ('\u{A75C}', '\u{A75D}'), ('\u{A75E}', '\u{A75F}'),
+  ('\u{A760}', '\u{A761}'), ('\u{A762}', '\u{A763}'), ('\u{A764}', '\u{A765}'), ('\u{A766}', '\u{A767}'), ('\u{A768}', '\u{A769}'), ('\u{A76A}', '\u{A76B}'),
+  ('\u{A76C}', '\u{A76D}'), ('\u{A76E}', '\u{A76F}'), ('\u{A779}', '\u{A77A}'), ('\u{A77B}', '\u{A77C}'), ('\u{A77D}', '\u{1D79}'), ('\u{A77E}', '\u{A77F}'),
+  ('\u{A780}', '\u{A781}'), ('\u{A782}', '\u{A783}'), ('\u{A784}', '\u{A785}'), ('\u{A786}', '\u{A787}'), ('\u{A78B}', '\u{A78C}'), ('\u{A78D}', '\u{265}'),
+  ('\u{A790}', '\u{A791}'), ('\u{A792}', '\u{A793}'), ('\u{A796}', '\u{A797}'), ('\u{A798}', '\u{A799}'), ('\u{A79A}', '\u{A79B}'), ('\u{A79C}', '\u{A79D}'),
+  ('\u{A79E}', '\u{A79F}'), ('\u{A7A0}', '\u{A7A1}'), ('\u{A7A2}', '\u{A7A3}'), ('\u{A7A4}', '\u{A7A5}'), ('\u{A7A6}', '\u{A7A7}'), ('\u{A7A8}', '\u{A7A9}'),
+  ('\u{A7AA}', '\u{266}'), ('\u{A7AB}', '\u{25C}'), ('\u{A7AC}', '\u{261}'), ('\u{A7AD}', '\u{26C}'), ('\u{A7AE}', '\u{26A}'), ('\u{A7B0}', '\u{29E}'),
+  ('\u{A7B1}', '\u{287}'), ('\u{A7B2}', '\u{29D}'), ('\u{A7B3}', '\u{AB53}'), ('\u{A7B4}', '\u{A7B5}'), ('\u{A7B6}', '\u{A7B7}'), ('\u{A7B8}', '\u{A7B9}'),
+  ('\u{A7BA}', '\u{A7BB}'), ('\u{A7BC}', '\u{A7BD}'), ('\u{A7BE}', '\u{A7BF}'), ('\u{A7C0}', '\u{A7C1}'), ('\u{A7C2}', '\u{A7C3}'), ('\u{A7C4}', '\u{A794}'),
+  ('\u{A7C5}', '\u{282}'), ('\u{A7C6}', '\u{1D8E}'), ('\u{A7C7}', '\u{A7C8}'), ('\u{A7C9}', '\u{A7CA}'), ('\u{A7D0}', '\u{A7D1}'), ('\u{A7D6}', '\u{A7D7}'),
+  ('\u{A7D8}', '\u{A7D9}'), ('\u{A7F5}', '\u{A7F6}'), ('\u{AB70}', '\u{13A0}'), ('\u{AB71}', '\u{13A1}'), ('\u{AB72}', '\u{13A2}'), ('\u{AB73}', '\u{13A3}'),
+  ('\u{AB74}', '\u{13A4}'), ('\u{AB75}', '\u{13A5}'), ('\u{AB76}', '\u{13A6}'), ('\u{AB77}', '\u{13A7}'), ('\u{AB78}', '\u{13A8}'), ('\u{AB79}', '\u{13A9}'),
+  ('\u{AB7A}', '\u{13AA}'), ('\u{AB7B}', '\u{13AB}'), ('\u{AB7C}', '\u{13AC}'), ('\u{AB7D}', '\u{13AD}'), ('\u{AB7E}', '\u{13AE}'), ('\u{AB7F}', '\u{13AF}'),
+  ('\u{AB80}', '\u{13B0}'), ('\u{AB81}', '\u{13B1}'), ('\u{AB82}', '\u{13B2}'), ('\u{AB83}', '\u{13B3}'), ('\u{AB84}', '\u{13B4}'), ('\u{AB85}', '\u{13B5}'),
+  ('\u{AB86}', '\u{13B6}'), ('\u{AB87}', '\u{13B7}'), ('\u{AB88}', '\u{13B8}'), ('\u{AB89}', '\u{13B9}'), ('\u{AB8A}', '\u{13BA}'), ('\u{AB8B}', '\u{13BB}'),
+  ('\u{AB8C}', '\u{13BC}'), ('\u{AB8D}', '\u{13BD}'), ('\u{AB8E}', '\u{13BE}'), ('\u{AB8F}', '\u{13BF}'), ('\u{AB90}', '\u{13C0}'), ('\u{AB91}', '\u{13C1}'),
+  ('\u{AB92}', '\u{13C2}'), ('\u{AB93}', '\u{13C3}'), ('\u{AB94}', '\u{13C4}'), ('\u{AB95}', '\u{13C5}'), ('\u{AB96}', '\u{13C6}'), ('\u{AB97}', '\u{13C7}'),
+  ('\u{AB98}', '\u{13C8}'), ('\u{AB99}', '\u{13C9}'), ('\u{AB9A}', '\u{13CA}'), ('\u{AB9B}', '\u{13CB}'), ('\u{AB9C}', '\u{13CC}'), ('\u{AB9D}', '\u{13CD}'),
+  ('\u{AB9E}', '\u{13CE}'), ('\u{AB9F}', '\u{13CF}'), ('\u{ABA0}', '\u{13D0}'), ('\u{ABA1}', '\u{13D1}'), ('\u{ABA2}', '\u{13D2}'), ('\u{ABA3}', '\u{13D3}'),
+  ('\u{ABA4}', '\u{13D4}'), ('\u{ABA5}', '\u{13D5}'), ('\u{ABA6}', '\u{13D6}'), ('\u{ABA7}', '\u{13D7}'), ('\u{ABA8}', '\u{13D8}'), ('\u{ABA9}', '\u{13D9}'),
+  ('\u{ABAA}', '\u{13DA}'), ('\u{ABAB}', '\u{13DB}'), ('\u{ABAC}', '\u{13DC}'), ('\u{ABAD}', '\u{13DD}'), ('\u{ABAE}', '\u{13DE}'), ('\u{ABAF}', '\u{13DF}'),
+  ('\u{ABB0}', '\u{13E0}'), ('\u{ABB1}', '\u{13E1}'), ('\u{ABB2}', '\u{13E2}'), ('\u{ABB3}', '\u{13E3}'), ('\u{ABB4}', '\u{13E4}'), ('\u{ABB5}', '\u{13E5}'),
+  ('\u{ABB6}', '\u{13E6}'), ('\u{ABB7}', '\u{13E7}'), ('\u{ABB8}', '\u{13E8}'), ('\u{ABB9}', '\u{13E9}'), ('\u{ABBA}', '\u{13EA}'), ('\u{ABBB}', '\u{13EB}'),
+  ('\u{ABBC}', '\u{13EC}'), ('\u{ABBD}', '\u{13ED}'), ('\u{ABBE}', '\u{13EE}'), ('\u{ABBF}', '\u{13EF}'), ('\u{FF21}', '\u{FF41}'), ('\u{FF22}', '\u{FF42}'),
+  ('\u{FF23}', '\u{FF43}'), ('\u{FF24}', '\u{FF44}'), ('\u{FF25}', '\u{FF45}'), ('\u{FF26}', '\u{FF46}'), ('\u{FF27}', '\u{FF47}'), ('\u{FF28}', '\u{FF48}'),
+  ('\u{FF29}', '\u{FF49}'), ('\u{FF2A}', '\u{FF4A}'), ('\u{FF2B}', '\u{FF4B}'), ('\u{FF2C}', '\u{FF4C}'), ('\u{FF2D}', '\u{FF4D}'), ('\u{FF2E}', '\u{FF4E}'),
+  ('\u{FF2F}', '\u{FF4F}'), ('\u{FF30}', '\u{FF50}'), ('\u{FF31}', '\u{FF51}'), ('\u{FF32}', '\u{FF52}'), ('\u{FF33}', '\u{FF53}'), ('\u{FF34}', '\u{FF54}'),
+  ('\u{FF35}', '\u{FF55}'), ('\u{FF36}', '\u{FF56}'), ('\u{FF37}', '\u{FF57}'), ('\u{FF38}', '\u{FF58}'), ('\u{FF39}', '\u{FF59}'), ('\u{FF3A}', '\u{FF5A}'),
+  ('\u{10400}', '\u{10428}'), ('\u{10401}', '\u{10429}'), ('\u{10402}', '\u{1042A}'), ('\u{10403}', '\u{1042B}'), ('\u{10404}', '\u{1042C}'), ('\u{10405}', '\u{1042D}'),
+  ('\u{10406}', '\u{1042E}'), ('\u{10407}', '\u{1042F}'), ('\u{10408}', '\u{10430}'), ('\u{10409}', '\u{10431}'), ('\u{1040A}', '\u{10432}'), ('\u{1040B}', '\u{10433}'),
+  ('\u{1040C}', '\u{10434}'), ('\u{1040D}', '\u{10435}'), ('\u{1040E}', '\u{10436}'), ('\u{1040F}', '\u{10437}'), ('\u{10410}', '\u{10438}'), ('\u{10411}', '\u{10439}'),
+  ('\u{10412}', '\u{1043A}'), ('\u{10413}', '\u{1043B}'), ('\u{10414}', '\u{1043C}'), ('\u{10415}', '\u{1043D}'), ('\u{10416}', '\u{1043E}'), ('\u{10417}', '\u{1043F}'),
+  ('\u{10418}', '\u{10440}'), ('\u{10419}', '\u{10441}'), ('\u{1041A}', '\u{10442}'), ('\u{1041B}', '\u{10443}'), ('\u{1041C}', '\u{10444}'), ('\u{1041D}', '\u{10445}'),
+  ('\u{1041E}', '\u{10446}'), ('\u{1041F}', '\u{10447}'), ('\u{10420}', '\u{10448}'), ('\u{10421}', '\u{10449}'), ('\u{10422}', '\u{1044A}'), ('\u{10423}', '\u{1044B}'),
+  ('\u{10424}', '\u{1044C}'), ('\u{10425}', '\u{1044D}'), ('\u{10426}', '\u{1044E}'), ('\u{10427}', '\u{1044F}'), ('\u{104B0}', '\u{104D8}'), ('\u{104B1}', '\u{104D9}'),
+  ('\u{104B2}', '\u{104DA}'), ('\u{104B3}', '\u{104DB}'), ('\u{104B4}', '\u{104DC}'), ('\u{104B5}', '\u{104DD}'), ('\u{104B6}', '\u{104DE}'), ('\u{104B7}', '\u{104DF}'),
+  ('\u{104B8}', '\u{104E0}'), ('\u{104B9}', '\u{104E1}'), ('\u{104BA}', '\u{104E2}'), ('\u{104BB}', '\u{104E3}'), ('\u{104BC}', '\u{104E4}'), ('\u{104BD}', '\u{104E5}'),
+  ('\u{104BE}', '\u{104E6}'), ('\u{104BF}', '\u{104E7}'), ('\u{104C0}', '\u{104E8}'), ('\u{104C1}', '\u{104E9}'), ('\u{104C2}', '\u{104EA}'), ('\u{104C3}', '\u{104EB}'),
+  ('\u{104C4}', '\u{104EC}'), ('\u{104C5}', '\u{104ED}'), ('\u{104C6}', '\u{104EE}'), ('\u{104C7}', '\u{104EF}'), ('\u{104C8}', '\u{104F0}'), ('\u{104C9}', '\u{104F1}'),
+  ('\u{104CA}', '\u{104F2}'), ('\u{104CB}', '\u{104F3}'), ('\u{104CC}', '\u{104F4}'), ('\u{104CD}', '\u{104F5}'), ('\u{104CE}', '\u{104F6}'), ('\u{104CF}', '\u{104F7}'),
+  ('\u{104D0}', '\u{104F8}'), ('\u{104D1}', '\u{104F9}'), ('\u{104D2}', '\u{104FA}'), ('\u{104D3}', '\u{104FB}'), ('\u{10570}', '\u{10597}'), ('\u{10571}', '\u{10598}'),
+  ('\u{10572}', '\u{10599}'), ('\u{10573}', '\u{1059A}'), ('\u{10574}', '\u{1059B}'), ('\u{10575}', '\u{1059C}'), ('\u{10576}', '\u{1059D}'), ('\u{10577}', '\u{1059E}'),
+  ('\u{10578}', '\u{1059F}'), ('\u{10579}', '\u{105A0}'), ('\u{1057A}', '\u{105A1}'), ('\u{1057C}', '\u{105A3}'), ('\u{1057D}', '\u{105A4}'), ('\u{1057E}', '\u{105A5}'),
+  ('\u{1057F}', '\u{105A6}'), ('\u{10580}', '\u{105A7}'), ('\u{10581}', '\u{105A8}'), ('\u{10582}', '\u{105A9}'), ('\u{10583}', '\u{105AA}'), ('\u{10584}', '\u{105AB}'),
+  ('\u{10585}', '\u{105AC}'), ('\u{10586}', '\u{105AD}'), ('\u{10587}', '\u{105AE}'), ('\u{10588}', '\u{105AF}'), ('\u{10589}', '\u{105B0}'), ('\u{1058A}', '\u{105B1}'),
+  ('\u{1058C}', '\u{105B3}'), ('\u{1058D}', '\u{105B4}'), ('\u{1058E}', '\u{105B5}'), ('\u{1058F}', '\u{105B6}'), ('\u{10590}', '\u{105B7}'), ('\u{10591}', '\u{105B8}'),
+  ('\u{10592}', '\u{105B9}'), ('\u{10594}', '\u{105BB}'), ('\u{10595}', '\u{105BC}'), ('\u{10C80}', '\u{10CC0}'), ('\u{10C81}', '\u{10CC1}'), ('\u{10C82}', '\u{10CC2}'),
+  ('\u{10C83}', '\u{10CC3}'), ('\u{10C84}', '\u{10CC4}'), ('\u{10C85}', '\u{10CC5}'), ('\u{10C86}', '\u{10CC6}'), ('\u{10C87}', '\u{10CC7}'), ('\u{10C88}', '\u{10CC8}'),
+  ('\u{10C89}', '\u{10CC9}'), ('\u{10C8A}', '\u{10CCA}'), ('\u{10C8B}', '\u{10CCB}'), ('\u{10C8C}', '\u{10CCC}'), ('\u{10C8D}', '\u{10CCD}'), ('\u{10C8E}', '\u{10CCE}'),
+  ('\u{10C8F}', '\u{10CCF}'), ('\u{10C90}', '\u{10CD0}'), ('\u{10C91}', '\u{10CD1}'), ('\u{10C92}', '\u{10CD2}'), ('\u{10C93}', '\u{10CD3}'), ('\u{10C94}', '\u{10CD4}'),
+  ('\u{10C95}', '\u{10CD5}'), ('\u{10C96}', '\u{10CD6}'), ('\u{10C97}', '\u{10CD7}'), ('\u{10C98}', '\u{10CD8}'), ('\u{10C99}', '\u{10CD9}'), ('\u{10C9A}', '\u{10CDA}'),
+  ('\u{10C9B}', '\u{10CDB}'), ('\u{10C9C}', '\u{10CDC}'), ('\u{10C9D}', '\u{10CDD}'), ('\u{10C9E}', '\u{10CDE}'), ('\u{10C9F}', '\u{10CDF}'), ('\u{10CA0}', '\u{10CE0}'),
+  ('\u{10CA1}', '\u{10CE1}'), ('\u{10CA2}', '\u{10CE2}'), ('\u{10CA3}', '\u{10CE3}'), ('\u{10CA4}', '\u{10CE4}'), ('\u{10CA5}', '\u{10CE5}'), ('\u{10CA6}', '\u{10CE6}'),
+  ('\u{10CA7}', '\u{10CE7}'), ('\u{10CA8}', '\u{10CE8}'), ('\u{10CA9}', '\u{10CE9}'), ('\u{10CAA}', '\u{10CEA}'), ('\u{10CAB}', '\u{10CEB}'), ('\u{10CAC}', '\u{10CEC}'),
+  ('\u{10CAD}', '\u{10CED}'), ('\u{10CAE}', '\u{10CEE}'), ('\u{10CAF}', '\u{10CEF}'), ('\u{10CB0}', '\u{10CF0}'), ('\u{10CB1}', '\u{10CF1}'), ('\u{10CB2}', '\u{10CF2}'),
+  ('\u{118A0}', '\u{118C0}'), ('\u{118A1}', '\u{118C1}'), ('\u{118A2}', '\u{118C2}'), ('\u{118A3}', '\u{118C3}'), ('\u{118A4}', '\u{118C4}'), ('\u{118A5}', '\u{118C5}'),
+  ('\u{118A6}', '\u{118C6}'), ('\u{118A7}', '\u{118C7}'), ('\u{118A8}', '\u{118C8}'), ('\u{118A9}', '\u{118C9}'), ('\u{118AA}', '\u{118CA}'), ('\u{118AB}', '\u{118CB}'),
+  ('\u{118AC}', '\u{118CC}'), ('\u{118AD}', '\u{118CD}'), ('\u{118AE}', '\u{118CE}'), ('\u{118AF}', '\u{118CF}'), ('\u{118B0}', '\u{118D0}'), ('\u{118B1}', '\u{118D1}'),
+  ('\u{118B2}', '\u{118D2}'), ('\u{118B3}', '\u{118D3}'), ('\u{118B4}', '\u{118D4}'), ('\u{118B5}', '\u{118D5}'), ('\u{118B6}', '\u{118D6}'), ('\u{118B7}', '\u{118D7}'),
+  ('\u{118B8}', '\u{118D8}'), ('\u{118B9}', '\u{118D9}'), ('\u{118BA}', '\u{118DA}'), ('\u{118BB}', '\u{118DB}'), ('\u{118BC}', '\u{118DC}'), ('\u{118BD}', '\u{118DD}'),
+  ('\u{118BE}', '\u{118DE}'), ('\u{118BF}', '\u{118DF}'), ('\u{16E40}', '\u{16E60}'), ('\u{16E41}', '\u{16E61}'), ('\u{16E42}', '\u{16E62}'), ('\u{16E43}', '\u{16E63}'),
+  ('\u{16E44}', '\u{16E64}'), ('\u{16E45}', '\u{16E65}'), ('\u{16E46}', '\u{16E66}'), ('\u{16E47}', '\u{16E67}'), ('\u{16E48}', '\u{16E68}'), ('\u{16E49}', '\u{16E69}'),
+  ('\u{16E4A}', '\u{16E6A}'), ('\u{16E4B}', '\u{16E6B}'), ('\u{16E4C}', '\u{16E6C}'), ('\u{16E4D}', '\u{16E6D}'), ('\u{16E4E}', '\u{16E6E}'), ('\u{16E4F}', '\u{16E6F}'),
+  ('\u{16E50}', '\u{16E70}'), ('\u{16E51}', '\u{16E71}'), ('\u{16E52}', '\u{16E72}'), ('\u{16E53}', '\u{16E73}'), ('\u{16E54}', '\u{16E74}'), ('\u{16E55}', '\u{16E75}'),
+  ('\u{16E56}', '\u{16E76}'), ('\u{16E57}', '\u{16E77}'), ('\u{16E58}', '\u{16E78}'), ('\u{16E59}', '\u{16E79}'), ('\u{16E5A}', '\u{16E7A}'), ('\u{16E5B}', '\u{16E7B}'),
+  ('\u{16E5C}', '\u{16E7C}'), ('\u{16E5D}', '\u{16E7D}'), ('\u{16E5E}', '\u{16E7E}'), ('\u{16E5F}', '\u{16E7F}'), ('\u{1E900}', '\u{1E922}'), ('\u{1E901}', '\u{1E923}'),
+  ('\u{1E902}', '\u{1E924}'), ('\u{1E903}', '\u{1E925}'), ('\u{1E904}', '\u{1E926}'), ('\u{1E905}', '\u{1E927}'), ('\u{1E906}', '\u{1E928}'), ('\u{1E907}', '\u{1E929}'),
+  ('\u{1E908}', '\u{1E92A}'), ('\u{1E909}', '\u{1E92B}'), ('\u{1E90A}', '\u{1E92C}'), ('\u{1E90B}', '\u{1E92D}'), ('\u{1E90C}', '\u{1E92E}'), ('\u{1E90D}', '\u{1E92F}'),
+  ('\u{1E90E}', '\u{1E930}'), ('\u{1E90F}', '\u{1E931}'), ('\u{1E910}', '\u{1E932}'), ('\u{1E911}', '\u{1E933}'), ('\u{1E912}', '\u{1E934}'), ('\u{1E913}', '\u{1E935}'),
+  ('\u{1E914}', '\u{1E936}'), ('\u{1E915}', '\u{1E937}'), ('\u{1E916}', '\u{1E938}'), ('\u{1E917}', '\u{1E939}'), ('\u{1E918}', '\u{1E93A}'), ('\u{1E919}', '\u{1E93B}'),
+  ('\u{1E91A}', '\u{1E93C}'), ('\u{1E91B}', '\u{1E93D}'), ('\u{1E91C}', '\u{1E93E}'), ('\u{1E91D}', '\u{1E93F}'), ('\u{1E91E}', '\u{1E940}'), ('\u{1E91F}', '\u{1E941}'),
+  ('\u{1E920}', '\u{1E942}'), ('\u{1E921}', '\u{1E943}'),
+];
+
+/// 完整展开的规范分解
+pub(crate) const DECOMPOSITION: &[(char, &str)] = &[
+  ('\u{C0}', "\u{41}\u{300}"), ('\u{C1}', "\u{41}\u{301}"), ('\u{C2}', "\u{41}\u{302}"), ('\u{C3}', "\u{41}\u{303}"), ('\u{C4}', "\u{41}\u{308}"), ('\u{C5}', "\u{41}\u{30A}"),
+  ('\u{C7}', "\u{43}\u{327}"), ('\u{C8}', "\u{45}\u{300}"), ('\u{C9}', "\u{45}\u{301}"), ('\u{CA}', "\u{45}\u{302}"), ('\u{CB}', "\u{45}\u{308}"), ('\u{CC}', "\u{49}\u{300}"),
+  ('\u{CD}', "\u{49}\u{301}"), ('\u{CE}', "\u{49}\u{302}"), ('\u{CF}', "\u{49}\u{308}"), ('\u{D1}', "\u{4E}\u{303}"), ('\u{D2}', "\u{4F}\u{300}"), ('\u{D3}', "\u{4F}\u{301}"),
+  ('\u{D4}', "\u{4F}\u{302}"), ('\u{D5}', "\u{4F}\u{303}"), ('\u{D6}', "\u{4F}\u{308}"), ('\u{D9}', "\u{55}\u{300}"), ('\u{DA}', "\u{55}\u{301}"), ('\u{DB}', "\u{55}\u{302}"),
+  ('\u{DC}', "\u{55}\u{308}"), ('\u{DD}', "\u{59}\u{301}"), ('\u{E0}', "\u{61}\u{300}"), ('\u{E1}', "\u{61}\u{301}"), ('\u{E2}', "\u{61}\u{302}"), ('\u{E3}', "\u{61}\u{303}"),
+  ('\u{E4}', "\u{61}\u{308}"), ('\u{E5}', "\u{61}\u{30A}"), ('\u{E7}', "\u{63}\u{327}"), ('\u{E8}', "\u{65}\u{300}"), ('\u{E9}', "\u{65}\u{301}"), ('\u{EA}', "\u{65}\u{302}"),
+  ('\u{EB}', "\u{65}\u{308}"), ('\u{EC}', "\u{69}\u{300}"), ('\u{ED}', "\u{69}\u{301}"), ('\u{EE}', "\u{69}\u{302}"), ('\u{EF}', "\u{69}\u{308}"), ('\u{F1}', "\u{6E}\u{303}"),
+  ('\u{F2}', "\u{6F}\u{300}"), ('\u{F3}', "\u{6F}\u{301}"), ('\u{F4}', "\u{6F}\u{302}"), ('\u{F5}', "\u{6F}\u{303}"), ('\u{F6}', "\u{6F}\u{308}"), ('\u{F9}', "\u{75}\u{300}"),
+  ('\u{FA}', "\u{75}\u{301}"), ('\u{FB}', "\u{75}\u{302}"), ('\u{FC}', "\u{75}\u{308}"), ('\u{FD}', "\u{79}\u{301}"), ('\u{FF}', "\u{79}\u{308}"), ('\u{100}', "\u{41}\u{304}"),
+  ('\u{101}', "\u{61}\u{304}"), ('\u{102}', "\u{41}\u{306}"), ('\u{103}', "\u{61}\u{306}"), ('\u{104}', "\u{41}\u{328}"), ('\u{105}', "\u{61}\u{328}"), ('\u{106}', "\u{43}\u{301}"),
+  ('\u{107}', "\u{63}\u{301}"), ('\u{108}', "\u{43}\u{302}"), ('\u{109}', "\u{63}\u{302}"), ('\u{10A}', "\u{43}\u{307}"), ('\u{10B}', "\u{63}\u{307}"), ('\u{10C}', "\u{43}\u{30C}"),
+  ('\u{10D}', "\u{63}\u{30C}"), ('\u{10E}', "\u{44}\u{30C}"), ('\u{10F}', "\u{64}\u{30C}"), ('\u{112}', "\u{45}\u{304}"), ('\u{113}', "\u{65}\u{304}"), ('\u{114}', "\u{45}\u{306}"),
+  ('\u{115}', "\u{65}\u{306}"), ('\u{116}', "\u{45}\u{307}"), ('\u{117}', "\u{65}\u{307}"), ('\u{118}', "\u{45}\u{328}"), ('\u{119}', "\u{65}\u{328}"), ('\u{11A}', "\u{45}\u{30C}"),
+  ('\u{11B}', "\u{65}\u{30C}"), ('\u{11C}', "\u{47}\u{302}"), ('\u{11D}', "\u{67}\u{302}"), ('\u{11E}', "\u{47}\u{306}"), ('\u{11F}', "\u{67}\u{306}"), ('\u{120}', "\u{47}\u{307}"),
+  ('\u{121}', "\u{67}\u{307}"), ('\u{122}', "\u{47}\u{327}"), ('\u{123}', "\u{67}\u{327}"), ('\u{124}', "\u{48}\u{302}"), ('\u{125}', "\u{68}\u{302}"), ('\u{128}', "\u{49}\u{303}"),
+  ('\u{129}', "\u{69}\u{303}"), ('\u{12A}', "\u{49}\u{304}"), ('\u{12B}', "\u{69}\u{304}"), ('\u{12C}', "\u{49}\u{306}"), ('\u{12D}', "\u{69}\u{306}"), ('\u{12E}', "\u{49}\u{328}"),
+  ('\u{12F}', "\u{69}\u{328}"), ('\u{130}', "\u{49}\u{307}"), ('\u{134}', "\u{4A}\u{302}"), ('\u{135}', "\u{6A}\u{302}"), ('\u{136}', "\u{4B}\u{327}"), ('\u{137}', "\u{6B}\u{327}"),
+  ('\u{139}', "\u{4C}\u{301}"), ('\u{13A}', "\u{6C}\u{301}"), ('\u{13B}', "\u{4C}\u{327}"), ('\u{13C}', "\u{6C}\u{327}"), ('\u{13D}', "\u{4C}\u{30C}"), ('\u{13E}', "\u{6C}\u{30C}"),
+  ('\u{143}', "\u{4E}\u{301}"), ('\u{144}', "\u{6E}\u{301}"), ('\u{145}', "\u{4E}\u{327}"), ('\u{146}', "\u{6E}\u{327}"), ('\u{147}', "\u{4E}\u{30C}"), ('\u{148}', "\u{6E}\u{30C}"),
+  ('\u{14C}', "\u{4F}\u{304}"), ('\u{14D}', "\u{6F}\u{304}"), ('\u{14E}', "\u{4F}\u{306}"), ('\u{14F}', "\u{6F}\u{306}"), ('\u{150}', "\u{4F}\u{30B}"), ('\u{151}', "\u{6F}\u{30B}"),
+  ('\u{154}', "\u{52}\u{301}"), ('\u{155}', "\u{72}\u{301}"), ('\u{156}', "\u{52}\u{327}"), ('\u{157}', "\u{72}\u{327}"), ('\u{158}', "\u{52}\u{30C}"), ('\u{159}', "\u{72}\u{30C}"),
+  ('\u{15A}', "\u{53}\u{301}"), ('\u{15B}', "\u{73}\u{301}"), ('\u{15C}', "\u{53}\u{302}"), ('\u{15D}', "\u{73}\u{302}"), ('\u{15E}', "\u{53}\u{327}"), ('\u{15F}', "\u{73}\u{327}"),
+  ('\u{160}', "\u{53}\u{30C}"), ('\u{161}', "\u{73}\u{30C}"), ('\u{162}', "\u{54}\u{327}"), ('\u{163}', "\u{74}\u{327}"), ('\u{164}', "\u{54}\u{30C}"), ('\u{165}', "\u{74}\u{30C}"),
+  ('\u{168}', "\u{55}\u{303}"), ('\u{169}', "\u{75}\u{303}"), ('\u{16A}', "\u{55}\u{304}"), ('\u{16B}', "\u{75}\u{304}"), ('\u{16C}', "\u{55}\u{306}"), ('\u{16D}', "\u{75}\u{306}"),
+  ('\u{16E}', "\u{55}\u{30A}"), ('\u{16F}', "\u{75}\u{30A}"), ('\u{170}', "\u{55}\u{30B}"), ('\u{171}', "\u{75}\u{30B}"), ('\u{172}', "\u{55}\u{328}"), ('\u{173}', "\u{75}\u{328}"),
+  ('\u{174}', "\u{57}\u{302}"), ('\u{175}', "\u{77}\u{302}"), ('\u{176}', "\u{59}\u{302}"), ('\u{177}', "\u{79}\u{302}"), ('\u{178}', "\u{59}\u{308}"), ('\u{179}', "\u{5A}\u{301}"),
+  ('\u{17A}', "\u{7A}\u{301}"), ('\u{17B}', "\u{5A}\u{307}"), ('\u{17C}', "\u{7A}\u{307}"), ('\u{17D}', "\u{5A}\u{30C}"), ('\u{17E}', "\u{7A}\u{30C}"), ('\u{1A0}', "\u{4F}\u{31B}"),
+  ('\u{1A1}', "\u{6F}\u{31B}"), ('\u{1AF}', "\u{55}\u{31B}"), ('\u{1B0}', "\u{75}\u{31B}"), ('\u{1CD}', "\u{41}\u{30C}"), ('\u{1CE}', "\u{61}\u{30C}"), ('\u{1CF}', "\u{49}\u{30C}"),
+  ('\u{1D0}', "\u{69}\u{30C}"), ('\u{1D1}', "\u{4F}\u{30C}"), ('\u{1D2}', "\u{6F}\u{30C}"), ('\u{1D3}', "\u{55}\u{30C}"), ('\u{1D4}', "\u{75}\u{30C}"), ('\u{1D5}', "\u{55}\u{308}\u{304}"),
+  ('\u{1D6}', "\u{75}\u{308}\u{304}"), ('\u{1D7}', "\u{55}\u{308}\u{301}"), ('\u{1D8}', "\u{75}\u{308}\u{301}"), ('\u{1D9}', "\u{55}\u{308}\u{30C}"), ('\u{1DA}', "\u{75}\u{308}\u{30C}"), ('\u{1DB}', "\u{55}\u{308}\u{300}"),
+  ('\u{1DC}', "\u{75}\u{308}\u{300}"), ('\u{1DE}', "\u{41}\u{308}\u{304}"), ('\u{1DF}', "\u{61}\u{308}\u{304}"), ('\u{1E0}', "\u{41}\u{307}\u{304}"), ('\u{1E1}', "\u{61}\u{307}\u{304}"), ('\u{1E2}', "\u{C6}\u{304}"),
+  ('\u{1E3}', "\u{E6}\u{304}"), ('\u{1E6}', "\u{47}\u{30C}"), ('\u{1E7}', "\u{67}\u{30C}"), ('\u{1E8}', "\u{4B}\u{30C}"), ('\u{1E9}', "\u{6B}\u{30C}"), ('\u{1EA}', "\u{4F}\u{328}"),
+  ('\u{1EB}', "\u{6F}\u{328}"), ('\u{1EC}', "\u{4F}\u{328}\u{304}"), ('\u{1ED}', "\u{6F}\u{328}\u{304}"), ('\u{1EE}', "\u{1B7}\u{30C}"), ('\u{1EF}', "\u{292}\u{30C}"), ('\u{1F0}', "\u{6A}\u{30C}"),
+  ('\u{1F4}', "\u{47}\u{301}"), ('\u{1F5}', "\u{67}\u{301}"), ('\u{1F8}', "\u{4E}\u{300}"), ('\u{1F9}', "\u{6E}\u{300}"), ('\u{1FA}', "\u{41}\u{30A}\u{301}"), ('\u{1FB}', "\u{61}\u{30A}\u{301}"),
+  ('\u{1FC}', "\u{C6}\u{301}"), ('\u{1FD}', "\u{E6}\u{301}"), ('\u{1FE}', "\u{D8}\u{301}"), ('\u{1FF}', "\u{F8}\u{301}"), ('\u{200}', "\u{41}\u{30F}"), ('\u{201}', "\u{61}\u{30F}"),
+  ('\u{202}', "\u{41}\u{311}"), ('\u{203}', "\u{61}\u{311}"), ('\u{204}', "\u{45}\u{30F}"), ('\u{205}', "\u{65}\u{30F}"), ('\u{206}', "\u{45}\u{311}"), ('\u{207}', "\u{65}\u{311}"),
+  ('\u{208}', "\u{49}\u{30F}"), ('\u{209}', "\u{69}\u{30F}"), ('\u{20A}', "\u{49}\u{311}"), ('\u{20B}', "\u{69}\u{311}"), ('\u{20C}', "\u{4F}\u{30F}"), ('\u{20D}', "\u{6F}\u{30F}"),
+  ('\u{20E}', "\u{4F}\u{311}"), ('\u{20F}', "\u{6F}\u{311}"), ('\u{210}', "\u{52}\u{30F}"), ('\u{211}', "\u{72}\u{30F}"), ('\u{212}', "\u{52}\u{311}"), ('\u{213}', "\u{72}\u{311}"),
+  ('\u{214}', "\u{55}\u{30F}"), ('\u{215}', "\u{75}\u{30F}"), ('\u{216}', "\u{55}\u{311}"), ('\u{217}', "\u{75}\u{311}"), ('\u{218}', "\u{53}\u{326}"), ('\u{219}', "\u{73}\u{326}"),
+  ('\u{21A}', "\u{54}\u{326}"), ('\u{21B}', "\u{74}\u{326}"), ('\u{21E}', "\u{48}\u{30C}"), ('\u{21F}', "\u{68}\u{30C}"), ('\u{226}', "\u{41}\u{307}"), ('\u{227}', "\u{61}\u{307}"),
+  ('\u{228}', "\u{45}\u{327}"), ('\u{229}', "\u{65}\u{327}"), ('\u{22A}', "\u{4F}\u{308}\u{304}"), ('\u{22B}', "\u{6F}\u{308}\u{304}"), ('\u{22C}', "\u{4F}\u{303}\u{304}"), ('\u{22D}', "\u{6F}\u{303}\u{304}"),
+  ('\u{22E}', "\u{4F}\u{307}"), ('\u{22F}', "\u{6F}\u{307}"), ('\u{230}', "\u{4F}\u{307}\u{304}"), ('\u{231}', "\u{6F}\u{307}\u{304}"), ('\u{232}', "\u{59}\u{304}"), ('\u{233}', "\u{79}\u{304}"),
+  ('\u{340}', "\u{300}"), ('\u{341}', "\u{301}"), ('\u{343}', "\u{313}"), ('\u{344}', "\u{308}\u{301}"), ('\u{374}', "\u{2B9}"), ('\u{37E}', "\u{3B}"),
+  ('\u{385}', "\u{A8}\u{301}"), ('\u{386}', "\u{391}\u{301}"), ('\u{387}', "\u{B7}"), ('\u{388}', "\u{395}\u{301}"), ('\u{389}', "\u{397}\u{301}"), ('\u{38A}', "\u{399}\u{301}"),
+  ('\u{38C}', "\u{39F}\u{301}"), ('\u{38E}', "\u{3A5}\u{301}"), ('\u{38F}', "\u{3A9}\u{301}"), ('\u{390}', "\u{3B9}\u{308}\u{301}"), ('\u{3AA}', "\u{399}\u{308}"), ('\u{3AB}', "\u{3A5}\u{308}"),
+  ('\u{3AC}', "\u{3B1}\u{301}"), ('\u{3AD}', "\u{3B5}\u{301}"), ('\u{3AE}', "\u{3B7}\u{301}"), ('\u{3AF}', "\u{3B9}\u{301}"), ('\u{3B0}', "\u{3C5}\u{308}\u{301}"), ('\u{3CA}', "\u{3B9}\u{308}"),
+  ('\u{3CB}', "\u{3C5}\u{308}"), ('\u{3CC}', "\u{3BF}\u{301}"), ('\u{3CD}', "\u{3C5}\u{301}"), ('\u{3CE}', "\u{3C9}\u{301}"), ('\u{3D3}', "\u{3D2}\u{301}"), ('\u{3D4}', "\u{3D2}\u{308}"),
+  ('\u{400}', "\u{415}\u{300}"), ('\u{401}', "\u{415}\u{308}"), ('\u{403}', "\u{413}\u{301}"), ('\u{407}', "\u{406}\u{308}"), ('\u{40C}', "\u{41A}\u{301}"), ('\u{40D}', "\u{418}\u{300}"),
+  ('\u{40E}', "\u{423}\u{306}"), ('\u{419}', "\u{418}\u{306}"), ('\u{439}', "\u{438}\u{306}"), ('\u{450}', "\u{435}\u{300}"), ('\u{451}', "\u{435}\u{308}"), ('\u{453}', "\u{433}\u{301}"),
+  ('\u{457}', "\u{456}\u{308}"), ('\u{45C}', "\u{43A}\u{301}"), ('\u{45D}', "\u{438}\u{300}"), ('\u{45E}', "\u{443}\u{306}"), ('\u{476}', "\u{474}\u{30F}"), ('\u{477}', "\u{475}\u{30F}"),
+  ('\u{4C1}', "\u{416}\u{306}"), ('\u{4C2}', "\u{436}\u{306}"), ('\u{4D0}', "\u{410}\u{306}"), ('\u{4D1}', "\u{430}\u{306}"), ('\u{4D2}', "\u{410}\u{308}"), ('\u{4D3}', "\u{430}\u{308}"),
+  ('\u{4D6}', "\u{415}\u{306}"), ('\u{4D7}', "\u{435}\u{306}"), ('\u{4DA}', "\u{4D8}\u{308}"), ('\u{4DB}', "\u{4D9}\u{308}"), ('\u{4DC}', "\u{416}\u{308}"), ('\u{4DD}', "\u{436}\u{308}"),
+  ('\u{4DE}', "\u{417}\u{308}"), ('\u{4DF}', "\u{437}\u{308}"), ('\u{4E2}', "\u{418}\u{304}"), ('\u{4E3}', "\u{438}\u{304}"), ('\u{4E4}', "\u{418}\u{308}"), ('\u{4E5}', "\u{438}\u{308}"),
+  ('\u{4E6}', "\u{41E}\u{308}"), ('\u{4E7}', "\u{43E}\u{308}"), ('\u{4EA}', "\u{4E8}\u{308}"), ('\u{4EB}', "\u{4E9}\u{308}"), ('\u{4EC}', "\u{42D}\u{308}"), ('\u{4ED}', "\u{44D}\u{308}"),
+  ('\u{4EE}', "\u{423}\u{304}"), ('\u{4EF}', "\u{443}\u{304}"), ('\u{4F0}', "\u{423}\u{308}"), ('\u{4F1}', "\u{443}\u{308}"), ('\u{4F2}', "\u{423}\u{30B}"), ('\u{4F3}', "\u{443}\u{30B}"),
+  ('\u{4F4}', "\u{427}\u{308}"), ('\u{4F5}', "\u{447}\u{308}"), ('\u{4F8}', "\u{42B}\u{308}"), ('\u{4F9}', "\u{44B}\u{308}"), ('\u{622}', "\u{627}\u{653}"), ('\u{623}', "\u{627}\u{654}"),
+  ('\u{624}', "\u{648}\u{654}"), ('\u{625}', "\u{627}\u{655}"), ('\u{626}', "\u{64A}\u{654}"), ('\u{6C0}', "\u{6D5}\u{654}"), ('\u{6C2}', "\u{6C1}\u{654}"), ('\u{6D3}', "\u{6D2}\u{654}"),
+  ('\u{929}', "\u{928}\u{93C}"), ('\u{931}', "\u{930}\u{93C}"), ('\u{934}', "\u{933}\u{93C}"), ('\u{958}', "\u{915}\u{93C}"), ('\u{959}', "\u{916}\u{93C}"), ('\u{95A}', "\u{917}\u{93C}"),
+  ('\u{95B}', "\u{91C}\u{93C}"), ('\u{95C}', "\u{921}\u{93C}"), ('\u{95D}', "\u{922}\u{93C}"), ('\u{95E}', "\u{92B}\u{93C}"), ('\u{95F}', "\u{92F}\u{93C}"), ('\u{9CB}', "\u{9C7}\u{9BE}"),
+  ('\u{9CC}', "\u{9C7}\u{9D7}"), ('\u{9DC}', "\u{9A1}\u{9BC}"), ('\u{9DD}', "\u{9A2}\u{9BC}"), ('\u{9DF}', "\u{9AF}\u{9BC}"), ('\u{A33}', "\u{A32}\u{A3C}"), ('\u{A36}', "\u{A38}\u{A3C}"),
+  ('\u{A59}', "\u{A16}\u{A3C}"), ('\u{A5A}', "\u{A17}\u{A3C}"), ('\u{A5B}', "\u{A1C}\u{A3C}"), ('\u{A5E}', "\u{A2B}\u{A3C}"), ('\u{B48}', "\u{B47}\u{B56}"), ('\u{B4B}', "\u{B47}\u{B3E}"),
+  ('\u{B4C}', "\u{B47}\u{B57}"), ('\u{B5C}', "\u{B21}\u{B3C}"), ('\u{B5D}', "\u{B22}\u{B3C}"), ('\u{B94}', "\u{B92}\u{BD7}"), ('\u{BCA}', "\u{BC6}\u{BBE}"), ('\u{BCB}', "\u{BC7}\u{BBE}"),
+  ('\u{BCC}', "\u{BC6}\u{BD7}"), ('\u{C48}', "\u{C46}\u{C56}"), ('\u{CC0}', "\u{CBF}\u{CD5}"), ('\u{CC7}', "\u{CC6}\u{CD5}"), ('\u{CC8}', "\u{CC6}\u{CD6}"), ('\u{CCA}', "\u{CC6}\u{CC2}"),
+  ('\u{CCB}', "\u{CC6}\u{CC2}\u{CD5}"), ('\u{D4A}', "\u{D46}\u{D3E}"), ('\u{D4B}', "\u{D47}\u{D3E}"), ('\u{D4C}', "\u{D46}\u{D57}"), ('\u{DDA}', "\u{DD9}\u{DCA}"), ('\u{DDC}', "\u{DD9}\u{DCF}"),
+  ('\u{DDD}', "\u{DD9}\u{DCF}\u{DCA}"), ('\u{DDE}', "\u{DD9}\u{DDF}"), ('\u{F43}', "\u{F42}\u{FB7}"), ('\u{F4D}', "\u{F4C}\u{FB7}"), ('\u{F52}', "\u{F51}\u{FB7}"), ('\u{F57}', "\u{F56}\u{FB7}"),
+  ('\u{F5C}', "\u{F5B}\u{FB7}"), ('\u{F69}', "\u{F40}\u{FB5}"), ('\u{F73}', "\u{F71}\u{F72}"), ('\u{F75}', "\u{F71}\u{F74}"), ('\u{F76}', "\u{FB2}\u{F80}"), ('\u{F78}', "\u{FB3}\u{F80}"),
+  ('\u{F81}', "\u{F71}\u{F80}"), ('\u{F93}', "\u{F92}\u{FB7}"), ('\u{F9D}', "\u{F9C}\u{FB7}"), ('\u{FA2}', "\u{FA1}\u{FB7}"), ('\u{FA7}', "\u{FA6}\u{FB7}"), ('\u{FAC}', "\u{FAB}\u{FB7}"),
+  ('\u{FB9}', "\u{F90}\u{FB5}"), ('\u{1026}', "\u{1025}\u{102E}"), ('\u{1B06}', "\u{1B05}\u{1B35}"), ('\u{1B08}', "\u{1B07}\u{1B35}"), ('\u{1B0A}', "\u{1B09}\u{1B35}"), ('\u{1B0C}', "\u{1B0B}\u{1B35}"),
+  ('\u{1B0E}', "\u{1B0D}\u{1B35}"), ('\u{1B12}', "\u{1B11}\u{1B35}"), ('\u{1B3B}', "\u{1B3A}\u{1B35}"), ('\u{1B3D}', "\u{1B3C}\u{1B35}"), ('\u{1B40}', "\u{1B3E}\u{1B35}"), ('\u{1B41}', "\u{1B3F}\u{1B35}"),
+  ('\u{1B43}', "\u{1B42}\u{1B35}"), ('\u{1E00}', "\u{41}\u{325}"), ('\u{1E01}', "\u{61}\u{325}"), ('\u{1E02}', "\u{42}\u{307}"), ('\u{1E03}', "\u{62}\u{307}"), ('\u{1E04}', "\u{42}\u{323}"),
+  ('\u{1E05}', "\u{62}\u{323}"), ('\u{1E06}', "\u{42}\u{331}"), ('\u{1E07}', "\u{62}\u{331}"), ('\u{1E08}', "\u{43}\u{327}\u{301}"), ('\u{1E09}', "\u{63}\u{327}\u{301}"), ('\u{1E0A}', "\u{44}\u{307}"),
+  ('\u{1E0B}', "\u{64}\u{307}"), ('\u{1E0C}', "\u{44}\u{323}"), ('\u{1E0D}', "\u{64}\u{323}"), ('\u{1E0E}', "\u{44}\u{331}"), ('\u{1E0F}', "\u{64}\u{331}"), ('\u{1E10}', "\u{44}\u{327}"),
+  ('\u{1E11}', "\u{64}\u{327}"), ('\u{1E12}', "\u{44}\u{32D}"), ('\u{1E13}', "\u{64}\u{32D}"), ('\u{1E14}', "\u{45}\u{304}\u{300}"), ('\u{1E15}', "\u{65}\u{304}\u{300}"), ('\u{1E16}', "\u{45}\u{304}\u{301}"),
+  ('\u{1E17}', "\u{65}\u{304}\u{301}"), ('\u{1E18}', "\u{45}\u{32D}"), ('\u{1E19}', "\u{65}\u{32D}"), ('\u{1E1A}', "\u{45}\u{330}"), ('\u{1E1B}', "\u{65}\u{330}"), ('\u{1E1C}', "\u{45}\u{327}\u{306}"),
+  ('\u{1E1D}', "\u{65}\u{327}\u{306}"), ('\u{1E1E}', "\u{46}\u{307}"), ('\u{1E1F}', "\u{66}\u{307}"), ('\u{1E20}', "\u{47}\u{304}"), ('\u{1E21}', "\u{67}\u{304}"), ('\u{1E22}', "\u{48}\u{307}"),
+  ('\u{1E23}', "\u{68}\u{307}"), ('\u{1E24}', "\u{48}\u{323}"), ('\u{1E25}', "\u{68}\u{323}"), ('\u{1E26}', "\u{48}\u{308}"), ('\u{1E27}', "\u{68}\u{308}"), ('\u{1E28}', "\u{48}\u{327}"),
+  ('\u{1E29}', "\u{68}\u{327}"), ('\u{1E2A}', "\u{48}\u{32E}"), ('\u{1E2B}', "\u{68}\u{32E}"), ('\u{1E2C}', "\u{49}\u{330}"), ('\u{1E2D}', "\u{69}\u{330}"), ('\u{1E2E}', "\u{49}\u{308}\u{301}"),
+  ('\u{1E2F}', "\u{69}\u{308}\u{301}"), ('\u{1E30}', "\u{4B}\u{301}"), ('\u{1E31}', "\u{6B}\u{301}"), ('\u{1E32}', "\u{4B}\u{323}"), ('\u{1E33}', "\u{6B}\u{323}"), ('\u{1E34}', "\u{4B}\u{331}"),
+  ('\u{1E35}', "\u{6B}\u{331}"), ('\u{1E36}', "\u{4C}\u{323}"), ('\u{1E37}', "\u{6C}\u{323}"), ('\u{1E38}', "\u{4C}\u{323}\u{304}"), ('\u{1E39}', "\u{6C}\u{323}\u{304}"), ('\u{1E3A}', "\u{4C}\u{331}"),
+  ('\u{1E3B}', "\u{6C}\u{331}"), ('\u{1E3C}', "\u{4C}\u{32D}"), ('\u{1E3D}', "\u{6C}\u{32D}"), ('\u{1E3E}', "\u{4D}\u{301}"), ('\u{1E3F}', "\u{6D}\u{301}"), ('\u{1E40}', "\u{4D}\u{307}"),
+  ('\u{1E41}', "\u{6D}\u{307}"), ('\u{1E42}', "\u{4D}\u{323}"), ('\u{1E43}', "\u{6D}\u{323}"), ('\u{1E44}', "\u{4E}\u{307}"), ('\u{1E45}', "\u{6E}\u{307}"), ('\u{1E46}', "\u{4E}\u{323}"),
+  ('\u{1E47}', "\u{6E}\u{323}"), ('\u{1E48}', "\u{4E}\u{331}"), ('\u{1E49}', "\u{6E}\u{331}"), ('\u{1E4A}', "\u{4E}\u{32D}"), ('\u{1E4B}', "\u{6E}\u{32D}"), ('\u{1E4C}', "\u{4F}\u{303}\u{301}"),
+  ('\u{1E4D}', "\u{6F}\u{303}\u{301}"), ('\u{1E4E}', "\u{4F}\u{303}\u{308}"), ('\u{1E4F}', "\u{6F}\u{303}\u{308}"), ('\u{1E50}', "\u{4F}\u{304}\u{300}"), ('\u{1E51}', "\u{6F}\u{304}\u{300}"), ('\u{1E52}', "\u{4F}\u{304}\u{301}"),
+  ('\u{1E53}', "\u{6F}\u{304}\u{301}"), ('\u{1E54}', "\u{50}\u{301}"), ('\u{1E55}', "\u{70}\u{301}"), ('\u{1E56}', "\u{50}\u{307}"), ('\u{1E57}', "\u{70}\u{307}"), ('\u{1E58}', "\u{52}\u{307}"),
+  ('\u{1E59}', "\u{72}\u{307}"), ('\u{1E5A}', "\u{52}\u{323}"), ('\u{1E5B}', "\u{72}\u{323}"), ('\u{1E5C}', "\u{52}\u{323}\u{304}"), ('\u{1E5D}', "\u{72}\u{323}\u{304}"), ('\u{1E5E}', "\u{52}\u{331}"),
+  ('\u{1E5F}', "\u{72}\u{331}"), ('\u{1E60}', "\u{53}\u{307}"), ('\u{1E61}', "\u{73}\u{307}"), ('\u{1E62}', "\u{53}\u{323}"), ('\u{1E63}', "\u{73}\u{323}"), ('\u{1E64}', "\u{53}\u{301}\u{307}"),
+  ('\u{1E65}', "\u{73}\u{301}\u{307}"), ('\u{1E66}', "\u{53}\u{30C}\u{307}"), ('\u{1E67}', "\u{73}\u{30C}\u{307}"), ('\u{1E68}', "\u{53}\u{323}\u{307}"), ('\u{1E69}', "\u{73}\u{323}\u{307}"), ('\u{1E6A}', "\u{54}\u{307}"),
+  ('\u{1E6B}', "\u{74}\u{307}"), ('\u{1E6C}', "\u{54}\u{323}"), ('\u{1E6D}', "\u{74}\u{323}"), ('\u{1E6E}', "\u{54}\u{331}"), ('\u{1E6F}', "\u{74}\u{331}"), ('\u{1E70}', "\u{54}\u{32D}"),
+  ('\u{1E71}', "\u{74}\u{32D}"), ('\u{1E72}', "\u{55}\u{324}"), ('\u{1E73}', "\u{75}\u{324}"), ('\u{1E74}', "\u{55}\u{330}"), ('\u{1E75}', "\u{75}\u{330}"), ('\u{1E76}', "\u{55}\u{32D}"),
+  ('\u{1E77}', "\u{75}\u{32D}"), ('\u{1E78}', "\u{55}\u{303}\u{301}"), ('\u{1E79}', "\u{75}\u{303}\u{301}"), ('\u{1E7A}', "\u{55}\u{304}\u{308}"), ('\u{1E7B}', "\u{75}\u{304}\u{308}"), ('\u{1E7C}', "\u{56}\u{303}"),
+  ('\u{1E7D}', "\u{76}\u{303}"), ('\u{1E7E}', "\u{56}\u{323}"), ('\u{1E7F}', "\u{76}\u{323}"), ('\u{1E80}', "\u{57}\u{300}"), ('\u{1E81}', "\u{77}\u{300}"), ('\u{1E82}', "\u{57}\u{301}"),
+  ('\u{1E83}', "\u{77}\u{301}"), ('\u{1E84}', "\u{57}\u{308}"), ('\u{1E85}', "\u{77}\u{308}"), ('\u{1E86}', "\u{57}\u{307}"), ('\u{1E87}', "\u{77}\u{307}"), ('\u{1E88}', "\u{57}\u{323}"),
+  ('\u{1E89}', "\u{77}\u{323}"), ('\u{1E8A}', "\u{58}\u{307}"), ('\u{1E8B}', "\u{78}\u{307}"), ('\u{1E8C}', "\u{58}\u{308}"), ('\u{1E8D}', "\u{78}\u{308}"), ('\u{1E8E}', "\u{59}\u{307}"),
+  ('\u{1E8F}', "\u{79}\u{307}"), ('\u{1E90}', "\u{5A}\u{302}"), ('\u{1E91}', "\u{7A}\u{302}"), ('\u{1E92}', "\u{5A}\u{323}"), ('\u{1E93}', "\u{7A}\u{323}"), ('\u{1E94}', "\u{5A}\u{331}"),
+  ('\u{1E95}', "\u{7A}\u{331}"), ('\u{1E96}', "\u{68}\u{331}"), ('\u{1E97}', "\u{74}\u{308}"), ('\u{1E98}', "\u{77}\u{30A}"), ('\u{1E99}', "\u{79}\u{30A}"), ('\u{1E9B}', "\u{17F}\u{307}"),
+  ('\u{1EA0}', "\u{41}\u{323}"), ('\u{1EA1}', "\u{61}\u{323}"), ('\u{1EA2}', "\u{41}\u{309}"), ('\u{1EA3}', "\u{61}\u{309}"), ('\u{1EA4}', "\u{41}\u{302}\u{301}"), ('\u{1EA5}', "\u{61}\u{302}\u{301}"),
+  ('\u{1EA6}', "\u{41}\u{302}\u{300}"), ('\u{1EA7}', "\u{61}\u{302}\u{300}"), ('\u{1EA8}', "\u{41}\u{302}\u{309}"), ('\u{1EA9}', "\u{61}\u{302}\u{309}"), ('\u{1EAA}', "\u{41}\u{302}\u{303}"), ('\u{1EAB}', "\u{61}\u{302}\u{303}"),
+  ('\u{1EAC}', "\u{41}\u{323}\u{302}"), ('\u{1EAD}', "\u{61}\u{323}\u{302}"), ('\u{1EAE}', "\u{41}\u{306}\u{301}"), ('\u{1EAF}', "\u{61}\u{306}\u{301}"), ('\u{1EB0}', "\u{41}\u{306}\u{300}"), ('\u{1EB1}', "\u{61}\u{306}\u{300}"),
+  ('\u{1EB2}', "\u{41}\u{306}\u{309}"), ('\u{1EB3}', "\u{61}\u{306}\u{309}"), ('\u{1EB4}', "\u{41}\u{306}\u{303}"), ('\u{1EB5}', "\u{61}\u{306}\u{303}"), ('\u{1EB6}', "\u{41}\u{323}\u{306}"), ('\u{1EB7}', "\u{61}\u{323}\u{306}"),
+  ('\u{1EB8}', "\u{45}\u{323}"), ('\u{1EB9}', "\u{65}\u{323}"), ('\u{1EBA}', "\u{45}\u{309}"), ('\u{1EBB}', "\u{65}\u{309}"), ('\u{1EBC}', "\u{45}\u{303}"), ('\u{1EBD}', "\u{65}\u{303}"),
+  ('\u{1EBE}', "\u{45}\u{302}\u{301}"), ('\u{1EBF}', "\u{65}\u{302}\u{301}"), ('\u{1EC0}', "\u{45}\u{302}\u{300}"), ('\u{1EC1}', "\u{65}\u{302}\u{300}"), ('\u{1EC2}', "\u{45}\u{302}\u{309}"), ('\u{1EC3}', "\u{65}\u{302}\u{309}"),
+  ('\u{1EC4}', "\u{45}\u{302}\u{303}"), ('\u{1EC5}', "\u{65}\u{302}\u{303}"), ('\u{1EC6}', "\u{45}\u{323}\u{302}"), ('\u{1EC7}', "\u{65}\u{323}\u{302}"), ('\u{1EC8}', "\u{49}\u{309}"), ('\u{1EC9}', "\u{69}\u{309}"),
+  ('\u{1ECA}', "\u{49}\u{323}"), ('\u{1ECB}', "\u{69}\u{323}"), ('\u{1ECC}', "\u{4F}\u{323}"), ('\u{1ECD}', "\u{6F}\u{323}"), ('\u{1ECE}', "\u{4F}\u{309}"), ('\u{1ECF}', "\u{6F}\u{309}"),
+  ('\u{1ED0}', "\u{4F}\u{302}\u{301}"), ('\u{1ED1}', "\u{6F}\u{302}\u{301}"), ('\u{1ED2}', "\u{4F}\u{302}\u{300}"), ('\u{1ED3}', "\u{6F}\u{302}\u{300}"), ('\u{1ED4}', "\u{4F}\u{302}\u{309}"), ('\u{1ED5}', "\u{6F}\u{302}\u{309}"),
+  ('\u{1ED6}', "\u{4F}\u{302}\u{303}"), ('\u{1ED7}', "\u{6F}\u{302}\u{303}"), ('\u{1ED8}', "\u{4F}\u{323}\u{302}"), ('\u{1ED9}', "\u{6F}\u{323}\u{302}"), ('\u{1EDA}', "\u{4F}\u{31B}\u{301}"), ('\u{1EDB}', "\u{6F}\u{31B}\u{301}"),
+  ('\u{1EDC}', "\u{4F}\u{31B}\u{300}"), ('\u{1EDD}', "\u{6F}\u{31B}\u{300}"), ('\u{1EDE}', "\u{4F}\u{31B}\u{309}"), ('\u{1EDF}', "\u{6F}\u{31B}\u{309}"), ('\u{1EE0}', "\u{4F}\u{31B}\u{303}"), ('\u{1EE1}', "\u{6F}\u{31B}\u{303}"),
+  ('\u{1EE2}', "\u{4F}\u{31B}\u{323}"), ('\u{1EE3}', "\u{6F}\u{31B}\u{323}"), ('\u{1EE4}', "\u{55}\u{323}"), ('\u{1EE5}', "\u{75}\u{323}"), ('\u{1EE6}', "\u{55}\u{309}"), ('\u{1EE7}', "\u{75}\u{309}"),
+  ('\u{1EE8}', "\u{55}\u{31B}\u{301}"), ('\u{1EE9}', "\u{75}\u{31B}\u{301}"), ('\u{1EEA}', "\u{55}\u{31B}\u{300}"), ('\u{1EEB}', "\u{75}\u{31B}\u{300}"), ('\u{1EEC}', "\u{55}\u{31B}\u{309}"), ('\u{1EED}', "\u{75}\u{31B}\u{309}"),
+  ('\u{1EEE}', "\u{55}\u{31B}\u{303}"), ('\u{1EEF}', "\u{75}\u{31B}\u{303}"), ('\u{1EF0}', "\u{55}\u{31B}\u{323}"), ('\u{1EF1}', "\u{75}\u{31B}\u{323}"), ('\u{1EF2}', "\u{59}\u{300}"), ('\u{1EF3}', "\u{79}\u{300}"),
+  ('\u{1EF4}', "\u{59}\u{323}"), ('\u{1EF5}', "\u{79}\u{323}"), ('\u{1EF6}', "\u{59}\u{309}"), ('\u{1EF7}', "\u{79}\u{309}"), ('\u{1EF8}', "\u{59}\u{303}"), ('\u{1EF9}', "\u{79}\u{303}"),
+  ('\u{1F00}', "\u{3B1}\u{313}"), ('\u{1F01}', "\u{3B1}\u{314}"), ('\u{1F02}', "\u{3B1}\u{313}\u{300}"), ('\u{1F03}', "\u{3B1}\u{314}\u{300}"), ('\u{1F04}', "\u{3B1}\u{313}\u{301}"), ('\u{1F05}', "\u{3B1}\u{314}\u{301}"),
+  ('\u{1F06}', "\u{3B1}\u{313}\u{342}"), ('\u{1F07}', "\u{3B1}\u{314}\u{342}"), ('\u{1F08}', "\u{391}\u{313}"), ('\u{1F09}', "\u{391}\u{314}"), ('\u{1F0A}', "\u{391}\u{313}\u{300}"), ('\u{1F0B}', "\u{391}\u{314}\u{300}"),
+  ('\u{1F0C}', "\u{391}\u{313}\u{301}"), ('\u{1F0D}', "\u{391}\u{314}\u{301}"), ('\u{1F0E}', "\u{391}\u{313}\u{342}"), ('\u{1F0F}', "\u{391}\u{314}\u{342}"), ('\u{1F10}', "\u{3B5}\u{313}"), ('\u{1F11}', "\u{3B5}\u{314}"),
+  ('\u{1F12}', "\u{3B5}\u{313}\u{300}"), ('\u{1F13}', "\u{3B5}\u{314}\u{300}"), ('\u{1F14}', "\u{3B5}\u{313}\u{301}"), ('\u{1F15}', "\u{3B5}\u{314}\u{301}"), ('\u{1F18}', "\u{395}\u{313}"), ('\u{1F19}', "\u{395}\u{314}"),
+  ('\u{1F1A}', "\u{395}\u{313}\u{300}"), ('\u{1F1B}', "\u{395}\u{314}\u{300}"), ('\u{1F1C}', "\u{395}\u{313}\u{301}"), ('\u{1F1D}', "\u{395}\u{314}\u{301}"), ('\u{1F20}', "\u{3B7}\u{313}"), ('\u{1F21}', "\u{3B7}\u{314}"),
+  ('\u{1F22}', "\u{3B7}\u{313}\u{300}"), ('\u{1F23}', "\u{3B7}\u{314}\u{300}"), ('\u{1F24}', "\u{3B7}\u{313}\u{301}"), ('\u{1F25}', "\u{3B7}\u{314}\u{301}"), ('\u{1F26}', "\u{3B7}\u{313}\u{342}"), ('\u{1F27}', "\u{3B7}\u{314}\u{342}"),
+  ('\u{1F28}', "\u{397}\u{313}"), ('\u{1F29}', "\u{397}\u{314}"), ('\u{1F2A}', "\u{397}\u{313}\u{300}"), ('\u{1F2B}', "\u{397}\u{314}\u{300}"), ('\u{1F2C}', "\u{397}\u{313}\u{301}"), ('\u{1F2D}', "\u{397}\u{314}\u{301}"),
+  ('\u{1F2E}', "\u{397}\u{313}\u{342}"), ('\u{1F2F}', "\u{397}\u{314}\u{342}"), ('\u{1F30}', "\u{3B9}\u{313}"), ('\u{1F31}', "\u{3B9}\u{314}"), ('\u{1F32}', "\u{3B9}\u{313}\u{300}"), ('\u{1F33}', "\u{3B9}\u{314}\u{300}"),
+  ('\u{1F34}', "\u{3B9}\u{313}\u{301}"), ('\u{1F35}', "\u{3B9}\u{314}\u{301}"), ('\u{1F36}', "\u{3B9}\u{313}\u{342}"), ('\u{1F37}', "\u{3B9}\u{314}\u{342}"), ('\u{1F38}', "\u{399}\u{313}"), ('\u{1F39}', "\u{399}\u{314}"),
+  ('\u{1F3A}', "\u{399}\u{313}\u{300}"), ('\u{1F3B}', "\u{399}\u{314}\u{300}"), ('\u{1F3C}', "\u{399}\u{313}\u{301}"), ('\u{1F3D}', "\u{399}\u{314}\u{301}"), ('\u{1F3E}', "\u{399}\u{313}\u{342}"), ('\u{1F3F}', "\u{399}\u{314}\u{342}"),
+  ('\u{1F40}', "\u{3BF}\u{313}"), ('\u{1F41}', "\u{3BF}\u{314}"), ('\u{1F42}', "\u{3BF}\u{313}\u{300}"), ('\u{1F43}', "\u{3BF}\u{314}\u{300}"), ('\u{1F44}', "\u{3BF}\u{313}\u{301}"), ('\u{1F45}', "\u{3BF}\u{314}\u{301}"),
+  ('\u{1F48}', "\u{39F}\u{313}"), ('\u{1F49}', "\u{39F}\u{314}"), ('\u{1F4A}', "\u{39F}\u{313}\u{300}"), ('\u{1F4B}', "\u{39F}\u{314}\u{300}"), ('\u{1F4C}', "\u{39F}\u{313}\u{301}"), ('\u{1F4D}', "\u{39F}\u{314}\u{301}"),
+  ('\u{1F50}', "\u{3C5}\u{313}"), ('\u{1F51}', "\u{3C5}\u{314}"), ('\u{1F52}', "\u{3C5}\u{313}\u{300}"), ('\u{1F53}', "\u{3C5}\u{314}\u{300}"), ('\u{1F54}', "\u{3C5}\u{313}\u{301}"), ('\u{1F55}', "\u{3C5}\u{314}\u{301}"),
+  ('\u{1F56}', "\u{3C5}\u{313}\u{342}"), ('\u{1F57}', "\u{3C5}\u{314}\u{342}"), ('\u{1F59}', "\u{3A5}\u{314}"), ('\u{1F5B}', "\u{3A5}\u{314}\u{300}"), ('\u{1F5D}', "\u{3A5}\u{314}\u{301}"), ('\u{1F5F}', "\u{3A5}\u{314}\u{342}"),
+  ('\u{1F60}', "\u{3C9}\u{313}"), ('\u{1F61}', "\u{3C9}\u{314}"), ('\u{1F62}', "\u{3C9}\u{313}\u{300}"), ('\u{1F63}', "\u{3C9}\u{314}\u{300}"), ('\u{1F64}', "\u{3C9}\u{313}\u{301}"), ('\u{1F65}', "\u{3C9}\u{314}\u{301}"),
+  ('\u{1F66}', "\u{3C9}\u{313}\u{342}"), ('\u{1F67}', "\u{3C9}\u{314}\u{342}"), ('\u{1F68}', "\u{3A9}\u{313}"), ('\u{1F69}', "\u{3A9}\u{314}"), ('\u{1F6A}', "\u{3A9}\u{313}\u{300}"), ('\u{1F6B}', "\u{3A9}\u{314}\u{300}"),
+  ('\u{1F6C}', "\u{3A9}\u{313}\u{301}"), ('\u{1F6D}', "\u{3A9}\u{314}\u{301}"), ('\u{1F6E}', "\u{3A9}\u{313}\u{342}"), ('\u{1F6F}', "\u{3A9}\u{314}\u{342}"), ('\u{1F70}', "\u{3B1}\u{300}"), ('\u{1F71}', "\u{3B1}\u{301}"),
+  ('\u{1F72}', "\u{3B5}\u{300}"), ('\u{1F73}', "\u{3B5}\u{301}"), ('\u{1F74}', "\u{3B7}\u{300}"), ('\u{1F75}', "\u{3B7}\u{301}"), ('\u{1F76}', "\u{3B9}\u{300}"), ('\u{1F77}', "\u{3B9}\u{301}"),
+  ('\u{1F78}', "\u{3BF}\u{300}"), ('\u{1F79}', "\u{3BF}\u{301}"), ('\u{1F7A}', "\u{3C5}\u{300}"), ('\u{1F7B}', "\u{3C5}\u{301}"), ('\u{1F7C}', "\u{3C9}\u{300}"), ('\u{1F7D}', "\u{3C9}\u{301}"),
+  ('\u{1F80}', "\u{3B1}\u{313}\u{345}"), ('\u{1F81}', "\u{3B1}\u{314}\u{345}"), ('\u{1F82}', "\u{3B1}\u{313}\u{300}\u{345}"), ('\u{1F83}', "\u{3B1}\u{314}\u{300}\u{345}"), ('\u{1F84}', "\u{3B1}\u{313}\u{301}\u{345}"), ('\u{1F85}', "\u{3B1}\u{314}\u{301}\u{345}"),
+  ('\u{1F86}', "\u{3B1}\u{313}\u{342}\u{345}"), ('\u{1F87}', "\u{3B1}\u{314}\u{342}\u{345}"), ('\u{1F88}', "\u{391}\u{313}\u{345}"), ('\u{1F89}', "\u{391}\u{314}\u{345}"), ('\u{1F8A}', "\u{391}\u{313}\u{300}\u{345}"), ('\u{1F8B}', "\u{391}\u{314}\u{300}\u{345}"),
+  ('\u{1F8C}', "\u{391}\u{313}\u{301}\u{345}"), ('\u{1F8D}', "\u{391}\u{314}\u{301}\u{345}"), ('\u{1F8E}', "\u{391}\u{313}\u{342}\u{345}"), ('\u{1F8F}', "\u{391}\u{314}\u{342}\u{345}"), ('\u{1F90}', "\u{3B7}\u{313}\u{345}"), ('\u{1F91}', "\u{3B7}\u{314}\u{345}"),
+  ('\u{1F92}', "\u{3B7}\u{313}\u{300}\u{345}"), ('\u{1F93}', "\u{3B7}\u{314}\u{300}\u{345}"), ('\u{1F94}', "\u{3B7}\u{313}\u{301}\u{345}"), ('\u{1F95}', "\u{3B7}\u{314}\u{301}\u{345}"), ('\u{1F96}', "\u{3B7}\u{313}\u{342}\u{345}"), ('\u{1F97}', "\u{3B7}\u{314}\u{342}\u{345}"),
+  ('\u{1F98}', "\u{397}\u{313}\u{345}"), ('\u{1F99}', "\u{397}\u{314}\u{345}"), ('\u{1F9A}', "\u{397}\u{313}\u{300}\u{345}"), ('\u{1F9B}', "\u{397}\u{314}\u{300}\u{345}"), ('\u{1F9C}', "\u{397}\u{313}\u{301}\u{345}"), ('\u{1F9D}', "\u{397}\u{314}\u{301}\u{345}"),
+  ('\u{1F9E}', "\u{397}\u{313}\u{342}\u{345}"), ('\u{1F9F}', "\u{397}\u{314}\u{342}\u{345}"), ('\u{1FA0}', "\u{3C9}\u{313}\u{345}"), ('\u{1FA1}', "\u{3C9}\u{314}\u{345}"), ('\u{1FA2}', "\u{3C9}\u{313}\u{300}\u{345}"), ('\u{1FA3}', "\u{3C9}\u{314}\u{300}\u{345}"),
+  ('\u{1FA4}', "\u{3C9}\u{313}\u{301}\u{345}"), ('\u{1FA5}', "\u{3C9}\u{314}\u{301}\u{345}"), ('\u{1FA6}', "\u{3C9}\u{313}\u{342}\u{345}"), ('\u{1FA7}', "\u{3C9}\u{314}\u{342}\u{345}"), ('\u{1FA8}', "\u{3A9}\u{313}\u{345}"), ('\u{1FA9}', "\u{3A9}\u{314}\u{345}"),
+  ('\u{1FAA}', "\u{3A9}\u{313}\u{300}\u{345}"), ('\u{1FAB}', "\u{3A9}\u{314}\u{300}\u{345}"), ('\u{1FAC}', "\u{3A9}\u{313}\u{301}\u{345}"), ('\u{1FAD}', "\u{3A9}\u{314}\u{301}\u{345}"), ('\u{1FAE}', "\u{3A9}\u{313}\u{342}\u{345}"), ('\u{1FAF}', "\u{3A9}\u{314}\u{342}\u{345}"),
+  ('\u{1FB0}', "\u{3B1}\u{306}"), ('\u{1FB1}', "\u{3B1}\u{304}"), ('\u{1FB2}', "\u{3B1}\u{300}\u{345}"), ('\u{1FB3}', "\u{3B1}\u{345}"), ('\u{1FB4}', "\u{3B1}\u{301}\u{345}"), ('\u{1FB6}', "\u{3B1}\u{342}"),
+  ('\u{1FB7}', "\u{3B1}\u{342}\u{345}"), ('\u{1FB8}', "\u{391}\u{306}"), ('\u{1FB9}', "\u{391}\u{304}"), ('\u{1FBA}', "\u{391}\u{300}"), ('\u{1FBB}', "\u{391}\u{301}"), ('\u{1FBC}', "\u{391}\u{345}"),
+  ('\u{1FBE}', "\u{3B9}"), ('\u{1FC1}', "\u{A8}\u{342}"), ('\u{1FC2}', "\u{3B7}\u{300}\u{345}"), ('\u{1FC3}', "\u{3B7}\u{345}"), ('\u{1FC4}', "\u{3B7}\u{301}\u{345}"), ('\u{1FC6}', "\u{3B7}\u{342}"),
+  ('\u{1FC7}', "\u{3B7}\u{342}\u{345}"), ('\u{1FC8}', "\u{395}\u{300}"), ('\u{1FC9}', "\u{395}\u{301}"), ('\u{1FCA}', "\u{397}\u{300}"), ('\u{1FCB}', "\u{397}\u{301}"), ('\u{1FCC}', "\u{397}\u{345}"),
+  ('\u{1FCD}', "\u{1FBF}\u{300}"), ('\u{1FCE}', "\u{1FBF}\u{301}"), ('\u{1FCF}', "\u{1FBF}\u{342}"), ('\u{1FD0}', "\u{3B9}\u{306}"), ('\u{1FD1}', "\u{3B9}\u{304}"), ('\u{1FD2}', "\u{3B9}\u{308}\u{300}"),
+  ('\u{1FD3}', "\u{3B9}\u{308}\u{301}"), ('\u{1FD6}', "\u{3B9}\u{342}"), ('\u{1FD7}', "\u{3B9}\u{308}\u{342}"), ('\u{1FD8}', "\u{399}\u{306}"), ('\u{1FD9}', "\u{399}\u{304}"), ('\u{1FDA}', "\u{399}\u{300}"),
+  ('\u{1FDB}', "\u{399}\u{301}"), ('\u{1FDD}', "\u{1FFE}\u{300}"), ('\u{1FDE}', "\u{1FFE}\u{301}"), ('\u{1FDF}', "\u{1FFE}\u{342}"), ('\u{1FE0}', "\u{3C5}\u{306}"), ('\u{1FE1}', "\u{3C5}\u{304}"),
+  ('\u{1FE2}', "\u{3C5}\u{308}\u{300}"), ('\u{1FE3}', "\u{3C5}\u{308}\u{301}"), ('\u{1FE4}', "\u{3C1}\u{313}"), ('\u{1FE5}', "\u{3C1}\u{314}"), ('\u{1FE6}', "\u{3C5}\u{342}"), ('\u{1FE7}', "\u{3C5}\u{308}\u{342}"),
+  ('\u{1FE8}', "\u{3A5}\u{306}"), ('\u{1FE9}', "\u{3A5}\u{304}"), ('\u{1FEA}', "\u{3A5}\u{300}"), ('\u{1FEB}', "\u{3A5}\u{301}"), ('\u{1FEC}', "\u{3A1}\u{314}"), ('\u{1FED}', "\u{A8}\u{300}"),
+  ('\u{1FEE}', "\u{A8}\u{301}"), ('\u{1FEF}', "\u{60}"), ('\u{1FF2}', "\u{3C9}\u{300}\u{345}"), ('\u{1FF3}', "\u{3C9}\u{345}"), ('\u{1FF4}', "\u{3C9}\u{301}\u{345}"), ('\u{1FF6}', "\u{3C9}\u{342}"),
+  ('\u{1FF7}', "\u{3C9}\u{342}\u{345}"), ('\u{1FF8}', "\u{39F}\u{300}"), ('\u{1FF9}', "\u{39F}\u{301}"), ('\u{1FFA}', "\u{3A9}\u{300}"), ('\u{1FFB}', "\u{3A9}\u{301}"), ('\u{1FFC}', "\u{3A9}\u{345}"),
+  ('\u{1FFD}', "\u{B4}"), ('\u{2000}', "\u{2002}"), ('\u{2001}', "\u{2003}"), ('\u{2126}', "\u{3A9}"), ('\u{212A}', "\u{4B}"), ('\u{212B}', "\u{41}\u{30A}"),
+  ('\u{219A}', "\u{2190}\u{338}"), ('\u{219B}', "\u{2192}\u{338}"), ('\u{21AE}', "\u{2194}\u{338}"), ('\u{21CD}', "\u{21D0}\u{338}"), ('\u{21CE}', "\u{21D4}\u{338}"), ('\u{21CF}', "\u{21D2}\u{338}"),
+  ('\u{2204}', "\u{2203}\u{338}"), ('\u{2209}', "\u{2208}\u{338}"), ('\u{220C}', "\u{220B}\u{338}"), ('\u{2224}', "\u{2223}\u{338}"), ('\u{2226}', "\u{2225}\u{338}"), ('\u{2241}', "\u{223C}\u{338}"),
+  ('\u{2244}', "\u{2243}\u{338}"), ('\u{2247}', "\u{2245}\u{338}"), ('\u{2249}', "\u{2248}\u{338}"), ('\u{2260}', "\u{3D}\u{338}"), ('\u{2262}', "\u{2261}\u{338}"), ('\u{226D}', "\u{224D}\u{338}"),
+  ('\u{226E}', "\u{3C}\u{338}"), ('\u{226F}', "\u{3E}\u{338}"), ('\u{2270}', "\u{2264}\u{338}"), ('\u{2271}', "\u{2265}\u{338}"), ('\u{2274}', "\u{2272}\u{338}"), ('\u{2275}', "\u{2273}\u{338}"),
+  ('\u{2278}', "\u{2276}\u{338}"), ('\u{2279}', "\u{2277}\u{338}"), ('\u{2280}', "\u{227A}\u{338}"), ('\u{2281}', "\u{227B}\u{338}"), ('\u{2284}', "\u{2282}\u{338}"), ('\u{2285}', "\u{2283}\u{338}"),
+  ('\u{2288}', "\u{2286}\u{338}"), ('\u{2289}', "\u{2287}\u{338}"), ('\u{22AC}', "\u{22A2}\u{338}"), ('\u{22AD}', "\u{22A8}\u{338}"), ('\u{22AE}', "\u{22A9}\u{338}"), ('\u{22AF}', "\u{22AB}\u{338}"),
+  ('\u{22E0}', "\u{227C}\u{338}"), ('\u{22E1}', "\u{227D}\u{338}"), ('\u{22E2}', "\u{2291}\u{338}"), ('\u{22E3}', "\u{2292}\u{338}"), ('\u{22EA}', "\u{22B2}\u{338}"), ('\u{22EB}', "\u{22B3}\u{338}"),
+  ('\u{22EC}', "\u{22B4}\u{338}"), ('\u{22ED}', "\u{22B5}\u{338}"), ('\u{2329}', "\u{3008}"), ('\u{232A}', "\u{3009}"), ('\u{2ADC}', "\u{2ADD}\u{338}"), ('\u{304C}', "\u{304B}\u{3099}"),
+  ('\u{304E}', "\u{304D}\u{3099}"), ('\u{3050}', "\u{304F}\u{3099}"), ('\u{3052}', "\u{3051}\u{3099}"), ('\u{3054}', "\u{3053}\u{3099}"), ('\u{3056}', "\u{3055}\u{3099}"), ('\u{3058}', "\u{3057}\u{3099}"),
+  ('\u{305A}', "\u{3059}\u{3099}"), ('\u{305C}', "\u{305B}\u{3099}"), ('\u{305E}', "\u{305D}\u{3099}"), ('\u{3060}', "\u{305F}\u{3099}"), ('\u{3062}', "\u{3061}\u{3099}"), ('\u{3065}', "\u{3064}\u{3099}"),
+  ('\u{3067}', "\u{3066}\u{3099}"), ('\u{3069}', "\u{3068}\u{3099}"), ('\u{3070}', "\u{306F}\u{3099}"), ('\u{3071}', "\u{306F}\u{309A}"), ('\u{3073}', "\u{3072}\u{3099}"), ('\u{3074}', "\u{3072}\u{309A}"),
+  ('\u{3076}', "\u{3075}\u{3099}"), ('\u{3077}', "\u{3075}\u{309A}"), ('\u{3079}', "\u{3078}\u{3099}"), ('\u{307A}', "\u{3078}\u{309A}"), ('\u{307C}', "\u{307B}\u{3099}"), ('\u{307D}', "\u{307B}\u{309A}"),
+  ('\u{3094}', "\u{3046}\u{3099}"), ('\u{309E}', "\u{309D}\u{3099}"), ('\u{30AC}', "\u{30AB}\u{3099}"), ('\u{30AE}', "\u{30AD}\u{3099}"), ('\u{30B0}', "\u{30AF}\u{3099}"), ('\u{30B2}', "\u{30B1}\u{3099}"),
+  ('\u{30B4}', "\u{30B3}\u{3099}"), ('\u{30B6}', "\u{30B5}\u{3099}"), ('\u{30B8}', "\u{30B7}\u{3099}"), ('\u{30BA}', "\u{30B9}\u{3099}"), ('\u{30BC}', "\u{30BB}\u{3099}"), ('\u{30BE}', "\u{30BD}\u{3099}"),
+  ('\u{30C0}', "\u{30BF}\u{3099}"), ('\u{30C2}', "\u{30C1}\u{3099}"), ('\u{30C5}', "\u{30C4}\u{3099}"), ('\u{30C7}', "\u{30C6}\u{3099}"), ('\u{30C9}', "\u{30C8}\u{3099}"), ('\u{30D0}', "\u{30CF}\u{3099}"),
+  ('\u{30D1}', "\u{30CF}\u{309A}"), ('\u{30D3}', "\u{30D2}\u{3099}"), ('\u{30D4}', "\u{30D2}\u{309A}"), ('\u{30D6}', "\u{30D5}\u{3099}"), ('\u{30D7}', "\u{30D5}\u{309A}"), ('\u{30D9}', "\u{30D8}\u{3099}"),
+  ('\u{30DA}', "\u{30D8}\u{309A}"), ('\u{30DC}', "\u{30DB}\u{3099}"), ('\u{30DD}', "\u{30DB}\u{309A}"), ('\u{30F4}', "\u{30A6}\u{3099}"), ('\u{30F7}', "\u{30EF}\u{3099}"), ('\u{30F8}', "\u{30F0}\u{3099}"),
+  ('\u{30F9}', "\u{30F1}\u{3099}"), ('\u{30FA}', "\u{30F2}\u{3099}"), ('\u{30FE}', "\u{30FD}\u{3099}"), ('\u{F900}', "\u{8C48}"), ('\u{F901}', "\u{66F4}"), ('\u{F902}', "\u{8ECA}"),
+  ('\u{F903}', "\u{8CC8}"), ('\u{F904}', "\u{6ED1}"), ('\u{F905}', "\u{4E32}"), ('\u{F906}', "\u{53E5}"), ('\u{F907}', "\u{9F9C}"), ('\u{F908}', "\u{9F9C}"),
+  ('\u{F909}', "\u{5951}"), ('\u{F90A}', "\u{91D1}"), ('\u{F90B}', "\u{5587}"), ('\u{F90C}', "\u{5948}"), ('\u{F90D}', "\u{61F6}"), ('\u{F90E}', "\u{7669}"),
+  ('\u{F90F}', "\u{7F85}"), ('\u{F910}', "\u{863F}"), ('\u{F911}', "\u{87BA}"), ('\u{F912}', "\u{88F8}"), ('\u{F913}', "\u{908F}"), ('\u{F914}', "\u{6A02}"),
+  ('\u{F915}', "\u{6D1B}"), ('\u{F916}', "\u{70D9}"), ('\u{F917}', "\u{73DE}"), ('\u{F918}', "\u{843D}"), ('\u{F919}', "\u{916A}"), ('\u{F91A}', "\u{99F1}"),
+  ('\u{F91B}', "\u{4E82}"), ('\u{F91C}', "\u{5375}"), ('\u{F91D}', "\u{6B04}"), ('\u{F91E}', "\u{721B}"), ('\u{F91F}', "\u{862D}"), ('\u{F920}', "\u{9E1E}"),
+  ('\u{F921}', "\u{5D50}"), ('\u{F922}', "\u{6FEB}"), ('\u{F923}', "\u{85CD}"), ('\u{F924}', "\u{8964}"), ('\u{F925}', "\u{62C9}"), ('\u{F926}', "\u{81D8}"),
+  ('\u{F927}', "\u{881F}"), ('\u{F928}', "\u{5ECA}"), ('\u{F929}', "\u{6717}"), ('\u{F92A}', "\u{6D6A}"), ('\u{F92B}', "\u{72FC}"), ('\u{F92C}', "\u{90CE}"),
+  ('\u{F92D}', "\u{4F86}"), ('\u{F92E}', "\u{51B7}"), ('\u{F92F}', "\u{52DE}"), ('\u{F930}', "\u{64C4}"), ('\u{F931}', "\u{6AD3}"), ('\u{F932}', "\u{7210}"),
+  ('\u{F933}', "\u{76E7}"), ('\u{F934}', "\u{8001}"), ('\u{F935}', "\u{8606}"), ('\u{F936}', "\u{865C}"), ('\u{F937}', "\u{8DEF}"), ('\u{F938}', "\u{9732}"),
+  ('\u{F939}', "\u{9B6F}"), ('\u{F93A}', "\u{9DFA}"), ('\u{F93B}', "\u{788C}"), ('\u{F93C}', "\u{797F}"), ('\u{F93D}', "\u{7DA0}"), ('\u{F93E}', "\u{83C9}"),
+  ('\u{F93F}', "\u{9304}"), ('\u{F940}', "\u{9E7F}"), ('\u{F941}', "\u{8AD6}"), ('\u{F942}', "\u{58DF}"), ('\u{F943}', "\u{5F04}"), ('\u{F944}', "\u{7C60}"),
+  ('\u{F945}', "\u{807E}"), ('\u{F946}', "\u{7262}"), ('\u{F947}', "\u{78CA}"), ('\u{F948}', "\u{8CC2}"), ('\u{F949}', "\u{96F7}"), ('\u{F94A}', "\u{58D8}"),
+  ('\u{F94B}', "\u{5C62}"), ('\u{F94C}', "\u{6A13}"), ('\u{F94D}', "\u{6DDA}"), ('\u{F94E}', "\u{6F0F}"), ('\u{F94F}', "\u{7D2F}"), ('\u{F950}', "\u{7E37}"),
+  ('\u{F951}', "\u{964B}"), ('\u{F952}', "\u{52D2}"), ('\u{F953}', "\u{808B}"), ('\u{F954}', "\u{51DC}"), ('\u{F955}', "\u{51CC}"), ('\u{F956}', "\u{7A1C}"),
+  ('\u{F957}', "\u{7DBE}"), ('\u{F958}', "\u{83F1}"), ('\u{F959}', "\u{9675}"), ('\u{F95A}', "\u{8B80}"), ('\u{F95B}', "\u{62CF}"), ('\u{F95C}', "\u{6A02}"),
+  ('\u{F95D}', "\u{8AFE}"), ('\u{F95E}', "\u{4E39}"), ('\u{F95F}', "\u{5BE7}"), ('\u{F960}', "\u{6012}"), ('\u{F961}', "\u{7387}"), ('\u{F962}', "\u{7570}"),
+  ('\u{F963}', "\u{5317}"), ('\u{F964}', "\u{78FB}"), ('\u{F965}', "\u{4FBF}"), ('\u{F966}', "\u{5FA9}"), ('\u{F967}', "\u{4E0D}"), ('\u{F968}', "\u{6CCC}"),
+  ('\u{F969}', "\u{6578}"), ('\u{F96A}', "\u{7D22}"), ('\u{F96B}', "\u{53C3}"), ('\u{F96C}', "\u{585E}"), ('\u{F96D}', "\u{7701}"), ('\u{F96E}', "\u{8449}"),
+  ('\u{F96F}', "\u{8AAA}"), ('\u{F970}', "\u{6BBA}"), ('\u{F971}', "\u{8FB0}"), ('\u{F972}', "\u{6C88}"), ('\u{F973}', "\u{62FE}"), ('\u{F974}', "\u{82E5}"),
+  ('\u{F975}', "\u{63A0}"), ('\u{F976}', "\u{7565}"), ('\u{F977}', "\u{4EAE}"), ('\u{F978}', "\u{5169}"), ('\u{F979}', "\u{51C9}"), ('\u{F97A}', "\u{6881}"),
+  ('\u{F97B}', "\u{7CE7}"), ('\u{F97C}', "\u{826F}"), ('\u{F97D}', "\u{8AD2}"), ('\u{F97E}', "\u{91CF}"), ('\u{F97F}', "\u{52F5}"), ('\u{F980}', "\u{5442}"),
+  ('\u{F981}', "\u{5973}"), ('\u{F982}', "\u{5EEC}"), ('\u{F983}', "\u{65C5}"), ('\u{F984}', "\u{6FFE}"), ('\u{F985}', "\u{792A}"), ('\u{F986}', "\u{95AD}"),
+  ('\u{F987}', "\u{9A6A}"), ('\u{F988}', "\u{9E97}"), ('\u{F989}', "\u{9ECE}"), ('\u{F98A}', "\u{529B}"), ('\u{F98B}', "\u{66C6}"), ('\u{F98C}', "\u{6B77}"),
+  ('\u{F98D}', "\u{8F62}"), ('\u{F98E}', "\u{5E74}"), ('\u{F98F}', "\u{6190}"), ('\u{F990}', "\u{6200}"), ('\u{F991}', "\u{649A}"), ('\u{F992}', "\u{6F23}"),
+  ('\u{F993}', "\u{7149}"), ('\u{F994}', "\u{7489}"), ('\u{F995}', "\u{79CA}"), ('\u{F996}', "\u{7DF4}"), ('\u{F997}', "\u{806F}"), ('\u{F998}', "\u{8F26}"),
+  ('\u{F999}', "\u{84EE}"), ('\u{F99A}', "\u{9023}"), ('\u{F99B}', "\u{934A}"), ('\u{F99C}', "\u{5217}"), ('\u{F99D}', "\u{52A3}"), ('\u{F99E}', "\u{54BD}"),
+  ('\u{F99F}', "\u{70C8}"), ('\u{F9A0}', "\u{88C2}"), ('\u{F9A1}', "\u{8AAA}"), ('\u{F9A2}', "\u{5EC9}"), ('\u{F9A3}', "\u{5FF5}"), ('\u{F9A4}', "\u{637B}"),
+  ('\u{F9A5}', "\u{6BAE}"), ('\u{F9A6}', "\u{7C3E}"), ('\u{F9A7}', "\u{7375}"), ('\u{F9A8}', "\u{4EE4}"), ('\u{F9A9}', "\u{56F9}"), ('\u{F9AA}', "\u{5BE7}"),
+  ('\u{F9AB}', "\u{5DBA}"), ('\u{F9AC}', "\u{601C}"), ('\u{F9AD}', "\u{73B2}"), ('\u{F9AE}', "\u{7469}"), ('\u{F9AF}', "\u{7F9A}"), ('\u{F9B0}', "\u{8046}"),
+  ('\u{F9B1}', "\u{9234}"), ('\u{F9B2}', "\u{96F6}"), ('\u{F9B3}', "\u{9748}"), ('\u{F9B4}', "\u{9818}"), ('\u{F9B5}', "\u{4F8B}"), ('\u{F9B6}', "\u{79AE}"),
+  ('\u{F9B7}', "\u{91B4}"), ('\u{F9B8}', "\u{96B8}"), ('\u{F9B9}', "\u{60E1}"), ('\u{F9BA}', "\u{4E86}"), ('\u{F9BB}', "\u{50DA}"), ('\u{F9BC}', "\u{5BEE}"),
+  ('\u{F9BD}', "\u{5C3F}"), ('\u{F9BE}', "\u{6599}"), ('\u{F9BF}', "\u{6A02}"), ('\u{F9C0}', "\u{71CE}"), ('\u{F9C1}', "\u{7642}"), ('\u{F9C2}', "\u{84FC}"),
+  ('\u{F9C3}', "\u{907C}"), ('\u{F9C4}', "\u{9F8D}"), ('\u{F9C5}', "\u{6688}"), ('\u{F9C6}', "\u{962E}"), ('\u{F9C7}', "\u{5289}"), ('\u{F9C8}', "\u{677B}"),
+  ('\u{F9C9}', "\u{67F3}"), ('\u{F9CA}', "\u{6D41}"), ('\u{F9CB}', "\u{6E9C}"), ('\u{F9CC}', "\u{7409}"), ('\u{F9CD}', "\u{7559}"), ('\u{F9CE}', "\u{786B}"),
+  ('\u{F9CF}', "\u{7D10}"), ('\u{F9D0}', "\u{985E}"), ('\u{F9D1}', "\u{516D}"), ('\u{F9D2}', "\u{622E}"), ('\u{F9D3}', "\u{9678}"), ('\u{F9D4}', "\u{502B}"),
+  ('\u{F9D5}', "\u{5D19}"), ('\u{F9D6}', "\u{6DEA}"), ('\u{F9D7}', "\u{8F2A}"), ('\u{F9D8}', "\u{5F8B}"), ('\u{F9D9}', "\u{6144}"), ('\u{F9DA}', "\u{6817}"),
+  ('\u{F9DB}', "\u{7387}"), ('\u{F9DC}', "\u{9686}"), ('\u{F9DD}', "\u{5229}"), ('\u{F9DE}', "\u{540F}"), ('\u{F9DF}', "\u{5C65}"), ('\u{F9E0}', "\u{6613}"),
+  ('\u{F9E1}', "\u{674E}"), ('\u{F9E2}', "\u{68A8}"), ('\u{F9E3}', "\u{6CE5}"), ('\u{F9E4}', "\u{7406}"), ('\u{F9E5}', "\u{75E2}"), ('\u{F9E6}', "\u{7F79}"),
+  ('\u{F9E7}', "\u{88CF}"), ('\u{F9E8}', "\u{88E1}"), ('\u{F9E9}', "\u{91CC}"), ('\u{F9EA}', "\u{96E2}"), ('\u{F9EB}', "\u{533F}"), ('\u{F9EC}', "\u{6EBA}"),
+  ('\u{F9ED}', "\u{541D}"), ('\u{F9EE}', "\u{71D0}"), ('\u{F9EF}', "\u{7498}"), ('\u{F9F0}', "\u{85FA}"), ('\u{F9F1}', "\u{96A3}"), ('\u{F9F2}', "\u{9C57}"),
+  ('\u{F9F3}', "\u{9E9F}"), ('\u{F9F4}', "\u{6797}"), ('\u{F9F5}', "\u{6DCB}"), ('\u{F9F6}', "\u{81E8}"), ('\u{F9F7}', "\u{7ACB}"), ('\u{F9F8}', "\u{7B20}"),
+  ('\u{F9F9}', "\u{7C92}"), ('\u{F9FA}', "\u{72C0}"), ('\u{F9FB}', "\u{7099}"), ('\u{F9FC}', "\u{8B58}"), ('\u{F9FD}', "\u{4EC0}"), ('\u{F9FE}', "\u{8336}"),
+  ('\u{F9FF}', "\u{523A}"), ('\u{FA00}', "\u{5207}"), ('\u{FA01}', "\u{5EA6}"), ('\u{FA02}', "\u{62D3}"), ('\u{FA03}', "\u{7CD6}"), ('\u{FA04}', "\u{5B85}"),
+  ('\u{FA05}', "\u{6D1E}"), ('\u{FA06}', "\u{66B4}"), ('\u{FA07}', "\u{8F3B}"), ('\u{FA08}', "\u{884C}"), ('\u{FA09}', "\u{964D}"), ('\u{FA0A}', "\u{898B}"),
+  ('\u{FA0B}', "\u{5ED3}"), ('\u{FA0C}', "\u{5140}"), ('\u{FA0D}', "\u{55C0}"), ('\u{FA10}', "\u{585A}"), ('\u{FA12}', "\u{6674}"), ('\u{FA15}', "\u{51DE}"),
+  ('\u{FA16}', "\u{732A}"), ('\u{FA17}', "\u{76CA}"), ('\u{FA18}', "\u{793C}"), ('\u{FA19}', "\u{795E}"), ('\u{FA1A}', "\u{7965}"), ('\u{FA1B}', "\u{798F}"),
+  ('\u{FA1C}', "\u{9756}"), ('\u{FA1D}', "\u{7CBE}"), ('\u{FA1E}', "\u{7FBD}"), ('\u{FA20}', "\u{8612}"), ('\u{FA22}', "\u{8AF8}"), ('\u{FA25}', "\u{9038}"),
+  ('\u{FA26}', "\u{90FD}"), ('\u{FA2A}', "\u{98EF}"), ('\u{FA2B}', "\u{98FC}"), ('\u{FA2C}', "\u{9928}"), ('\u{FA2D}', "\u{9DB4}"), ('\u{FA2E}', "\u{90DE}"),
+  ('\u{FA2F}', "\u{96B7}"), ('\u{FA30}', "\u{4FAE}"), ('\u{FA31}', "\u{50E7}"), ('\u{FA32}', "\u{514D}"), ('\u{FA33}', "\u{52C9}"), ('\u{FA34}', "\u{52E4}"),
+  ('\u{FA35}', "\u{5351}"), ('\u{FA36}', "\u{559D}"), ('\u{FA37}', "\u{5606}"), ('\u{FA38}', "\u{5668}"), ('\u{FA39}', "\u{5840}"), ('\u{FA3A}', "\u{58A8}"),
+  ('\u{FA3B}', "\u{5C64}"), ('\u{FA3C}', "\u{5C6E}"), ('\u{FA3D}', "\u{6094}"), ('\u{FA3E}', "\u{6168}"), ('\u{FA3F}', "\u{618E}"), ('\u{FA40}', "\u{61F2}"),
+  ('\u{FA41}', "\u{654F}"), ('\u{FA42}', "\u{65E2}"), ('\u{FA43}', "\u{6691}"), ('\u{FA44}', "\u{6885}"), ('\u{FA45}', "\u{6D77}"), ('\u{FA46}', "\u{6E1A}"),
+  ('\u{FA47}', "\u{6F22}"), ('\u{FA48}', "\u{716E}"), ('\u{FA49}', "\u{722B}"), ('\u{FA4A}', "\u{7422}"), ('\u{FA4B}', "\u{7891}"), ('\u{FA4C}', "\u{793E}"),
+  ('\u{FA4D}', "\u{7949}"), ('\u{FA4E}', "\u{7948}"), ('\u{FA4F}', "\u{7950}"), ('\u{FA50}', "\u{7956}"), ('\u{FA51}', "\u{795D}"), ('\u{FA52}', "\u{798D}"),
+  ('\u{FA53}', "\u{798E}"), ('\u{FA54}', "\u{7A40}"), ('\u{FA55}', "\u{7A81}"), ('\u{FA56}', "\u{7BC0}"), ('\u{FA57}', "\u{7DF4}"), ('\u{FA58}', "\u{7E09}"),
+  ('\u{FA59}', "\u{7E41}"), ('\u{FA5A}', "\u{7F72}"), ('\u{FA5B}', "\u{8005}"), ('\u{FA5C}', "\u{81ED}"), ('\u{FA5D}', "\u{8279}"), ('\u{FA5E}', "\u{8279}"),
+  ('\u{FA5F}', "\u{8457}"), ('\u{FA60}', "\u{8910}"), ('\u{FA61}', "\u{8996}"), ('\u{FA62}', "\u{8B01}"), ('\u{FA63}', "\u{8B39}"), ('\u{FA64}', "\u{8CD3}"),
+  ('\u{FA65}', "\u{8D08}"), ('\u{FA66}', "\u{8FB6}"), ('\u{FA67}', "\u{9038}"), ('\u{FA68}', "\u{96E3}"), ('\u{FA69}', "\u{97FF}"), ('\u{FA6A}', "\u{983B}"),
+  ('\u{FA6B}', "\u{6075}"), ('\u{FA6C}', "\u{242EE}"), ('\u{FA6D}', "\u{8218}"), ('\u{FA70}', "\u{4E26}"), ('\u{FA71}', "\u{51B5}"), ('\u{FA72}', "\u{5168}"),
+  ('\u{FA73}', "\u{4F80}"), ('\u{FA74}', "\u{5145}"), ('\u{FA75}', "\u{5180}"), ('\u{FA76}', "\u{52C7}"), ('\u{FA77}', "\u{52FA}"), ('\u{FA78}', "\u{559D}"),
+  ('\u{FA79}', "\u{5555}"), ('\u{FA7A}', "\u{5599}"), ('\u{FA7B}', "\u{55E2}"), ('\u{FA7C}', "\u{585A}"), ('\u{FA7D}', "\u{58B3}"), ('\u{FA7E}', "\u{5944}"),
+  ('\u{FA7F}', "\u{5954}"), ('\u{FA80}', "\u{5A62}"), ('\u{FA81}', "\u{5B28}"), ('\u{FA82}', "\u{5ED2}"), ('\u{FA83}', "\u{5ED9}"), ('\u{FA84}', "\u{5F69}"),
+  ('\u{FA85}', "\u{5FAD}"), ('\u{FA86}', "\u{60D8}"), ('\u{FA87}', "\u{614E}"), ('\u{FA88}', "\u{6108}"), ('\u{FA89}', "\u{618E}"), ('\u{FA8A}', "\u{6160}"),
+  ('\u{FA8B}', "\u{61F2}"), ('\u{FA8C}', "\u{6234}"), ('\u{FA8D}', "\u{63C4}"), ('\u{FA8E}', "\u{641C}"), ('\u{FA8F}', "\u{6452}"), ('\u{FA90}', "\u{6556}"),
+  ('\u{FA91}', "\u{6674}"), ('\u{FA92}', "\u{6717}"), ('\u{FA93}', "\u{671B}"), ('\u{FA94}', "\u{6756}"), ('\u{FA95}', "\u{6B79}"), ('\u{FA96}', "\u{6BBA}"),
+  ('\u{FA97}', "\u{6D41}"), ('\u{FA98}', "\u{6EDB}"), ('\u{FA99}', "\u{6ECB}"), ('\u{FA9A}', "\u{6F22}"), ('\u{FA9B}', "\u{701E}"), ('\u{FA9C}', "\u{716E}"),
+  ('\u{FA9D}', "\u{77A7}"), ('\u{FA9E}', "\u{7235}"), ('\u{FA9F}', "\u{72AF}"), ('\u{FAA0}', "\u{732A}"), ('\u{FAA1}', "\u{7471}"), ('\u{FAA2}', "\u{7506}"),
+  ('\u{FAA3}', "\u{753B}"), ('\u{FAA4}', "\u{761D}"), ('\u{FAA5}', "\u{761F}"), ('\u{FAA6}', "\u{76CA}"), ('\u{FAA7}', "\u{76DB}"), ('\u{FAA8}', "\u{76F4}"),
+  ('\u{FAA9}', "\u{774A}"), ('\u{FAAA}', "\u{7740}"), ('\u{FAAB}', "\u{78CC}"), ('\u{FAAC}', "\u{7AB1}"), ('\u{FAAD}', "\u{7BC0}"), ('\u{FAAE}', "\u{7C7B}"),
+  ('\u{FAAF}', "\u{7D5B}"), ('\u{FAB0}', "\u{7DF4}"), ('\u{FAB1}', "\u{7F3E}"), ('\u{FAB2}', "\u{8005}"), ('\u{FAB3}', "\u{8352}"), ('\u{FAB4}', "\u{83EF}"),
+  ('\u{FAB5}', "\u{8779}"), ('\u{FAB6}', "\u{8941}"), ('\u{FAB7}', "\u{8986}"), ('\u{FAB8}', "\u{8996}"), ('\u{FAB9}', "\u{8ABF}"), ('\u{FABA}', "\u{8AF8}"),
+  ('\u{FABB}', "\u{8ACB}"), ('\u{FABC}', "\u{8B01}"), ('\u{FABD}', "\u{8AFE}"), ('\u{FABE}', "\u{8AED}"), ('\u{FABF}', "\u{8B39}"), ('\u{FAC0}', "\u{8B8A}"),
+  ('\u{FAC1}', "\u{8D08}"), ('\u{FAC2}', "\u{8F38}"), ('\u{FAC3}', "\u{9072}"), ('\u{FAC4}', "\u{9199}"), ('\u{FAC5}', "\u{9276}"), ('\u{FAC6}', "\u{967C}"),
+  ('\u{FAC7}', "\u{96E3}"), ('\u{FAC8}', "\u{9756}"), ('\u{FAC9}', "\u{97DB}"), ('\u{FACA}', "\u{97FF}"), ('\u{FACB}', "\u{980B}"), ('\u{FACC}', "\u{983B}"),
+  ('\u{FACD}', "\u{9B12}"), ('\u{FACE}', "\u{9F9C}"), ('\u{FACF}', "\u{2284A}"), ('\u{FAD0}', "\u{22844}"), ('\u{FAD1}', "\u{233D5}"), ('\u{FAD2}', "\u{3B9D}"),
+  ('\u{FAD3}', "\u{4018}"), ('\u{FAD4}', "\u{4039}"), ('\u{FAD5}', "\u{25249}"), ('\u{FAD6}', "\u{25CD0}"), ('\u{FAD7}', "\u{27ED3}"), ('\u{FAD8}', "\u{9F43}"),
+  ('\u{FAD9}', "\u{9F8E}"), ('\u{FB1D}', "\u{5D9}\u{5B4}"), ('\u{FB1F}', "\u{5F2}\u{5B7}"), ('\u{FB2A}', "\u{5E9}\u{5C1}"), ('\u{FB2B}', "\u{5E9}\u{5C2}"), ('\u{FB2C}', "\u{5E9}\u{5BC}\u{5C1}"),
+  ('\u{FB2D}', "\u{5E9}\u{5BC}\u{5C2}"), ('\u{FB2E}', "\u{5D0}\u{5B7}"), ('\u{FB2F}', "\u{5D0}\u{5B8}"), ('\u{FB30}', "\u{5D0}\u{5BC}"), ('\u{FB31}', "\u{5D1}\u{5BC}"), ('\u{FB32}', "\u{5D2}\u{5BC}"),
+  ('\u{FB33}', "\u{5D3}\u{5BC}"), ('\u{FB34}', "\u{5D4}\u{5BC}"), ('\u{FB35}', "\u{5D5}\u{5BC}"), ('\u{FB36}', "\u{5D6}\u{5BC}"), ('\u{FB38}', "\u{5D8}\u{5BC}"), ('\u{FB39}', "\u{5D9}\u{5BC}"),
+  ('\u{FB3A}', "\u{5DA}\u{5BC}"), ('\u{FB3B}', "\u{5DB}\u{5BC}"), ('\u{FB3C}', "\u{5DC}\u{5BC}"), ('\u{FB3E}', "\u{5DE}\u{5BC}"), ('\u{FB40}', "\u{5E0}\u{5BC}"), ('\u{FB41}', "\u{5E1}\u{5BC}"),
+  ('\u{FB43}', "\u{5E3}\u{5BC}"), ('\u{FB44}', "\u{5E4}\u{5BC}"), ('\u{FB46}', "\u{5E6}\u{5BC}"), ('\u{FB47}', "\u{5E7}\u{5BC}"), ('\u{FB48}', "\u{5E8}\u{5BC}"), ('\u{FB49}', "\u{5E9}\u{5BC}"),
+  ('\u{FB4A}', "\u{5EA}\u{5BC}"), ('\u{FB4B}', "\u{5D5}\u{5B9}"), ('\u{FB4C}', "\u{5D1}\u{5BF}"), ('\u{FB4D}', "\u{5DB}\u{5BF}"), ('\u{FB4E}', "\u{5E4}\u{5BF}"), ('\u{1109A}', "\u{11099}\u{110BA}"),
+  ('\u{1109C}', "\u{1109B}\u{110BA}"), ('\u{110AB}', "\u{110A5}\u{110BA}"), ('\u{1112E}', "\u{11131}\u{11127}"), ('\u{1112F}', "\u{11132}\u{11127}"), ('\u{1134B}', "\u{11347}\u{1133E}"), ('\u{1134C}', "\u{11347}\u{11357}"),
+  ('\u{114BB}', "\u{114B9}\u{114BA}"), ('\u{114BC}', "\u{114B9}\u{114B0}"), ('\u{114BE}', "\u{114B9}\u{114BD}"), ('\u{115BA}', "\u{115B8}\u{115AF}"), ('\u{115BB}', "\u{115B9}\u{115AF}"), ('\u{11938}', "\u{11935}\u{11930}"),
+  ('\u{1D15E}', "\u{1D157}\u{1D165}"), ('\u{1D15F}', "\u{1D158}\u{1D165}"), ('\u{1D160}', "\u{1D158}\u{1D165}\u{1D16E}"), ('\u{1D161}', "\u{1D158}\u{1D165}\u{1D16F}"), ('\u{1D162}', "\u{1D158}\u{1D165}\u{1D170}"), ('\u{1D163}', "\u{1D158}\u{1D165}\u{1D171}"),
+  ('\u{1D164}', "\u{1D158}\u{1D165}\u{1D172}"), ('\u{1D1BB}', "\u{1D1B9}\u{1D165}"), ('\u{1D1BC}', "\u{1D1BA}\u{1D165}"), ('\u{1D1BD}', "\u{1D1B9}\u{1D165}\u{1D16E}"), ('\u{1D1BE}', "\u{1D1BA}\u{1D165}\u{1D16E}"), ('\u{1D1BF}', "\u{1D1B9}\u{1D165}\u{1D16F}"),
+  ('\u{1D1C0}', "\u{1D1BA}\u{1D165}\u{1D16F}"), ('\u{2F800}', "\u{4E3D}"), ('\u{2F801}', "\u{4E38}"), ('\u{2F802}', "\u{4E41}"), ('\u{2F803}', "\u{20122}"), ('\u{2F804}', "\u{4F60}"),
+  ('\u{2F805}', "\u{4FAE}"), ('\u{2F806}', "\u{4FBB}"), ('\u{2F807}', "\u{5002}"), ('\u{2F808}', "\u{507A}"), ('\u{2F809}', "\u{5099}"), ('\u{2F80A}', "\u{50E7}"),
+  ('\u{2F80B}', "\u{50CF}"), ('\u{2F80C}', "\u{349E}"), ('\u{2F80D}', "\u{2063A}"), ('\u{2F80E}', "\u{514D}"), ('\u{2F80F}', "\u{5154}"), ('\u{2F810}', "\u{5164}"),
+  ('\u{2F811}', "\u{5177}"), ('\u{2F812}', "\u{2051C}"), ('\u{2F813}', "\u{34B9}"), ('\u{2F814}', "\u{5167}"), ('\u{2F815}', "\u{518D}"), ('\u{2F816}', "\u{2054B}"),
+  ('\u{2F817}', "\u{5197}"), ('\u{2F818}', "\u{51A4}"), ('\u{2F819}', "\u{4ECC}"), ('\u{2F81A}', "\u{51AC}"), ('\u{2F81B}', "\u{51B5}"), ('\u{2F81C}', "\u{291DF}"),
+  ('\u{2F81D}', "\u{51F5}"), ('\u{2F81E}', "\u{5203}"), ('\u{2F81F}', "\u{34DF}"), ('\u{2F820}', "\u{523B}"), ('\u{2F821}', "\u{5246}"), ('\u{2F822}', "\u{5272}"),
+  ('\u{2F823}', "\u{5277}"), ('\u{2F824}', "\u{3515}"), ('\u{2F825}', "\u{52C7}"), ('\u{2F826}', "\u{52C9}"), ('\u{2F827}', "\u{52E4}"), ('\u{2F828}', "\u{52FA}"),
+  ('\u{2F829}', "\u{5305}"), ('\u{2F82A}', "\u{5306}"), ('\u{2F82B}', "\u{5317}"), ('\u{2F82C}', "\u{5349}"), ('\u{2F82D}', "\u{5351}"), ('\u{2F82E}', "\u{535A}"),
+  ('\u{2F82F}', "\u{5373}"), ('\u{2F830}', "\u{537D}"), ('\u{2F831}', "\u{537F}"), ('\u{2F832}', "\u{537F}"), ('\u{2F833}', "\u{537F}"), ('\u{2F834}', "\u{20A2C}"),
+  ('\u{2F835}', "\u{7070}"), ('\u{2F836}', "\u{53CA}"), ('\u{2F837}', "\u{53DF}"), ('\u{2F838}', "\u{20B63}"), ('\u{2F839}', "\u{53EB}"), ('\u{2F83A}', "\u{53F1}"),
+  ('\u{2F83B}', "\u{5406}"), ('\u{2F83C}', "\u{549E}"), ('\u{2F83D}', "\u{5438}"), ('\u{2F83E}', "\u{5448}"), ('\u{2F83F}', "\u{5468}"), ('\u{2F840}', "\u{54A2}"),
+  ('\u{2F841}', "\u{54F6}"), ('\u{2F842}', "\u{5510}"), ('\u{2F843}', "\u{5553}"), ('\u{2F844}', "\u{5563}"), ('\u{2F845}', "\u{5584}"), ('\u{2F846}', "\u{5584}"),
+  ('\u{2F847}', "\u{5599}"), ('\u{2F848}', "\u{55AB}"), ('\u{2F849}', "\u{55B3}"), ('\u{2F84A}', "\u{55C2}"), ('\u{2F84B}', "\u{5716}"), ('\u{2F84C}', "\u{5606}"),
+  ('\u{2F84D}', "\u{5717}"), ('\u{2F84E}', "\u{5651}"), ('\u{2F84F}', "\u{5674}"), ('\u{2F850}', "\u{5207}"), ('\u{2F851}', "\u{58EE}"), ('\u{2F852}', "\u{57CE}"),
+  ('\u{2F853}', "\u{57F4}"), ('\u{2F854}', "\u{580D}"), ('\u{2F855}', "\u{578B}"), ('\u{2F856}', "\u{5832}"), ('\u{2F857}', "\u{5831}"), ('\u{2F858}', "\u{58AC}"),
+  ('\u{2F859}', "\u{214E4}"), ('\u{2F85A}', "\u{58F2}"), ('\u{2F85B}', "\u{58F7}"), ('\u{2F85C}', "\u{5906}"), ('\u{2F85D}', "\u{591A}"), ('\u{2F85E}', "\u{5922}"),
+  ('\u{2F85F}', "\u{5962}"), ('\u{2F860}', "\u{216A8}"), ('\u{2F861}', "\u{216EA}"), ('\u{2F862}', "\u{59EC}"), ('\u{2F863}', "\u{5A1B}"), ('\u{2F864}', "\u{5A27}"),
+  ('\u{2F865}', "\u{59D8}"), ('\u{2F866}', "\u{5A66}"), ('\u{2F867}', "\u{36EE}"), ('\u{2F868}', "\u{36FC}"), ('\u{2F869}', "\u{5B08}"), ('\u{2F86A}', "\u{5B3E}"),
+  ('\u{2F86B}', "\u{5B3E}"), ('\u{2F86C}', "\u{219C8}"), ('\u{2F86D}', "\u{5BC3}"), ('\u{2F86E}', "\u{5BD8}"), ('\u{2F86F}', "\u{5BE7}"), ('\u{2F870}', "\u{5BF3}"),
+  ('\u{2F871}', "\u{21B18}"), ('\u{2F872}', "\u{5BFF}"), ('\u{2F873}', "\u{5C06}"), ('\u{2F874}', "\u{5F53}"), ('\u{2F875}', "\u{5C22}"), ('\u{2F876}', "\u{3781}"),
+  ('\u{2F877}', "\u{5C60}"), ('\u{2F878}', "\u{5C6E}"), ('\u{2F879}', "\u{5CC0}"), ('\u{2F87A}', "\u{5C8D}"), ('\u{2F87B}', "\u{21DE4}"), ('\u{2F87C}', "\u{5D43}"),
+  ('\u{2F87D}', "\u{21DE6}"), ('\u{2F87E}', "\u{5D6E}"), ('\u{2F87F}', "\u{5D6B}"), ('\u{2F880}', "\u{5D7C}"), ('\u{2F881}', "\u{5DE1}"), ('\u{2F882}', "\u{5DE2}"),
+  ('\u{2F883}', "\u{382F}"), ('\u{2F884}', "\u{5DFD}"), ('\u{2F885}', "\u{5E28}"), ('\u{2F886}', "\u{5E3D}"), ('\u{2F887}', "\u{5E69}"), ('\u{2F888}', "\u{3862}"),
+  ('\u{2F889}', "\u{22183}"), ('\u{2F88A}', "\u{387C}"), ('\u{2F88B}', "\u{5EB0}"), ('\u{2F88C}', "\u{5EB3}"), ('\u{2F88D}', "\u{5EB6}"), ('\u{2F88E}', "\u{5ECA}"),
+  ('\u{2F88F}', "\u{2A392}"), ('\u{2F890}', "\u{5EFE}"), ('\u{2F891}', "\u{22331}"), ('\u{2F892}', "\u{22331}"), ('\u{2F893}', "\u{8201}"), ('\u{2F894}', "\u{5F22}"),
+  ('\u{2F895}', "\u{5F22}"), ('\u{2F896}', "\u{38C7}"), ('\u{2F897}', "\u{232B8}"), ('\u{2F898}', "\u{261DA}"), ('\u{2F899}', "\u{5F62}"), ('\u{2F89A}', "\u{5F6B}"),
+  ('\u{2F89B}', "\u{38E3}"), ('\u{2F89C}', "\u{5F9A}"), ('\u{2F89D}', "\u{5FCD}"), ('\u{2F89E}', "\u{5FD7}"), ('\u{2F89F}', "\u{5FF9}"), ('\u{2F8A0}', "\u{6081}"),
+  ('\u{2F8A1}', "\u{393A}"), ('\u{2F8A2}', "\u{391C}"), ('\u{2F8A3}', "\u{6094}"), ('\u{2F8A4}', "\u{226D4}"), ('\u{2F8A5}', "\u{60C7}"), ('\u{2F8A6}', "\u{6148}"),
+  ('\u{2F8A7}', "\u{614C}"), ('\u{2F8A8}', "\u{614E}"), ('\u{2F8A9}', "\u{614C}"), ('\u{2F8AA}', "\u{617A}"), ('\u{2F8AB}', "\u{618E}"), ('\u{2F8AC}', "\u{61B2}"),
+  ('\u{2F8AD}', "\u{61A4}"), ('\u{2F8AE}', "\u{61AF}"), ('\u{2F8AF}', "\u{61DE}"), ('\u{2F8B0}', "\u{61F2}"), ('\u{2F8B1}', "\u{61F6}"), ('\u{2F8B2}', "\u{6210}"),
+  ('\u{2F8B3}', "\u{621B}"), ('\u{2F8B4}', "\u{625D}"), ('\u{2F8B5}', "\u{62B1}"), ('\u{2F8B6}', "\u{62D4}"), ('\u{2F8B7}', "\u{6350}"), ('\u{2F8B8}', "\u{22B0C}"),
+  ('\u{2F8B9}', "\u{633D}"), ('\u{2F8BA}', "\u{62FC}"), ('\u{2F8BB}', "\u{6368}"), ('\u{2F8BC}', "\u{6383}"), ('\u{2F8BD}', "\u{63E4}"), ('\u{2F8BE}', "\u{22BF1}"),
+  ('\u{2F8BF}', "\u{6422}"), ('\u{2F8C0}', "\u{63C5}"), ('\u{2F8C1}', "\u{63A9}"), ('\u{2F8C2}', "\u{3A2E}"), ('\u{2F8C3}', "\u{6469}"), ('\u{2F8C4}', "\u{647E}"),
+  ('\u{2F8C5}', "\u{649D}"), ('\u{2F8C6}', "\u{6477}"), ('\u{2F8C7}', "\u{3A6C}"), ('\u{2F8C8}', "\u{654F}"), ('\u{2F8C9}', "\u{656C}"), ('\u{2F8CA}', "\u{2300A}"),
+  ('\u{2F8CB}', "\u{65E3}"), ('\u{2F8CC}', "\u{66F8}"), ('\u{2F8CD}', "\u{6649}"), ('\u{2F8CE}', "\u{3B19}"), ('\u{2F8CF}', "\u{6691}"), ('\u{2F8D0}', "\u{3B08}"),
+  ('\u{2F8D1}', "\u{3AE4}"), ('\u{2F8D2}', "\u{5192}"), ('\u{2F8D3}', "\u{5195}"), ('\u{2F8D4}', "\u{6700}"), ('\u{2F8D5}', "\u{669C}"), ('\u{2F8D6}', "\u{80AD}"),
+  ('\u{2F8D7}', "\u{43D9}"), ('\u{2F8D8}', "\u{6717}"), ('\u{2F8D9}', "\u{671B}"), ('\u{2F8DA}', "\u{6721}"), ('\u{2F8DB}', "\u{675E}"), ('\u{2F8DC}', "\u{6753}"),
+  ('\u{2F8DD}', "\u{233C3}"), ('\u{2F8DE}', "\u{3B49}"), ('\u{2F8DF}', "\u{67FA}"), ('\u{2F8E0}', "\u{6785}"), ('\u{2F8E1}', "\u{6852}"), ('\u{2F8E2}', "\u{6885}"),
+  ('\u{2F8E3}', "\u{2346D}"), ('\u{2F8E4}', "\u{688E}"), ('\u{2F8E5}', "\u{681F}"), ('\u{2F8E6}', "\u{6914}"), ('\u{2F8E7}', "\u{3B9D}"), ('\u{2F8E8}', "\u{6942}"),
+  ('\u{2F8E9}', "\u{69A3}"), ('\u{2F8EA}', "\u{69EA}"), ('\u{2F8EB}', "\u{6AA8}"), ('\u{2F8EC}', "\u{236A3}"), ('\u{2F8ED}', "\u{6ADB}"), ('\u{2F8EE}', "\u{3C18}"),
+  ('\u{2F8EF}', "\u{6B21}"), ('\u{2F8F0}', "\u{238A7}"), ('\u{2F8F1}', "\u{6B54}"), ('\u{2F8F2}', "\u{3C4E}"), ('\u{2F8F3}', "\u{6B72}"), ('\u{2F8F4}', "\u{6B9F}"),
+  ('\u{2F8F5}', "\u{6BBA}"), ('\u{2F8F6}', "\u{6BBB}"), ('\u{2F8F7}', "\u{23A8D}"), ('\u{2F8F8}', "\u{21D0B}"), ('\u{2F8F9}', "\u{23AFA}"), ('\u{2F8FA}', "\u{6C4E}"),
+  ('\u{2F8FB}', "\u{23CBC}"), ('\u{2F8FC}', "\u{6CBF}"), ('\u{2F8FD}', "\u{6CCD}"), ('\u{2F8FE}', "\u{6C67}"), ('\u{2F8FF}', "\u{6D16}"), ('\u{2F900}', "\u{6D3E}"),
+  ('\u{2F901}', "\u{6D77}"), ('\u{2F902}', "\u{6D41}"), ('\u{2F903}', "\u{6D69}"), ('\u{2F904}', "\u{6D78}"), ('\u{2F905}', "\u{6D85}"), ('\u{2F906}', "\u{23D1E}"),
+  ('\u{2F907}', "\u{6D34}"), ('\u{2F908}', "\u{6E2F}"), ('\u{2F909}', "\u{6E6E}"), ('\u{2F90A}', "\u{3D33}"), ('\u{2F90B}', "\u{6ECB}"), ('\u{2F90C}', "\u{6EC7}"),
+  ('\u{2F90D}', "\u{23ED1}"), ('\u{2F90E}', "\u{6DF9}"), ('\u{2F90F}', "\u{6F6E}"), ('\u{2F910}', "\u{23F5E}"), ('\u{2F911}', "\u{23F8E}"), ('\u{2F912}', "\u{6FC6}"),
+  ('\u{2F913}', "\u{7039}"), ('\u{2F914}', "\u{701E}"), ('\u{2F915}', "\u{701B}"), ('\u{2F916}', "\u{3D96}"), ('\u{2F917}', "\u{704A}"), ('\u{2F918}', "\u{707D}"),
+  ('\u{2F919}', "\u{7077}"), ('\u{2F91A}', "\u{70AD}"), ('\u{2F91B}', "\u{20525}"), ('\u{2F91C}', "\u{7145}"), ('\u{2F91D}', "\u{24263}"), ('\u{2F91E}', "\u{719C}"),
+  ('\u{2F91F}', "\u{243AB}"), ('\u{2F920}', "\u{7228}"), ('\u{2F921}', "\u{7235}"), ('\u{2F922}', "\u{7250}"), ('\u{2F923}', "\u{24608}"), ('\u{2F924}', "\u{7280}"),
+  ('\u{2F925}', "\u{7295}"), ('\u{2F926}', "\u{24735}"), ('\u{2F927}', "\u{24814}"), ('\u{2F928}', "\u{737A}"), ('\u{2F929}', "\u{738B}"), ('\u{2F92A}', "\u{3EAC}"),
+  ('\u{2F92B}', "\u{73A5}"), ('\u{2F92C}', "\u{3EB8}"), ('\u{2F92D}', "\u{3EB8}"), ('\u{2F92E}', "\u{7447}"), ('\u{2F92F}', "\u{745C}"), ('\u{2F930}', "\u{7471}"),
+  ('\u{2F931}', "\u{7485}"), ('\u{2F932}', "\u{74CA}"), ('\u{2F933}', "\u{3F1B}"), ('\u{2F934}', "\u{7524}"), ('\u{2F935}', "\u{24C36}"), ('\u{2F936}', "\u{753E}"),
+  ('\u{2F937}', "\u{24C92}"), ('\u{2F938}', "\u{7570}"), ('\u{2F939}', "\u{2219F}"), ('\u{2F93A}', "\u{7610}"), ('\u{2F93B}', "\u{24FA1}"), ('\u{2F93C}', "\u{24FB8}"),
+  ('\u{2F93D}', "\u{25044}"), ('\u{2F93E}', "\u{3FFC}"), ('\u{2F93F}', "\u{4008}"), ('\u{2F940}', "\u{76F4}"), ('\u{2F941}', "\u{250F3}"), ('\u{2F942}', "\u{250F2}"),
+  ('\u{2F943}', "\u{25119}"), ('\u{2F944}', "\u{25133}"), ('\u{2F945}', "\u{771E}"), ('\u{2F946}', "\u{771F}"), ('\u{2F947}', "\u{771F}"), ('\u{2F948}', "\u{774A}"),
+  ('\u{2F949}', "\u{4039}"), ('\u{2F94A}', "\u{778B}"), ('\u{2F94B}', "\u{4046}"), ('\u{2F94C}', "\u{4096}"), ('\u{2F94D}', "\u{2541D}"), ('\u{2F94E}', "\u{784E}"),
+  ('\u{2F94F}', "\u{788C}"), ('\u{2F950}', "\u{78CC}"), ('\u{2F951}', "\u{40E3}"), ('\u{2F952}', "\u{25626}"), ('\u{2F953}', "\u{7956}"), ('\u{2F954}', "\u{2569A}"),
+  ('\u{2F955}', "\u{256C5}"), ('\u{2F956}', "\u{798F}"), ('\u{2F957}', "\u{79EB}"), ('\u{2F958}', "\u{412F}"), ('\u{2F959}', "\u{7A40}"), ('\u{2F95A}', "\u{7A4A}"),
+  ('\u{2F95B}', "\u{7A4F}"), ('\u{2F95C}', "\u{2597C}"), ('\u{2F95D}', "\u{25AA7}"), ('\u{2F95E}', "\u{25AA7}"), ('\u{2F95F}', "\u{7AEE}"), ('\u{2F960}', "\u{4202}"),
+  ('\u{2F961}', "\u{25BAB}"), ('\u{2F962}', "\u{7BC6}"), ('\u{2F963}', "\u{7BC9}"), ('\u{2F964}', "\u{4227}"), ('\u{2F965}', "\u{25C80}"), ('\u{2F966}', "\u{7CD2}"),
+  ('\u{2F967}', "\u{42A0}"), ('\u{2F968}', "\u{7CE8}"), ('\u{2F969}', "\u{7CE3}"), ('\u{2F96A}', "\u{7D00}"), ('\u{2F96B}', "\u{25F86}"), ('\u{2F96C}', "\u{7D63}"),
+  ('\u{2F96D}', "\u{4301}"), ('\u{2F96E}', "\u{7DC7}"), ('\u{2F96F}', "\u{7E02}"), ('\u{2F970}', "\u{7E45}"), ('\u{2F971}', "\u{4334}"), ('\u{2F972}', "\u{26228}"),
+  ('\u{2F973}', "\u{26247}"), ('\u{2F974}', "\u{4359}"), ('\u{2F975}', "\u{262D9}"), ('\u{2F976}', "\u{7F7A}"), ('\u{2F977}', "\u{2633E}"), ('\u{2F978}', "\u{7F95}"),
+  ('\u{2F979}', "\u{7FFA}"), ('\u{2F97A}', "\u{8005}"), ('\u{2F97B}', "\u{264DA}"), ('\u{2F97C}', "\u{26523}"), ('\u{2F97D}', "\u{8060}"), ('\u{2F97E}', "\u{265A8}"),
+  ('\u{2F97F}', "\u{8070}"), ('\u{2F980}', "\u{2335F}"), ('\u{2F981}', "\u{43D5}"), ('\u{2F982}', "\u{80B2}"), ('\u{2F983}', "\u{8103}"), ('\u{2F984}', "\u{440B}"),
+  ('\u{2F985}', "\u{813E}"), ('\u{2F986}', "\u{5AB5}"), ('\u{2F987}', "\u{267A7}"), ('\u{2F988}', "\u{267B5}"), ('\u{2F989}', "\u{23393}"), ('\u{2F98A}', "\u{2339C}"),
+  ('\u{2F98B}', "\u{8201}"), ('\u{2F98C}', "\u{8204}"), ('\u{2F98D}', "\u{8F9E}"), ('\u{2F98E}', "\u{446B}"), ('\u{2F98F}', "\u{8291}"), ('\u{2F990}', "\u{828B}"),
+  ('\u{2F991}', "\u{829D}"), ('\u{2F992}', "\u{52B3}"), ('\u{2F993}', "\u{82B1}"), ('\u{2F994}', "\u{82B3}"), ('\u{2F995}', "\u{82BD}"), ('\u{2F996}', "\u{82E6}"),
+  ('\u{2F997}', "\u{26B3C}"), ('\u{2F998}', "\u{82E5}"), ('\u{2F999}', "\u{831D}"), ('\u{2F99A}', "\u{8363}"), ('\u{2F99B}', "\u{83AD}"), ('\u{2F99C}', "\u{8323}"),
+  ('\u{2F99D}', "\u{83BD}"), ('\u{2F99E}', "\u{83E7}"), ('\u{2F99F}', "\u{8457}"), ('\u{2F9A0}', "\u{8353}"), ('\u{2F9A1}', "\u{83CA}"), ('\u{2F9A2}', "\u{83CC}"),
+  ('\u{2F9A3}', "\u{83DC}"), ('\u{2F9A4}', "\u{26C36}"), ('\u{2F9A5}', "\u{26D6B}"), ('\u{2F9A6}', "\u{26CD5}"), ('\u{2F9A7}', "\u{452B}"), ('\u{2F9A8}', "\u{84F1}"),
+  ('\u{2F9A9}', "\u{84F3}"), ('\u{2F9AA}', "\u{8516}"), ('\u{2F9AB}', "\u{273CA}"), ('\u{2F9AC}', "\u{8564}"), ('\u{2F9AD}', "\u{26F2C}"), ('\u{2F9AE}', "\u{455D}"),
+  ('\u{2F9AF}', "\u{4561}"), ('\u{2F9B0}', "\u{26FB1}"), ('\u{2F9B1}', "\u{270D2}"), ('\u{2F9B2}', "\u{456B}"), ('\u{2F9B3}', "\u{8650}"), ('\u{2F9B4}', "\u{865C}"),
+  ('\u{2F9B5}', "\u{8667}"), ('\u{2F9B6}', "\u{8669}"), ('\u{2F9B7}', "\u{86A9}"), ('\u{2F9B8}', "\u{8688}"), ('\u{2F9B9}', "\u{870E}"), ('\u{2F9BA}', "\u{86E2}"),
+  ('\u{2F9BB}', "\u{8779}"), ('\u{2F9BC}', "\u{8728}"), ('\u{2F9BD}', "\u{876B}"), ('\u{2F9BE}', "\u{8786}"), ('\u{2F9BF}', "\u{45D7}"), ('\u{2F9C0}', "\u{87E1}"),
+  ('\u{2F9C1}', "\u{8801}"), ('\u{2F9C2}', "\u{45F9}"), ('\u{2F9C3}', "\u{8860}"), ('\u{2F9C4}', "\u{8863}"), ('\u{2F9C5}', "\u{27667}"), ('\u{2F9C6}', "\u{88D7}"),
+  ('\u{2F9C7}', "\u{88DE}"), ('\u{2F9C8}', "\u{4635}"), ('\u{2F9C9}', "\u{88FA}"), ('\u{2F9CA}', "\u{34BB}"), ('\u{2F9CB}', "\u{278AE}"), ('\u{2F9CC}', "\u{27966}"),
+  ('\u{2F9CD}', "\u{46BE}"), ('\u{2F9CE}', "\u{46C7}"), ('\u{2F9CF}', "\u{8AA0}"), ('\u{2F9D0}', "\u{8AED}"), ('\u{2F9D1}', "\u{8B8A}"), ('\u{2F9D2}', "\u{8C55}"),
+  ('\u{2F9D3}', "\u{27CA8}"), ('\u{2F9D4}', "\u{8CAB}"), ('\u{2F9D5}', "\u{8CC1}"), ('\u{2F9D6}', "\u{8D1B}"), ('\u{2F9D7}', "\u{8D77}"), ('\u{2F9D8}', "\u{27F2F}"),
+  ('\u{2F9D9}', "\u{20804}"), ('\u{2F9DA}', "\u{8DCB}"), ('\u{2F9DB}', "\u{8DBC}"), ('\u{2F9DC}', "\u{8DF0}"), ('\u{2F9DD}', "\u{208DE}"), ('\u{2F9DE}', "\u{8ED4}"),
+  ('\u{2F9DF}', "\u{8F38}"), ('\u{2F9E0}', "\u{285D2}"), ('\u{2F9E1}', "\u{285ED}"), ('\u{2F9E2}', "\u{9094}"), ('\u{2F9E3}', "\u{90F1}"), ('\u{2F9E4}', "\u{9111}"),
+  ('\u{2F9E5}', "\u{2872E}"), ('\u{2F9E6}', "\u{911B}"), ('\u{2F9E7}', "\u{9238}"), ('\u{2F9E8}', "\u{92D7}"), ('\u{2F9E9}', "\u{92D8}"), ('\u{2F9EA}', "\u{927C}"),
+  ('\u{2F9EB}', "\u{93F9}"), ('\u{2F9EC}', "\u{9415}"), ('\u{2F9ED}', "\u{28BFA}"), ('\u{2F9EE}', "\u{958B}"), ('\u{2F9EF}', "\u{4995}"), ('\u{2F9F0}', "\u{95B7}"),
+  ('\u{2F9F1}', "\u{28D77}"), ('\u{2F9F2}', "\u{49E6}"), ('\u{2F9F3}', "\u{96C3}"), ('\u{2F9F4}', "\u{5DB2}"), ('\u{2F9F5}', "\u{9723}"), ('\u{2F9F6}', "\u{29145}"),
+  ('\u{2F9F7}', "\u{2921A}"), ('\u{2F9F8}', "\u{4A6E}"), ('\u{2F9F9}', "\u{4A76}"), ('\u{2F9FA}', "\u{97E0}"), ('\u{2F9FB}', "\u{2940A}"), ('\u{2F9FC}', "\u{4AB2}"),
+  ('\u{2F9FD}', "\u{29496}"), ('\u{2F9FE}', "\u{980B}"), ('\u{2F9FF}', "\u{980B}"), ('\u{2FA00}', "\u{9829}"), ('\u{2FA01}', "\u{295B6}"), ('\u{2FA02}', "\u{98E2}"),
+  ('\u{2FA03}', "\u{4B33}"), ('\u{2FA04}', "\u{9929}"), ('\u{2FA05}', "\u{99A7}"), ('\u{2FA06}', "\u{99C2}"), ('\u{2FA07}', "\u{99FE}"), ('\u{2FA08}', "\u{4BCE}"),
+  ('\u{2FA09}', "\u{29B30}"), ('\u{2FA0A}', "\u{9B12}"), ('\u{2FA0B}', "\u{9C40}"), ('\u{2FA0C}', "\u{9CFD}"), ('\u{2FA0D}', "\u{4CCE}"), ('\u{2FA0E}', "\u{4CED}"),
+  ('\u{2FA0F}', "\u{9D67}"), ('\u{2FA10}', "\u{2A0CE}"), ('\u{2FA11}', "\u{4CF8}"), ('\u{2FA12}', "\u{2A105}"), ('\u{2FA13}', "\u{2A20E}"), ('\u{2FA14}', "\u{2A291}"),
+  ('\u{2FA15}', "\u{9EBB}"), ('\u{2FA16}', "\u{4D56}"), ('\u{2FA17}', "\u{9EF9}"), ('\u{2FA18}', "\u{9EFE}"), ('\u{2FA19}', "\u{9F05}"), ('\u{2FA1A}', "\u{9F0F}"),
+  ('\u{2FA1B}', "\u{9F16}"), ('\u{2FA1C}', "\u{9F3B}"), ('\u{2FA1D}', "\u{2A600}"),
+];
+
+/// 非零的规范组合类, 按区间合并
+pub(crate) const COMBINING_CLASS: &[(char, char, u8)] = &[
+  ('\u{300}', '\u{314}', 230), ('\u{315}', '\u{315}', 232), ('\u{316}', '\u{319}', 220), ('\u{31A}', '\u{31A}', 232), ('\u{31B}', '\u{31B}', 216), ('\u{31C}', '\u{320}', 220),
+  ('\u{321}', '\u{322}', 202), ('\u{323}', '\u{326}', 220), ('\u{327}', '\u{328}', 202), ('\u{329}', '\u{333}', 220), ('\u{334}', '\u{338}', 1), ('\u{339}', '\u{33C}', 220),
+  ('\u{33D}', '\u{344}', 230), ('\u{345}', '\u{345}', 240), ('\u{346}', '\u{346}', 230), ('\u{347}', '\u{349}', 220), ('\u{34A}', '\u{34C}', 230), ('\u{34D}', '\u{34E}', 220),
+  ('\u{350}', '\u{352}', 230), ('\u{353}', '\u{356}', 220), ('\u{357}', '\u{357}', 230), ('\u{358}', '\u{358}', 232), ('\u{359}', '\u{35A}', 220), ('\u{35B}', '\u{35B}', 230),
+  ('\u{35C}', '\u{35C}', 233), ('\u{35D}', '\u{35E}', 234), ('\u{35F}', '\u{35F}', 233), ('\u{360}', '\u{361}', 234), ('\u{362}', '\u{362}', 233), ('\u{363}', '\u{36F}', 230),
+  ('\u{483}', '\u{487}', 230), ('\u{591}', '\u{591}', 220), ('\u{592}', '\u{595}', 230), ('\u{596}', '\u{596}', 220), ('\u{597}', '\u{599}', 230), ('\u{59A}', '\u{59A}', 222),
+  ('\u{59B}', '\u{59B}', 220), ('\u{59C}', '\u{5A1}', 230), ('\u{5A2}', '\u{5A7}', 220), ('\u{5A8}', '\u{5A9}', 230), ('\u{5AA}', '\u{5AA}', 220), ('\u{5AB}', '\u{5AC}', 230),
+  ('\u{5AD}', '\u{5AD}', 222), ('\u{5AE}', '\u{5AE}', 228), ('\u{5AF}', '\u{5AF}', 230), ('\u{5B0}', '\u{5B0}', 10), ('\u{5B1}', '\u{5B1}', 11), ('\u{5B2}', '\u{5B2}', 12),
+  ('\u{5B3}', '\u{5B3}', 13), ('\u{5B4}', '\u{5B4}', 14), ('\u{5B5}', '\u{5B5}', 15), ('\u{5B6}', '\u{5B6}', 16), ('\u{5B7}', '\u{5B7}', 17), ('\u{5B8}', '\u{5B8}', 18),
+  ('\u{5B9}', '\u{5BA}', 19), ('\u{5BB}', '\u{5BB}', 20), ('\u{5BC}', '\u{5BC}', 21), ('\u{5BD}', '\u{5BD}', 22), ('\u{5BF}', '\u{5BF}', 23), ('\u{5C1}', '\u{5C1}', 24),
+  ('\u{5C2}', '\u{5C2}', 25), ('\u{5C4}', '\u{5C4}', 230), ('\u{5C5}', '\u{5C5}', 220), ('\u{5C7}', '\u{5C7}', 18), ('\u{610}', '\u{617}', 230), ('\u{618}', '\u{618}', 30),
+  ('\u{619}', '\u{619}', 31), ('\u{61A}', '\u{61A}', 32), ('\u{64B}', '\u{64B}', 27), ('\u{64C}', '\u{64C}', 28), ('\u{64D}', '\u{64D}', 29), ('\u{64E}', '\u{64E}', 30),
+  ('\u{64F}', '\u{64F}', 31), ('\u{650}', '\u{650}', 32), ('\u{651}', '\u{651}', 33), ('\u{652}', '\u{652}', 34), ('\u{653}', '\u{654}', 230), ('\u{655}', '\u{656}', 220),
+  ('\u{657}', '\u{65B}', 230), ('\u{65C}', '\u{65C}', 220), ('\u{65D}', '\u{65E}', 230), ('\u{65F}', '\u{65F}', 220), ('\u{670}', '\u{670}', 35), ('\u{6D6}', '\u{6DC}', 230),
+  ('\u{6DF}', '\u{6E2}', 230), ('\u{6E3}', '\u{6E3}', 220), ('\u{6E4}', '\u{6E4}', 230), ('\u{6E7}', '\u{6E8}', 230), ('\u{6EA}', '\u{6EA}', 220), ('\u{6EB}', '\u{6EC}', 230),
+  ('\u{6ED}', '\u{6ED}', 220), ('\u{711}', '\u{711}', 36), ('\u{730}', '\u{730}', 230), ('\u{731}', '\u{731}', 220), ('\u{732}', '\u{733}', 230), ('\u{734}', '\u{734}', 220),
+  ('\u{735}', '\u{736}', 230), ('\u{737}', '\u{739}', 220), ('\u{73A}', '\u{73A}', 230), ('\u{73B}', '\u{73C}', 220), ('\u{73D}', '\u{73D}', 230), ('\u{73E}', '\u{73E}', 220),
+  ('\u{73F}', '\u{741}', 230), ('\u{742}', '\u{742}', 220), ('\u{743}', '\u{743}', 230), ('\u{744}', '\u{744}', 220), ('\u{745}', '\u{745}', 230), ('\u{746}', '\u{746}', 220),
+  ('\u{747}', '\u{747}', 230), ('\u{748}', '\u{748}', 220), ('\u{749}', '\u{74A}', 230), ('\u{7EB}', '\u{7F1}', 230), ('\u{7F2}', '\u{7F2}', 220), ('\u{7F3}', '\u{7F3}', 230),
+  ('\u{7FD}', '\u{7FD}', 220), ('\u{816}', '\u{819}', 230), ('\u{81B}', '\u{823}', 230), ('\u{825}', '\u{827}', 230), ('\u{829}', '\u{82D}', 230), ('\u{859}', '\u{85B}', 220),
+  ('\u{898}', '\u{898}', 230), ('\u{899}', '\u{89B}', 220), ('\u{89C}', '\u{89F}', 230), ('\u{8CA}', '\u{8CE}', 230), ('\u{8CF}', '\u{8D3}', 220), ('\u{8D4}', '\u{8E1}', 230),
+  ('\u{8E3}', '\u{8E3}', 220), ('\u{8E4}', '\u{8E5}', 230), ('\u{8E6}', '\u{8E6}', 220), ('\u{8E7}', '\u{8E8}', 230), ('\u{8E9}', '\u{8E9}', 220), ('\u{8EA}', '\u{8EC}', 230),
+  ('\u{8ED}', '\u{8EF}', 220), ('\u{8F0}', '\u{8F0}', 27), ('\u{8F1}', '\u{8F1}', 28), ('\u{8F2}', '\u{8F2}', 29), ('\u{8F3}', '\u{8F5}', 230), ('\u{8F6}', '\u{8F6}', 220),
+  ('\u{8F7}', '\u{8F8}', 230), ('\u{8F9}', '\u{8FA}', 220), ('\u{8FB}', '\u{8FF}', 230), ('\u{93C}', '\u{93C}', 7), ('\u{94D}', '\u{94D}', 9), ('\u{951}', '\u{951}', 230),
+  ('\u{952}', '\u{952}', 220), ('\u{953}', '\u{954}', 230), ('\u{9BC}', '\u{9BC}', 7), ('\u{9CD}', '\u{9CD}', 9), ('\u{9FE}', '\u{9FE}', 230), ('\u{A3C}', '\u{A3C}', 7),
+  ('\u{A4D}', '\u{A4D}', 9), ('\u{ABC}', '\u{ABC}', 7), ('\u{ACD}', '\u{ACD}', 9), ('\u{B3C}', '\u{B3C}', 7), ('\u{B4D}', '\u{B4D}', 9), ('\u{BCD}', '\u{BCD}', 9),
+  ('\u{C3C}', '\u{C3C}', 7), ('\u{C4D}', '\u{C4D}', 9), ('\u{C55}', '\u{C55}', 84), ('\u{C56}', '\u{C56}', 91), ('\u{CBC}', '\u{CBC}', 7), ('\u{CCD}', '\u{CCD}', 9),
+  ('\u{D3B}', '\u{D3C}', 9), ('\u{D4D}', '\u{D4D}', 9), ('\u{DCA}', '\u{DCA}', 9), ('\u{E38}', '\u{E39}', 103), ('\u{E3A}', '\u{E3A}', 9), ('\u{E48}', '\u{E4B}', 107),
+  ('\u{EB8}', '\u{EB9}', 118), ('\u{EBA}', '\u{EBA}', 9), ('\u{EC8}', '\u{ECB}', 122), ('\u{F18}', '\u{F19}', 220), ('\u{F35}', '\u{F35}', 220), ('\u{F37}', '\u{F37}', 220),
+  ('\u{F39}', '\u{F39}', 216), ('\u{F71}', '\u{F71}', 129), ('\u{F72}', '\u{F72}', 130), ('\u{F74}', '\u{F74}', 132), ('\u{F7A}', '\u{F7D}', 130), ('\u{F80}', '\u{F80}', 130),
+  ('\u{F82}', '\u{F83}', 230), ('\u{F84}', '\u{F84}', 9), ('\u{F86}', '\u{F87}', 230), ('\u{FC6}', '\u{FC6}', 220), ('\u{1037}', '\u{1037}', 7), ('\u{1039}', '\u{103A}', 9),
+  ('\u{108D}', '\u{108D}', 220), ('\u{135D}', '\u{135F}', 230), ('\u{1714}', '\u{1715}', 9), ('\u{1734}', '\u{1734}', 9), ('\u{17D2}', '\u{17D2}', 9), ('\u{17DD}', '\u{17DD}', 230),
+  ('\u{18A9}', '\u{18A9}', 228), ('\u{1939}', '\u{1939}', 222), ('\u{193A}', '\u{193A}', 230), ('\u{193B}', '\u{193B}', 220), ('\u{1A17}', '\u{1A17}', 230), ('\u{1A18}', '\u{1A18}', 220),
+  ('\u{1A60}', '\u{1A60}', 9), ('\u{1A75}', '\u{1A7C}', 230), ('\u{1A7F}', '\u{1A7F}', 220), ('\u{1AB0}', '\u{1AB4}', 230), ('\u{1AB5}', '\u{1ABA}', 220), ('\u{1ABB}', '\u{1ABC}', 230),
+  ('\u{1ABD}', '\u{1ABD}', 220), ('\u{1ABF}', '\u{1AC0}', 220), ('\u{1AC1}', '\u{1AC2}', 230), ('\u{1AC3}', '\u{1AC4}', 220), ('\u{1AC5}', '\u{1AC9}', 230), ('\u{1ACA}', '\u{1ACA}', 220),
+  ('\u{1ACB}', '\u{1ACE}', 230), ('\u{1B34}', '\u{1B34}', 7), ('\u{1B44}', '\u{1B44}', 9), ('\u{1B6B}', '\u{1B6B}', 230), ('\u{1B6C}', '\u{1B6C}', 220), ('\u{1B6D}', '\u{1B73}', 230),
+  ('\u{1BAA}', '\u{1BAB}', 9), ('\u{1BE6}', '\u{1BE6}', 7), ('\u{1BF2}', '\u{1BF3}', 9), ('\u{1C37}', '\u{1C37}', 7), ('\u{1CD0}', '\u{1CD2}', 230), ('\u{1CD4}', '\u{1CD4}', 1),
+  ('\u{1CD5}', '\u{1CD9}', 220), ('\u{1CDA}', '\u{1CDB}', 230), ('\u{1CDC}', '\u{1CDF}', 220), ('\u{1CE0}', '\u{1CE0}', 230), ('\u{1CE2}', '\u{1CE8}', 1), ('\u{1CED}', '\u{1CED}', 220),
+  ('\u{1CF4}', '\u{1CF4}', 230), ('\u{1CF8}', '\u{1CF9}', 230), ('\u{1DC0}', '\u{1DC1}', 230), ('\u{1DC2}', '\u{1DC2}', 220), ('\u{1DC3}', '\u{1DC9}', 230), ('\u{1DCA}', '\u{1DCA}', 220),
+  ('\u{1DCB}', '\u{1DCC}', 230), ('\u{1DCD}', '\u{1DCD}', 234), ('\u{1DCE}', '\u{1DCE}', 214), ('\u{1DCF}', '\u{1DCF}', 220), ('\u{1DD0}', '\u{1DD0}', 202), ('\u{1DD1}', '\u{1DF5}', 230),
+  ('\u{1DF6}', '\u{1DF6}', 232), ('\u{1DF7}', '\u{1DF8}', 228), ('\u{1DF9}', '\u{1DF9}', 220), ('\u{1DFA}', '\u{1DFA}', 218), ('\u{1DFB}', '\u{1DFB}', 230), ('\u{1DFC}', '\u{1DFC}', 233),
+  ('\u{1DFD}', '\u{1DFD}', 220), ('\u{1DFE}', '\u{1DFE}', 230), ('\u{1DFF}', '\u{1DFF}', 220), ('\u{20D0}', '\u{20D1}', 230), ('\u{20D2}', '\u{20D3}', 1), ('\u{20D4}', '\u{20D7}', 230),
+  ('\u{20D8}', '\u{20DA}', 1), ('\u{20DB}', '\u{20DC}', 230), ('\u{20E1}', '\u{20E1}', 230), ('\u{20E5}', '\u{20E6}', 1), ('\u{20E7}', '\u{20E7}', 230), ('\u{20E8}', '\u{20E8}', 220),
+  ('\u{20E9}', '\u{20E9}', 230), ('\u{20EA}', '\u{20EB}', 1), ('\u{20EC}', '\u{20EF}', 220), ('\u{20F0}', '\u{20F0}', 230), ('\u{2CEF}', '\u{2CF1}', 230), ('\u{2D7F}', '\u{2D7F}', 9),
+  ('\u{2DE0}', '\u{2DFF}', 230), ('\u{302A}', '\u{302A}', 218), ('\u{302B}', '\u{302B}', 228), ('\u{302C}', '\u{302C}', 232), ('\u{302D}', '\u{302D}', 222), ('\u{302E}', '\u{302F}', 224),
+  ('\u{3099}', '\u{309A}', 8), ('\u{A66F}', '\u{A66F}', 230), ('\u{A674}', '\u{A67D}', 230), ('\u{A69E}', '\u{A69F}', 230), ('\u{A6F0}', '\u{A6F1}', 230), ('\u{A806}', '\u{A806}', 9),
+  ('\u{A82C}', '\u{A82C}', 9), ('\u{A8C4}', '\u{A8C4}', 9), ('\u{A8E0}', '\u{A8F1}', 230), ('\u{A92B}', '\u{A92D}', 220), ('\u{A953}', '\u{A953}', 9), ('\u{A9B3}', '\u{A9B3}', 7),
+  ('\u{A9C0}', '\u{A9C0}', 9), ('\u{AAB0}', '\u{AAB0}', 230), ('\u{AAB2}', '\u{AAB3}', 230), ('\u{AAB4}', '\u{AAB4}', 220), ('\u{AAB7}', '\u{AAB8}', 230), ('\u{AABE}', '\u{AABF}', 230),
+  ('\u{AAC1}', '\u{AAC1}', 230), ('\u{AAF6}', '\u{AAF6}', 9), ('\u{ABED}', '\u{ABED}', 9), ('\u{FB1E}', '\u{FB1E}', 26), ('\u{FE20}', '\u{FE26}', 230), ('\u{FE27}', '\u{FE2D}', 220),
+  ('\u{FE2E}', '\u{FE2F}', 230), ('\u{101FD}', '\u{101FD}', 220), ('\u{102E0}', '\u{102E0}', 220), ('\u{10376}', '\u{1037A}', 230), ('\u{10A0D}', '\u{10A0D}', 220), ('\u{10A0F}', '\u{10A0F}', 230),
+  ('\u{10A38}', '\u{10A38}', 230), ('\u{10A39}', '\u{10A39}', 1), ('\u{10A3A}', '\u{10A3A}', 220), ('\u{10A3F}', '\u{10A3F}', 9), ('\u{10AE5}', '\u{10AE5}', 230), ('\u{10AE6}', '\u{10AE6}', 220),
+  ('\u{10D24}', '\u{10D27}', 230), ('\u{10EAB}', '\u{10EAC}', 230), ('\u{10F46}', '\u{10F47}', 220), ('\u{10F48}', '\u{10F4A}', 230), ('\u{10F4B}', '\u{10F4B}', 220), ('\u{10F4C}', '\u{10F4C}', 230),
+  ('\u{10F4D}', '\u{10F50}', 220), ('\u{10F82}', '\u{10F82}', 230), ('\u{10F83}', '\u{10F83}', 220), ('\u{10F84}', '\u{10F84}', 230), ('\u{10F85}', '\u{10F85}', 220), ('\u{11046}', '\u{11046}', 9),
+  ('\u{11070}', '\u{11070}', 9), ('\u{1107F}', '\u{1107F}', 9), ('\u{110B9}', '\u{110B9}', 9), ('\u{110BA}', '\u{110BA}', 7), ('\u{11100}', '\u{11102}', 230), ('\u{11133}', '\u{11134}', 9),
+  ('\u{11173}', '\u{11173}', 7), ('\u{111C0}', '\u{111C0}', 9), ('\u{111CA}', '\u{111CA}', 7), ('\u{11235}', '\u{11235}', 9), ('\u{11236}', '\u{11236}', 7), ('\u{112E9}', '\u{112E9}', 7),
+  ('\u{112EA}', '\u{112EA}', 9), ('\u{1133B}', '\u{1133C}', 7), ('\u{1134D}', '\u{1134D}', 9), ('\u{11366}', '\u{1136C}', 230), ('\u{11370}', '\u{11374}', 230), ('\u{11442}', '\u{11442}', 9),
+  ('\u{11446}', '\u{11446}', 7), ('\u{1145E}', '\u{1145E}', 230), ('\u{114C2}', '\u{114C2}', 9), ('\u{114C3}', '\u{114C3}', 7), ('\u{115BF}', '\u{115BF}', 9), ('\u{115C0}', '\u{115C0}', 7),
+  ('\u{1163F}', '\u{1163F}', 9), ('\u{116B6}', '\u{116B6}', 9), ('\u{116B7}', '\u{116B7}', 7), ('\u{1172B}', '\u{1172B}', 9), ('\u{11839}', '\u{11839}', 9), ('\u{1183A}', '\u{1183A}', 7),
+  ('\u{1193D}', '\u{1193E}', 9), ('\u{11943}', '\u{11943}', 7), ('\u{119E0}', '\u{119E0}', 9), ('\u{11A34}', '\u{11A34}', 9), ('\u{11A47}', '\u{11A47}', 9), ('\u{11A99}', '\u{11A99}', 9),
+  ('\u{11C3F}', '\u{11C3F}', 9), ('\u{11D42}', '\u{11D42}', 7), ('\u{11D44}', '\u{11D45}', 9), ('\u{11D97}', '\u{11D97}', 9), ('\u{16AF0}', '\u{16AF4}', 1), ('\u{16B30}', '\u{16B36}', 230),
+  ('\u{16FF0}', '\u{16FF1}', 6), ('\u{1BC9E}', '\u{1BC9E}', 1), ('\u{1D165}', '\u{1D166}', 216), ('\u{1D167}', '\u{1D169}', 1), ('\u{1D16D}', '\u{1D16D}', 226), ('\u{1D16E}', '\u{1D172}', 216),
+  ('\u{1D17B}', '\u{1D182}', 220), ('\u{1D185}', '\u{1D189}', 230), ('\u{1D18A}', '\u{1D18B}', 220), ('\u{1D1AA}', '\u{1D1AD}', 230), ('\u{1D242}', '\u{1D244}', 230), ('\u{1E000}', '\u{1E006}', 230),
+  ('\u{1E008}', '\u{1E018}', 230), ('\u{1E01B}', '\u{1E021}', 230), ('\u{1E023}', '\u{1E024}', 230), ('\u{1E026}', '\u{1E02A}', 230), ('\u{1E130}', '\u{1E136}', 230), ('\u{1E2AE}', '\u{1E2AE}', 230),
+  ('\u{1E2EC}', '\u{1E2EF}', 230), ('\u{1E8D0}', '\u{1E8D6}', 220), ('\u{1E944}', '\u{1E949}', 230), ('\u{1E94A}', '\u{1E94A}', 7),
+];
+
+/// 规范组合: (起始字符, 组合字符) -> 组合结果
+pub(crate) const COMPOSITION: &[(char, char, char)] = &[
+  ('\u{3C}', '\u{338}', '\u{226E}'), ('\u{3D}', '\u{338}', '\u{2260}'), ('\u{3E}', '\u{338}', '\u{226F}'), ('\u{41}', '\u{300}', '\u{C0}'), ('\u{41}', '\u{301}', '\u{C1}'), ('\u{41}', '\u{302}', '\u{C2}'),
+  ('\u{41}', '\u{303}', '\u{C3}'), ('\u{41}', '\u{304}', '\u{100}'), ('\u{41}', '\u{306}', '\u{102}'), ('\u{41}', '\u{307}', '\u{226}'), ('\u{41}', '\u{308}', '\u{C4}'), ('\u{41}', '\u{309}', '\u{1EA2}'),
+  ('\u{41}', '\u{30A}', '\u{C5}'), ('\u{41}', '\u{30C}', '\u{1CD}'), ('\u{41}', '\u{30F}', '\u{200}'), ('\u{41}', '\u{311}', '\u{202}'), ('\u{41}', '\u{323}', '\u{1EA0}'), ('\u{41}', '\u{325}', '\u{1E00}'),
+  ('\u{41}', '\u{328}', '\u{104}'), ('\u{42}', '\u{307}', '\u{1E02}'), ('\u{42}', '\u{323}', '\u{1E04}'), ('\u{42}', '\u{331}', '\u{1E06}'), ('\u{43}', '\u{301}', '\u{106}'), ('\u{43}', '\u{302}', '\u{108}'),
+  ('\u{43}', '\u{307}', '\u{10A}'), ('\u{43}', '\u{30C}', '\u{10C}'), ('\u{43}', '\u{327}', '\u{C7}'), ('\u{44}', '\u{307}', '\u{1E0A}'), ('\u{44}', '\u{30C}', '\u{10E}'), ('\u{44}', '\u{323}', '\u{1E0C}'),
+  ('\u{44}', '\u{327}', '\u{1E10}'), ('\u{44}', '\u{32D}', '\u{1E12}'), ('\u{44}', '\u{331}', '\u{1E0E}'), ('\u{45}', '\u{300}', '\u{C8}'), ('\u{45}', '\u{301}', '\u{C9}'), ('\u{45}', '\u{302}', '\u{CA}'),
+  ('\u{45}', '\u{303}', '\u{1EBC}'), ('\u{45}', '\u{304}', '\u{112}'), ('\u{45}', '\u{306}', '\u{114}'), ('\u{45}', '\u{307}', '\u{116}'), ('\u{45}', '\u{308}', '\u{CB}'), ('\u{45}', '\u{309}', '\u{1EBA}'),
+  ('\u{45}', '\u{30C}', '\u{11A}'), ('\u{45}', '\u{30F}', '\u{204}'), ('\u{45}', '\u{311}', '\u{206}'), ('\u{45}', '\u{323}', '\u{1EB8}'), ('\u{45}', '\u{327}', '\u{228}'), ('\u{45}', '\u{328}', '\u{118}'),
+  ('\u{45}', '\u{32D}', '\u{1E18}'), ('\u{45}', '\u{330}', '\u{1E1A}'), ('\u{46}', '\u{307}', '\u{1E1E}'), ('\u{47}', '\u{301}', '\u{1F4}'), ('\u{47}', '\u{302}', '\u{11C}'), ('\u{47}', '\u{304}', '\u{1E20}'),
+  ('\u{47}', '\u{306}', '\u{11E}'), ('\u{47}', '\u{307}', '\u{120}'), ('\u{47}', '\u{30C}', '\u{1E6}'), ('\u{47}', '\u{327}', '\u{122}'), ('\u{48}', '\u{302}', '\u{124}'), ('\u{48}', '\u{307}', '\u{1E22}'),
+  ('\u{48}', '\u{308}', '\u{1E26}'), ('\u{48}', '\u{30C}', '\u{21E}'), ('\u{48}', '\u{323}', '\u{1E24}'), ('\u{48}', '\u{327}', '\u{1E28}'), ('\u{48}', '\u{32E}', '\u{1E2A}'), ('\u{49}', '\u{300}', '\u{CC}'),
+  ('\u{49}', '\u{301}', '\u{CD}'), ('\u{49}', '\u{302}', '\u{CE}'), ('\u{49}', '\u{303}', '\u{128}'), ('\u{49}', '\u{304}', '\u{12A}'), ('\u{49}', '\u{306}', '\u{12C}'), ('\u{49}', '\u{307}', '\u{130}'),
+  ('\u{49}', '\u{308}', '\u{CF}'), ('\u{49}', '\u{309}', '\u{1EC8}'), ('\u{49}', '\u{30C}', '\u{1CF}'), ('\u{49}', '\u{30F}', '\u{208}'), ('\u{49}', '\u{311}', '\u{20A}'), ('\u{49}', '\u{323}', '\u{1ECA}'),
+  ('\u{49}', '\u{328}', '\u{12E}'), ('\u{49}', '\u{330}', '\u{1E2C}'), ('\u{4A}', '\u{302}', '\u{134}'), ('\u{4B}', '\u{301}', '\u{1E30}'), ('\u{4B}', '\u{30C}', '\u{1E8}'), ('\u{4B}', '\u{323}', '\u{1E32}'),
+  ('\u{4B}', '\u{327}', '\u{136}'), ('\u{4B}', '\u{331}', '\u{1E34}'), ('\u{4C}', '\u{301}', '\u{139}'), ('\u{4C}', '\u{30C}', '\u{13D}'), ('\u{4C}', '\u{323}', '\u{1E36}'), ('\u{4C}', '\u{327}', '\u{13B}'),
+  ('\u{4C}', '\u{32D}', '\u{1E3C}'), ('\u{4C}', '\u{331}', '\u{1E3A}'), ('\u{4D}', '\u{301}', '\u{1E3E}'), ('\u{4D}', '\u{307}', '\u{1E40}'), ('\u{4D}', '\u{323}', '\u{1E42}'), ('\u{4E}', '\u{300}', '\u{1F8}'),
+  ('\u{4E}', '\u{301}', '\u{143}'), ('\u{4E}', '\u{303}', '\u{D1}'), ('\u{4E}', '\u{307}', '\u{1E44}'), ('\u{4E}', '\u{30C}', '\u{147}'), ('\u{4E}', '\u{323}', '\u{1E46}'), ('\u{4E}', '\u{327}', '\u{145}'),
+  ('\u{4E}', '\u{32D}', '\u{1E4A}'), ('\u{4E}', '\u{331}', '\u{1E48}'), ('\u{4F}', '\u{300}', '\u{D2}'), ('\u{4F}', '\u{301}', '\u{D3}'), ('\u{4F}', '\u{302}', '\u{D4}'), ('\u{4F}', '\u{303}', '\u{D5}'),
+  ('\u{4F}', '\u{304}', '\u{14C}'), ('\u{4F}', '\u{306}', '\u{14E}'), ('\u{4F}', '\u{307}', '\u{22E}'), ('\u{4F}', '\u{308}', '\u{D6}'), ('\u{4F}', '\u{309}', '\u{1ECE}'), ('\u{4F}', '\u{30B}', '\u{150}'),
+  ('\u{4F}', '\u{30C}', '\u{1D1}'), ('\u{4F}', '\u{30F}', '\u{20C}'), ('\u{4F}', '\u{311}', '\u{20E}'), ('\u{4F}', '\u{31B}', '\u{1A0}'), ('\u{4F}', '\u{323}', '\u{1ECC}'), ('\u{4F}', '\u{328}', '\u{1EA}'),
+  ('\u{50}', '\u{301}', '\u{1E54}'), ('\u{50}', '\u{307}', '\u{1E56}'), ('\u{52}', '\u{301}', '\u{154}'), ('\u{52}', '\u{307}', '\u{1E58}'), ('\u{52}', '\u{30C}', '\u{158}'), ('\u{52}', '\u{30F}', '\u{210}'),
+  ('\u{52}', '\u{311}', '\u{212}'), ('\u{52}', '\u{323}', '\u{1E5A}'), ('\u{52}', '\u{327}', '\u{156}'), ('\u{52}', '\u{331}', '\u{1E5E}'), ('\u{53}', '\u{301}', '\u{15A}'), ('\u{53}', '\u{302}', '\u{15C}'),
+  ('\u{53}', '\u{307}', '\u{1E60}'), ('\u{53}', '\u{30C}', '\u{160}'), ('\u{53}', '\u{323}', '\u{1E62}'), ('\u{53}', '\u{326}', '\u{218}'), ('\u{53}', '\u{327}', '\u{15E}'), ('\u{54}', '\u{307}', '\u{1E6A}'),
+  ('\u{54}', '\u{30C}', '\u{164}'), ('\u{54}', '\u{323}', '\u{1E6C}'), ('\u{54}', '\u{326}', '\u{21A}'), ('\u{54}', '\u{327}', '\u{162}'), ('\u{54}', '\u{32D}', '\u{1E70}'), ('\u{54}', '\u{331}', '\u{1E6E}'),
+  ('\u{55}', '\u{300}', '\u{D9}'), ('\u{55}', '\u{301}', '\u{DA}'), ('\u{55}', '\u{302}', '\u{DB}'), ('\u{55}', '\u{303}', '\u{168}'), ('\u{55}', '\u{304}', '\u{16A}'), ('\u{55}', '\u{306}', '\u{16C}'),
+  ('\u{55}', '\u{308}', '\u{DC}'), ('\u{55}', '\u{309}', '\u{1EE6}'), ('\u{55}', '\u{30A}', '\u{16E}'), ('\u{55}', '\u{30B}', '\u{170}'), ('\u{55}', '\u{30C}', '\u{1D3}'), ('\u{55}', '\u{30F}', '\u{214}'),
+  ('\u{55}', '\u{311}', '\u{216}'), ('\u{55}', '\u{31B}', '\u{1AF}'), ('\u{55}', '\u{323}', '\u{1EE4}'), ('\u{55}', '\u{324}', '\u{1E72}'), ('\u{55}', '\u{328}', '\u{172}'), ('\u{55}', '\u{32D}', '\u{1E76}'),
+  ('\u{55}', '\u{330}', '\u{1E74}'), ('\u{56}', '\u{303}', '\u{1E7C}'), ('\u{56}', '\u{323}', '\u{1E7E}'), ('\u{57}', '\u{300}', '\u{1E80}'), ('\u{57}', '\u{301}', '\u{1E82}'), ('\u{57}', '\u{302}', '\u{174}'),
+  ('\u{57}', '\u{307}', '\u{1E86}'), ('\u{57}', '\u{308}', '\u{1E84}'), ('\u{57}', '\u{323}', '\u{1E88}'), ('\u{58}', '\u{307}', '\u{1E8A}'), ('\u{58}', '\u{308}', '\u{1E8C}'), ('\u{59}', '\u{300}', '\u{1EF2}'),
+  ('\u{59}', '\u{301}', '\u{DD}'), ('\u{59}', '\u{302}', '\u{176}'), ('\u{59}', '\u{303}', '\u{1EF8}'), ('\u{59}', '\u{304}', '\u{232}'), ('\u{59}', '\u{307}', '\u{1E8E}'), ('\u{59}', '\u{308}', '\u{178}'),
+  ('\u{59}', '\u{309}', '\u{1EF6}'), ('\u{59}', '\u{323}', '\u{1EF4}'), ('\u{5A}', '\u{301}', '\u{179}'), ('\u{5A}', '\u{302}', '\u{1E90}'), ('\u{5A}', '\u{307}', '\u{17B}'), ('\u{5A}', '\u{30C}', '\u{17D}'),
+  ('\u{5A}', '\u{323}', '\u{1E92}'), ('\u{5A}', '\u{331}', '\u{1E94}'), ('\u{61}', '\u{300}', '\u{E0}'), ('\u{61}', '\u{301}', '\u{E1}'), ('\u{61}', '\u{302}', '\u{E2}'), ('\u{61}', '\u{303}', '\u{E3}'),
+  ('\u{61}', '\u{304}', '\u{101}'), ('\u{61}', '\u{306}', '\u{103}'), ('\u{61}', '\u{307}', '\u{227}'), ('\u{61}', '\u{308}', '\u{E4}'), ('\u{61}', '\u{309}', '\u{1EA3}'), ('\u{61}', '\u{30A}', '\u{E5}'),
+  ('\u{61}', '\u{30C}', '\u{1CE}'), ('\u{61}', '\u{30F}', '\u{201}'), ('\u{61}', '\u{311}', '\u{203}'), ('\u{61}', '\u{323}', '\u{1EA1}'), ('\u{61}', '\u{325}', '\u{1E01}'), ('\u{61}', '\u{328}', '\u{105}'),
+  ('\u{62}', '\u{307}', '\u{1E03}'), ('\u{62}', '\u{323}', '\u{1E05}'), ('\u{62}', '\u{331}', '\u{1E07}'), ('\u{63}', '\u{301}', '\u{107}'), ('\u{63}', '\u{302}', '\u{109}'), ('\u{63}', '\u{307}', '\u{10B}'),
+  ('\u{63}', '\u{30C}', '\u{10D}'), ('\u{63}', '\u{327}', '\u{E7}'), ('\u{64}', '\u{307}', '\u{1E0B}'), ('\u{64}', '\u{30C}', '\u{10F}'), ('\u{64}', '\u{323}', '\u{1E0D}'), ('\u{64}', '\u{327}', '\u{1E11}'),
+  ('\u{64}', '\u{32D}', '\u{1E13}'), ('\u{64}', '\u{331}', '\u{1E0F}'), ('\u{65}', '\u{300}', '\u{E8}'), ('\u{65}', '\u{301}', '\u{E9}'), ('\u{65}', '\u{302}', '\u{EA}'), ('\u{65}', '\u{303}', '\u{1EBD}'),
+  ('\u{65}', '\u{304}', '\u{113}'), ('\u{65}', '\u{306}', '\u{115}'), ('\u{65}', '\u{307}', '\u{117}'), ('\u{65}', '\u{308}', '\u{EB}'), ('\u{65}', '\u{309}', '\u{1EBB}'), ('\u{65}', '\u{30C}', '\u{11B}'),
+  ('\u{65}', '\u{30F}', '\u{205}'), ('\u{65}', '\u{311}', '\u{207}'), ('\u{65}', '\u{323}', '\u{1EB9}'), ('\u{65}', '\u{327}', '\u{229}'), ('\u{65}', '\u{328}', '\u{119}'), ('\u{65}', '\u{32D}', '\u{1E19}'),
+  ('\u{65}', '\u{330}', '\u{1E1B}'), ('\u{66}', '\u{307}', '\u{1E1F}'), ('\u{67}', '\u{301}', '\u{1F5}'), ('\u{67}', '\u{302}', '\u{11D}'), ('\u{67}', '\u{304}', '\u{1E21}'), ('\u{67}', '\u{306}', '\u{11F}'),
+  ('\u{67}', '\u{307}', '\u{121}'), ('\u{67}', '\u{30C}', '\u{1E7}'), ('\u{67}', '\u{327}', '\u{123}'), ('\u{68}', '\u{302}', '\u{125}'), ('\u{68}', '\u{307}', '\u{1E23}'), ('\u{68}', '\u{308}', '\u{1E27}'),
+  ('\u{68}', '\u{30C}', '\u{21F}'), ('\u{68}', '\u{323}', '\u{1E25}'), ('\u{68}', '\u{327}', '\u{1E29}'), ('\u{68}', '\u{32E}', '\u{1E2B}'), ('\u{68}', '\u{331}', '\u{1E96}'), ('\u{69}', '\u{300}', '\u{EC}'),
+  ('\u{69}', '\u{301}', '\u{ED}'), ('\u{69}', '\u{302}', '\u{EE}'), ('\u{69}', '\u{303}', '\u{129}'), ('\u{69}', '\u{304}', '\u{12B}'), ('\u{69}', '\u{306}', '\u{12D}'), ('\u{69}', '\u{308}', '\u{EF}'),
+  ('\u{69}', '\u{309}', '\u{1EC9}'), ('\u{69}', '\u{30C}', '\u{1D0}'), ('\u{69}', '\u{30F}', '\u{209}'), ('\u{69}', '\u{311}', '\u{20B}'), ('\u{69}', '\u{323}', '\u{1ECB}'), ('\u{69}', '\u{328}', '\u{12F}'),
+  ('\u{69}', '\u{330}', '\u{1E2D}'), ('\u{6A}', '\u{302}', '\u{135}'), ('\u{6A}', '\u{30C}', '\u{1F0}'), ('\u{6B}', '\u{301}', '\u{1E31}'), ('\u{6B}', '\u{30C}', '\u{1E9}'), ('\u{6B}', '\u{323}', '\u{1E33}'),
+  ('\u{6B}', '\u{327}', '\u{137}'), ('\u{6B}', '\u{331}', '\u{1E35}'), ('\u{6C}', '\u{301}', '\u{13A}'), ('\u{6C}', '\u{30C}', '\u{13E}'), ('\u{6C}', '\u{323}', '\u{1E37}'), ('\u{6C}', '\u{327}', '\u{13C}'),
+  ('\u{6C}', '\u{32D}', '\u{1E3D}'), ('\u{6C}', '\u{331}', '\u{1E3B}'), ('\u{6D}', '\u{301}', '\u{1E3F}'), ('\u{6D}', '\u{307}', '\u{1E41}'), ('\u{6D}', '\u{323}', '\u{1E43}'), ('\u{6E}', '\u{300}', '\u{1F9}'),
+  ('\u{6E}', '\u{301}', '\u{144}'), ('\u{6E}', '\u{303}', '\u{F1}'), ('\u{6E}', '\u{307}', '\u{1E45}'), ('\u{6E}', '\u{30C}', '\u{148}'), ('\u{6E}', '\u{323}', '\u{1E47}'), ('\u{6E}', '\u{327}', '\u{146}'),
+  ('\u{6E}', '\u{32D}', '\u{1E4B}'), ('\u{6E}', '\u{331}', '\u{1E49}'), ('\u{6F}', '\u{300}', '\u{F2}'), ('\u{6F}', '\u{301}', '\u{F3}'), ('\u{6F}', '\u{302}', '\u{F4}'), ('\u{6F}', '\u{303}', '\u{F5}'),
+  ('\u{6F}', '\u{304}', '\u{14D}'), ('\u{6F}', '\u{306}', '\u{14F}'), ('\u{6F}', '\u{307}', '\u{22F}'), ('\u{6F}', '\u{308}', '\u{F6}'), ('\u{6F}', '\u{309}', '\u{1ECF}'), ('\u{6F}', '\u{30B}', '\u{151}'),
+  ('\u{6F}', '\u{30C}', '\u{1D2}'), ('\u{6F}', '\u{30F}', '\u{20D}'), ('\u{6F}', '\u{311}', '\u{20F}'), ('\u{6F}', '\u{31B}', '\u{1A1}'), ('\u{6F}', '\u{323}', '\u{1ECD}'), ('\u{6F}', '\u{328}', '\u{1EB}'),
+  ('\u{70}', '\u{301}', '\u{1E55}'), ('\u{70}', '\u{307}', '\u{1E57}'), ('\u{72}', '\u{301}', '\u{155}'), ('\u{72}', '\u{307}', '\u{1E59}'), ('\u{72}', '\u{30C}', '\u{159}'), ('\u{72}', '\u{30F}', '\u{211}'),
+  ('\u{72}', '\u{311}', '\u{213}'), ('\u{72}', '\u{323}', '\u{1E5B}'), ('\u{72}', '\u{327}', '\u{157}'), ('\u{72}', '\u{331}', '\u{1E5F}'), ('\u{73}', '\u{301}', '\u{15B}'), ('\u{73}', '\u{302}', '\u{15D}'),
+  ('\u{73}', '\u{307}', '\u{1E61}'), ('\u{73}', '\u{30C}', '\u{161}'), ('\u{73}', '\u{323}', '\u{1E63}'), ('\u{73}', '\u{326}', '\u{219}'), ('\u{73}', '\u{327}', '\u{15F}'), ('\u{74}', '\u{307}', '\u{1E6B}'),
+  ('\u{74}', '\u{308}', '\u{1E97}'), ('\u{74}', '\u{30C}', '\u{165}'), ('\u{74}', '\u{323}', '\u{1E6D}'), ('\u{74}', '\u{326}', '\u{21B}'), ('\u{74}', '\u{327}', '\u{163}'), ('\u{74}', '\u{32D}', '\u{1E71}'),
+  ('\u{74}', '\u{331}', '\u{1E6F}'), ('\u{75}', '\u{300}', '\u{F9}'), ('\u{75}', '\u{301}', '\u{FA}'), ('\u{75}', '\u{302}', '\u{FB}'), ('\u{75}', '\u{303}', '\u{169}'), ('\u{75}', '\u{304}', '\u{16B}'),
+  ('\u{75}', '\u{306}', '\u{16D}'), ('\u{75}', '\u{308}', '\u{FC}'), ('\u{75}', '\u{309}', '\u{1EE7}'), ('\u{75}', '\u{30A}', '\u{16F}'), ('\u{75}', '\u{30B}', '\u{171}'), ('\u{75}', '\u{30C}', '\u{1D4}'),
+  ('\u{75}', '\u{30F}', '\u{215}'), ('\u{75}', '\u{311}', '\u{217}'), ('\u{75}', '\u{31B}', '\u{1B0}'), ('\u{75}', '\u{323}', '\u{1EE5}'), ('\u{75}', '\u{324}', '\u{1E73}'), ('\u{75}', '\u{328}', '\u{173}'),
+  ('\u{75}', '\u{32D}', '\u{1E77}'), ('\u{75}', '\u{330}', '\u{1E75}'), ('\u{76}', '\u{303}', '\u{1E7D}'), ('\u{76}', '\u{323}', '\u{1E7F}'), ('\u{77}', '\u{300}', '\u{1E81}'), ('\u{77}', '\u{301}', '\u{1E83}'),
+  ('\u{77}', '\u{302}', '\u{175}'), ('\u{77}', '\u{307}', '\u{1E87}'), ('\u{77}', '\u{308}', '\u{1E85}'), ('\u{77}', '\u{30A}', '\u{1E98}'), ('\u{77}', '\u{323}', '\u{1E89}'), ('\u{78}', '\u{307}', '\u{1E8B}'),
+  ('\u{78}', '\u{308}', '\u{1E8D}'), ('\u{79}', '\u{300}', '\u{1EF3}'), ('\u{79}', '\u{301}', '\u{FD}'), ('\u{79}', '\u{302}', '\u{177}'), ('\u{79}', '\u{303}', '\u{1EF9}'), ('\u{79}', '\u{304}', '\u{233}'),
+  ('\u{79}', '\u{307}', '\u{1E8F}'), ('\u{79}', '\u{308}', '\u{FF}'), ('\u{79}', '\u{309}', '\u{1EF7}'), ('\u{79}', '\u{30A}', '\u{1E99}'), ('\u{79}', '\u{323}', '\u{1EF5}'), ('\u{7A}', '\u{301}', '\u{17A}'),
+  ('\u{7A}', '\u{302}', '\u{1E91}'), ('\u{7A}', '\u{307}', '\u{17C}'), ('\u{7A}', '\u{30C}', '\u{17E}'), ('\u{7A}', '\u{323}', '\u{1E93}'), ('\u{7A}', '\u{331}', '\u{1E95}'), ('\u{A8}', '\u{300}', '\u{1FED}'),
+  ('\u{A8}', '\u{301}', '\u{385}'), ('\u{A8}', '\u{342}', '\u{1FC1}'), ('\u{C2}', '\u{300}', '\u{1EA6}'), ('\u{C2}', '\u{301}', '\u{1EA4}'), ('\u{C2}', '\u{303}', '\u{1EAA}'), ('\u{C2}', '\u{309}', '\u{1EA8}'),
+  ('\u{C4}', '\u{304}', '\u{1DE}'), ('\u{C5}', '\u{301}', '\u{1FA}'), ('\u{C6}', '\u{301}', '\u{1FC}'), ('\u{C6}', '\u{304}', '\u{1E2}'), ('\u{C7}', '\u{301}', '\u{1E08}'), ('\u{CA}', '\u{300}', '\u{1EC0}'),
+  ('\u{CA}', '\u{301}', '\u{1EBE}'), ('\u{CA}', '\u{303}', '\u{1EC4}'), ('\u{CA}', '\u{309}', '\u{1EC2}'), ('\u{CF}', '\u{301}', '\u{1E2E}'), ('\u{D4}', '\u{300}', '\u{1ED2}'), ('\u{D4}', '\u{301}', '\u{1ED0}'),
+  ('\u{D4}', '\u{303}', '\u{1ED6}'), ('\u{D4}', '\u{309}', '\u{1ED4}'), ('\u{D5}', '\u{301}', '\u{1E4C}'), ('\u{D5}', '\u{304}', '\u{22C}'), ('\u{D5}', '\u{308}', '\u{1E4E}'), ('\u{D6}', '\u{304}', '\u{22A}'),
+  ('\u{D8}', '\u{301}', '\u{1FE}'), ('\u{DC}', '\u{300}', '\u{1DB}'), ('\u{DC}', '\u{301}', '\u{1D7}'), ('\u{DC}', '\u{304}', '\u{1D5}'), ('\u{DC}', '\u{30C}', '\u{1D9}'), ('\u{E2}', '\u{300}', '\u{1EA7}'),
+  ('\u{E2}', '\u{301}', '\u{1EA5}'), ('\u{E2}', '\u{303}', '\u{1EAB}'), ('\u{E2}', '\u{309}', '\u{1EA9}'), ('\u{E4}', '\u{304}', '\u{1DF}'), ('\u{E5}', '\u{301}', '\u{1FB}'), ('\u{E6}', '\u{301}', '\u{1FD}'),
+  ('\u{E6}', '\u{304}', '\u{1E3}'), ('\u{E7}', '\u{301}', '\u{1E09}'), ('\u{EA}', '\u{300}', '\u{1EC1}'), ('\u{EA}', '\u{301}', '\u{1EBF}'), ('\u{EA}', '\u{303}', '\u{1EC5}'), ('\u{EA}', '\u{309}', '\u{1EC3}'),
+  ('\u{EF}', '\u{301}', '\u{1E2F}'), ('\u{F4}', '\u{300}', '\u{1ED3}'), ('\u{F4}', '\u{301}', '\u{1ED1}'), ('\u{F4}', '\u{303}', '\u{1ED7}'), ('\u{F4}', '\u{309}', '\u{1ED5}'), ('\u{F5}', '\u{301}', '\u{1E4D}'),
+  ('\u{F5}', '\u{304}', '\u{22D}'), ('\u{F5}', '\u{308}', '\u{1E4F}'), ('\u{F6}', '\u{304}', '\u{22B}'), ('\u{F8}', '\u{301}', '\u{1FF}'), ('\u{FC}', '\u{300}', '\u{1DC}'), ('\u{FC}', '\u{301}', '\u{1D8}'),
+  ('\u{FC}', '\u{304}', '\u{1D6}'), ('\u{FC}', '\u{30C}', '\u{1DA}'), ('\u{102}', '\u{300}', '\u{1EB0}'), ('\u{102}', '\u{301}', '\u{1EAE}'), ('\u{102}', '\u{303}', '\u{1EB4}'), ('\u{102}', '\u{309}', '\u{1EB2}'),
+  ('\u{103}', '\u{300}', '\u{1EB1}'), ('\u{103}', '\u{301}', '\u{1EAF}'), ('\u{103}', '\u{303}', '\u{1EB5}'), ('\u{103}', '\u{309}', '\u{1EB3}'), ('\u{112}', '\u{300}', '\u{1E14}'), ('\u{112}', '\u{301}', '\u{1E16}'),
+  ('\u{113}', '\u{300}', '\u{1E15}'), ('\u{113}', '\u{301}', '\u{1E17}'), ('\u{14C}', '\u{300}', '\u{1E50}'), ('\u{14C}', '\u{301}', '\u{1E52}'), ('\u{14D}', '\u{300}', '\u{1E51}'), ('\u{14D}', '\u{301}', '\u{1E53}'),
+  ('\u{15A}', '\u{307}', '\u{1E64}'), ('\u{15B}', '\u{307}', '\u{1E65}'), ('\u{160}', '\u{307}', '\u{1E66}'), ('\u{161}', '\u{307}', '\u{1E67}'), ('\u{168}', '\u{301}', '\u{1E78}'), ('\u{169}', '\u{301}', '\u{1E79}'),
+  ('\u{16A}', '\u{308}', '\u{1E7A}'), ('\u{16B}', '\u{308}', '\u{1E7B}'), ('\u{17F}', '\u{307}', '\u{1E9B}'), ('\u{1A0}', '\u{300}', '\u{1EDC}'), ('\u{1A0}', '\u{301}', '\u{1EDA}'), ('\u{1A0}', '\u{303}', '\u{1EE0}'),
+  ('\u{1A0}', '\u{309}', '\u{1EDE}'), ('\u{1A0}', '\u{323}', '\u{1EE2}'), ('\u{1A1}', '\u{300}', '\u{1EDD}'), ('\u{1A1}', '\u{301}', '\u{1EDB}'), ('\u{1A1}', '\u{303}', '\u{1EE1}'), ('\u{1A1}', '\u{309}', '\u{1EDF}'),
+  ('\u{1A1}', '\u{323}', '\u{1EE3}'), ('\u{1AF}', '\u{300}', '\u{1EEA}'), ('\u{1AF}', '\u{301}', '\u{1EE8}'), ('\u{1AF}', '\u{303}', '\u{1EEE}'), ('\u{1AF}', '\u{309}', '\u{1EEC}'), ('\u{1AF}', '\u{323}', '\u{1EF0}'),
+  ('\u{1B0}', '\u{300}', '\u{1EEB}'), ('\u{1B0}', '\u{301}', '\u{1EE9}'), ('\u{1B0}', '\u{303}', '\u{1EEF}'), ('\u{1B0}', '\u{309}', '\u{1EED}'), ('\u{1B0}', '\u{323}', '\u{1EF1}'), ('\u{1B7}', '\u{30C}', '\u{1EE}'),
+  ('\u{1EA}', '\u{304}', '\u{1EC}'), ('\u{1EB}', '\u{304}', '\u{1ED}'), ('\u{226}', '\u{304}', '\u{1E0}'), ('\u{227}', '\u{304}', '\u{1E1}'), ('\u{228}', '\u{306}', '\u{1E1C}'), ('\u{229}', '\u{306}', '\u{1E1D}'),
+  ('\u{22E}', '\u{304}', '\u{230}'), ('\u{22F}', '\u{304}', '\u{231}'), ('\u{292}', '\u{30C}', '\u{1EF}'), ('\u{391}', '\u{300}', '\u{1FBA}'), ('\u{391}', '\u{301}', '\u{386}'), ('\u{391}', '\u{304}', '\u{1FB9}'),
+  ('\u{391}', '\u{306}', '\u{1FB8}'), ('\u{391}', '\u{313}', '\u{1F08}'), ('\u{391}', '\u{314}', '\u{1F09}'), ('\u{391}', '\u{345}', '\u{1FBC}'), ('\u{395}', '\u{300}', '\u{1FC8}'), ('\u{395}', '\u{301}', '\u{388}'),
+  ('\u{395}', '\u{313}', '\u{1F18}'), ('\u{395}', '\u{314}', '\u{1F19}'), ('\u{397}', '\u{300}', '\u{1FCA}'), ('\u{397}', '\u{301}', '\u{389}'), ('\u{397}', '\u{313}', '\u{1F28}'), ('\u{397}', '\u{314}', '\u{1F29}'),
+  ('\u{397}', '\u{345}', '\u{1FCC}'), ('\u{399}', '\u{300}', '\u{1FDA}'), ('\u{399}', '\u{301}', '\u{38A}'), ('\u{399}', '\u{304}', '\u{1FD9}'), ('\u{399}', '\u{306}', '\u{1FD8}'), ('\u{399}', '\u{308}', '\u{3AA}'),
+  ('\u{399}', '\u{313}', '\u{1F38}'), ('\u{399}', '\u{314}', '\u{1F39}'), ('\u{39F}', '\u{300}', '\u{1FF8}'), ('\u{39F}', '\u{301}', '\u{38C}'), ('\u{39F}', '\u{313}', '\u{1F48}'), ('\u{39F}', '\u{314}', '\u{1F49}'),
+  ('\u{3A1}', '\u{314}', '\u{1FEC}'), ('\u{3A5}', '\u{300}', '\u{1FEA}'), ('\u{3A5}', '\u{301}', '\u{38E}'), ('\u{3A5}', '\u{304}', '\u{1FE9}'), ('\u{3A5}', '\u{306}', '\u{1FE8}'), ('\u{3A5}', '\u{308}', '\u{3AB}'),
+  ('\u{3A5}', '\u{314}', '\u{1F59}'), ('\u{3A9}', '\u{300}', '\u{1FFA}'), ('\u{3A9}', '\u{301}', '\u{38F}'), ('\u{3A9}', '\u{313}', '\u{1F68}'), ('\u{3A9}', '\u{314}', '\u{1F69}'), ('\u{3A9}', '\u{345}', '\u{1FFC}'),
+  ('\u{3AC}', '\u{345}', '\u{1FB4}'), ('\u{3AE}', '\u{345}', '\u{1FC4}'), ('\u{3B1}', '\u{300}', '\u{1F70}'), ('\u{3B1}', '\u{301}', '\u{3AC}'), ('\u{3B1}', '\u{304}', '\u{1FB1}'), ('\u{3B1}', '\u{306}', '\u{1FB0}'),
+  ('\u{3B1}', '\u{313}', '\u{1F00}'), ('\u{3B1}', '\u{314}', '\u{1F01}'), ('\u{3B1}', '\u{342}', '\u{1FB6}'), ('\u{3B1}', '\u{345}', '\u{1FB3}'), ('\u{3B5}', '\u{300}', '\u{1F72}'), ('\u{3B5}', '\u{301}', '\u{3AD}'),
+  ('\u{3B5}', '\u{313}', '\u{1F10}'), ('\u{3B5}', '\u{314}', '\u{1F11}'), ('\u{3B7}', '\u{300}', '\u{1F74}'), ('\u{3B7}', '\u{301}', '\u{3AE}'), ('\u{3B7}', '\u{313}', '\u{1F20}'), ('\u{3B7}', '\u{314}', '\u{1F21}'),
+  ('\u{3B7}', '\u{342}', '\u{1FC6}'), ('\u{3B7}', '\u{345}', '\u{1FC3}'), ('\u{3B9}', '\u{300}', '\u{1F76}'), ('\u{3B9}', '\u{301}', '\u{3AF}'), ('\u{3B9}', '\u{304}', '\u{1FD1}'), ('\u{3B9}', '\u{306}', '\u{1FD0}'),
+  ('\u{3B9}', '\u{308}', '\u{3CA}'), ('\u{3B9}', '\u{313}', '\u{1F30}'), ('\u{3B9}', '\u{314}', '\u{1F31}'), ('\u{3B9}', '\u{342}', '\u{1FD6}'), ('\u{3BF}', '\u{300}', '\u{1F78}'), ('\u{3BF}', '\u{301}', '\u{3CC}'),
+  ('\u{3BF}', '\u{313}', '\u{1F40}'), ('\u{3BF}', '\u{314}', '\u{1F41}'), ('\u{3C1}', '\u{313}', '\u{1FE4}'), ('\u{3C1}', '\u{314}', '\u{1FE5}'), ('\u{3C5}', '\u{300}', '\u{1F7A}'), ('\u{3C5}', '\u{301}', '\u{3CD}'),
+  ('\u{3C5}', '\u{304}', '\u{1FE1}'), ('\u{3C5}', '\u{306}', '\u{1FE0}'), ('\u{3C5}', '\u{308}', '\u{3CB}'), ('\u{3C5}', '\u{313}', '\u{1F50}'), ('\u{3C5}', '\u{314}', '\u{1F51}'), ('\u{3C5}', '\u{342}', '\u{1FE6}'),
+  ('\u{3C9}', '\u{300}', '\u{1F7C}'), ('\u{3C9}', '\u{301}', '\u{3CE}'), ('\u{3C9}', '\u{313}', '\u{1F60}'), ('\u{3C9}', '\u{314}', '\u{1F61}'), ('\u{3C9}', '\u{342}', '\u{1FF6}'), ('\u{3C9}', '\u{345}', '\u{1FF3}'),
+  ('\u{3CA}', '\u{300}', '\u{1FD2}'), ('\u{3CA}', '\u{301}', '\u{390}'), ('\u{3CA}', '\u{342}', '\u{1FD7}'), ('\u{3CB}', '\u{300}', '\u{1FE2}'), ('\u{3CB}', '\u{301}', '\u{3B0}'), ('\u{3CB}', '\u{342}', '\u{1FE7}'),
+  ('\u{3CE}', '\u{345}', '\u{1FF4}'), ('\u{3D2}', '\u{301}', '\u{3D3}'), ('\u{3D2}', '\u{308}', '\u{3D4}'), ('\u{406}', '\u{308}', '\u{407}'), ('\u{410}', '\u{306}', '\u{4D0}'), ('\u{410}', '\u{308}', '\u{4D2}'),
+  ('\u{413}', '\u{301}', '\u{403}'), ('\u{415}', '\u{300}', '\u{400}'), ('\u{415}', '\u{306}', '\u{4D6}'), ('\u{415}', '\u{308}', '\u{401}'), ('\u{416}', '\u{306}', '\u{4C1}'), ('\u{416}', '\u{308}', '\u{4DC}'),
+  ('\u{417}', '\u{308}', '\u{4DE}'), ('\u{418}', '\u{300}', '\u{40D}'), ('\u{418}', '\u{304}', '\u{4E2}'), ('\u{418}', '\u{306}', '\u{419}'), ('\u{418}', '\u{308}', '\u{4E4}'), ('\u{41A}', '\u{301}', '\u{40C}'),
+  ('\u{41E}', '\u{308}', '\u{4E6}'), ('\u{423}', '\u{304}', '\u{4EE}'), ('\u{423}', '\u{306}', '\u{40E}'), ('\u{423}', '\u{308}', '\u{4F0}'), ('\u{423}', '\u{30B}', '\u{4F2}'), ('\u{427}', '\u{308}', '\u{4F4}'),
+  ('\u{42B}', '\u{308}', '\u{4F8}'), ('\u{42D}', '\u{308}', '\u{4EC}'), ('\u{430}', '\u{306}', '\u{4D1}'), ('\u{430}', '\u{308}', '\u{4D3}'), ('\u{433}', '\u{301}', '\u{453}'), ('\u{435}', '\u{300}', '\u{450}'),
+  ('\u{435}', '\u{306}', '\u{4D7}'), ('\u{435}', '\u{308}', '\u{451}'), ('\u{436}', '\u{306}', '\u{4C2}'), ('\u{436}', '\u{308}', '\u{4DD}'), ('\u{437}', '\u{308}', '\u{4DF}'), ('\u{438}', '\u{300}', '\u{45D}'),
+  ('\u{438}', '\u{304}', '\u{4E3}'), ('\u{438}', '\u{306}', '\u{439}'), ('\u{438}', '\u{308}', '\u{4E5}'), ('\u{43A}', '\u{301}', '\u{45C}'), ('\u{43E}', '\u{308}', '\u{4E7}'), ('\u{443}', '\u{304}', '\u{4EF}'),
+  ('\u{443}', '\u{306}', '\u{45E}'), ('\u{443}', '\u{308}', '\u{4F1}'), ('\u{443}', '\u{30B}', '\u{4F3}'), ('\u{447}', '\u{308}', '\u{4F5}'), ('\u{44B}', '\u{308}', '\u{4F9}'), ('\u{44D}', '\u{308}', '\u{4ED}'),
+  ('\u{456}', '\u{308}', '\u{457}'), ('\u{474}', '\u{30F}', '\u{476}'), ('\u{475}', '\u{30F}', '\u{477}'), ('\u{4D8}', '\u{308}', '\u{4DA}'), ('\u{4D9}', '\u{308}', '\u{4DB}'), ('\u{4E8}', '\u{308}', '\u{4EA}'),
+  ('\u{4E9}', '\u{308}', '\u{4EB}'), ('\u{627}', '\u{653}', '\u{622}'), ('\u{627}', '\u{654}', '\u{623}'), ('\u{627}', '\u{655}', '\u{625}'), ('\u{648}', '\u{654}', '\u{624}'), ('\u{64A}', '\u{654}', '\u{626}'),
+  ('\u{6C1}', '\u{654}', '\u{6C2}'), ('\u{6D2}', '\u{654}', '\u{6D3}'), ('\u{6D5}', '\u{654}', '\u{6C0}'), ('\u{928}', '\u{93C}', '\u{929}'), ('\u{930}', '\u{93C}', '\u{931}'), ('\u{933}', '\u{93C}', '\u{934}'),
+  ('\u{9C7}', '\u{9BE}', '\u{9CB}'), ('\u{9C7}', '\u{9D7}', '\u{9CC}'), ('\u{B47}', '\u{B3E}', '\u{B4B}'), ('\u{B47}', '\u{B56}', '\u{B48}'), ('\u{B47}', '\u{B57}', '\u{B4C}'), ('\u{B92}', '\u{BD7}', '\u{B94}'),
+  ('\u{BC6}', '\u{BBE}', '\u{BCA}'), ('\u{BC6}', '\u{BD7}', '\u{BCC}'), ('\u{BC7}', '\u{BBE}', '\u{BCB}'), ('\u{C46}', '\u{C56}', '\u{C48}'), ('\u{CBF}', '\u{CD5}', '\u{CC0}'), ('\u{CC6}', '\u{CC2}', '\u{CCA}'),
+  ('\u{CC6}', '\u{CD5}', '\u{CC7}'), ('\u{CC6}', '\u{CD6}', '\u{CC8}'), ('\u{CCA}', '\u{CD5}', '\u{CCB}'), ('\u{D46}', '\u{D3E}', '\u{D4A}'), ('\u{D46}', '\u{D57}', '\u{D4C}'), ('\u{D47}', '\u{D3E}', '\u{D4B}'),
+  ('\u{DD9}', '\u{DCA}', '\u{DDA}'), ('\u{DD9}', '\u{DCF}', '\u{DDC}'), ('\u{DD9}', '\u{DDF}', '\u{DDE}'), ('\u{DDC}', '\u{DCA}', '\u{DDD}'), ('\u{1025}', '\u{102E}', '\u{1026}'), ('\u{1B05}', '\u{1B35}', '\u{1B06}'),
+  ('\u{1B07}', '\u{1B35}', '\u{1B08}'), ('\u{1B09}', '\u{1B35}', '\u{1B0A}'), ('\u{1B0B}', '\u{1B35}', '\u{1B0C}'), ('\u{1B0D}', '\u{1B35}', '\u{1B0E}'), ('\u{1B11}', '\u{1B35}', '\u{1B12}'), ('\u{1B3A}', '\u{1B35}', '\u{1B3B}'),
+  ('\u{1B3C}', '\u{1B35}', '\u{1B3D}'), ('\u{1B3E}', '\u{1B35}', '\u{1B40}'), ('\u{1B3F}', '\u{1B35}', '\u{1B41}'), ('\u{1B42}', '\u{1B35}', '\u{1B43}'), ('\u{1E36}', '\u{304}', '\u{1E38}'), ('\u{1E37}', '\u{304}', '\u{1E39}'),
+  ('\u{1E5A}', '\u{304}', '\u{1E5C}'), ('\u{1E5B}', '\u{304}', '\u{1E5D}'), ('\u{1E62}', '\u{307}', '\u{1E68}'), ('\u{1E63}', '\u{307}', '\u{1E69}'), ('\u{1EA0}', '\u{302}', '\u{1EAC}'), ('\u{1EA0}', '\u{306}', '\u{1EB6}'),
+  ('\u{1EA1}', '\u{302}', '\u{1EAD}'), ('\u{1EA1}', '\u{306}', '\u{1EB7}'), ('\u{1EB8}', '\u{302}', '\u{1EC6}'), ('\u{1EB9}', '\u{302}', '\u{1EC7}'), ('\u{1ECC}', '\u{302}', '\u{1ED8}'), ('\u{1ECD}', '\u{302}', '\u{1ED9}'),
+  ('\u{1F00}', '\u{300}', '\u{1F02}'), ('\u{1F00}', '\u{301}', '\u{1F04}'), ('\u{1F00}', '\u{342}', '\u{1F06}'), ('\u{1F00}', '\u{345}', '\u{1F80}'), ('\u{1F01}', '\u{300}', '\u{1F03}'), ('\u{1F01}', '\u{301}', '\u{1F05}'),
+  ('\u{1F01}', '\u{342}', '\u{1F07}'), ('\u{1F01}', '\u{345}', '\u{1F81}'), ('\u{1F02}', '\u{345}', '\u{1F82}'), ('\u{1F03}', '\u{345}', '\u{1F83}'), ('\u{1F04}', '\u{345}', '\u{1F84}'), ('\u{1F05}', '\u{345}', '\u{1F85}'),
+  ('\u{1F06}', '\u{345}', '\u{1F86}'), ('\u{1F07}', '\u{345}', '\u{1F87}'), ('\u{1F08}', '\u{300}', '\u{1F0A}'), ('\u{1F08}', '\u{301}', '\u{1F0C}'), ('\u{1F08}', '\u{342}', '\u{1F0E}'), ('\u{1F08}', '\u{345}', '\u{1F88}'),
+  ('\u{1F09}', '\u{300}', '\u{1F0B}'), ('\u{1F09}', '\u{301}', '\u{1F0D}'), ('\u{1F09}', '\u{342}', '\u{1F0F}'), ('\u{1F09}', '\u{345}', '\u{1F89}'), ('\u{1F0A}', '\u{345}', '\u{1F8A}'), ('\u{1F0B}', '\u{345}', '\u{1F8B}'),
+  ('\u{1F0C}', '\u{345}', '\u{1F8C}'), ('\u{1F0D}', '\u{345}', '\u{1F8D}'), ('\u{1F0E}', '\u{345}', '\u{1F8E}'), ('\u{1F0F}', '\u{345}', '\u{1F8F}'), ('\u{1F10}', '\u{300}', '\u{1F12}'), ('\u{1F10}', '\u{301}', '\u{1F14}'),
+  ('\u{1F11}', '\u{300}', '\u{1F13}'), ('\u{1F11}', '\u{301}', '\u{1F15}'), ('\u{1F18}', '\u{300}', '\u{1F1A}'), ('\u{1F18}', '\u{301}', '\u{1F1C}'), ('\u{1F19}', '\u{300}', '\u{1F1B}'), ('\u{1F19}', '\u{301}', '\u{1F1D}'),
+  ('\u{1F20}', '\u{300}', '\u{1F22}'), ('\u{1F20}', '\u{301}', '\u{1F24}'), ('\u{1F20}', '\u{342}', '\u{1F26}'), ('\u{1F20}', '\u{345}', '\u{1F90}'), ('\u{1F21}', '\u{300}', '\u{1F23}'), ('\u{1F21}', '\u{301}', '\u{1F25}'),
+  ('\u{1F21}', '\u{342}', '\u{1F27}'), ('\u{1F21}', '\u{345}', '\u{1F91}'), ('\u{1F22}', '\u{345}', '\u{1F92}'), ('\u{1F23}', '\u{345}', '\u{1F93}'), ('\u{1F24}', '\u{345}', '\u{1F94}'), ('\u{1F25}', '\u{345}', '\u{1F95}'),
+  ('\u{1F26}', '\u{345}', '\u{1F96}'), ('\u{1F27}', '\u{345}', '\u{1F97}'), ('\u{1F28}', '\u{300}', '\u{1F2A}'), ('\u{1F28}', '\u{301}', '\u{1F2C}'), ('\u{1F28}', '\u{342}', '\u{1F2E}'), ('\u{1F28}', '\u{345}', '\u{1F98}'),
+  ('\u{1F29}', '\u{300}', '\u{1F2B}'), ('\u{1F29}', '\u{301}', '\u{1F2D}'), ('\u{1F29}', '\u{342}', '\u{1F2F}'), ('\u{1F29}', '\u{345}', '\u{1F99}'), ('\u{1F2A}', '\u{345}', '\u{1F9A}'), ('\u{1F2B}', '\u{345}', '\u{1F9B}'),
+  ('\u{1F2C}', '\u{345}', '\u{1F9C}'), ('\u{1F2D}', '\u{345}', '\u{1F9D}'), ('\u{1F2E}', '\u{345}', '\u{1F9E}'), ('\u{1F2F}', '\u{345}', '\u{1F9F}'), ('\u{1F30}', '\u{300}', '\u{1F32}'), ('\u{1F30}', '\u{301}', '\u{1F34}'),
+  ('\u{1F30}', '\u{342}', '\u{1F36}'), ('\u{1F31}', '\u{300}', '\u{1F33}'), ('\u{1F31}', '\u{301}', '\u{1F35}'), ('\u{1F31}', '\u{342}', '\u{1F37}'), ('\u{1F38}', '\u{300}', '\u{1F3A}'), ('\u{1F38}', '\u{301}', '\u{1F3C}'),
+  ('\u{1F38}', '\u{342}', '\u{1F3E}'), ('\u{1F39}', '\u{300}', '\u{1F3B}'), ('\u{1F39}', '\u{301}', '\u{1F3D}'), ('\u{1F39}', '\u{342}', '\u{1F3F}'), ('\u{1F40}', '\u{300}', '\u{1F42}'), ('\u{1F40}', '\u{301}', '\u{1F44}'),
+  ('\u{1F41}', '\u{300}', '\u{1F43}'), ('\u{1F41}', '\u{301}', '\u{1F45}'), ('\u{1F48}', '\u{300}', '\u{1F4A}'), ('\u{1F48}', '\u{301}', '\u{1F4C}'), ('\u{1F49}', '\u{300}', '\u{1F4B}'), ('\u{1F49}', '\u{301}', '\u{1F4D}'),
+  ('\u{1F50}', '\u{300}', '\u{1F52}'), ('\u{1F50}', '\u{301}', '\u{1F54}'), ('\u{1F50}', '\u{342}', '\u{1F56}'), ('\u{1F51}', '\u{300}', '\u{1F53}'), ('\u{1F51}', '\u{301}', '\u{1F55}'), ('\u{1F51}', '\u{342}', '\u{1F57}'),
+  ('\u{1F59}', '\u{300}', '\u{1F5B}'), ('\u{1F59}', '\u{301}', '\u{1F5D}'), ('\u{1F59}', '\u{342}', '\u{1F5F}'), ('\u{1F60}', '\u{300}', '\u{1F62}'), ('\u{1F60}', '\u{301}', '\u{1F64}'), ('\u{1F60}', '\u{342}', '\u{1F66}'),
+  ('\u{1F60}', '\u{345}', '\u{1FA0}'), ('\u{1F61}', '\u{300}', '\u{1F63}'), ('\u{1F61}', '\u{301}', '\u{1F65}'), ('\u{1F61}', '\u{342}', '\u{1F67}'), ('\u{1F61}', '\u{345}', '\u{1FA1}'), ('\u{1F62}', '\u{345}', '\u{1FA2}'),
+  ('\u{1F63}', '\u{345}', '\u{1FA3}'), ('\u{1F64}', '\u{345}', '\u{1FA4}'), ('\u{1F65}', '\u{345}', '\u{1FA5}'), ('\u{1F66}', '\u{345}', '\u{1FA6}'), ('\u{1F67}', '\u{345}', '\u{1FA7}'), ('\u{1F68}', '\u{300}', '\u{1F6A}'),
+  ('\u{1F68}', '\u{301}', '\u{1F6C}'), ('\u{1F68}', '\u{342}', '\u{1F6E}'), ('\u{1F68}', '\u{345}', '\u{1FA8}'), ('\u{1F69}', '\u{300}', '\u{1F6B}'), ('\u{1F69}', '\u{301}', '\u{1F6D}'), ('\u{1F69}', '\u{342}', '\u{1F6F}'),
+  ('\u{1F69}', '\u{345}', '\u{1FA9}'), ('\u{1F6A}', '\u{345}', '\u{1FAA}'), ('\u{1F6B}', '\u{345}', '\u{1FAB}'), ('\u{1F6C}', '\u{345}', '\u{1FAC}'), ('\u{1F6D}', '\u{345}', '\u{1FAD}'), ('\u{1F6E}', '\u{345}', '\u{1FAE}'),
+  ('\u{1F6F}', '\u{345}', '\u{1FAF}'), ('\u{1F70}', '\u{345}', '\u{1FB2}'), ('\u{1F74}', '\u{345}', '\u{1FC2}'), ('\u{1F7C}', '\u{345}', '\u{1FF2}'), ('\u{1FB6}', '\u{345}', '\u{1FB7}'), ('\u{1FBF}', '\u{300}', '\u{1FCD}'),
+  ('\u{1FBF}', '\u{301}', '\u{1FCE}'), ('\u{1FBF}', '\u{342}', '\u{1FCF}'), ('\u{1FC6}', '\u{345}', '\u{1FC7}'), ('\u{1FF6}', '\u{345}', '\u{1FF7}'), ('\u{1FFE}', '\u{300}', '\u{1FDD}'), ('\u{1FFE}', '\u{301}', '\u{1FDE}'),
+  ('\u{1FFE}', '\u{342}', '\u{1FDF}'), ('\u{2190}', '\u{338}', '\u{219A}'), ('\u{2192}', '\u{338}', '\u{219B}'), ('\u{2194}', '\u{338}', '\u{21AE}'), ('\u{21D0}', '\u{338}', '\u{21CD}'), ('\u{21D2}', '\u{338}', '\u{21CF}'),
+  ('\u{21D4}', '\u{338}', '\u{21CE}'), ('\u{2203}', '\u{338}', '\u{2204}'), ('\u{2208}', '\u{338}', '\u{2209}'), ('\u{220B}', '\u{338}', '\u{220C}'), ('\u{2223}', '\u{338}', '\u{2224}'), ('\u{2225}', '\u{338}', '\u{2226}'),
+  ('\u{223C}', '\u{338}', '\u{2241}'), ('\u{2243}', '\u{338}', '\u{2244}'), ('\u{2245}', '\u{338}', '\u{2247}'), ('\u{2248}', '\u{338}', '\u{2249}'), ('\u{224D}', '\u{338}', '\u{226D}'), ('\u{2261}', '\u{338}', '\u{2262}'),
+  ('\u{2264}', '\u{338}', '\u{2270}'), ('\u{2265}', '\u{338}', '\u{2271}'), ('\u{2272}', '\u{338}', '\u{2274}'), ('\u{2273}', '\u{338}', '\u{2275}'), ('\u{2276}', '\u{338}', '\u{2278}'), ('\u{2277}', '\u{338}', '\u{2279}'),
+  ('\u{227A}', '\u{338}', '\u{2280}'), ('\u{227B}', '\u{338}', '\u{2281}'), ('\u{227C}', '\u{338}', '\u{22E0}'), ('\u{227D}', '\u{338}', '\u{22E1}'), ('\u{2282}', '\u{338}', '\u{2284}'), ('\u{2283}', '\u{338}', '\u{2285}'),
+  ('\u{2286}', '\u{338}', '\u{2288}'), ('\u{2287}', '\u{338}', '\u{2289}'), ('\u{2291}', '\u{338}', '\u{22E2}'), ('\u{2292}', '\u{338}', '\u{22E3}'), ('\u{22A2}', '\u{338}', '\u{22AC}'), ('\u{22A8}', '\u{338}', '\u{22AD}'),
+  ('\u{22A9}', '\u{338}', '\u{22AE}'), ('\u{22AB}', '\u{338}', '\u{22AF}'), ('\u{22B2}', '\u{338}', '\u{22EA}'), ('\u{22B3}', '\u{338}', '\u{22EB}'), ('\u{22B4}', '\u{338}', '\u{22EC}'), ('\u{22B5}', '\u{338}', '\u{22ED}'),
+  ('\u{3046}', '\u{3099}', '\u{3094}'), ('\u{304B}', '\u{3099}', '\u{304C}'), ('\u{304D}', '\u{3099}', '\u{304E}'), ('\u{304F}', '\u{3099}', '\u{3050}'), ('\u{3051}', '\u{3099}', '\u{3052}'), ('\u{3053}', '\u{3099}', '\u{3054}'),
+  ('\u{3055}', '\u{3099}', '\u{3056}'), ('\u{3057}', '\u{3099}', '\u{3058}'), ('\u{3059}', '\u{3099}', '\u{305A}'), ('\u{305B}', '\u{3099}', '\u{305C}'), ('\u{305D}', '\u{3099}', '\u{305E}'), ('\u{305F}', '\u{3099}', '\u{3060}'),
+  ('\u{3061}', '\u{3099}', '\u{3062}'), ('\u{3064}', '\u{3099}', '\u{3065}'), ('\u{3066}', '\u{3099}', '\u{3067}'), ('\u{3068}', '\u{3099}', '\u{3069}'), ('\u{306F}', '\u{3099}', '\u{3070}'), ('\u{306F}', '\u{309A}', '\u{3071}'),
+  ('\u{3072}', '\u{3099}', '\u{3073}'), ('\u{3072}', '\u{309A}', '\u{3074}'), ('\u{3075}', '\u{3099}', '\u{3076}'), ('\u{3075}', '\u{309A}', '\u{3077}'), ('\u{3078}', '\u{3099}', '\u{3079}'), ('\u{3078}', '\u{309A}', '\u{307A}'),
+  ('\u{307B}', '\u{3099}', '\u{307C}'), ('\u{307B}', '\u{309A}', '\u{307D}'), ('\u{309D}', '\u{3099}', '\u{309E}'), ('\u{30A6}', '\u{3099}', '\u{30F4}'), ('\u{30AB}', '\u{3099}', '\u{30AC}'), ('\u{30AD}', '\u{3099}', '\u{30AE}'),
+  ('\u{30AF}', '\u{3099}', '\u{30B0}'), ('\u{30B1}', '\u{3099}', '\u{30B2}'), ('\u{30B3}', '\u{3099}', '\u{30B4}'), ('\u{30B5}', '\u{3099}', '\u{30B6}'), ('\u{30B7}', '\u{3099}', '\u{30B8}'), ('\u{30B9}', '\u{3099}', '\u{30BA}'),
+  ('\u{30BB}', '\u{3099}', '\u{30BC}'), ('\u{30BD}', '\u{3099}', '\u{30BE}'), ('\u{30BF}', '\u{3099}', '\u{30C0}'), ('\u{30C1}', '\u{3099}', '\u{30C2}'), ('\u{30C4}', '\u{3099}', '\u{30C5}'), ('\u{30C6}', '\u{3099}', '\u{30C7}'),
+  ('\u{30C8}', '\u{3099}', '\u{30C9}'), ('\u{30CF}', '\u{3099}', '\u{30D0}'), ('\u{30CF}', '\u{309A}', '\u{30D1}'), ('\u{30D2}', '\u{3099}', '\u{30D3}'), ('\u{30D2}', '\u{309A}', '\u{30D4}'), ('\u{30D5}', '\u{3099}', '\u{30D6}'),
+  ('\u{30D5}', '\u{309A}', '\u{30D7}'), ('\u{30D8}', '\u{3099}', '\u{30D9}'), ('\u{30D8}', '\u{309A}', '\u{30DA}'), ('\u{30DB}', '\u{3099}', '\u{30DC}'), ('\u{30DB}', '\u{309A}', '\u{30DD}'), ('\u{30EF}', '\u{3099}', '\u{30F7}'),
+  ('\u{30F0}', '\u{3099}', '\u{30F8}'), ('\u{30F1}', '\u{3099}', '\u{30F9}'), ('\u{30F2}', '\u{3099}', '\u{30FA}'), ('\u{30FD}', '\u{3099}', '\u{30FE}'), ('\u{11099}', '\u{110BA}', '\u{1109A}'), ('\u{1109B}', '\u{110BA}', '\u{1109C}'),
+  ('\u{110A5}', '\u{110BA}', '\u{110AB}'), ('\u{11131}', '\u{11127}', '\u{1112E}'), ('\u{11132}', '\u{11127}', '\u{1112F}'), ('\u{11347}', '\u{1133E}', '\u{1134B}'), ('\u{11347}', '\u{11357}', '\u{1134C}'), ('\u{114B9}', '\u{114B0}', '\u{114BC}'),
+  ('\u{114B9}', '\u{114BA}', '\u{114BB}'), ('\u{114B9}', '\u{114BD}', '\u{114BE}'), ('\u{115B8}', '\u{115AF}', '\u{115BA}'), ('\u{115B9}', '\u{115AF}', '\u{115BB}'), ('\u{11935}', '\u{11930}', '\u{11938}'),
+];
@@ -0,0 +1,292 @@
+//! 递归遍历目录, 按字典序产出其中的普通文件
+use crate::{ignore::IgnoreStack, index, Error};
+use std::{
+  fs, io,
+  path::{Path, PathBuf},
+  rc::Rc,
+};
+
+#[derive(Debug, Clone)]
+pub struct WalkOptions {
+  /// 跟随目录中的符号链接(命令行上直接给出的路径总是会被跟随)
+  pub follow_links: bool,
+  /// 是否包含以 `.` 开头的隐藏文件和目录
+  pub hidden: bool,
+  /// 最大递归深度, 命令行给出的路径深度为 0
+  pub max_depth: Option<usize>,
+  /// 是否遵循 `.gitignore`、`.ignore` 和全局忽略文件
+  pub ignore: bool,
+  pub global_ignore: Option<PathBuf>,
+}
+
+impl Default for WalkOptions {
+  fn default() -> WalkOptions {
+    WalkOptions {
+      follow_links: false,
+      hidden: false,
+      max_depth: None,
+      ignore: true,
+      global_ignore: None,
+    }
+  }
+}
+
+/// 跟随符号链接时记录祖先目录的真实路径, 链接指回祖先即为循环
+struct Ancestor {
+  dir: PathBuf,
+  parent: Option<Rc<Ancestor>>,
+}
+
+impl Ancestor {
+  fn contains(ancestor: &Option<Rc<Ancestor>>, dir: &Path) -> bool {
+    let mut node = ancestor.as_ref();
+    while let Some(ancestor) = node {
+      if ancestor.dir == dir {
+        return true;
+      }
+      node = ancestor.parent.as_ref();
+    }
+    false
+  }
+}
+
+struct Entry {
+  path: PathBuf,
+  depth: usize,
+  ancestor: Option<Rc<Ancestor>>,
+  ignores: Option<Rc<IgnoreStack>>,
+}
+
+pub struct Walk {
+  stack: Vec<Entry>,
+  options: WalkOptions,
+}
+
+impl Walk {
+  pub fn new<P: AsRef<Path>>(roots: &[P], options: WalkOptions) -> Walk {
+    Walk {
+      stack: roots
+        .iter()
+        .rev()
+        .map(|root| Entry {
+          path: root.as_ref().to_path_buf(),
+          depth: 0,
+          ancestor: None,
+          ignores: None,
+        })
+        .collect(),
+      options,
+    }
+  }
+
+  fn descend(&mut self, dir: Entry) -> io::Result<()> {
+    if self.options.max_depth.is_some_and(|max| dir.depth >= max) {
+      return Ok(());
+    }
+    let ancestor = if self.options.follow_links {
+      let real = dir.path.canonicalize()?;
+      if Ancestor::contains(&dir.ancestor, &real) {
+        return Ok(());
+      }
+      Some(Rc::new(Ancestor {
+        dir: real,
+        parent: dir.ancestor,
+      }))
+    } else {
+      None
+    };
+    let ignores = if !self.options.ignore {
+      None
+    } else if dir.depth == 0 {
+      let root = IgnoreStack::root(&dir.path, self.options.global_ignore.as_deref())?;
+      IgnoreStack::child(root, &dir.path)?
+    } else {
+      IgnoreStack::child(dir.ignores, &dir.path)?
+    };
+
+    let mut entries = vec![];
+    for entry in fs::read_dir(&dir.path)? {
+      let entry = entry?;
+      if !self.options.hidden && is_hidden(&entry.file_name()) {
+        continue;
+      }
+      //minigrep 自己的索引不是要搜索的内容, 即使给出 --hidden 也跳过
+      if entry.file_name().to_str().is_some_and(index::is_index_file) {
+        continue;
+      }
+      if let Some(ignores) = &ignores {
+        let is_dir = if self.options.follow_links {
+          entry.path().is_dir()
+        } else {
+          entry.file_type()?.is_dir()
+        };
+        if ignores.is_ignored(&entry.path(), is_dir) {
+          continue;
+        }
+      }
+      entries.push(entry.path());
+    }
+    entries.sort();
+    let depth = dir.depth + 1;
+    self.stack.extend(entries.into_iter().rev().map(|path| Entry {
+      path,
+      depth,
+      ancestor: ancestor.clone(),
+      ignores: ignores.clone(),
+    }));
+    Ok(())
+  }
+}
+
+impl Iterator for Walk {
+  type Item = Result<PathBuf, Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    while let Some(entry) = self.stack.pop() {
+      let meta = if entry.depth == 0 || self.options.follow_links {
+        fs::metadata(&entry.path)
+      } else {
+        fs::symlink_metadata(&entry.path)
+      };
+      let meta = match meta {
+        Ok(meta) => meta,
+        Err(e) => return Some(Err(Error::io(&entry.path, e))),
+      };
+
+      if meta.is_dir() {
+        let path = entry.path.clone();
+        if let Err(e) = self.descend(entry) {
+          return Some(Err(Error::io(&path, e)));
+        }
+      } else if meta.is_file() {
+        return Some(Ok(entry.path));
+      }
+    }
+    None
+  }
+}
+
+fn is_hidden(name: &std::ffi::OsStr) -> bool {
+  name.to_str().is_some_and(|name| name.starts_with('.'))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn fixture(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("minigrep-walk-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    for dir in &["src/nested", ".git"] {
+      fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    for file in &["a.txt", "src/b.rs", "src/nested/c.rs", ".hidden", ".git/config"] {
+      fs::write(root.join(file), "").unwrap();
+    }
+    root
+  }
+
+  fn walk(root: &Path, options: WalkOptions) -> Vec<String> {
+    Walk::new(&[root], options)
+      .map(|path| {
+        let path = path.unwrap();
+        path.strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/")
+      })
+      .collect()
+  }
+
+  #[test]
+  fn sorted_and_skips_hidden() {
+    let root = fixture("hidden");
+    assert_eq!(vec!["a.txt", "src/b.rs", "src/nested/c.rs"], walk(&root, WalkOptions::default()));
+
+    let options = WalkOptions {
+      hidden: true,
+      ..WalkOptions::default()
+    };
+    assert_eq!(
+      vec![".git/config", ".hidden", "a.txt", "src/b.rs", "src/nested/c.rs"],
+      walk(&root, options)
+    );
+    fs::remove_dir_all(root).unwrap();
+  }
+
+  #[test]
+  fn max_depth() {
+    let root = fixture("depth");
+    let depth = |max_depth| WalkOptions {
+      max_depth: Some(max_depth),
+      ..WalkOptions::default()
+    };
+    assert!(walk(&root, depth(0)).is_empty());
+    assert_eq!(vec!["a.txt"], walk(&root, depth(1)));
+    assert_eq!(vec!["a.txt", "src/b.rs"], walk(&root, depth(2)));
+    fs::remove_dir_all(root).unwrap();
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn symlinks() {
+    let root = fixture("links");
+    std::os::unix::fs::symlink(root.join("src"), root.join("src/nested/loop")).unwrap();
+    std::os::unix::fs::symlink(root.join("src/nested"), root.join("link")).unwrap();
+    assert_eq!(vec!["a.txt", "src/b.rs", "src/nested/c.rs"], walk(&root, WalkOptions::default()));
+
+    let options = WalkOptions {
+      follow_links: true,
+      ..WalkOptions::default()
+    };
+    assert_eq!(
+      vec!["a.txt", "link/c.rs", "link/loop/b.rs", "src/b.rs", "src/nested/c.rs"],
+      walk(&root, options)
+    );
+    fs::remove_dir_all(root).unwrap();
+  }
+
+  #[test]
+  fn ignore_files() {
+    let root = std::env::temp_dir().join(format!("minigrep-walk-ignore-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    for dir in &[".git", "build", "src/build", "src/tmp"] {
+      fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    let files = [
+      ("global", "*.bak\n"),
+      (".gitignore", "*.log\n/build/\ntmp/\nglobal\n"),
+      ("src/.gitignore", "!debug.log\n*.rs\n!main.rs\n!util.rs\n"),
+      ("src/.ignore", "util.rs\n"),
+      ("a.txt", ""),
+      ("a.bak", ""),
+      ("app.log", ""),
+      ("build/out.txt", ""),
+      ("src/build/gen.txt", ""),
+      ("src/debug.log", ""),
+      ("src/lib.rs", ""),
+      ("src/main.rs", ""),
+      ("src/tmp/x.txt", ""),
+      ("src/util.rs", ""),
+    ];
+    for (file, contents) in &files {
+      fs::write(root.join(file), contents).unwrap();
+    }
+    let options = WalkOptions {
+      global_ignore: Some(root.join("global")),
+      ..WalkOptions::default()
+    };
+
+    assert_eq!(
+      vec!["a.txt", "src/build/gen.txt", "src/debug.log", "src/main.rs"],
+      walk(&root, options.clone())
+    );
+    //从子目录开始搜索时, 仓库根目录中的规则同样生效
+    let src = root.join("src");
+    assert_eq!(vec!["build/gen.txt", "debug.log", "main.rs"], walk(&src, options.clone()));
+
+    let options = WalkOptions {
+      ignore: false,
+      ..options
+    };
+    assert_eq!(11, walk(&root, options).len());
+    fs::remove_dir_all(root).unwrap();
+  }
+}
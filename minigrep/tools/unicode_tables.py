@@ -0,0 +1,94 @@
+#!/usr/bin/env python3
+"""生成 src/unicode_tables.rs: 大小写折叠和规范化(NFC/NFD)用到的表
+
+数据来自 Python 自带的 unicodedata, 用法: python3 tools/unicode_tables.py > src/unicode_tables.rs
+"""
+import sys
+import unicodedata
+
+PER_LINE = 6
+
+
+def rust_char(c):
+    return "'\\u{%X}'" % ord(c)
+
+
+def rust_str(s):
+    return '"' + "".join("\\u{%X}" % ord(c) for c in s) + '"'
+
+
+def simple_fold(c):
+    # CaseFolding.txt 的 C+S: 完整折叠是单个字符时两者相同, 否则退回到单字符的小写
+    full = c.casefold()
+    if len(full) == 1:
+        return full
+    lower = c.lower()
+    return lower if len(lower) == 1 else c
+
+
+def table(name, ty, rows):
+    out = ["pub(crate) const %s: &[%s] = &[" % (name, ty)]
+    for i in range(0, len(rows), PER_LINE):
+        out.append("  " + " ".join(r + "," for r in rows[i:i + PER_LINE]))
+    out.append("];")
+    return "\n".join(out)
+
+
+def main():
+    chars = [chr(cp) for cp in range(0x110000) if not 0xD800 <= cp < 0xE000]
+    full = [(c, c.casefold()) for c in chars if c.casefold() != c]
+    simple = [(c, simple_fold(c)) for c in chars if simple_fold(c) != c]
+
+    # 完整(递归)展开的规范分解, 韩文音节按算法处理不在表中
+    decomp = []
+    for c in chars:
+        if 0xAC00 <= ord(c) < 0xAC00 + 11172:
+            continue
+        d = unicodedata.normalize("NFD", c)
+        if d != c:
+            decomp.append((c, d))
+
+    ccc = []
+    for c in chars:
+        cc = unicodedata.combining(c)
+        if cc == 0:
+            continue
+        if ccc and ccc[-1][2] == cc and ord(ccc[-1][1]) + 1 == ord(c):
+            ccc[-1][1] = c
+        else:
+            ccc.append([c, c, cc])
+
+    # 只有能由 NFC 重新得到的两字符分解才参与组合, 这样自然排除了组合排除表中的字符
+    compose = []
+    for c in chars:
+        if 0xAC00 <= ord(c) < 0xAC00 + 11172:
+            continue
+        d = unicodedata.decomposition(c)
+        if not d or d.startswith("<"):
+            continue
+        parts = [chr(int(x, 16)) for x in d.split()]
+        if len(parts) == 2 and unicodedata.normalize("NFC", "".join(parts)) == c:
+            compose.append((parts[0], parts[1], c))
+    compose.sort(key=lambda t: (ord(t[0]), ord(t[1])))
+
+    print("//! 由 tools/unicode_tables.py 生成, 不要手动修改")
+    print("//! Unicode %s" % unicodedata.unidata_version)
+    print()
+    print("/// 完整大小写折叠(CaseFolding.txt 的 C+F), 只列出会改变的字符")
+    print(table("FULL_FOLD", "(char, &str)", ["(%s, %s)" % (rust_char(c), rust_str(f)) for c, f in full]))
+    print()
+    print("/// 简单大小写折叠(CaseFolding.txt 的 C+S)")
+    print(table("SIMPLE_FOLD", "(char, char)", ["(%s, %s)" % (rust_char(c), rust_char(f)) for c, f in simple]))
+    print()
+    print("/// 完整展开的规范分解")
+    print(table("DECOMPOSITION", "(char, &str)", ["(%s, %s)" % (rust_char(c), rust_str(d)) for c, d in decomp]))
+    print()
+    print("/// 非零的规范组合类, 按区间合并")
+    print(table("COMBINING_CLASS", "(char, char, u8)", ["(%s, %s, %d)" % (rust_char(a), rust_char(b), cc) for a, b, cc in ccc]))
+    print()
+    print("/// 规范组合: (起始字符, 组合字符) -> 组合结果")
+    print(table("COMPOSITION", "(char, char, char)", ["(%s, %s, %s)" % (rust_char(a), rust_char(b), rust_char(c)) for a, b, c in compose]))
+
+
+if __name__ == "__main__":
+    sys.exit(main())